@@ -0,0 +1,107 @@
+#ifndef RENAME_H
+#define RENAME_H
+
+/* Generated by cbindgen from src/capi.rs; do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * A configuration being set up, created by `rename_config_new`.
+ */
+typedef struct RenameConfig RenameConfig;
+
+typedef struct RenamePlan RenamePlan;
+
+/**
+ * Starts a configuration for renaming the files in `dir` after `data_file`,
+ * or returns null if either is not valid UTF-8.
+ *
+ * # Safety
+ *
+ * Both arguments must be valid NUL-terminated strings.
+ */
+RenameConfig *rename_config_new(const char *data_file, const char *dir);
+
+/**
+ * Sets the option `name`, named as the command-line flag without its
+ * dashes, such as `template` or `on-conflict`, to `value`. Flags are set by
+ * `true` or `false`, and repeatable options such as `include` are added to.
+ * Returns 0 on success and -1 if the option is unknown or the value invalid,
+ * leaving the configuration as it was.
+ *
+ * # Safety
+ *
+ * `config` must come from `rename_config_new` and not be freed or planned
+ * yet; `name` and `value` must be valid NUL-terminated strings.
+ */
+int rename_config_set(RenameConfig *config, const char *name, const char *value, char **error);
+
+/**
+ * Frees a configuration that was not planned.
+ *
+ * # Safety
+ *
+ * `config` must come from `rename_config_new` and not be freed or planned
+ * yet, or be null.
+ */
+void rename_config_free(RenameConfig *config);
+
+/**
+ * Plans the renaming without touching any files, consuming the
+ * configuration. Returns null if the configuration is invalid or planning
+ * fails.
+ *
+ * # Safety
+ *
+ * `config` must come from `rename_config_new` and not be freed or planned
+ * yet. It is freed by this call.
+ */
+RenamePlan *rename_plan(RenameConfig *config, char **error);
+
+/**
+ * Describes the plan as a JSON array of rows with the old and new name, lot,
+ * inventory number and status of every file, and the rows of the data file
+ * that matched no files.
+ *
+ * # Safety
+ *
+ * `plan` must come from `rename_plan` and not be freed or executed yet.
+ */
+char *rename_plan_rows(const RenamePlan *plan);
+
+/**
+ * Renames the files as planned, consuming the plan, and describes the outcome
+ * as the JSON report the command line writes with `--report json`: the
+ * renamed files and those left alone, or just the error.
+ *
+ * # Safety
+ *
+ * `plan` must come from `rename_plan` and not be freed or executed yet. It is
+ * freed by this call.
+ */
+char *rename_execute(RenamePlan *plan);
+
+/**
+ * Frees a plan that was not executed.
+ *
+ * # Safety
+ *
+ * `plan` must come from `rename_plan` and not be freed or executed yet, or be
+ * null.
+ */
+void rename_plan_free(RenamePlan *plan);
+
+/**
+ * Frees a string returned by the library.
+ *
+ * # Safety
+ *
+ * `string` must come from this library and not be freed yet, or be null.
+ */
+void rename_string_free(char *string);
+
+#endif  /* RENAME_H */
@@ -0,0 +1,119 @@
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+// AuditEntry is a line of the audit log. Lines are only ever appended, so the
+// log tells which file got which name, when, across all runs.
+#[derive(Debug, Serialize)]
+struct AuditEntry<'a> {
+    // When the renaming finished, in RFC 3339 and UTC.
+    time: String,
+    // What was done: rename, copy, or revert when a renaming is rolled back.
+    action: &'a str,
+    old: String,
+    new: String,
+    // "ok" or why it failed.
+    result: String,
+    // The SHA-256 checksum of the file, in hexadecimal, when verifying.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    checksum: Option<String>,
+}
+
+// AuditLog appends a line to the audit log for every renaming.
+pub struct AuditLog {
+    file: Mutex<File>,
+}
+
+impl AuditLog {
+    // open continues the audit log at path, which is created if it does not exist.
+    pub fn open(path: &Path) -> io::Result<AuditLog> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        Ok(AuditLog {
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn record(
+        &self,
+        action: &str,
+        old_path: &Path,
+        new_path: &Path,
+        result: &io::Result<()>,
+        checksum: Option<Vec<u8>>,
+    ) -> io::Result<()> {
+        let entry = AuditEntry {
+            time: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            action,
+            old: old_path.display().to_string(),
+            new: new_path.display().to_string(),
+            result: match result {
+                Ok(()) => String::from("ok"),
+                Err(err) => err.to_string(),
+            },
+            checksum: checksum.map(|checksum| {
+                checksum
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect()
+            }),
+        };
+
+        let line = serde_json::to_string(&entry)?;
+        let mut file = self.file.lock().unwrap();
+        writeln!(file, "{}", line)?;
+        file.sync_data()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn append_to_audit_log() {
+        let path = Path::new("tests/tmp_audit.jsonl");
+        let _ = fs::remove_file(path);
+
+        let failure = Err(io::Error::new(io::ErrorKind::NotFound, "not found"));
+        AuditLog::open(path)
+            .unwrap()
+            .record(
+                "rename",
+                Path::new("a.jpg"),
+                Path::new("1_1.jpg"),
+                &Ok(()),
+                None,
+            )
+            .unwrap();
+        AuditLog::open(path)
+            .unwrap()
+            .record(
+                "copy",
+                Path::new("b.jpg"),
+                Path::new("1_2.jpg"),
+                &failure,
+                Some(vec![0, 255]),
+            )
+            .unwrap();
+
+        let contents = fs::read_to_string(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        let lines: Vec<serde_json::Value> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(2, lines.len());
+        assert_eq!("a.jpg", lines[0]["old"]);
+        assert_eq!("ok", lines[0]["result"]);
+        assert!(lines[0].get("checksum").is_none());
+        assert_eq!("not found", lines[1]["result"]);
+        assert_eq!("00ff", lines[1]["checksum"]);
+        assert!(lines[1]["time"].as_str().unwrap().ends_with('Z'));
+    }
+}
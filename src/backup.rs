@@ -0,0 +1,141 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::storage::Storage;
+use crate::RenameError;
+
+// The directory, in the target directory, where files displaced by
+// overwriting are kept by default.
+pub const BACKUP_DIR: &str = ".rename-backup";
+
+// run_dir is where a run starting now keeps the files it displaces: a
+// subdirectory of the backup directory named by the time to the millisecond,
+// so that the files of different runs are kept apart and the latest run sorts
+// last. On the local file system the directory is created, with a counter
+// added to its name if another run took that name already.
+pub fn run_dir(storage: &dyn Storage, backup_dir: &Path) -> io::Result<PathBuf> {
+    let time = humantime::format_rfc3339_millis(SystemTime::now()).to_string();
+    // Colons are not allowed in file names on Windows.
+    let name = time.replace(':', "");
+    if !storage.is_local() {
+        return Ok(backup_dir.join(name));
+    }
+
+    fs::create_dir_all(backup_dir)?;
+    for count in 1.. {
+        let run_dir = match count {
+            1 => backup_dir.join(&name),
+            _ => backup_dir.join(format!("{}-{}", name, count)),
+        };
+        match fs::create_dir(&run_dir) {
+            Ok(()) => return Ok(run_dir),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    unreachable!("a run directory is found before the counter runs out")
+}
+
+// clean_up removes the directory of a run that displaced no files, and the
+// backup directory if no other runs are kept in it, so that restoring finds
+// the latest run that did.
+pub fn clean_up(run_dir: &Path) {
+    if fs::remove_dir(run_dir).is_ok() {
+        if let Some(backup_dir) = run_dir.parent() {
+            let _ = fs::remove_dir(backup_dir);
+        }
+    }
+}
+
+// restore moves the files displaced by the latest run that kept a backup in
+// backup_dir, relative to dir, back to their names in dir. None is moved if
+// any of the names is taken, as it is until the run is undone.
+pub fn restore(dir: impl AsRef<Path>, backup_dir: &Path) -> Result<(), RenameError> {
+    let dir = dir.as_ref();
+    let _lock = crate::lock::DirLock::acquire(dir)?;
+    let backup_dir = dir.join(backup_dir);
+
+    let mut runs: Vec<String> = crate::list_files(&backup_dir)
+        .into_iter()
+        .filter(|run| backup_dir.join(run).is_dir())
+        .collect();
+    let Some(run) = runs.pop() else {
+        return Err(RenameError::Io(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no backup found in {}", backup_dir.display()),
+        )));
+    };
+    let run_dir = backup_dir.join(run);
+
+    let mut files = vec![];
+    list_tree(&run_dir, "", &mut files)?;
+    if let Some(file) = files.iter().find(|file| dir.join(file).exists()) {
+        return Err(RenameError::Io(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!(
+                "{} already exists; undo the run that displaced it first",
+                dir.join(file).display()
+            ),
+        )));
+    }
+
+    for file in &files {
+        log::info!("restoring {}", file);
+        let path = dir.join(file);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(run_dir.join(file), path)?;
+    }
+
+    fs::remove_dir_all(&run_dir)?;
+    if runs.is_empty() {
+        fs::remove_dir_all(&backup_dir)?;
+    }
+
+    Ok(())
+}
+
+// list_tree lists the files in dir and its subdirectories, by their paths
+// relative to the directory the listing started in.
+fn list_tree(dir: &Path, prefix: &str, files: &mut Vec<String>) -> io::Result<()> {
+    for name in crate::list_files(dir) {
+        let path = dir.join(&name);
+        let name = format!("{}{}", prefix, name);
+        if path.is_dir() {
+            list_tree(&path, &format!("{}/", name), files)?;
+        } else {
+            files.push(name);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::LocalStorage;
+
+    #[test]
+    fn keep_runs_in_the_same_millisecond_apart() {
+        let backup_dir = Path::new("tests/tmp_run_dirs");
+        let _ = fs::remove_dir_all(backup_dir);
+        let storage = LocalStorage::default();
+
+        let first = run_dir(&storage, backup_dir).unwrap();
+        let second = run_dir(&storage, backup_dir).unwrap();
+        clean_up(&first);
+        let second_kept = second.exists();
+        clean_up(&second);
+        let removed = !backup_dir.exists();
+
+        let _ = fs::remove_dir_all(backup_dir);
+
+        assert_ne!(first, second);
+        assert!(second_kept);
+        assert!(removed);
+    }
+}
@@ -0,0 +1,755 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::filter::FileFilter;
+use crate::{
+    Column, Condition, Config, ConflictPolicy, CrowdedLotPolicy, Dimensions, DuplicatePolicy,
+    Encoding, FileSize, Format, Link, LotTransform, Lots, MalformedPolicy, MatchMode,
+    Normalization, RenameError, ReportFormat, Storage, SuffixStrategy, Template, Transform, Widths,
+};
+
+// ConfigBuilder sets up a Config option by option and checks it as a whole in
+// build, so that programs embedding the crate get the same validation as the
+// command line.
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl ConfigBuilder {
+    pub fn new(data_file: impl Into<PathBuf>, dir: impl Into<PathBuf>) -> ConfigBuilder {
+        ConfigBuilder {
+            config: Config::new(data_file, dir),
+        }
+    }
+
+    pub fn data_file(mut self, data_file: impl Into<PathBuf>) -> ConfigBuilder {
+        self.config.data_file = data_file.into();
+        self
+    }
+
+    // extra_data_file adds a data file whose rows are merged with those of the
+    // data file.
+    pub fn extra_data_file(mut self, data_file: impl Into<PathBuf>) -> ConfigBuilder {
+        self.config.extra_data_files.push(data_file.into());
+        self
+    }
+
+    pub fn dir(mut self, dir: impl Into<PathBuf>) -> ConfigBuilder {
+        self.config.dir = dir.into();
+        self
+    }
+
+    // extra_dir adds a directory whose files are renamed along with those in
+    // the directory.
+    pub fn extra_dir(mut self, dir: impl Into<PathBuf>) -> ConfigBuilder {
+        self.config.extra_dirs.push(dir.into());
+        self
+    }
+
+    pub fn dry_run(mut self, dry_run: bool) -> ConfigBuilder {
+        self.config.dry_run = dry_run;
+        self
+    }
+
+    pub fn delimiter(mut self, delimiter: u8) -> ConfigBuilder {
+        self.config.delimiter = delimiter;
+        self
+    }
+
+    pub fn encoding(mut self, encoding: Encoding) -> ConfigBuilder {
+        self.config.encoding = Some(encoding);
+        self
+    }
+
+    pub fn lot_column(mut self, column: Column) -> ConfigBuilder {
+        self.config.lot_column = column;
+        self
+    }
+
+    pub fn inventory_column(mut self, column: Column) -> ConfigBuilder {
+        self.config.inventory_column = column;
+        self
+    }
+
+    pub fn date_column(mut self, column: Column) -> ConfigBuilder {
+        self.config.date_column = Some(column);
+        self
+    }
+
+    // hero_column takes the photo number of the photo of each object to show
+    // first from the column, which is then numbered 1.
+    pub fn hero_column(mut self, column: Column) -> ConfigBuilder {
+        self.config.hero_column = Some(column);
+        self
+    }
+
+    pub fn has_headers(mut self, has_headers: bool) -> ConfigBuilder {
+        self.config.has_headers = has_headers;
+        self
+    }
+
+    pub fn template(mut self, template: impl Into<String>) -> ConfigBuilder {
+        self.config.template = template.into();
+        self
+    }
+
+    pub fn keep_extension(mut self, keep_extension: bool) -> ConfigBuilder {
+        self.config.keep_extension = keep_extension;
+        self
+    }
+
+    pub fn lowercase_extension(mut self, lowercase_extension: bool) -> ConfigBuilder {
+        self.config.lowercase_extension = lowercase_extension;
+        self
+    }
+
+    pub fn jobs(mut self, jobs: usize) -> ConfigBuilder {
+        self.config.jobs = jobs;
+        self
+    }
+
+    // stage_all moves every file out of the way under a temporary name before
+    // giving any file its new name.
+    pub fn stage_all(mut self, stage_all: bool) -> ConfigBuilder {
+        self.config.stage_all = stage_all;
+        self
+    }
+
+    // from_regex renames the files after the capture groups of the regex
+    // instead of after the data file, which is not read.
+    pub fn from_regex(mut self, pattern: impl Into<String>) -> ConfigBuilder {
+        self.config.from_regex = Some(pattern.into());
+        self
+    }
+
+    // retries tries a renaming failing with a transient error this many times
+    // again.
+    pub fn retries(mut self, retries: u32) -> ConfigBuilder {
+        self.config.retries = retries;
+        self
+    }
+
+    // retry_delay is the wait before the first retry, which doubles before
+    // each next one.
+    pub fn retry_delay(mut self, delay: Duration) -> ConfigBuilder {
+        self.config.retry_delay = delay;
+        self
+    }
+
+    // skip_locked leaves files that another program holds under their old
+    // names instead of failing the run.
+    pub fn skip_locked(mut self, skip_locked: bool) -> ConfigBuilder {
+        self.config.skip_locked = skip_locked;
+        self
+    }
+
+    // skip leaves the first files of the plan, ordered by name, for later runs,
+    // with the rest of the photos of their objects.
+    pub fn skip(mut self, skip: usize) -> ConfigBuilder {
+        self.config.skip = skip;
+        self
+    }
+
+    // limit renames at most this many files, leaving the rest for later runs.
+    // Objects are not split, so an object with more files is renamed whole.
+    pub fn limit(mut self, limit: usize) -> ConfigBuilder {
+        self.config.limit = Some(limit);
+        self
+    }
+
+    pub fn format(mut self, format: Format) -> ConfigBuilder {
+        self.config.format = format;
+        self
+    }
+
+    pub fn sheet(mut self, sheet: impl Into<String>) -> ConfigBuilder {
+        self.config.sheet = Some(sheet.into());
+        self
+    }
+
+    pub fn widths(mut self, widths: Widths) -> ConfigBuilder {
+        self.config.widths = Some(widths);
+        self
+    }
+
+    pub fn lot_key(mut self, key: impl Into<String>) -> ConfigBuilder {
+        self.config.lot_key = key.into();
+        self
+    }
+
+    pub fn inventory_key(mut self, key: impl Into<String>) -> ConfigBuilder {
+        self.config.inventory_key = key.into();
+        self
+    }
+
+    pub fn query(mut self, query: impl Into<String>) -> ConfigBuilder {
+        self.config.query = query.into();
+        self
+    }
+
+    pub fn copy(mut self, copy: bool) -> ConfigBuilder {
+        self.config.copy = copy;
+        self
+    }
+
+    pub fn link(mut self, link: Link) -> ConfigBuilder {
+        self.config.link = Some(link);
+        self
+    }
+
+    pub fn output_dir(mut self, output_dir: impl Into<PathBuf>) -> ConfigBuilder {
+        self.config.output_dir = Some(output_dir.into());
+        self
+    }
+
+    pub fn strict(mut self, strict: bool) -> ConfigBuilder {
+        self.config.strict = strict;
+        self
+    }
+
+    pub fn ignore_case(mut self, ignore_case: bool) -> ConfigBuilder {
+        self.config.ignore_case = ignore_case;
+        self
+    }
+
+    pub fn trim(mut self, trim: bool) -> ConfigBuilder {
+        self.config.trim = trim;
+        self
+    }
+
+    // normalize_lot writes lot numbers as plain integers, without thousands
+    // separators or leading zeros.
+    pub fn normalize_lot(mut self, normalize_lot: bool) -> ConfigBuilder {
+        self.config.normalize_lot = normalize_lot;
+        self
+    }
+
+    pub fn lots(mut self, lots: Lots) -> ConfigBuilder {
+        self.config.lots = Some(lots);
+        self
+    }
+
+    // condition adds a condition that rows must meet for their files to be
+    // renamed.
+    pub fn condition(mut self, condition: Condition) -> ConfigBuilder {
+        self.config.conditions.push(condition);
+        self
+    }
+
+    pub fn match_mode(mut self, mode: MatchMode) -> ConfigBuilder {
+        self.config.match_mode = mode;
+        self
+    }
+
+    // key_column adds a column to the key that file names are matched by.
+    pub fn key_column(mut self, column: Column) -> ConfigBuilder {
+        self.config.key_columns.push(column);
+        self
+    }
+
+    pub fn key_separator(mut self, separator: impl Into<String>) -> ConfigBuilder {
+        self.config.key_separator = separator.into();
+        self
+    }
+
+    // symlinks renames symbolic links to files along with regular files.
+    pub fn symlinks(mut self, symlinks: bool) -> ConfigBuilder {
+        self.config.symlinks = symlinks;
+        self
+    }
+
+    // timings measures how long the stages of the run take.
+    pub fn timings(mut self, timings: bool) -> ConfigBuilder {
+        self.config.timings = timings;
+        self
+    }
+
+    pub fn verify(mut self, verify: bool) -> ConfigBuilder {
+        self.config.verify = verify;
+        self
+    }
+
+    // post_check lists the directories again after renaming and fails the run
+    // if a file is missing under its new name or still there under its old one.
+    pub fn post_check(mut self, post_check: bool) -> ConfigBuilder {
+        self.config.post_check = post_check;
+        self
+    }
+
+    pub fn preserve_xattrs(mut self, preserve_xattrs: bool) -> ConfigBuilder {
+        self.config.preserve_xattrs = preserve_xattrs;
+        self
+    }
+
+    pub fn on_conflict(mut self, policy: ConflictPolicy) -> ConfigBuilder {
+        self.config.on_conflict = policy;
+        self
+    }
+
+    pub fn watch(mut self, watch: bool) -> ConfigBuilder {
+        self.config.watch = watch;
+        self
+    }
+
+    // include adds a glob pattern of files to take part.
+    pub fn include(mut self, pattern: impl Into<String>) -> ConfigBuilder {
+        self.config.include.push(pattern.into());
+        self
+    }
+
+    // exclude adds a glob pattern of files to leave out.
+    pub fn exclude(mut self, pattern: impl Into<String>) -> ConfigBuilder {
+        self.config.exclude.push(pattern.into());
+        self
+    }
+
+    pub fn on_malformed(mut self, policy: MalformedPolicy) -> ConfigBuilder {
+        self.config.on_malformed = policy;
+        self
+    }
+
+    pub fn on_bad_row(mut self, policy: MalformedPolicy) -> ConfigBuilder {
+        self.config.on_bad_row = policy;
+        self
+    }
+
+    pub fn on_duplicate(mut self, policy: DuplicatePolicy) -> ConfigBuilder {
+        self.config.on_duplicate = policy;
+        self
+    }
+
+    // max_per_lot reports lots with more files than max, or fails the run if
+    // the policy for crowded lots is to error.
+    pub fn max_per_lot(mut self, max: usize) -> ConfigBuilder {
+        self.config.max_per_lot = Some(max);
+        self
+    }
+
+    pub fn on_crowded_lot(mut self, policy: CrowdedLotPolicy) -> ConfigBuilder {
+        self.config.on_crowded_lot = policy;
+        self
+    }
+
+    pub fn suffix(mut self, suffix: SuffixStrategy) -> ConfigBuilder {
+        self.config.suffix = suffix;
+        self
+    }
+
+    // resequence numbers the photos of each object 1, 2, 3 and so on in the
+    // order of their photo numbers.
+    pub fn resequence(mut self, resequence: bool) -> ConfigBuilder {
+        self.config.resequence = resequence;
+        self
+    }
+
+    pub fn lot_width(mut self, width: usize) -> ConfigBuilder {
+        self.config.lot_width = width;
+        self
+    }
+
+    pub fn index_width(mut self, width: usize) -> ConfigBuilder {
+        self.config.index_width = width;
+        self
+    }
+
+    pub fn progress(mut self, progress: bool) -> ConfigBuilder {
+        self.config.progress = progress;
+        self
+    }
+
+    pub fn color(mut self, color: bool) -> ConfigBuilder {
+        self.config.color = color;
+        self
+    }
+
+    pub fn resume(mut self, resume: bool) -> ConfigBuilder {
+        self.config.resume = resume;
+        self
+    }
+
+    // lot_transform adds a transform to apply to the lot number in new names.
+    pub fn lot_transform(mut self, transform: LotTransform) -> ConfigBuilder {
+        self.config.lot_transforms.push(transform);
+        self
+    }
+
+    // transform adds a transform to apply to new names.
+    pub fn transform(mut self, transform: Transform) -> ConfigBuilder {
+        self.config.transforms.push(transform);
+        self
+    }
+
+    // sidecar renames the files with this extension along with the file of
+    // the same stem.
+    pub fn sidecar(mut self, extension: impl Into<String>) -> ConfigBuilder {
+        let extension = extension.into();
+        self.config
+            .sidecars
+            .push(extension.trim_start_matches('.').to_string());
+        self
+    }
+
+    pub fn script(mut self, path: impl Into<PathBuf>) -> ConfigBuilder {
+        self.config.script = Some(path.into());
+        self
+    }
+
+    pub fn lot_dirs(mut self, lot_dirs: bool) -> ConfigBuilder {
+        self.config.lot_dirs = lot_dirs;
+        self
+    }
+
+    pub fn sanitize(mut self, sanitize: bool) -> ConfigBuilder {
+        self.config.sanitize = sanitize;
+        self
+    }
+
+    pub fn normalization(mut self, normalization: Normalization) -> ConfigBuilder {
+        self.config.normalization = Some(normalization);
+        self
+    }
+
+    pub fn log_level(mut self, level: log::LevelFilter) -> ConfigBuilder {
+        self.config.log_level = level;
+        self
+    }
+
+    pub fn report_format(mut self, format: ReportFormat) -> ConfigBuilder {
+        self.config.report_format = Some(format);
+        self
+    }
+
+    pub fn report_file(mut self, report_file: impl Into<PathBuf>) -> ConfigBuilder {
+        self.config.report_file = Some(report_file.into());
+        self
+    }
+
+    pub fn audit_log(mut self, path: impl Into<PathBuf>) -> ConfigBuilder {
+        self.config.audit_log = Some(path.into());
+        self
+    }
+
+    pub fn mapping_file(mut self, path: impl Into<PathBuf>) -> ConfigBuilder {
+        self.config.mapping_file = Some(path.into());
+        self
+    }
+
+    // history records the runs that rename files in the SQLite database at
+    // path.
+    pub fn history(mut self, path: impl Into<PathBuf>) -> ConfigBuilder {
+        self.config.history = Some(path.into());
+        self
+    }
+
+    pub fn preview_html(mut self, path: impl Into<PathBuf>) -> ConfigBuilder {
+        self.config.preview_html = Some(path.into());
+        self
+    }
+
+    pub fn find_duplicates(mut self, find_duplicates: bool) -> ConfigBuilder {
+        self.config.find_duplicates = find_duplicates;
+        self
+    }
+
+    // backup keeps the files replaced when overwriting in this directory,
+    // relative to the target directory.
+    pub fn backup(mut self, backup_dir: impl Into<PathBuf>) -> ConfigBuilder {
+        self.config.backup = Some(backup_dir.into());
+        self
+    }
+
+    pub fn verify_images(mut self, verify_images: bool) -> ConfigBuilder {
+        self.config.verify_images = verify_images;
+        self
+    }
+
+    // min_size leaves alone files to rename that are smaller than size.
+    pub fn min_size(mut self, size: FileSize) -> ConfigBuilder {
+        self.config.min_size = Some(size);
+        self
+    }
+
+    // max_size leaves alone files to rename that are larger than size.
+    pub fn max_size(mut self, size: FileSize) -> ConfigBuilder {
+        self.config.max_size = Some(size);
+        self
+    }
+
+    // min_dimensions leaves alone images smaller than min in either
+    // orientation.
+    pub fn min_dimensions(mut self, min: Dimensions) -> ConfigBuilder {
+        self.config.min_dimensions = Some(min);
+        self
+    }
+
+    // storage renames the files somewhere else than on the local file system.
+    // The storage is used as given, with its own options; without it, the
+    // local file system is used with those set by verify, symlinks and
+    // preserve_xattrs.
+    pub fn storage(mut self, storage: impl Storage + 'static) -> ConfigBuilder {
+        self.config.storage = Some(Arc::new(storage));
+        self
+    }
+
+    // build checks that the options make sense together and that the
+    // directories exist, if they are on the local file system.
+    pub fn build(self) -> Result<Config, RenameError> {
+        let config = self.config;
+        let local = config.storage().is_local();
+
+        if local && !crate::validate_dir(&config.dir) {
+            return Err(invalid("given directory path is not a directory"));
+        }
+
+        if let Some(output_dir) = config.output_dir.as_ref().filter(|_| local) {
+            if output_dir.exists() && !crate::validate_dir(output_dir) {
+                return Err(invalid("given output directory path is not a directory"));
+            }
+        }
+
+        if config
+            .extra_dirs
+            .iter()
+            .any(|dir| !crate::validate_dir(dir))
+        {
+            return Err(invalid("given extra directory path is not a directory"));
+        }
+
+        if !local && !config.extra_dirs.is_empty() {
+            return Err(invalid(
+                "only directories on the local file system can be merged",
+            ));
+        }
+
+        if config.watch && !config.extra_dirs.is_empty() {
+            return Err(invalid("only a single directory can be watched"));
+        }
+
+        if !local && config.watch {
+            return Err(invalid(
+                "only directories on the local file system can be watched",
+            ));
+        }
+
+        if !local && config.resume {
+            return Err(invalid("only runs on the local file system can be resumed"));
+        }
+
+        if !local && config.history.is_some() {
+            return Err(invalid(
+                "only runs on the local file system can be kept in a history",
+            ));
+        }
+
+        #[cfg(not(feature = "sqlite"))]
+        if config.history.is_some() {
+            return Err(invalid("keeping a history needs the sqlite feature"));
+        }
+
+        if !local && config.find_duplicates {
+            return Err(invalid(
+                "only files on the local file system can be checked for duplicates",
+            ));
+        }
+
+        if !local && config.verify_images {
+            return Err(invalid(
+                "only files on the local file system can be checked for being images",
+            ));
+        }
+
+        if !local && (config.min_size.is_some() || config.max_size.is_some()) {
+            return Err(invalid(
+                "only files on the local file system can be checked for their size",
+            ));
+        }
+
+        if let (Some(min_size), Some(max_size)) = (config.min_size, config.max_size) {
+            if min_size > max_size {
+                return Err(invalid("minimum size must not exceed maximum size"));
+            }
+        }
+
+        if !local && config.min_dimensions.is_some() {
+            return Err(invalid(
+                "only files on the local file system can be checked for their dimensions",
+            ));
+        }
+
+        if config.data_file == Path::new(crate::reader::STDIN) {
+            #[cfg(feature = "xlsx")]
+            if config.format == Format::Xlsx {
+                return Err(invalid("an xlsx data file cannot be read from stdin"));
+            }
+            #[cfg(feature = "sqlite")]
+            if config.format == Format::Sqlite {
+                return Err(invalid("an sqlite data file cannot be read from stdin"));
+            }
+            if config.watch {
+                return Err(invalid(
+                    "the data file cannot be read from stdin in watch mode",
+                ));
+            }
+        }
+
+        if !matches!(config.format, Format::Csv | Format::FixedWidth) && config.encoding.is_some() {
+            return Err(invalid(
+                "an encoding can only be given for csv and fixed-width data files",
+            ));
+        }
+
+        if config.format == Format::FixedWidth && config.widths.is_none() {
+            return Err(invalid("a fixed-width data file needs column widths"));
+        }
+
+        if config.format != Format::FixedWidth && config.widths.is_some() {
+            return Err(invalid(
+                "column widths only apply to fixed-width data files",
+            ));
+        }
+
+        if config.format.has_fixed_columns() && !config.key_columns.is_empty() {
+            return Err(invalid(
+                "key columns can only be used with csv and xlsx data files",
+            ));
+        }
+
+        if config.format.has_fixed_columns() && !config.conditions.is_empty() {
+            return Err(invalid(
+                "conditions can only be used with csv and xlsx data files",
+            ));
+        }
+
+        if config.format.has_fixed_columns() && config.date_column.is_some() {
+            return Err(invalid(
+                "a date column can only be used with csv and xlsx data files",
+            ));
+        }
+
+        if config.format.has_fixed_columns() && config.hero_column.is_some() {
+            return Err(invalid(
+                "a hero column can only be used with csv and xlsx data files",
+            ));
+        }
+
+        if config.copy && config.link.is_some() {
+            return Err(invalid("files cannot be both copied and linked"));
+        }
+
+        if config.backup.is_some() && config.on_conflict != ConflictPolicy::Overwrite {
+            return Err(invalid(
+                "a backup directory only applies when overwriting files",
+            ));
+        }
+
+        if config.jobs == 0 {
+            return Err(invalid("jobs must be a positive integer"));
+        }
+
+        if config.limit == Some(0) {
+            return Err(invalid("limit must be a positive integer"));
+        }
+
+        if config.max_per_lot == Some(0) {
+            return Err(invalid("maximum per lot must be a positive integer"));
+        }
+
+        if let Some(pattern) = &config.from_regex {
+            let Ok(regex) = crate::whole_name_regex(pattern) else {
+                return Err(invalid("given regex is invalid"));
+            };
+            if Template::parse_with_groups(&config.template, &regex).is_err() {
+                return Err(invalid(
+                    "given name template is invalid or refers to a group the regex lacks",
+                ));
+            }
+            if config.lot_dirs {
+                return Err(invalid("lot directories need a data file with lot numbers"));
+            }
+            if config.min_dimensions.is_some() {
+                return Err(invalid(
+                    "minimum dimensions need a data file with lot numbers",
+                ));
+            }
+        } else if Template::parse(&config.template).is_err() {
+            return Err(invalid("given name template is invalid"));
+        }
+
+        if FileFilter::new(&config.include, &config.exclude).is_err() {
+            return Err(invalid("given include or exclude pattern is invalid"));
+        }
+
+        Ok(config)
+    }
+}
+
+// invalid is the error for options that do not make sense together.
+fn invalid(reason: &str) -> RenameError {
+    RenameError::InvalidConfig(reason.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_config() {
+        let config = ConfigBuilder::new("tests/data.csv", "tests/files")
+            .delimiter(b',')
+            .lot_column(Column::Name(String::from("Lot Number")))
+            .dry_run(true)
+            .template("{lot}-{index}.jpg")
+            .output_dir("tests/tmp_builder")
+            .build()
+            .unwrap();
+
+        assert_eq!(b',', config.delimiter);
+        assert_eq!(Column::Name(String::from("Lot Number")), config.lot_column);
+        assert!(config.dry_run);
+        assert_eq!("{lot}-{index}.jpg", config.template);
+        assert_eq!(Some(PathBuf::from("tests/tmp_builder")), config.output_dir);
+
+        let builder = || ConfigBuilder::new("tests/data.csv", "tests/files");
+        assert!(matches!(
+            builder().template("{lot").build(),
+            Err(RenameError::InvalidConfig(reason)) if reason == "given name template is invalid"
+        ));
+        assert!(builder().jobs(0).build().is_err());
+        assert!(builder().copy(true).link(Link::Sym).build().is_err());
+        assert!(builder().backup(crate::BACKUP_DIR).build().is_err());
+        assert!(builder().include("[").build().is_err());
+        assert!(builder().format(Format::FixedWidth).build().is_err());
+        assert!(builder()
+            .format(Format::FixedWidth)
+            .widths("8,8".parse().unwrap())
+            .build()
+            .is_ok());
+        assert!(builder()
+            .min_size(FileSize(1000))
+            .max_size(FileSize(10))
+            .build()
+            .is_err());
+        assert!(builder().dir("tests/data.csv").build().is_err());
+        assert!(builder().output_dir("tests/data.csv").build().is_err());
+        assert!(builder().data_file("-").build().is_ok());
+        assert!(builder().data_file("-").watch(true).build().is_err());
+        assert!(builder()
+            .storage(crate::LocalStorage::default())
+            .watch(true)
+            .build()
+            .is_ok());
+
+        // The last storage given is used as it is.
+        let config = builder()
+            .storage(crate::LocalStorage {
+                verify: true,
+                ..crate::LocalStorage::default()
+            })
+            .storage(crate::LocalStorage::default())
+            .build()
+            .unwrap();
+        assert!(config.storage.is_some());
+        assert!(!config.verify);
+    }
+}
@@ -0,0 +1,267 @@
+// The C interface to the renaming engine, for programs that embed it
+// in-process instead of running the command-line program. The header is
+// `include/rename.h`, generated by cbindgen from this module.
+//
+// Strings are UTF-8 and NUL-terminated. Strings returned by the library are
+// owned by the caller, who frees them with `rename_string_free`. Functions
+// that can fail take an `error` out-parameter, which may be null, and set it
+// to a message describing the failure.
+
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use crate::options::setter;
+use crate::{Config, ConfigBuilder, RenamePlan};
+
+/// A configuration being set up, created by `rename_config_new`.
+pub struct RenameConfig {
+    // Setting an option hands the builder over and takes it back.
+    builder: Option<ConfigBuilder>,
+}
+
+/// Starts a configuration for renaming the files in `dir` after `data_file`,
+/// or returns null if either is not valid UTF-8.
+///
+/// # Safety
+///
+/// Both arguments must be valid NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn rename_config_new(
+    data_file: *const c_char,
+    dir: *const c_char,
+) -> *mut RenameConfig {
+    let (Some(data_file), Some(dir)) = (to_str(data_file), to_str(dir)) else {
+        return ptr::null_mut();
+    };
+
+    Box::into_raw(Box::new(RenameConfig {
+        builder: Some(Config::builder(data_file, dir)),
+    }))
+}
+
+/// Sets the option `name`, named as the command-line flag without its
+/// dashes, such as `template` or `on-conflict`, to `value`. Flags are set by
+/// `true` or `false`, and repeatable options such as `include` are added to.
+/// Returns 0 on success and -1 if the option is unknown or the value invalid,
+/// leaving the configuration as it was.
+///
+/// # Safety
+///
+/// `config` must come from `rename_config_new` and not be freed or planned
+/// yet; `name` and `value` must be valid NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn rename_config_set(
+    config: *mut RenameConfig,
+    name: *const c_char,
+    value: *const c_char,
+    error: *mut *mut c_char,
+) -> c_int {
+    let (Some(config), Some(name), Some(value)) = (config.as_mut(), to_str(name), to_str(value))
+    else {
+        set_error(error, "config, name and value must be valid UTF-8 strings");
+        return -1;
+    };
+    match setter(name, value) {
+        Ok(set) => {
+            config.builder = config.builder.take().map(set);
+            0
+        }
+        Err(message) => {
+            set_error(error, &message);
+            -1
+        }
+    }
+}
+
+/// Frees a configuration that was not planned.
+///
+/// # Safety
+///
+/// `config` must come from `rename_config_new` and not be freed or planned
+/// yet, or be null.
+#[no_mangle]
+pub unsafe extern "C" fn rename_config_free(config: *mut RenameConfig) {
+    if !config.is_null() {
+        drop(Box::from_raw(config));
+    }
+}
+
+/// Plans the renaming without touching any files, consuming the
+/// configuration. Returns null if the configuration is invalid or planning
+/// fails.
+///
+/// # Safety
+///
+/// `config` must come from `rename_config_new` and not be freed or planned
+/// yet. It is freed by this call.
+#[no_mangle]
+pub unsafe extern "C" fn rename_plan(
+    config: *mut RenameConfig,
+    error: *mut *mut c_char,
+) -> *mut RenamePlan {
+    if config.is_null() {
+        set_error(error, "config is null");
+        return ptr::null_mut();
+    }
+    let config = Box::from_raw(config);
+
+    let result = guard(|| {
+        let builder = config.builder.ok_or("config is incomplete")?;
+        let config = builder.build().map_err(|err| err.to_string())?;
+        crate::plan(config).map_err(|err| err.to_string())
+    });
+    match result {
+        Ok(plan) => Box::into_raw(Box::new(plan)),
+        Err(message) => {
+            set_error(error, &message);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Describes the plan as a JSON array of rows with the old and new name, lot,
+/// inventory number and status of every file, and the rows of the data file
+/// that matched no files.
+///
+/// # Safety
+///
+/// `plan` must come from `rename_plan` and not be freed or executed yet.
+#[no_mangle]
+pub unsafe extern "C" fn rename_plan_rows(plan: *const RenamePlan) -> *mut c_char {
+    match plan.as_ref() {
+        Some(plan) => {
+            to_c_string(serde_json::to_string(&plan.rows()).expect("rows are serializable"))
+        }
+        None => ptr::null_mut(),
+    }
+}
+
+/// Renames the files as planned, consuming the plan, and describes the outcome
+/// as the JSON report the command line writes with `--report json`: the
+/// renamed files and those left alone, or just the error.
+///
+/// # Safety
+///
+/// `plan` must come from `rename_plan` and not be freed or executed yet. It is
+/// freed by this call.
+#[no_mangle]
+pub unsafe extern "C" fn rename_execute(plan: *mut RenamePlan) -> *mut c_char {
+    if plan.is_null() {
+        return ptr::null_mut();
+    }
+    let plan = Box::from_raw(plan);
+
+    let json = match guard(|| Ok(plan.execute())) {
+        Ok(result) => crate::to_json(&result),
+        Err(message) => serde_json::json!({ "error": message }).to_string(),
+    };
+    to_c_string(json)
+}
+
+/// Frees a plan that was not executed.
+///
+/// # Safety
+///
+/// `plan` must come from `rename_plan` and not be freed or executed yet, or be
+/// null.
+#[no_mangle]
+pub unsafe extern "C" fn rename_plan_free(plan: *mut RenamePlan) {
+    if !plan.is_null() {
+        drop(Box::from_raw(plan));
+    }
+}
+
+/// Frees a string returned by the library.
+///
+/// # Safety
+///
+/// `string` must come from this library and not be freed yet, or be null.
+#[no_mangle]
+pub unsafe extern "C" fn rename_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+// guard turns a panic into an error, as unwinding into the calling program is
+// undefined behaviour.
+fn guard<T>(f: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|panic| {
+        let message = panic
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        Err(format!("renaming panicked: {}", message))
+    })
+}
+
+// to_str reads a string passed by the calling program.
+unsafe fn to_str<'a>(string: *const c_char) -> Option<&'a str> {
+    if string.is_null() {
+        return None;
+    }
+    CStr::from_ptr(string).to_str().ok()
+}
+
+// to_c_string hands a string over to the calling program.
+fn to_c_string(string: String) -> *mut c_char {
+    CString::new(string.replace('\0', ""))
+        .expect("NUL bytes are removed")
+        .into_raw()
+}
+
+unsafe fn set_error(error: *mut *mut c_char, message: &str) {
+    if !error.is_null() {
+        *error = to_c_string(message.to_owned());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plan_through_c() {
+        let data_file = CString::new("tests/data.csv").unwrap();
+        let dir = CString::new("tests/files").unwrap();
+        let option =
+            |name: &str, value: &str| (CString::new(name).unwrap(), CString::new(value).unwrap());
+
+        unsafe {
+            let config = rename_config_new(data_file.as_ptr(), dir.as_ptr());
+            let mut error = ptr::null_mut();
+
+            let (name, value) = option("pad-lot", "four");
+            assert_eq!(
+                -1,
+                rename_config_set(config, name.as_ptr(), value.as_ptr(), &mut error)
+            );
+            assert_eq!(
+                "\"four\" is not a number",
+                CStr::from_ptr(error).to_str().unwrap()
+            );
+            rename_string_free(error);
+
+            let (name, value) = option("pad-lot", "4");
+            assert_eq!(
+                0,
+                rename_config_set(config, name.as_ptr(), value.as_ptr(), &mut error)
+            );
+
+            let plan = rename_plan(config, &mut error);
+            assert!(!plan.is_null());
+            let rows = rename_plan_rows(plan);
+            let json: serde_json::Value =
+                serde_json::from_str(CStr::from_ptr(rows).to_str().unwrap()).unwrap();
+            rename_string_free(rows);
+            rename_plan_free(plan);
+
+            assert_eq!(20, json.as_array().unwrap().len());
+            assert_eq!("00243344.1.jpg", json[0]["old"]);
+            assert_eq!("0003_1.jpg", json[0]["new"]);
+            assert_eq!("ok", json[0]["status"]);
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+// capture_time reads when the photo was taken from its EXIF data, falling back
+// on when it was last changed by the camera. EXIF writes both as
+// `YYYY:MM:DD HH:MM:SS`, which sorts in time order.
+pub fn capture_time(path: &Path) -> Option<String> {
+    let file = File::open(path).ok()?;
+    let exif = exif::Reader::new()
+        .read_from_container(&mut BufReader::new(file))
+        .ok()?;
+
+    [exif::Tag::DateTimeOriginal, exif::Tag::DateTime]
+        .into_iter()
+        .find_map(|tag| match &exif.get_field(tag, exif::In::PRIMARY)?.value {
+            exif::Value::Ascii(values) => values
+                .first()
+                .map(|value| String::from_utf8_lossy(value).into_owned()),
+            _ => None,
+        })
+}
+
+// number_by_capture_time numbers the files of an object from 1 in the order
+// they were taken, regardless of the counter in their names. Files taken in
+// the same second keep the order of their names. Files without a capture time
+// get no number.
+pub fn number_by_capture_time(dir: &Path, files: &[String]) -> Vec<Option<String>> {
+    let times: Vec<Option<String>> = files
+        .iter()
+        .map(|file| capture_time(&dir.join(file)))
+        .collect();
+
+    let mut order: Vec<usize> = (0..files.len()).filter(|&i| times[i].is_some()).collect();
+    order.sort_by(|&a, &b| (&times[a], &files[a]).cmp(&(&times[b], &files[b])));
+
+    let mut numbers: Vec<Option<String>> = vec![None; files.len()];
+    for (number, i) in order.into_iter().enumerate() {
+        numbers[i] = Some((number + 1).to_string());
+    }
+
+    numbers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // jpeg_taken_at makes a minimal JPEG file holding just an EXIF
+    // DateTimeOriginal.
+    fn jpeg_taken_at(time: &str) -> Vec<u8> {
+        let mut tiff: Vec<u8> = b"MM\x00\x2a\x00\x00\x00\x08".to_vec();
+        // IFD0 points to the EXIF IFD at offset 26.
+        tiff.extend([0, 1, 0x87, 0x69, 0, 4, 0, 0, 0, 1, 0, 0, 0, 26, 0, 0, 0, 0]);
+        // The EXIF IFD holds the 20 bytes of the time at offset 44.
+        tiff.extend([0, 1, 0x90, 0x03, 0, 2, 0, 0, 0, 20, 0, 0, 0, 44, 0, 0, 0, 0]);
+        tiff.extend(time.as_bytes());
+        tiff.push(0);
+
+        let mut jpeg: Vec<u8> = vec![0xff, 0xd8, 0xff, 0xe1];
+        jpeg.extend(((tiff.len() + 8) as u16).to_be_bytes());
+        jpeg.extend(b"Exif\x00\x00");
+        jpeg.extend(tiff);
+        jpeg.extend([0xff, 0xd9]);
+
+        jpeg
+    }
+
+    #[test]
+    fn number_files_by_capture_time() {
+        let dir = Path::new("tests/tmp_capture");
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir(dir).unwrap();
+        // The counter of the camera wrapped between the first and second photo.
+        fs::write(
+            dir.join("IMG_9999.jpg"),
+            jpeg_taken_at("2024:05:01 10:00:00"),
+        )
+        .unwrap();
+        fs::write(
+            dir.join("IMG_0001.jpg"),
+            jpeg_taken_at("2024:05:01 10:00:05"),
+        )
+        .unwrap();
+        fs::write(dir.join("IMG_0002.jpg"), b"no exif").unwrap();
+
+        let files = vec![
+            "IMG_0001.jpg".to_string(),
+            "IMG_0002.jpg".to_string(),
+            "IMG_9999.jpg".to_string(),
+        ];
+        let numbers = number_by_capture_time(dir, &files);
+
+        fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(
+            vec![Some("2".to_string()), None, Some("1".to_string())],
+            numbers
+        );
+    }
+}
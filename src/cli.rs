@@ -0,0 +1,862 @@
+use clap::builder::{PossibleValue, TypedValueParser};
+use clap::error::ErrorKind;
+use clap::{Args, Parser, Subcommand};
+use clap_complete::Shell;
+use std::ffi::OsStr;
+use std::io::IsTerminal;
+use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use rename::{
+    Column, Condition, Config, ConflictPolicy, CrowdedLotPolicy, Dimensions, DuplicatePolicy,
+    Encoding, FileSize, Format, Link, LotTransform, Lots, MalformedPolicy, MatchMode,
+    Normalization, RenameError, ReportFormat, SuffixStrategy, Transform, Widths, BACKUP_DIR,
+    DEFAULT_HISTORY,
+};
+
+// Without a subcommand the arguments of `apply` are accepted, so that
+// `rename <data-file> <directory>` keeps working.
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Rename auction photos after the lot numbers in a catalogue",
+    args_conflicts_with_subcommands = true,
+    args_override_self = true,
+    after_help = "Exit codes: 1 for a failure not listed here, 2 for invalid arguments, 3 for a data file that cannot be read, 4 for conflicting new names or, with --strict, any anomaly, and 5 for renaming that failed partway and was rolled back."
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub run: Option<RunArgs>,
+
+    /// Only log errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Also log which row each file matched
+    #[arg(short, long, global = true)]
+    pub verbose: bool,
+
+    /// Read options from this TOML file instead of rename.toml in the working directory
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Show what would be renamed without touching any files
+    #[command(args_override_self = true)]
+    Plan(RunArgs),
+    /// Rename the files
+    #[command(args_override_self = true)]
+    Apply(RunArgs),
+    /// Check the data file against the photos for anything that would keep them from being renamed, without touching any files
+    #[command(args_override_self = true)]
+    Check(RunArgs),
+    /// Print a script that completes the commands, options and their values in the shell
+    Completions {
+        /// The shell to complete in
+        shell: Shell,
+    },
+    /// Revert the renamings of earlier runs in a directory
+    Undo {
+        /// The directory that was renamed
+        directory: PathBuf,
+    },
+    /// Put back the files that the latest run replaced when overwriting, after undoing it
+    Restore {
+        /// The directory that the files were replaced in
+        directory: PathBuf,
+
+        /// The backup directory in that directory
+        #[arg(long, default_value = BACKUP_DIR)]
+        backup: PathBuf,
+    },
+    /// Look up and undo the runs recorded with --history
+    #[cfg(feature = "sqlite")]
+    History {
+        #[command(subcommand)]
+        command: HistoryCommand,
+
+        /// The history database
+        #[arg(long, global = true, default_value = DEFAULT_HISTORY)]
+        history: PathBuf,
+    },
+}
+
+#[cfg(feature = "sqlite")]
+#[derive(Subcommand)]
+pub enum HistoryCommand {
+    /// List the runs in the history
+    List,
+    /// Show the files a run renamed
+    Show {
+        /// The id of the run, as listed
+        run_id: i64,
+    },
+    /// Revert the renamings of a run, even if later runs renamed other files
+    Undo {
+        /// The id of the run, as listed
+        run_id: i64,
+    },
+}
+
+#[derive(Args)]
+pub struct RunArgs {
+    /// The catalogue export with lot and inventory numbers, or a glob such as 'sale-*.csv'; with --from-regex, the directory with the photos
+    pub data_file: PathBuf,
+
+    /// The directory with the photos
+    #[arg(required_unless_present = "from_regex")]
+    pub directory: Option<PathBuf>,
+
+    /// Rename the files whose name matches this regex, such as '(?P<id>\d+)\.(?P<n>\d+)\.jpg', instead of reading a data file
+    #[arg(long, requires = "to")]
+    pub from_regex: Option<String>,
+
+    /// Template of the new names with the groups of --from-regex by name or number, such as '{id}-{2}.jpeg'
+    #[arg(long, requires = "from_regex", conflicts_with = "template")]
+    pub to: Option<String>,
+
+    /// Also read rows from this data file or glob, merging them with the data file; may be repeated
+    #[arg(long = "data-file")]
+    pub extra_data_files: Vec<PathBuf>,
+
+    /// Also rename the files in this directory, moving them to the output directory or the directory with the photos; may be repeated
+    #[arg(long = "dir")]
+    pub extra_dirs: Vec<PathBuf>,
+
+    /// Only show what would be renamed
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Field delimiter of the data file: a character, \t, tab, comma, semicolon or pipe
+    #[arg(short, long, default_value = "\\t", value_parser = parse_delimiter)]
+    pub delimiter: u8,
+
+    /// Character encoding of a delimited or fixed-width data file, such as windows-1252 or utf-16; a byte order mark takes precedence
+    #[arg(long)]
+    pub encoding: Option<Encoding>,
+
+    /// Column with the lot number, by zero-based index or header name
+    #[arg(long, default_value = "0")]
+    pub lot_column: Column,
+
+    /// Column with the inventory number, by zero-based index or header name
+    #[arg(long, default_value = "8")]
+    pub inventory_column: Column,
+
+    /// Column with the date that {date} in the template stands for, instead of the date the photo was taken
+    #[arg(long)]
+    pub date_column: Option<Column>,
+
+    /// Column with the photo number of the photo to show first, which is numbered 1 and the others after it
+    #[arg(long)]
+    pub hero_column: Option<Column>,
+
+    /// Match file names by the values of these columns instead of the inventory number; may be repeated
+    #[arg(long = "key-column")]
+    pub key_columns: Vec<Column>,
+
+    /// Separator between the values of the key columns in file names
+    #[arg(long, default_value = "_")]
+    pub key_separator: String,
+
+    /// The data file starts with data instead of a header
+    #[arg(long)]
+    pub no_headers: bool,
+
+    /// Match inventory numbers in file names regardless of case
+    #[arg(short, long)]
+    pub ignore_case: bool,
+
+    /// Only rename the files of these lots, e.g. 100-250 or 7,9,12
+    #[arg(long)]
+    pub lots: Option<Lots>,
+
+    /// Only rename the files of rows whose column has this value, e.g. Status=approved, or not, e.g. Status!=draft; may be repeated
+    #[arg(long = "where")]
+    pub conditions: Vec<Condition>,
+
+    /// Where an inventory number may end in a file name: prefix (anywhere) or boundary (at ., _, - or the end)
+    #[arg(long = "match", default_value = "prefix", value_parser = choices::<MatchMode>(&["prefix", "boundary"]), hide_possible_values = true)]
+    pub match_mode: MatchMode,
+
+    /// Strip whitespace around lot and inventory numbers in the data file
+    #[arg(long)]
+    pub trim: bool,
+
+    /// Write lot numbers as plain integers, so 1.001, 1 001 and 01001 become 1001, keeping suffixes such as 101A
+    #[arg(long)]
+    pub normalize_lot: bool,
+
+    /// Format of the data file: csv, tsv, fixed-width, xlsx, json, toml, yaml or sqlite
+    #[arg(short, long, default_value = "csv", value_parser = choices::<Format>(&["csv", "tsv", "fixed-width", "xlsx", "json", "toml", "yaml", "sqlite"]), hide_possible_values = true)]
+    pub format: Format,
+
+    /// Columns of a fixed-width data file: their widths in characters, e.g. 6,10,40, or offset:width to skip filler, e.g. 0:6,20:10
+    #[arg(long)]
+    pub widths: Option<Widths>,
+
+    /// Worksheet of an xlsx data file; the first one by default
+    #[arg(long)]
+    pub sheet: Option<String>,
+
+    /// Key of the lot number in a JSON data file
+    #[arg(long, default_value = "lot")]
+    pub lot_key: String,
+
+    /// Key of the inventory number in a JSON data file
+    #[arg(long, default_value = "inventory")]
+    pub inventory_key: String,
+
+    /// Query selecting the lot and inventory number from an SQLite data file
+    #[arg(long, default_value = rename::DEFAULT_QUERY)]
+    pub query: String,
+
+    /// Template of the new names
+    #[arg(short, long, default_value = rename::DEFAULT_TEMPLATE)]
+    pub template: String,
+
+    /// Rhai script whose new_name(row, fields, name) function composes the new names
+    #[arg(long)]
+    pub script: Option<PathBuf>,
+
+    /// Keep the extension of the original file
+    #[arg(long)]
+    pub keep_extension: bool,
+
+    /// Lowercase the kept extension
+    #[arg(long)]
+    pub lowercase_extension: bool,
+
+    /// Rename files with these extensions, such as xmp,cr2, along with the photo of the same name
+    #[arg(long, value_delimiter = ',')]
+    pub sidecars: Vec<String>,
+
+    /// Change the lot number in new names: add:N, subtract:N, prefix:TEXT or suffix:TEXT, e.g. add:1000; may be repeated
+    #[arg(long = "lot-transform")]
+    pub lot_transforms: Vec<LotTransform>,
+
+    /// Transform new names: lowercase, uppercase, underscores or strip-diacritics; may be repeated or comma-separated
+    #[arg(long = "transform", value_delimiter = ',', value_parser = choices::<Transform>(&["lowercase", "uppercase", "underscores", "strip-diacritics"]), hide_possible_values = true)]
+    pub transforms: Vec<Transform>,
+
+    /// Match file names and write new names in this Unicode normalization form: nfc or nfd
+    #[arg(long, value_parser = choices::<Normalization>(&["nfc", "nfd"]), hide_possible_values = true)]
+    pub normalize: Option<Normalization>,
+
+    /// Make new names legal on Windows, replacing characters such as : and ?, instead of refusing them
+    #[arg(long)]
+    pub sanitize: bool,
+
+    /// How to take the photo number from a file name: number, segment:N, trailing-digits, regex:PATTERN or capture-time
+    #[arg(long, default_value = "number")]
+    pub suffix: SuffixStrategy,
+
+    /// Number the photos of each object 1, 2, 3 and so on in the order of their photo numbers, closing gaps
+    #[arg(long)]
+    pub resequence: bool,
+
+    /// What to do with matching files without a photo number: skip or error
+    #[arg(long, default_value = "skip", value_parser = choices::<MalformedPolicy>(&["skip", "error"]), hide_possible_values = true)]
+    pub on_malformed: MalformedPolicy,
+
+    /// What to do with rows of the data file that lack a column or hold an invalid value: skip or error
+    #[arg(long, default_value = "skip", value_parser = choices::<MalformedPolicy>(&["skip", "error"]), hide_possible_values = true)]
+    pub on_bad_row: MalformedPolicy,
+
+    /// Which row names the files of an inventory number that rows give different lot numbers: first, last or error
+    #[arg(long, default_value = "error", value_parser = choices::<DuplicatePolicy>(&["first", "last", "error"]), hide_possible_values = true)]
+    pub on_duplicate: DuplicatePolicy,
+
+    /// Warn about lots with more matching files than this, such as the number of images the catalogue takes
+    #[arg(long)]
+    pub max_per_lot: Option<usize>,
+
+    /// What to do with lots with more files than --max-per-lot: warn or error
+    #[arg(long, default_value = "warn", value_parser = choices::<CrowdedLotPolicy>(&["warn", "error"]), hide_possible_values = true)]
+    pub on_crowded_lot: CrowdedLotPolicy,
+
+    /// Pad lot numbers with zeros to this width
+    #[arg(long, default_value_t = 0)]
+    pub pad_lot: usize,
+
+    /// Pad photo numbers with zeros to this width
+    #[arg(long, default_value_t = 0)]
+    pub pad_index: usize,
+
+    /// Report how long reading the data file, listing the files, planning and renaming took
+    #[arg(long)]
+    pub timings: bool,
+
+    /// Also rename symbolic links to files; subdirectories and other entries are never renamed
+    #[arg(long)]
+    pub symlinks: bool,
+
+    /// Only rename files matching this glob; may be repeated
+    #[arg(long)]
+    pub include: Vec<String>,
+
+    /// Leave out files matching this glob; may be repeated
+    #[arg(long)]
+    pub exclude: Vec<String>,
+
+    /// Copy the files instead of renaming them
+    #[arg(long)]
+    pub copy: bool,
+
+    /// Link the files under their new names instead of renaming them: hard or sym
+    #[arg(long, conflicts_with = "copy", value_parser = choices::<Link>(&["hard", "sym"]), hide_possible_values = true)]
+    pub link: Option<Link>,
+
+    /// Place the new files in this directory
+    #[arg(short, long)]
+    pub output_dir: Option<PathBuf>,
+
+    /// Place the new files in a subdirectory per lot, named by the padded lot number
+    #[arg(long)]
+    pub lot_dirs: bool,
+
+    /// Fail on any anomaly, such as unmatched files or rows, malformed names or rows, duplicates, corrupt images, files of the wrong size, small images or crowded lots, listing them all
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Warn about files to rename that are byte-identical, such as a photo exported twice
+    #[arg(long)]
+    pub find_duplicates: bool,
+
+    /// Leave alone files to rename that are not intact JPEG, PNG or TIFF images, such as truncated uploads
+    #[arg(long)]
+    pub verify_images: bool,
+
+    /// Leave alone files to rename that are smaller than this, such as the empty files of failed uploads, e.g. 10KB
+    #[arg(long)]
+    pub min_size: Option<FileSize>,
+
+    /// Leave alone files to rename that are larger than this, e.g. 50MB
+    #[arg(long)]
+    pub max_size: Option<FileSize>,
+
+    /// Leave alone images smaller than this in either orientation, reading only their headers, e.g. 1600x1200
+    #[arg(long)]
+    pub min_dimensions: Option<Dimensions>,
+
+    /// What to do with files whose new name already exists: abort, skip, overwrite or number
+    #[arg(long, default_value = "abort", value_parser = choices::<ConflictPolicy>(&["abort", "skip", "overwrite", "number"]), hide_possible_values = true)]
+    pub on_conflict: ConflictPolicy,
+
+    /// When overwriting, move the replaced files to this directory in the target directory instead of destroying them
+    #[arg(long, num_args = 0..=1, default_missing_value = BACKUP_DIR)]
+    pub backup: Option<PathBuf>,
+
+    /// Check copied files, also those moved to another file system, with SHA-256
+    #[arg(long)]
+    pub verify: bool,
+
+    /// After renaming, list the directories again and fail if a file is missing under its new name or still there under its old one
+    #[arg(long)]
+    pub post_check: bool,
+
+    /// Copy extended attributes along with copied files, also those moved to another file system
+    #[arg(long)]
+    pub preserve_xattrs: bool,
+
+    /// Number of files to rename in parallel
+    #[arg(short, long, default_value_t = 1)]
+    pub jobs: usize,
+
+    /// Renaming in place, first give every file a temporary name and only then the new names, instead of only the files whose name is taken
+    #[arg(long)]
+    pub stage_all: bool,
+
+    /// Try a renaming that fails with a transient error, such as a busy file on a network share, this many times again
+    #[arg(long, default_value_t = 0)]
+    pub retries: u32,
+
+    /// Wait this long before the first retry, doubling before each next one, as in 100ms or 2s
+    #[arg(long, default_value = "100ms", value_parser = humantime::parse_duration)]
+    pub retry_delay: Duration,
+
+    /// Leave files that another program holds, such as photos open in an image editor, under their old names and list them at the end, instead of failing
+    #[arg(long)]
+    pub skip_locked: bool,
+
+    /// Leave this many files of the plan, ordered by name, with the rest of the photos of their objects for a later run
+    #[arg(long, default_value_t = 0)]
+    pub skip: usize,
+
+    /// Rename at most this many files of the plan, ordered by name and in whole objects, leaving the rest for a later run
+    #[arg(long)]
+    pub limit: Option<usize>,
+
+    /// Show a progress bar while renaming
+    #[arg(short, long)]
+    pub progress: bool,
+
+    /// Finish a run that was interrupted, instead of planning a new one
+    #[arg(long)]
+    pub resume: bool,
+
+    /// Keep running and rename files as they arrive
+    #[arg(short, long)]
+    pub watch: bool,
+
+    /// Colour the status column of the plan: auto, always or never
+    #[arg(long, default_value = "auto", value_parser = choices::<When>(&["auto", "always", "never"]), hide_possible_values = true)]
+    pub color: When,
+
+    /// Print a report of the run in this format: json
+    #[arg(long, value_parser = choices::<ReportFormat>(&["json"]), hide_possible_values = true)]
+    pub report: Option<ReportFormat>,
+
+    /// Write the report to this file instead of stdout
+    #[arg(long, requires = "report")]
+    pub report_file: Option<PathBuf>,
+
+    /// Append a timestamped line for every renaming to this file
+    #[arg(long)]
+    pub audit_log: Option<PathBuf>,
+
+    /// After renaming, write the old and new name of every file with its lot and inventory number to this CSV file
+    #[arg(long = "write-mapping")]
+    pub mapping_file: Option<PathBuf>,
+
+    /// Record the run in this SQLite database, to list and undo it later with the history subcommand
+    #[arg(long, num_args = 0..=1, default_missing_value = DEFAULT_HISTORY)]
+    pub history: Option<PathBuf>,
+
+    /// Before renaming, write the plan as an HTML page with a thumbnail of every file next to its old and new name to this file
+    #[arg(long)]
+    pub preview_html: Option<PathBuf>,
+}
+
+impl RunArgs {
+    // config turns the arguments into a checked Config.
+    pub fn config(self, log_level: log::LevelFilter) -> Result<Config, RenameError> {
+        // Without a data file, the only path given is the directory.
+        let (data_file, directory) = match self.directory {
+            Some(directory) => (self.data_file, directory),
+            None => (PathBuf::new(), self.data_file),
+        };
+        let mut builder = Config::builder(data_file, directory)
+            .dry_run(self.dry_run)
+            .delimiter(self.delimiter)
+            .lot_column(self.lot_column)
+            .inventory_column(self.inventory_column)
+            .has_headers(!self.no_headers)
+            .ignore_case(self.ignore_case)
+            .trim(self.trim)
+            .normalize_lot(self.normalize_lot)
+            .match_mode(self.match_mode)
+            .key_separator(self.key_separator)
+            .format(self.format)
+            .lot_key(self.lot_key)
+            .inventory_key(self.inventory_key)
+            .query(self.query)
+            .template(self.template)
+            .keep_extension(self.keep_extension)
+            .lowercase_extension(self.lowercase_extension)
+            .sanitize(self.sanitize)
+            .suffix(self.suffix)
+            .on_malformed(self.on_malformed)
+            .on_bad_row(self.on_bad_row)
+            .on_duplicate(self.on_duplicate)
+            .on_crowded_lot(self.on_crowded_lot)
+            .resequence(self.resequence)
+            .lot_width(self.pad_lot)
+            .index_width(self.pad_index)
+            .copy(self.copy)
+            .lot_dirs(self.lot_dirs)
+            .strict(self.strict)
+            .find_duplicates(self.find_duplicates)
+            .verify_images(self.verify_images)
+            .on_conflict(self.on_conflict)
+            .verify(self.verify)
+            .post_check(self.post_check)
+            .preserve_xattrs(self.preserve_xattrs)
+            .symlinks(self.symlinks)
+            .timings(self.timings)
+            .stage_all(self.stage_all)
+            .jobs(self.jobs)
+            .skip(self.skip)
+            .retries(self.retries)
+            .retry_delay(self.retry_delay)
+            .skip_locked(self.skip_locked)
+            .watch(self.watch)
+            .progress(self.progress)
+            .color(self.color.enabled())
+            .resume(self.resume)
+            .log_level(log_level);
+
+        if let Some(link) = self.link {
+            builder = builder.link(link);
+        }
+        if let Some(pattern) = self.from_regex {
+            builder = builder.from_regex(pattern);
+        }
+        if let Some(template) = self.to {
+            builder = builder.template(template);
+        }
+        if let Some(limit) = self.limit {
+            builder = builder.limit(limit);
+        }
+        if let Some(max) = self.max_per_lot {
+            builder = builder.max_per_lot(max);
+        }
+        if let Some(lots) = self.lots {
+            builder = builder.lots(lots);
+        }
+        if let Some(script) = self.script {
+            builder = builder.script(script);
+        }
+        if let Some(normalization) = self.normalize {
+            builder = builder.normalization(normalization);
+        }
+        if let Some(column) = self.date_column {
+            builder = builder.date_column(column);
+        }
+        if let Some(column) = self.hero_column {
+            builder = builder.hero_column(column);
+        }
+        if let Some(encoding) = self.encoding {
+            builder = builder.encoding(encoding);
+        }
+        if let Some(sheet) = self.sheet {
+            builder = builder.sheet(sheet);
+        }
+        if let Some(widths) = self.widths {
+            builder = builder.widths(widths);
+        }
+        if let Some(size) = self.min_size {
+            builder = builder.min_size(size);
+        }
+        if let Some(size) = self.max_size {
+            builder = builder.max_size(size);
+        }
+        if let Some(min) = self.min_dimensions {
+            builder = builder.min_dimensions(min);
+        }
+        if let Some(output_dir) = self.output_dir {
+            builder = builder.output_dir(output_dir);
+        }
+        if let Some(report) = self.report {
+            builder = builder.report_format(report);
+        }
+        if let Some(report_file) = self.report_file {
+            builder = builder.report_file(report_file);
+        }
+        if let Some(backup) = self.backup {
+            builder = builder.backup(backup);
+        }
+        if let Some(mapping_file) = self.mapping_file {
+            builder = builder.mapping_file(mapping_file);
+        }
+        if let Some(history) = self.history {
+            builder = builder.history(history);
+        }
+        if let Some(preview_html) = self.preview_html {
+            builder = builder.preview_html(preview_html);
+        }
+        if let Some(audit_log) = self.audit_log {
+            builder = builder.audit_log(audit_log);
+        }
+        for data_file in self.extra_data_files {
+            builder = builder.extra_data_file(data_file);
+        }
+        for dir in self.extra_dirs {
+            builder = builder.extra_dir(dir);
+        }
+        for column in self.key_columns {
+            builder = builder.key_column(column);
+        }
+        for condition in self.conditions {
+            builder = builder.condition(condition);
+        }
+        for transform in self.lot_transforms {
+            builder = builder.lot_transform(transform);
+        }
+        for transform in self.transforms {
+            builder = builder.transform(transform);
+        }
+        for extension in self.sidecars {
+            builder = builder.sidecar(extension);
+        }
+        for pattern in self.include {
+            builder = builder.include(pattern);
+        }
+        for pattern in self.exclude {
+            builder = builder.exclude(pattern);
+        }
+
+        builder.build()
+    }
+}
+
+impl Cli {
+    pub fn log_level(&self) -> log::LevelFilter {
+        if self.quiet {
+            log::LevelFilter::Error
+        } else if self.verbose {
+            log::LevelFilter::Debug
+        } else {
+            log::LevelFilter::Info
+        }
+    }
+}
+
+// When tells when to colour output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum When {
+    // Only when writing to a terminal and NO_COLOR is not set.
+    Auto,
+    Always,
+    Never,
+}
+
+impl std::str::FromStr for When {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<When, &'static str> {
+        match value {
+            "auto" => Ok(When::Auto),
+            "always" => Ok(When::Always),
+            "never" => Ok(When::Never),
+            _ => Err("color must be auto, always or never"),
+        }
+    }
+}
+
+impl When {
+    fn enabled(self) -> bool {
+        match self {
+            When::Auto => std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+            When::Always => true,
+            When::Never => false,
+        }
+    }
+}
+
+// Choices parses the value of an option that takes one of a few known values,
+// which shells complete. Values are parsed as the option type parses them, so
+// aliases such as yml for yaml are accepted too.
+#[derive(Clone)]
+struct Choices<T> {
+    values: &'static [&'static str],
+    parsed: PhantomData<T>,
+}
+
+fn choices<T>(values: &'static [&'static str]) -> Choices<T> {
+    Choices {
+        values,
+        parsed: PhantomData,
+    }
+}
+
+impl<T> TypedValueParser for Choices<T>
+where
+    T: std::str::FromStr<Err = &'static str> + Clone + Send + Sync + 'static,
+{
+    type Value = T;
+
+    fn parse_ref(
+        &self,
+        cmd: &clap::Command,
+        arg: Option<&clap::Arg>,
+        value: &OsStr,
+    ) -> Result<T, clap::Error> {
+        let value = value
+            .to_str()
+            .ok_or_else(|| clap::Error::new(ErrorKind::InvalidUtf8).with_cmd(cmd))?;
+        value.parse().map_err(|err| {
+            let arg = arg.map_or(String::from("..."), |arg| arg.to_string());
+            clap::Error::raw(
+                ErrorKind::InvalidValue,
+                format!("invalid value '{}' for '{}': {}\n", value, arg, err),
+            )
+            .with_cmd(cmd)
+        })
+    }
+
+    fn possible_values(&self) -> Option<Box<dyn Iterator<Item = PossibleValue> + '_>> {
+        Some(Box::new(self.values.iter().map(PossibleValue::new)))
+    }
+}
+
+// parse_delimiter accepts a single ASCII character or a common escape such as `\t`.
+fn parse_delimiter(value: &str) -> Result<u8, &'static str> {
+    match value {
+        "\\t" | "tab" => Ok(b'\t'),
+        "comma" => Ok(b','),
+        "semicolon" => Ok(b';'),
+        "pipe" => Ok(b'|'),
+        _ if value.len() == 1 && value.is_ascii() => Ok(value.as_bytes()[0]),
+        _ => Err("delimiter must be a single ASCII character or \\t"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(std::iter::once("rename").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn verify_cli() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn parse_without_subcommand() {
+        let cli = parse(&["--dry-run", "tests/data.csv", "tests/files"]).unwrap();
+        assert!(cli.command.is_none());
+
+        let config = cli.run.unwrap().config(log::LevelFilter::Info).unwrap();
+        assert!(config.dry_run);
+        assert_eq!(PathBuf::from("tests/data.csv"), config.data_file);
+        assert_eq!(PathBuf::from("tests/files"), config.dir);
+    }
+
+    #[test]
+    fn parse_subcommands() {
+        let cli = parse(&["plan", "-d", ",", "tests/data.csv", "tests/files"]).unwrap();
+        match cli.command {
+            Some(Command::Plan(args)) => assert_eq!(b',', args.delimiter),
+            _ => panic!("expected the plan subcommand"),
+        }
+
+        let cli = parse(&["undo", "-q", "tests/files"]).unwrap();
+        assert_eq!(log::LevelFilter::Error, cli.log_level());
+        assert!(matches!(cli.command, Some(Command::Undo { .. })));
+
+        let cli = parse(&["restore", "tests/files"]).unwrap();
+        match cli.command {
+            Some(Command::Restore { backup, .. }) => assert_eq!(PathBuf::from(BACKUP_DIR), backup),
+            _ => panic!("expected the restore subcommand"),
+        }
+
+        let cli = parse(&["history", "undo", "3", "--history", "runs.db"]).unwrap();
+        match cli.command {
+            Some(Command::History {
+                command: HistoryCommand::Undo { run_id },
+                history,
+            }) => {
+                assert_eq!(3, run_id);
+                assert_eq!(PathBuf::from("runs.db"), history);
+            }
+            _ => panic!("expected the history undo subcommand"),
+        }
+
+        assert!(parse(&["apply", "tests/data.csv"]).is_err());
+        assert!(parse(&["-x", "tests/data.csv", "tests/files"]).is_err());
+    }
+
+    #[test]
+    fn complete_option_values() {
+        let cli = parse(&["completions", "zsh"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Completions { shell: Shell::Zsh })
+        ));
+
+        let mut script = vec![];
+        clap_complete::generate(Shell::Bash, &mut Cli::command(), "rename", &mut script);
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.contains("csv tsv fixed-width xlsx json toml yaml sqlite"));
+        assert!(script.contains("first last error"));
+
+        // Values are parsed as before, aliases included.
+        let cli = parse(&["plan", "--format", "yml", "tests/data.csv", "tests/files"]).unwrap();
+        match cli.command {
+            Some(Command::Plan(args)) => assert_eq!(Format::Yaml, args.format),
+            _ => panic!("expected the plan subcommand"),
+        }
+        assert!(parse(&["--on-conflict", "ask", "tests/data.csv", "tests/files"]).is_err());
+    }
+
+    #[test]
+    fn parse_regex_mode() {
+        let cli = parse(&[
+            "--from-regex",
+            r"(?P<id>\d+)\.(?P<n>\d+)\.jpg",
+            "--to",
+            "{id}-{n}.jpeg",
+            "tests/files",
+        ])
+        .unwrap();
+        let config = cli.run.unwrap().config(log::LevelFilter::Info).unwrap();
+
+        assert_eq!(PathBuf::from("tests/files"), config.dir);
+        assert_eq!("{id}-{n}.jpeg", config.template);
+        assert!(parse(&["--from-regex", r"(\d+)\.jpg", "tests/files"]).is_err());
+        assert!(parse(&["--to", "{1}.jpg", "tests/files"]).is_err());
+    }
+
+    #[test]
+    fn parse_verbosity_flags() {
+        let quiet = parse(&["--quiet", "tests/data.csv", "tests/files"]).unwrap();
+        let verbose = parse(&["apply", "-v", "tests/data.csv", "tests/files"]).unwrap();
+
+        assert_eq!(log::LevelFilter::Error, quiet.log_level());
+        assert_eq!(log::LevelFilter::Debug, verbose.log_level());
+    }
+
+    #[test]
+    fn parse_column_flags() {
+        let cli = parse(&[
+            "--lot-column",
+            "2",
+            "--inventory-column",
+            "Inv Number",
+            "tests/data.csv",
+            "tests/files",
+        ])
+        .unwrap();
+        let config = cli.run.unwrap().config(log::LevelFilter::Info).unwrap();
+
+        assert_eq!(Column::Index(2), config.lot_column);
+        assert_eq!(
+            Column::Name("Inv Number".to_string()),
+            config.inventory_column
+        );
+        assert!(parse(&["--lot-column", "", "tests/data.csv", "tests/files"]).is_err());
+    }
+
+    #[test]
+    fn every_flag_is_an_option_of_the_library() {
+        // These only concern the output of the command-line program.
+        let excluded = ["progress", "watch", "color", "report", "report-file"];
+        let command = RunArgs::augment_args(clap::Command::new("run"));
+        let flags: Vec<&str> = command
+            .get_arguments()
+            .filter_map(|arg| arg.get_long())
+            .collect();
+
+        for flag in &flags {
+            assert!(
+                rename::OPTIONS.contains(flag) || excluded.contains(flag),
+                "--{} cannot be set through the library",
+                flag
+            );
+        }
+        for option in rename::OPTIONS {
+            assert!(flags.contains(option), "{} is not a flag", option);
+        }
+    }
+
+    #[test]
+    fn parse_delimiters() {
+        assert_eq!(Ok(b'\t'), parse_delimiter("\\t"));
+        assert_eq!(Ok(b'\t'), parse_delimiter("\t"));
+        assert_eq!(Ok(b','), parse_delimiter(","));
+        assert_eq!(Ok(b';'), parse_delimiter("semicolon"));
+        assert!(parse_delimiter(",;").is_err());
+        assert!(parse_delimiter("").is_err());
+    }
+}
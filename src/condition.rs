@@ -0,0 +1,63 @@
+use crate::Column;
+
+// Condition selects rows by the value in one of their columns, written as
+// `Status=approved`, or `Status!=draft` for the rows without that value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    pub column: Column,
+    pub value: String,
+    pub negated: bool,
+}
+
+impl std::str::FromStr for Condition {
+    type Err = &'static str;
+
+    fn from_str(condition: &str) -> Result<Condition, &'static str> {
+        let (column, value) = condition
+            .split_once('=')
+            .ok_or("condition must be a column and a value, e.g. Status=approved")?;
+        let column = column.trim();
+        let (column, negated) = match column.strip_suffix('!') {
+            Some(column) => (column, true),
+            None => (column, false),
+        };
+
+        Ok(Condition {
+            column: column.trim().parse()?,
+            value: value.trim().to_owned(),
+            negated,
+        })
+    }
+}
+
+impl Condition {
+    // holds tells whether the value of the column meets the condition. Values
+    // are compared without surrounding whitespace.
+    pub fn holds(&self, value: &str) -> bool {
+        (value.trim() == self.value) != self.negated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_conditions() {
+        let approved: Condition = "Status=approved".parse().unwrap();
+        assert_eq!(Column::Name(String::from("Status")), approved.column);
+        assert!(approved.holds(" approved"));
+        assert!(!approved.holds("draft"));
+
+        let not_draft: Condition = "4 != draft".parse().unwrap();
+        assert_eq!(Column::Index(4), not_draft.column);
+        assert!(not_draft.holds("approved"));
+        assert!(!not_draft.holds("draft"));
+
+        let empty: Condition = "Status=".parse().unwrap();
+        assert!(empty.holds(""));
+
+        assert!("Status".parse::<Condition>().is_err());
+        assert!("=approved".parse::<Condition>().is_err());
+    }
+}
@@ -0,0 +1,265 @@
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs;
+use std::path::PathBuf;
+
+use clap::{ArgAction, Command, CommandFactory};
+
+use crate::cli::Cli;
+
+// CONFIG_FILE is read from the working directory, unless `--config` names
+// another file.
+pub const CONFIG_FILE: &str = "rename.toml";
+
+// with_config_file adds the options in the config file to the command line
+// arguments. Each key is the name of an option, with a string, number, boolean
+// or array of those as value, such as `delimiter = ","` or
+// `include = ["*.jpg", "*.tif"]`. The options are placed before those given on
+// the command line, so that the command line wins. Options that may be given
+// more than once are left out when the command line gives them, so that its
+// values replace those in the file instead of adding to them.
+pub fn with_config_file(mut args: Vec<OsString>) -> Result<Vec<OsString>, String> {
+    let path = match config_path(&args) {
+        Some(path) => path,
+        None if fs::metadata(CONFIG_FILE).is_ok() => PathBuf::from(CONFIG_FILE),
+        None => return Ok(args),
+    };
+
+    let content = fs::read_to_string(&path)
+        .map_err(|err| format!("could not read {}: {}", path.display(), err))?;
+    let mut table: toml::Table = toml::from_str(&content)
+        .map_err(|err| format!("could not parse {}: {}", path.display(), err))?;
+
+    // Options go after the subcommand that takes them; undo, restore,
+    // completions and history take none.
+    let mut cli = Cli::command();
+    cli.build();
+    let (position, command) = match subcommand(&cli, &args) {
+        Some((index, command)) if matches!(command.get_name(), "plan" | "apply" | "check") => {
+            (index + 1, command)
+        }
+        Some(_) => return Ok(args),
+        None => (1.min(args.len()), &cli),
+    };
+
+    let (given, _) = parse(command, &args[position..]);
+    table.retain(|key, _| {
+        let long = key.replace('_', "-");
+        !command.get_arguments().any(|arg| {
+            arg.get_long() == Some(long.as_str())
+                && matches!(arg.get_action(), ArgAction::Append)
+                && given.contains(arg.get_id().as_str())
+        })
+    });
+    args.splice(position..position, to_args(&table)?);
+
+    Ok(args)
+}
+
+// config_path finds the config file given with `--config`, if any.
+fn config_path(args: &[OsString]) -> Option<PathBuf> {
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        let arg = arg.to_string_lossy();
+        if arg == "--config" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Some(PathBuf::from(path));
+        }
+    }
+
+    None
+}
+
+// subcommand finds the subcommand among the arguments, with its index. It is
+// the first argument that is neither an option nor the value of one, if it
+// names a subcommand; otherwise the options of a run are given without one.
+fn subcommand<'a>(cli: &'a Command, args: &[OsString]) -> Option<(usize, &'a Command)> {
+    let (_, positionals) = parse(cli, args.get(1..).unwrap_or_default());
+    let index = positionals.first()? + 1;
+    let command = cli.find_subcommand(&args[index])?;
+
+    Some((index, command))
+}
+
+// parse tells apart the options of the command among the arguments, by id,
+// and the indices of the other arguments, skipping the values of options.
+fn parse<'a>(command: &'a Command, args: &[OsString]) -> (HashSet<&'a str>, Vec<usize>) {
+    let mut options = HashSet::new();
+    let mut positionals = vec![];
+    let mut index = 0;
+    while index < args.len() {
+        let arg = args[index].to_string_lossy();
+        index += 1;
+        if arg == "--" {
+            positionals.extend(index..args.len());
+            break;
+        }
+
+        if let Some(long) = arg.strip_prefix("--") {
+            let (name, has_value) = match long.split_once('=') {
+                Some((name, _)) => (name, true),
+                None => (long, false),
+            };
+            if let Some(option) = command.get_arguments().find(|a| a.get_long() == Some(name)) {
+                options.insert(option.get_id().as_str());
+                if !has_value && option.get_action().takes_values() {
+                    index += 1;
+                }
+            }
+        } else if let Some(shorts) = arg.strip_prefix('-').filter(|shorts| !shorts.is_empty()) {
+            // Short flags may be combined, and the last may take the rest of
+            // the argument or the next one as its value.
+            for (at, short) in shorts.char_indices() {
+                let Some(option) = command
+                    .get_arguments()
+                    .find(|a| a.get_short() == Some(short))
+                else {
+                    break;
+                };
+                options.insert(option.get_id().as_str());
+                if option.get_action().takes_values() {
+                    if at + short.len_utf8() == shorts.len() {
+                        index += 1;
+                    }
+                    break;
+                }
+            }
+        } else {
+            positionals.push(index - 1);
+        }
+    }
+
+    (options, positionals)
+}
+
+// to_args turns the keys of the table into options, in the order of the file.
+fn to_args(table: &toml::Table) -> Result<Vec<OsString>, String> {
+    let mut args = vec![];
+    for (key, value) in table {
+        let option = format!("--{}", key.replace('_', "-"));
+        let values = match value {
+            toml::Value::Array(values) => values.iter().collect(),
+            value => vec![value],
+        };
+        for value in values {
+            let value = match value {
+                toml::Value::String(value) => value.clone(),
+                toml::Value::Integer(value) => value.to_string(),
+                toml::Value::Float(value) => value.to_string(),
+                toml::Value::Boolean(true) => {
+                    args.push(OsString::from(&option));
+                    continue;
+                }
+                toml::Value::Boolean(false) => continue,
+                _ => {
+                    return Err(format!(
+                        "option {} must be a string, number, boolean or array of those",
+                        key
+                    ))
+                }
+            };
+            args.push(OsString::from(&option));
+            args.push(OsString::from(value));
+        }
+    }
+
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Command;
+    use clap::Parser;
+
+    fn os_strings(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn merge_config_file_with_cli() {
+        let path = "tests/tmp_rename.toml";
+        fs::write(
+            path,
+            r#"
+            delimiter = ","
+            lot_column = "Lot Number"
+            include = ["*.jpg", "*.tif"]
+            strict = true
+            dry-run = false
+            "#,
+        )
+        .unwrap();
+
+        let args = with_config_file(os_strings(&[
+            "rename",
+            "plan",
+            "--config",
+            path,
+            "-d",
+            ";",
+            "tests/data.csv",
+            "tests/files",
+        ]));
+        let undo = with_config_file(os_strings(&["rename", "undo", "--config", path, "dir"]));
+        // An option value that names a subcommand is no subcommand.
+        let run = with_config_file(os_strings(&[
+            "rename",
+            "--config",
+            path,
+            "--lot-column",
+            "history",
+            "tests/data.csv",
+            "tests/files",
+        ]));
+        let include = with_config_file(os_strings(&[
+            "rename",
+            "apply",
+            "--config",
+            path,
+            "--include=*.png",
+            "tests/data.csv",
+            "tests/files",
+        ]));
+        let missing = with_config_file(os_strings(&["rename", "--config", "tests/missing.toml"]));
+        fs::write(path, "jobs = { max = 2 }").unwrap();
+        let invalid = with_config_file(os_strings(&["rename", "--config", path]));
+        fs::remove_file(path).unwrap();
+
+        let cli = Cli::try_parse_from(args.unwrap()).unwrap();
+        let Some(Command::Plan(args)) = cli.command else {
+            panic!("expected the plan subcommand");
+        };
+        // The command line wins.
+        assert_eq!(b';', args.delimiter);
+        assert_eq!(
+            rename::Column::Name("Lot Number".to_string()),
+            args.lot_column
+        );
+        assert_eq!(vec!["*.jpg", "*.tif"], args.include);
+        assert!(args.strict);
+        assert!(!args.dry_run);
+
+        assert_eq!(
+            os_strings(&["rename", "undo", "--config", path, "dir"]),
+            undo.unwrap()
+        );
+
+        let cli = Cli::try_parse_from(run.unwrap()).unwrap();
+        let args = cli.run.unwrap();
+        assert_eq!(b',', args.delimiter);
+        assert_eq!(rename::Column::Name("history".to_string()), args.lot_column);
+
+        // Options given more than once replace those in the config file.
+        let cli = Cli::try_parse_from(include.unwrap()).unwrap();
+        let Some(Command::Apply(args)) = cli.command else {
+            panic!("expected the apply subcommand");
+        };
+        assert_eq!(vec!["*.png"], args.include);
+        assert_eq!(b',', args.delimiter);
+        assert!(missing.is_err());
+        assert!(invalid.is_err());
+    }
+}
@@ -0,0 +1,236 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+use crate::template::split_extension;
+
+// ConflictPolicy determines what happens to a file whose new name is already
+// taken by a file in the target directory.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum ConflictPolicy {
+    // Rename nothing and report the conflict.
+    #[default]
+    Abort,
+    // Leave the file alone.
+    Skip,
+    // Replace the existing file.
+    Overwrite,
+    // Append `_2`, `_3` and so on to the new name until it is free.
+    Number,
+}
+
+impl std::str::FromStr for ConflictPolicy {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<ConflictPolicy, &'static str> {
+        match value {
+            "abort" => Ok(ConflictPolicy::Abort),
+            "skip" => Ok(ConflictPolicy::Skip),
+            "overwrite" => Ok(ConflictPolicy::Overwrite),
+            "number" => Ok(ConflictPolicy::Number),
+            _ => Err("conflict policy must be abort, skip, overwrite or number"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Conflict {
+    // Several files would be renamed to the same new name.
+    DuplicateTarget {
+        new_name: String,
+        old_names: Vec<String>,
+    },
+    // The new name is already taken by a file in the directory.
+    ExistingTarget {
+        old_name: String,
+        new_name: String,
+    },
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Conflict::DuplicateTarget {
+                new_name,
+                old_names,
+            } => write!(
+                f,
+                "{} would be the new name of multiple files: {}",
+                new_name,
+                old_names.join(", ")
+            ),
+            Conflict::ExistingTarget { old_name, new_name } => write!(
+                f,
+                "{} cannot be renamed to {}: file already exists",
+                old_name, new_name
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ConflictError {
+    pub conflicts: Vec<Conflict>,
+}
+
+impl fmt::Display for ConflictError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "found {} conflict(s), nothing was renamed:",
+            self.conflicts.len()
+        )?;
+        for conflict in &self.conflicts {
+            write!(f, "\n  {}", conflict)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConflictError {}
+
+// detect_conflicts lists all renamings that would overwrite another file, either
+// because they share a new name or because the new name already exists on disk.
+pub fn detect_conflicts(
+    renamings: &BTreeMap<String, String>,
+    existing_files: &[String],
+) -> Vec<Conflict> {
+    let mut conflicts: Vec<Conflict> = vec![];
+
+    let mut old_names_by_target: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for (old_name, new_name) in renamings {
+        old_names_by_target
+            .entry(new_name)
+            .or_default()
+            .push(old_name.clone());
+    }
+
+    for (new_name, old_names) in old_names_by_target {
+        if old_names.len() > 1 {
+            conflicts.push(Conflict::DuplicateTarget {
+                new_name: new_name.to_owned(),
+                old_names,
+            });
+        }
+    }
+
+    let existing: HashSet<&str> = existing_files.iter().map(String::as_str).collect();
+    let existing_targets = renamings.iter().filter(|(old_name, new_name)| {
+        old_name != new_name && existing.contains(new_name.as_str())
+    });
+
+    for (old_name, new_name) in existing_targets {
+        conflicts.push(Conflict::ExistingTarget {
+            old_name: old_name.clone(),
+            new_name: new_name.clone(),
+        });
+    }
+
+    conflicts
+}
+
+// resolve_existing_targets applies the policy to the renamings whose new name
+// already exists, and returns the old names of the files that are skipped.
+pub fn resolve_existing_targets(
+    renamings: &mut BTreeMap<String, String>,
+    existing_files: &[String],
+    policy: ConflictPolicy,
+) -> Vec<String> {
+    let existing: HashSet<&str> = existing_files.iter().map(String::as_str).collect();
+    let conflicting: Vec<String> = renamings
+        .iter()
+        .filter(|(old_name, new_name)| old_name != new_name && existing.contains(new_name.as_str()))
+        .map(|(old_name, _)| old_name.clone())
+        .collect();
+
+    match policy {
+        ConflictPolicy::Abort | ConflictPolicy::Overwrite => vec![],
+        ConflictPolicy::Skip => {
+            for old_name in &conflicting {
+                renamings.remove(old_name);
+            }
+            conflicting
+        }
+        ConflictPolicy::Number => {
+            let mut taken: HashSet<String> = existing_files.iter().cloned().collect();
+            taken.extend(renamings.values().cloned());
+            for old_name in &conflicting {
+                let new_name = number_name(&renamings[old_name], &taken);
+                taken.insert(new_name.clone());
+                renamings.insert(old_name.clone(), new_name);
+            }
+            vec![]
+        }
+    }
+}
+
+// number_name appends the lowest number from 2 up to the stem of the name that
+// makes it differ from the taken names.
+fn number_name(name: &str, taken: &HashSet<String>) -> String {
+    let (stem, extension) = split_extension(name);
+    (2..)
+        .map(|number| {
+            if extension.is_empty() {
+                format!("{}_{}", stem, number)
+            } else {
+                format!("{}_{}.{}", stem, number, extension)
+            }
+        })
+        .find(|candidate| !taken.contains(candidate))
+        .expect("some number is free")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_existing_targets_by_policy() {
+        let renamings: BTreeMap<String, String> = BTreeMap::from([
+            ("00243344.1.jpg".to_string(), "3_1.jpg".to_string()),
+            ("00243344.2.jpg".to_string(), "3_2.jpg".to_string()),
+        ]);
+        let existing_files = vec!["3_1.jpg".to_string(), "3_1_2.jpg".to_string()];
+
+        let mut skipped = renamings.clone();
+        assert_eq!(
+            vec!["00243344.1.jpg".to_string()],
+            resolve_existing_targets(&mut skipped, &existing_files, ConflictPolicy::Skip)
+        );
+        assert!(!skipped.contains_key("00243344.1.jpg"));
+
+        let mut numbered = renamings.clone();
+        resolve_existing_targets(&mut numbered, &existing_files, ConflictPolicy::Number);
+        assert_eq!("3_1_3.jpg", numbered["00243344.1.jpg"]);
+        assert_eq!("3_2.jpg", numbered["00243344.2.jpg"]);
+
+        let mut overwritten = renamings.clone();
+        resolve_existing_targets(&mut overwritten, &existing_files, ConflictPolicy::Overwrite);
+        assert_eq!(renamings, overwritten);
+    }
+
+    #[test]
+    fn detect_duplicate_and_existing_targets() {
+        let renamings: BTreeMap<String, String> = BTreeMap::from([
+            ("00243878.1.jpg".to_string(), "1_1.jpg".to_string()),
+            ("00243880.1.jpg".to_string(), "1_1.jpg".to_string()),
+            ("00243344.1.jpg".to_string(), "3_1.jpg".to_string()),
+            ("00243344.2.jpg".to_string(), "3_2.jpg".to_string()),
+        ]);
+        let existing_files = vec!["3_1.jpg".to_string(), "00243344.2.jpg".to_string()];
+
+        assert_eq!(
+            vec![
+                Conflict::DuplicateTarget {
+                    new_name: "1_1.jpg".to_string(),
+                    old_names: vec!["00243878.1.jpg".to_string(), "00243880.1.jpg".to_string()],
+                },
+                Conflict::ExistingTarget {
+                    old_name: "00243344.1.jpg".to_string(),
+                    new_name: "3_1.jpg".to_string(),
+                },
+            ],
+            detect_conflicts(&renamings, &existing_files)
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use std::fs;
+use std::path::Path;
+
+use crate::capture;
+
+// The format of {date} without one of its own.
+pub const DEFAULT_DATE_FORMAT: &str = "%Y%m%d";
+
+// Date is a moment as given by the data file or a photo, without a time zone.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Date {
+    year: u32,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+}
+
+impl Date {
+    // parse reads a date as catalogues and cameras write it: year first, as
+    // in 2024-05-01, 2024:05:01 and 20240501, or day first, as in 01-05-2024
+    // and 01/05/2024. A time may follow.
+    pub fn parse(value: &str) -> Option<Date> {
+        let parts: Vec<&str> = value
+            .split(|c: char| !c.is_ascii_digit())
+            .filter(|part| !part.is_empty())
+            .collect();
+
+        let numbers: Vec<u32> = match parts.as_slice() {
+            [date] if date.len() == 8 => vec![&date[..4], &date[4..6], &date[6..]]
+                .into_iter()
+                .map(|part| part.parse().ok())
+                .collect::<Option<_>>()?,
+            [day, month, year, ..] if year.len() == 4 && day.len() <= 2 => {
+                let mut numbers = vec![year.parse().ok()?, month.parse().ok()?, day.parse().ok()?];
+                for part in &parts[3..] {
+                    numbers.push(part.parse().ok()?);
+                }
+                numbers
+            }
+            [year, ..] if year.len() == 4 => parts
+                .iter()
+                .map(|part| part.parse().ok())
+                .collect::<Option<_>>()?,
+            _ => return None,
+        };
+        if numbers.len() < 3 || numbers.len() > 6 {
+            return None;
+        }
+
+        let number = |i: usize| numbers.get(i).copied().unwrap_or(0);
+        let date = Date {
+            year: number(0),
+            month: number(1),
+            day: number(2),
+            hour: number(3),
+            minute: number(4),
+            second: number(5),
+        };
+        let valid = (1..=12).contains(&date.month)
+            && (1..=31).contains(&date.day)
+            && date.hour < 24
+            && date.minute < 60
+            && date.second < 61;
+
+        valid.then_some(date)
+    }
+
+    // of_file is when the photo was taken, from its EXIF data, or else when
+    // the file was last modified, in UTC.
+    pub fn of_file(path: &Path) -> Option<Date> {
+        if let Some(date) = capture::capture_time(path).and_then(|time| Date::parse(&time)) {
+            return Some(date);
+        }
+
+        let modified = fs::metadata(path).ok()?.modified().ok()?;
+        Date::parse(&humantime::format_rfc3339_seconds(modified).to_string())
+    }
+
+    // format writes the date after the format, in which %Y, %y, %m, %d, %H,
+    // %M and %S stand for the parts of the date and %% for a percent sign.
+    pub fn format(&self, format: &str) -> String {
+        let mut formatted = String::new();
+        let mut chars = format.chars();
+
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                formatted.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('Y') => formatted.push_str(&format!("{:04}", self.year)),
+                Some('y') => formatted.push_str(&format!("{:02}", self.year % 100)),
+                Some('m') => formatted.push_str(&format!("{:02}", self.month)),
+                Some('d') => formatted.push_str(&format!("{:02}", self.day)),
+                Some('H') => formatted.push_str(&format!("{:02}", self.hour)),
+                Some('M') => formatted.push_str(&format!("{:02}", self.minute)),
+                Some('S') => formatted.push_str(&format!("{:02}", self.second)),
+                Some('%') => formatted.push('%'),
+                _ => {}
+            }
+        }
+
+        formatted
+    }
+}
+
+// check_format tells whether the format only holds the specifiers that
+// Date::format knows.
+pub fn check_format(format: &str) -> bool {
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c == '%'
+            && !matches!(
+                chars.next(),
+                Some('Y' | 'y' | 'm' | 'd' | 'H' | 'M' | 'S' | '%')
+            )
+        {
+            return false;
+        }
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_format_dates() {
+        let date = Date::parse("2024-05-01").unwrap();
+        assert_eq!("20240501", date.format(DEFAULT_DATE_FORMAT));
+        assert_eq!(Some(date), Date::parse("01-05-2024"));
+        assert_eq!(Some(date), Date::parse("1/5/2024"));
+        assert_eq!(Some(date), Date::parse("20240501"));
+
+        let date = Date::parse("2024:05:01 10:04:09").unwrap();
+        assert_eq!(
+            "24-05-01 10.04.09 100%",
+            date.format("%y-%m-%d %H.%M.%S 100%%")
+        );
+
+        assert_eq!(None, Date::parse("2024-13-01"));
+        assert_eq!(None, Date::parse("lot 12"));
+        assert_eq!(None, Date::parse(""));
+
+        assert!(check_format("%Y%m%d_%H%M"));
+        assert!(!check_format("%Y-%B"));
+        assert!(!check_format("%"));
+    }
+}
@@ -0,0 +1,71 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::operation::checksum;
+
+// find_duplicates groups the files in the directory that are byte-identical,
+// such as a frame exported twice. Only files of the same size are hashed. The
+// groups and the files in them are ordered by name.
+pub fn find_duplicates<'a>(
+    dir: &Path,
+    files: impl IntoIterator<Item = &'a String>,
+) -> io::Result<Vec<Vec<String>>> {
+    let mut by_size: HashMap<u64, Vec<&String>> = HashMap::new();
+    for file in files {
+        let size = fs::metadata(dir.join(file))?.len();
+        by_size.entry(size).or_default().push(file);
+    }
+
+    let mut by_checksum: BTreeMap<Vec<u8>, Vec<String>> = BTreeMap::new();
+    for files in by_size.into_values().filter(|files| files.len() > 1) {
+        for file in files {
+            by_checksum
+                .entry(checksum(&dir.join(file))?)
+                .or_default()
+                .push(file.to_owned());
+        }
+    }
+
+    let mut groups: Vec<Vec<String>> = by_checksum
+        .into_values()
+        .filter(|files| files.len() > 1)
+        .map(|mut files| {
+            files.sort();
+            files
+        })
+        .collect();
+    groups.sort();
+
+    Ok(groups)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_identical_files() {
+        let dir = Path::new("tests/tmp_duplicate");
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir(dir).unwrap();
+        fs::write(dir.join("00243878.1.jpg"), "frame 1").unwrap();
+        fs::write(dir.join("00243878.2.jpg"), "frame 2").unwrap();
+        fs::write(dir.join("00243878.3.jpg"), "frame 1").unwrap();
+        fs::write(dir.join("00243344.1.jpg"), "frame 3").unwrap();
+
+        let files = crate::list_files(dir);
+        let duplicates = find_duplicates(dir, &files);
+
+        fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(
+            vec![vec![
+                "00243878.1.jpg".to_string(),
+                "00243878.3.jpg".to_string()
+            ]],
+            duplicates.unwrap()
+        );
+    }
+}
@@ -0,0 +1,404 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::filter::IGNORE_FILE_NAME;
+use crate::lock::{LockHolder, LOCK_FILE_NAME};
+use crate::{
+    ConflictError, CrowdedLot, Discrepancy, DuplicateRow, RollbackError, RowError, StrictError,
+    TemplateError,
+};
+
+#[derive(Debug)]
+pub enum RenameError {
+    // The delimited data file could not be parsed.
+    CsvParse(ParseError),
+    // The xlsx data file could not be read or lacks the requested sheet.
+    #[cfg(feature = "xlsx")]
+    Spreadsheet(calamine::XlsxError),
+    // The JSON data file is not an array of objects.
+    Json(serde_json::Error),
+    // The TOML or YAML data file is not a mapping of inventory numbers to lot
+    // numbers.
+    Toml(toml::de::Error),
+    Yaml(serde_yaml::Error),
+    // The SQLite database could not be opened or the query failed.
+    #[cfg(feature = "sqlite")]
+    Sqlite(rusqlite::Error),
+    // An object in the JSON data file lacks a configured key or its value is
+    // not a string or number. Objects are counted from 1.
+    MissingKey {
+        row: usize,
+        key: String,
+    },
+    // A row of the data file lacks a configured column. Rows are counted from 1,
+    // excluding the header.
+    MissingColumn {
+        row: usize,
+        column: usize,
+    },
+    // A column given by name is not in the header of the data file.
+    UnknownColumn(String),
+    // A data file could not be opened or read.
+    DataFile(PathBuf, std::io::Error),
+    // A data file has another header than the first data file.
+    MismatchedHeaders(PathBuf),
+    // Two data files give an inventory number different lot numbers.
+    DisagreeingDataFiles {
+        inventory: String,
+        first: (PathBuf, String),
+        second: (PathBuf, String),
+    },
+    // No date could be found for a file whose new name needs one.
+    MissingDate(String),
+    Io(std::io::Error),
+    // A file matched a row, but no photo number could be taken from its name.
+    MalformedFileName(String),
+    // The new name of a file is not a legal file name on all platforms.
+    IllegalName {
+        file: String,
+        name: String,
+        reason: &'static str,
+    },
+    Conflict(ConflictError),
+    Rollback(RollbackError),
+    Template(TemplateError),
+    // The naming script could not be loaded or failed for a file.
+    Script(Box<rhai::EvalAltResult>),
+    // An include, exclude or data file pattern is not a valid glob.
+    Glob(globset::Error),
+    // The ignore file of the directory cannot be read or holds an invalid
+    // pattern.
+    IgnoreFile(ignore::Error),
+    // The regex to rename files by is invalid.
+    Regex(regex::Error),
+    // The directory could not be watched for new files.
+    #[cfg(feature = "watch")]
+    Watch(notify::Error),
+    // The state file of an interrupted run was found in the directory, which
+    // must be resumed first.
+    InterruptedRun(String),
+    // Another run holds the lock of the directory. The holder is unknown if
+    // the lock file could not be read.
+    Locked {
+        dir: String,
+        holder: Option<LockHolder>,
+    },
+    // The history has no run with this id.
+    UnknownRun(i64),
+    // The run in the history with this id was undone already.
+    UndoneRun(i64),
+    // The options given do not make sense together, or a directory they name
+    // is not one.
+    InvalidConfig(String),
+    // Rows of the data file could not be used, which the policy for bad rows
+    // does not allow.
+    BadRows(Vec<RowError>),
+    // Lots have more files than the maximum per lot, which the policy for
+    // crowded lots does not allow.
+    CrowdedLots(Vec<CrowdedLot>),
+    // Rows of the data file give an inventory number different lot numbers,
+    // which the policy for duplicates does not resolve.
+    DuplicateRows(Vec<DuplicateRow>),
+    // After renaming, files were found missing under their new names or still
+    // under their old ones, as when another program wrote to the directory.
+    PostCheck(Vec<Discrepancy>),
+    // In strict mode, the plan has conflicts, unmatched files or rows,
+    // malformed file names or duplicate files.
+    Strict(Box<StrictError>),
+}
+
+impl fmt::Display for RenameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RenameError::CsvParse(err) => write!(f, "could not parse data file: {}", err),
+            #[cfg(feature = "xlsx")]
+            RenameError::Spreadsheet(err) => write!(f, "could not read spreadsheet: {}", err),
+            RenameError::Json(err) => write!(f, "could not parse data file: {}", err),
+            RenameError::Toml(err) => write!(f, "could not parse data file: {}", err),
+            RenameError::Yaml(err) => write!(f, "could not parse data file: {}", err),
+            #[cfg(feature = "sqlite")]
+            RenameError::Sqlite(err) => write!(f, "could not query database: {}", err),
+            RenameError::MissingKey { row, key } => write!(
+                f,
+                "malformed data file: object {} has no string or number {:?}",
+                row, key
+            ),
+            RenameError::MissingColumn { row, column } => write!(
+                f,
+                "malformed data file: row {} has no column {}",
+                row, column
+            ),
+            RenameError::UnknownColumn(name) => {
+                write!(f, "data file has no column named {:?}", name)
+            }
+            RenameError::DataFile(data_file, err) => {
+                write!(
+                    f,
+                    "could not read data file {}: {}",
+                    data_file.display(),
+                    err
+                )
+            }
+            RenameError::MismatchedHeaders(data_file) => write!(
+                f,
+                "data file {} has another header than the first data file",
+                data_file.display()
+            ),
+            RenameError::DisagreeingDataFiles {
+                inventory,
+                first,
+                second,
+            } => write!(
+                f,
+                "data files disagree about inventory number {}: lot {} in {}, lot {} in {}",
+                inventory,
+                first.1,
+                first.0.display(),
+                second.1,
+                second.0.display()
+            ),
+            RenameError::MissingDate(file) => write!(f, "no date found for {}", file),
+            RenameError::Io(err) => write!(f, "{}", err),
+            RenameError::MalformedFileName(file) => {
+                write!(f, "malformed file name {}: no photo number found", file)
+            }
+            RenameError::IllegalName { file, name, reason } => write!(
+                f,
+                "new name {:?} of {} is not a legal file name: {}",
+                name, file, reason
+            ),
+            RenameError::Conflict(err) => write!(f, "{}", err),
+            RenameError::Rollback(err) => write!(f, "{}", err),
+            RenameError::Template(err) => write!(f, "{}", err),
+            RenameError::Script(err) => write!(f, "naming script failed: {}", err),
+            RenameError::Glob(err) => write!(f, "invalid pattern: {}", err),
+            RenameError::IgnoreFile(err) => {
+                write!(f, "invalid {}: {}", IGNORE_FILE_NAME, err)
+            }
+            RenameError::Regex(err) => write!(f, "invalid regex: {}", err),
+            #[cfg(feature = "watch")]
+            RenameError::Watch(err) => write!(f, "could not watch directory: {}", err),
+            RenameError::InterruptedRun(dir) => write!(
+                f,
+                "a run in {} was interrupted; pass --resume to finish it",
+                dir
+            ),
+            RenameError::Locked { dir, holder } => {
+                match holder {
+                    Some(holder) => write!(f, "{} is locked by {}", dir, holder)?,
+                    None => write!(f, "{} is locked by another run", dir)?,
+                }
+                write!(
+                    f,
+                    "; if no run is going on, remove {} from it",
+                    LOCK_FILE_NAME
+                )
+            }
+            RenameError::UnknownRun(id) => write!(f, "the history has no run {}", id),
+            RenameError::UndoneRun(id) => write!(f, "run {} was undone already", id),
+            RenameError::InvalidConfig(reason) => write!(f, "{}", reason),
+            RenameError::BadRows(rows) => {
+                write!(f, "{} row(s) of the data file cannot be used:", rows.len())?;
+                for row in rows {
+                    write!(f, "\n  {}", row)?;
+                }
+                Ok(())
+            }
+            RenameError::CrowdedLots(lots) => {
+                write!(f, "{} lot(s) have too many files:", lots.len())?;
+                for lot in lots {
+                    write!(f, "\n  {}", lot)?;
+                }
+                Ok(())
+            }
+            RenameError::DuplicateRows(rows) => {
+                write!(
+                    f,
+                    "{} row(s) give an inventory number another lot; pass --on-duplicate first or last to choose:",
+                    rows.len()
+                )?;
+                for row in rows {
+                    write!(f, "\n  {}", row)?;
+                }
+                Ok(())
+            }
+            RenameError::PostCheck(discrepancies) => {
+                write!(
+                    f,
+                    "{} renaming(s) did not hold up after the run; did another program change the directory?",
+                    discrepancies.len()
+                )?;
+                for discrepancy in discrepancies {
+                    write!(f, "\n  {}", discrepancy)?;
+                }
+                Ok(())
+            }
+            RenameError::Strict(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for RenameError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RenameError::CsvParse(err) => Some(err),
+            #[cfg(feature = "xlsx")]
+            RenameError::Spreadsheet(err) => Some(err),
+            RenameError::Json(err) => Some(err),
+            RenameError::Toml(err) => Some(err),
+            RenameError::Yaml(err) => Some(err),
+            #[cfg(feature = "sqlite")]
+            RenameError::Sqlite(err) => Some(err),
+            RenameError::MissingKey { .. } => None,
+            RenameError::MissingColumn { .. } => None,
+            RenameError::UnknownColumn(_) => None,
+            RenameError::DataFile(_, err) => Some(err),
+            RenameError::MismatchedHeaders(_) => None,
+            RenameError::DisagreeingDataFiles { .. } => None,
+            RenameError::BadRows(_) => None,
+            RenameError::CrowdedLots(_) => None,
+            RenameError::DuplicateRows(_) => None,
+            RenameError::PostCheck(_) => None,
+            RenameError::MissingDate(_) => None,
+            RenameError::Io(err) => Some(err),
+            RenameError::MalformedFileName(_) => None,
+            RenameError::IllegalName { .. } => None,
+            RenameError::Conflict(err) => Some(err),
+            RenameError::Rollback(err) => Some(err),
+            RenameError::Template(err) => Some(err),
+            RenameError::Script(err) => Some(err.as_ref()),
+            RenameError::Glob(err) => Some(err),
+            RenameError::IgnoreFile(err) => Some(err),
+            RenameError::Regex(err) => Some(err),
+            #[cfg(feature = "watch")]
+            RenameError::Watch(err) => Some(err),
+            RenameError::InterruptedRun(_) => None,
+            RenameError::Locked { .. } => None,
+            RenameError::UnknownRun(_) => None,
+            RenameError::UndoneRun(_) => None,
+            RenameError::InvalidConfig(_) => None,
+            RenameError::Strict(err) => Some(err.as_ref()),
+        }
+    }
+}
+
+// ParseError is a record of a delimited data file that could not be parsed,
+// with the line it starts on, if known.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line: Option<u64>,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+// csv_error turns an error of the CSV reader into the error of a run, keeping
+// the reader's types out of the public interface. Reading errors stay I/O
+// errors.
+pub(crate) fn csv_error(err: csv::Error) -> RenameError {
+    let line = err.position().map(|position| position.line());
+    let message = err.to_string();
+    match err.into_kind() {
+        csv::ErrorKind::Io(err) => RenameError::Io(err),
+        _ => RenameError::CsvParse(ParseError { line, message }),
+    }
+}
+
+#[cfg(feature = "xlsx")]
+impl From<calamine::XlsxError> for RenameError {
+    fn from(err: calamine::XlsxError) -> RenameError {
+        RenameError::Spreadsheet(err)
+    }
+}
+
+impl From<serde_json::Error> for RenameError {
+    fn from(err: serde_json::Error) -> RenameError {
+        RenameError::Json(err)
+    }
+}
+
+impl From<toml::de::Error> for RenameError {
+    fn from(err: toml::de::Error) -> RenameError {
+        RenameError::Toml(err)
+    }
+}
+
+impl From<serde_yaml::Error> for RenameError {
+    fn from(err: serde_yaml::Error) -> RenameError {
+        RenameError::Yaml(err)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for RenameError {
+    fn from(err: rusqlite::Error) -> RenameError {
+        RenameError::Sqlite(err)
+    }
+}
+
+impl From<std::io::Error> for RenameError {
+    fn from(err: std::io::Error) -> RenameError {
+        RenameError::Io(err)
+    }
+}
+
+impl From<ConflictError> for RenameError {
+    fn from(err: ConflictError) -> RenameError {
+        RenameError::Conflict(err)
+    }
+}
+
+impl From<StrictError> for RenameError {
+    fn from(err: StrictError) -> RenameError {
+        RenameError::Strict(Box::new(err))
+    }
+}
+
+impl From<RollbackError> for RenameError {
+    fn from(err: RollbackError) -> RenameError {
+        RenameError::Rollback(err)
+    }
+}
+
+impl From<TemplateError> for RenameError {
+    fn from(err: TemplateError) -> RenameError {
+        RenameError::Template(err)
+    }
+}
+
+impl From<Box<rhai::EvalAltResult>> for RenameError {
+    fn from(err: Box<rhai::EvalAltResult>) -> RenameError {
+        RenameError::Script(err)
+    }
+}
+
+impl From<globset::Error> for RenameError {
+    fn from(err: globset::Error) -> RenameError {
+        RenameError::Glob(err)
+    }
+}
+
+impl From<ignore::Error> for RenameError {
+    fn from(err: ignore::Error) -> RenameError {
+        RenameError::IgnoreFile(err)
+    }
+}
+
+impl From<regex::Error> for RenameError {
+    fn from(err: regex::Error) -> RenameError {
+        RenameError::Regex(err)
+    }
+}
+
+#[cfg(feature = "watch")]
+impl From<notify::Error> for RenameError {
+    fn from(err: notify::Error) -> RenameError {
+        RenameError::Watch(err)
+    }
+}
@@ -0,0 +1,132 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::operation::Transfer;
+use crate::progress::Progress;
+
+// The result of a single renaming, or None if it was not attempted because
+// another renaming failed first.
+pub type Outcome = Option<std::io::Result<()>>;
+
+// rename_sequentially renames the files one by one and stops at the first
+// failure, other than of a file the transfer skips.
+pub fn rename_sequentially(
+    transfer: &Transfer,
+    renamings: &[(&String, &String)],
+    progress: &Progress,
+) -> Vec<Outcome> {
+    let mut outcomes: Vec<Outcome> = renamings.iter().map(|_| None).collect();
+
+    for (i, (old_name, new_name)) in renamings.iter().enumerate() {
+        progress
+            .suspend(|| log::info!("{} {} to {}", transfer.operation.verb(), old_name, new_name));
+        let outcome = transfer.apply(old_name, new_name);
+        progress.inc(1);
+        let failed = outcome.as_ref().is_err_and(|err| !transfer.skips(err));
+        outcomes[i] = Some(outcome);
+
+        if failed {
+            break;
+        }
+    }
+
+    outcomes
+}
+
+// rename_in_parallel distributes the renamings over a number of worker threads,
+// which stop picking up new renamings once any of them fails, other than on a
+// file the transfer skips. The renamings are reported in their given order
+// once all workers are done.
+pub fn rename_in_parallel(
+    transfer: &Transfer,
+    renamings: &[(&String, &String)],
+    jobs: usize,
+    progress: &Progress,
+) -> Vec<Outcome> {
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let outcomes: Vec<Mutex<Outcome>> = renamings.iter().map(|_| Mutex::new(None)).collect();
+
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| {
+                while !failed.load(Ordering::SeqCst) {
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+                    let Some((old_name, new_name)) = renamings.get(i) else {
+                        break;
+                    };
+
+                    let outcome = transfer.apply(old_name, new_name);
+                    progress.inc(1);
+                    if outcome.as_ref().is_err_and(|err| !transfer.skips(err)) {
+                        failed.store(true, Ordering::SeqCst);
+                    }
+                    *outcomes[i].lock().unwrap() = Some(outcome);
+                }
+            });
+        }
+    });
+
+    let outcomes: Vec<Outcome> = outcomes
+        .into_iter()
+        .map(|outcome| outcome.into_inner().unwrap())
+        .collect();
+
+    for ((old_name, new_name), outcome) in renamings.iter().zip(&outcomes) {
+        if outcome.is_some() {
+            log::info!("{} {} to {}", transfer.operation.verb(), old_name, new_name);
+        }
+    }
+
+    outcomes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operation::Operation;
+    use crate::retry::Retry;
+    use crate::storage::LocalStorage;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn progress_counts_processed_files() {
+        let dir = Path::new("tests/tmp_progress");
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir(dir).unwrap();
+        fs::copy("tests/files/00243878.1.jpg", dir.join("00243878.1.jpg")).unwrap();
+        fs::copy("tests/files/00243878.2.jpg", dir.join("00243878.2.jpg")).unwrap();
+
+        let transfer = Transfer {
+            storage: &LocalStorage::default(),
+            source: dir,
+            target: dir,
+            operation: Operation::Copy,
+            verify: false,
+            overwrite: false,
+            backup: None,
+            state: None,
+            audit: None,
+            retry: Retry::default(),
+            skip_locked: false,
+        };
+        let names: Vec<(String, String)> = vec![
+            ("00243878.1.jpg".to_string(), "1_1.jpg".to_string()),
+            ("00243878.2.jpg".to_string(), "1_2.jpg".to_string()),
+        ];
+        let renamings: Vec<(&String, &String)> =
+            names.iter().map(|(old, new)| (old, new)).collect();
+
+        let sequential = Progress::new(1, false);
+        rename_sequentially(&transfer, &renamings[..1], &sequential);
+        let parallel = Progress::new(1, false);
+        rename_in_parallel(&transfer, &renamings[1..], 2, &parallel);
+
+        fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(1, sequential.position());
+        assert_eq!(1, parallel.position());
+    }
+}
@@ -0,0 +1,117 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::path::Path;
+
+// The ignore file lists files in the directory that never take part, such as
+// proof sheets and scratch files, in the syntax of .gitignore.
+pub const IGNORE_FILE_NAME: &str = ".renameignore";
+
+// FileFilter decides which files in the directory take part in the renaming.
+pub struct FileFilter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+    ignore: Option<Gitignore>,
+}
+
+impl FileFilter {
+    // new accepts glob patterns such as `*.jpg`. Without include patterns, all
+    // files not excluded take part.
+    pub fn new(include: &[String], exclude: &[String]) -> Result<FileFilter, globset::Error> {
+        let include = if include.is_empty() {
+            None
+        } else {
+            Some(build_glob_set(include)?)
+        };
+
+        Ok(FileFilter {
+            include,
+            exclude: build_glob_set(exclude)?,
+            ignore: None,
+        })
+    }
+
+    // ignore_file also leaves out the files that the ignore file in the
+    // directory lists, if it has one.
+    pub fn ignore_file(mut self, dir: &Path) -> Result<FileFilter, ignore::Error> {
+        let path = dir.join(IGNORE_FILE_NAME);
+        if path.is_file() {
+            let mut builder = GitignoreBuilder::new(dir);
+            if let Some(err) = builder.add(&path) {
+                return Err(err);
+            }
+            self.ignore = Some(builder.build()?);
+        }
+
+        Ok(self)
+    }
+
+    pub fn matches(&self, file_name: &str) -> bool {
+        let included = match &self.include {
+            Some(include) => include.is_match(file_name),
+            None => true,
+        };
+
+        let ignored = self.ignore.as_ref().is_some_and(|ignore| {
+            ignore
+                .matched_path_or_any_parents(file_name, false)
+                .is_ignore()
+        });
+
+        included && !self.exclude.is_match(file_name) && !ignored
+    }
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet, globset::Error> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern)?);
+    }
+
+    builder.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn include_and_exclude() {
+        let filter = FileFilter::new(
+            &["*.jpg".to_string(), "*.tif".to_string()],
+            &["*_thumb.*".to_string()],
+        )
+        .unwrap();
+
+        assert!(filter.matches("00243344.1.jpg"));
+        assert!(filter.matches("00243344.2.tif"));
+        assert!(!filter.matches("00243344.1_thumb.jpg"));
+        assert!(!filter.matches(".DS_Store"));
+
+        let filter = FileFilter::new(&[], &[".*".to_string()]).unwrap();
+        assert!(filter.matches("00243344.1.jpg"));
+        assert!(!filter.matches(".DS_Store"));
+    }
+
+    #[test]
+    fn respect_ignore_file() {
+        let dir = Path::new("tests/tmp_ignore_file");
+        let _ = std::fs::remove_dir_all(dir);
+        std::fs::create_dir(dir).unwrap();
+        std::fs::write(
+            dir.join(IGNORE_FILE_NAME),
+            "# Not photos\nproofsheet.pdf\n*~\nthumbs/\n!keep~\n",
+        )
+        .unwrap();
+
+        let filter = FileFilter::new(&[], &[]).unwrap().ignore_file(dir);
+
+        std::fs::remove_dir_all(dir).unwrap();
+
+        let filter = filter.unwrap();
+        assert!(filter.matches("00243344.1.jpg"));
+        assert!(!filter.matches("proofsheet.pdf"));
+        assert!(!filter.matches("00243344.1.jpg~"));
+        assert!(!filter.matches("thumbs/00243344.1.jpg"));
+        assert!(filter.matches("keep~"));
+    }
+}
@@ -0,0 +1,343 @@
+use rusqlite::{Connection, OptionalExtension, Row};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::journal::{Journal, JournalEntry};
+use crate::{Operation, RenameError, Renaming};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    time TEXT NOT NULL,
+    operation TEXT NOT NULL,
+    source_dir TEXT NOT NULL,
+    target_dir TEXT,
+    undone TEXT
+);
+CREATE TABLE IF NOT EXISTS renamings (
+    run INTEGER NOT NULL REFERENCES runs (id),
+    old TEXT NOT NULL,
+    new TEXT NOT NULL
+);
+";
+
+// HistoryRun is a run recorded in the history.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryRun {
+    pub id: i64,
+    // When the run finished, in RFC 3339 and UTC.
+    pub time: String,
+    pub operation: Operation,
+    pub source_dir: String,
+    // The directory holding the new names, if not the source directory.
+    pub target_dir: Option<String>,
+    // The number of files the run renamed.
+    pub files: usize,
+    // When the run was undone, if it was.
+    pub undone: Option<String>,
+}
+
+impl fmt::Display for HistoryRun {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}  {}  {} {} file(s) in {}",
+            self.id,
+            self.time,
+            self.operation.name(),
+            self.files,
+            self.source_dir
+        )?;
+        if let Some(target_dir) = &self.target_dir {
+            write!(f, " to {}", target_dir)?;
+        }
+        if let Some(undone) = &self.undone {
+            write!(f, ", undone {}", undone)?;
+        }
+
+        Ok(())
+    }
+}
+
+// record adds a run that renamed files from source_dir to target_dir to the
+// history at path, which is created if it does not exist, and returns its id.
+pub fn record(
+    path: &Path,
+    operation: Operation,
+    source_dir: &Path,
+    target_dir: &Path,
+    renamed: &[Renaming],
+) -> Result<i64, RenameError> {
+    let source_dir = fs::canonicalize(source_dir)?;
+    let target_dir = fs::canonicalize(target_dir)?;
+    let target_dir = (target_dir != source_dir).then(|| target_dir.to_string_lossy().into_owned());
+
+    let mut connection = open(path)?;
+    let transaction = connection.transaction()?;
+    transaction.execute(
+        "INSERT INTO runs (time, operation, source_dir, target_dir) VALUES (?1, ?2, ?3, ?4)",
+        (
+            now(),
+            operation.name(),
+            source_dir.to_string_lossy().into_owned(),
+            target_dir,
+        ),
+    )?;
+    let id = transaction.last_insert_rowid();
+    {
+        let mut insert =
+            transaction.prepare("INSERT INTO renamings (run, old, new) VALUES (?1, ?2, ?3)")?;
+        for renaming in renamed {
+            insert.execute((id, &renaming.old, &renaming.new))?;
+        }
+    }
+    transaction.commit()?;
+
+    Ok(id)
+}
+
+// list_runs lists the runs in the history at path, the earliest first.
+pub fn list_runs(path: &Path) -> Result<Vec<HistoryRun>, RenameError> {
+    let connection = open(path)?;
+    let mut query = connection.prepare(&format!("{} ORDER BY id", SELECT_RUNS))?;
+    let runs = query
+        .query_map((), history_run)?
+        .collect::<Result<_, _>>()?;
+
+    Ok(runs)
+}
+
+// show_run finds the run in the history at path by its id, with the renamings
+// it made.
+pub fn show_run(path: &Path, id: i64) -> Result<(HistoryRun, Vec<Renaming>), RenameError> {
+    read_run(&open(path)?, id)
+}
+
+// undo_run reverts the renamings of a run in the history at path, however
+// long ago it was, and marks it as undone. Every file must still have the
+// name the run gave it; if one has not, none are reverted. The renamings are
+// also taken out of the journal of the directory, so that `undo` does not
+// revert them again.
+pub fn undo_run(path: &Path, id: i64) -> Result<(), RenameError> {
+    let connection = open(path)?;
+    let (run, renamings) = read_run(&connection, id)?;
+    if run.undone.is_some() {
+        return Err(RenameError::UndoneRun(id));
+    }
+
+    let dir = Path::new(&run.source_dir);
+    let target_dir = run.target_dir.as_deref().map_or(dir, Path::new);
+    let _locks = crate::lock::acquire_all([dir, target_dir], &[])?;
+    if let Some(missing) = renamings
+        .iter()
+        .find(|renaming| fs::symlink_metadata(target_dir.join(&renaming.new)).is_err())
+    {
+        return Err(RenameError::Io(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!(
+                "{} no longer has the name run {} gave it, so the run cannot be undone",
+                missing.new, id
+            ),
+        )));
+    }
+
+    let journal = Journal {
+        operation: run.operation,
+        target_dir: run.target_dir,
+        renamings: renamings
+            .into_iter()
+            .map(|renaming| JournalEntry {
+                old: renaming.old,
+                new: renaming.new,
+            })
+            .collect(),
+    };
+    crate::revert(dir, &journal)?;
+    journal.forget(dir)?;
+    connection.execute("UPDATE runs SET undone = ?1 WHERE id = ?2", (now(), id))?;
+
+    Ok(())
+}
+
+// renamed_in lists the names that the runs in the history at path gave files
+// in place in dir, leaving out the runs that were undone.
+pub fn renamed_in(path: &Path, dir: &Path) -> Result<BTreeSet<String>, RenameError> {
+    let dir = fs::canonicalize(dir)?;
+    let connection = open(path)?;
+    let mut query = connection.prepare(
+        "SELECT new FROM renamings JOIN runs ON renamings.run = runs.id
+        WHERE runs.source_dir = ?1 AND runs.target_dir IS NULL
+            AND runs.operation = ?2 AND runs.undone IS NULL",
+    )?;
+    let names = query
+        .query_map(
+            (dir.to_string_lossy().into_owned(), Operation::Rename.name()),
+            |row| row.get(0),
+        )?
+        .collect::<Result<_, _>>()?;
+
+    Ok(names)
+}
+
+// expand_home puts the home directory in place of a leading `~`, which the
+// shell leaves alone in the default path of the history.
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), std::env::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+// open opens the history at path, creating it and its directory if need be.
+fn open(path: &Path) -> Result<Connection, RenameError> {
+    let path = expand_home(path);
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    let connection = Connection::open(path)?;
+    connection.execute_batch(SCHEMA)?;
+
+    Ok(connection)
+}
+
+const SELECT_RUNS: &str = "
+SELECT id, time, operation, source_dir, target_dir, undone,
+    (SELECT COUNT(*) FROM renamings WHERE run = runs.id)
+FROM runs";
+
+// read_run reads the run with the id and its renamings, in the order they
+// were recorded.
+fn read_run(connection: &Connection, id: i64) -> Result<(HistoryRun, Vec<Renaming>), RenameError> {
+    let run = connection
+        .query_row(&format!("{} WHERE id = ?1", SELECT_RUNS), [id], history_run)
+        .optional()?
+        .ok_or(RenameError::UnknownRun(id))?;
+    let mut query =
+        connection.prepare("SELECT old, new FROM renamings WHERE run = ?1 ORDER BY rowid")?;
+    let renamings = query
+        .query_map([id], |row| {
+            Ok(Renaming {
+                old: row.get(0)?,
+                new: row.get(1)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+
+    Ok((run, renamings))
+}
+
+fn history_run(row: &Row) -> rusqlite::Result<HistoryRun> {
+    let operation: String = row.get(2)?;
+    let operation = [
+        Operation::Rename,
+        Operation::Copy,
+        Operation::HardLink,
+        Operation::Symlink,
+    ]
+    .into_iter()
+    .find(|known| known.name() == operation)
+    .ok_or_else(|| {
+        rusqlite::Error::FromSqlConversionFailure(
+            2,
+            rusqlite::types::Type::Text,
+            format!("unknown operation {:?}", operation).into(),
+        )
+    })?;
+
+    Ok(HistoryRun {
+        id: row.get(0)?,
+        time: row.get(1)?,
+        operation,
+        source_dir: row.get(3)?,
+        target_dir: row.get(4)?,
+        undone: row.get(5)?,
+        files: row.get::<_, i64>(6)? as usize,
+    })
+}
+
+fn now() -> String {
+    humantime::format_rfc3339_seconds(SystemTime::now()).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_list_and_undo_runs() {
+        let history = Path::new("tests/tmp_history/history.db");
+        let dir = Path::new("tests/tmp_history_dir");
+        let _ = fs::remove_dir_all("tests/tmp_history");
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir(dir).unwrap();
+        for name in ["1_1.jpg", "1_2.jpg", "2_1.jpg"] {
+            fs::write(dir.join(name), name).unwrap();
+        }
+        let renamed = |pairs: &[(&str, &str)]| -> Vec<Renaming> {
+            pairs
+                .iter()
+                .map(|(old, new)| Renaming {
+                    old: old.to_string(),
+                    new: new.to_string(),
+                })
+                .collect()
+        };
+        let first = renamed(&[("00243878.1.jpg", "1_1.jpg"), ("00243878.2.jpg", "1_2.jpg")]);
+        let second = renamed(&[("00243880.1.jpg", "2_1.jpg")]);
+        let mut journal = Journal::default();
+        for renaming in first.iter().chain(&second) {
+            journal.record(&renaming.old, &renaming.new);
+        }
+        journal.write(dir).unwrap();
+
+        let first_id = record(history, Operation::Rename, dir, dir, &first).unwrap();
+        let second_id = record(history, Operation::Rename, dir, dir, &second).unwrap();
+        undo_run(history, first_id).unwrap();
+        let again = undo_run(history, first_id);
+        let unknown = undo_run(history, 42);
+
+        let renamed = renamed_in(history, dir).unwrap();
+        let runs = list_runs(history).unwrap();
+        let (shown, renamings) = show_run(history, second_id).unwrap();
+        let files = crate::list_files(dir);
+        let left = Journal::read(dir).unwrap();
+
+        fs::remove_dir_all("tests/tmp_history").unwrap();
+        fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(BTreeSet::from(["2_1.jpg".to_string()]), renamed);
+        assert_eq!(2, runs.len());
+        assert_eq!(2, runs[0].files);
+        assert!(runs[0].undone.is_some());
+        assert_eq!(None, runs[0].target_dir);
+        assert!(runs[0].source_dir.ends_with("tmp_history_dir"));
+        assert_eq!(runs[1], shown);
+        assert_eq!(second, renamings);
+        assert_eq!(
+            vec!["00243878.1.jpg", "00243878.2.jpg", "2_1.jpg"],
+            files[1..]
+        );
+        assert_eq!(1, left.renamings.len());
+        assert!(matches!(again, Err(RenameError::UndoneRun(_))));
+        assert!(matches!(unknown, Err(RenameError::UnknownRun(42))));
+    }
+
+    #[test]
+    fn expand_home_directory() {
+        let Some(home) = std::env::home_dir() else {
+            return;
+        };
+
+        assert_eq!(
+            home.join(".rename/history.db"),
+            expand_home(Path::new(crate::DEFAULT_HISTORY))
+        );
+        assert_eq!(
+            PathBuf::from("history.db"),
+            expand_home(Path::new("history.db"))
+        );
+    }
+}
@@ -0,0 +1,199 @@
+use serde::Serialize;
+use std::fmt;
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+use image::{ImageFormat, ImageReader};
+
+// The formats photos are expected in.
+const FORMATS: [ImageFormat; 3] = [ImageFormat::Jpeg, ImageFormat::Png, ImageFormat::Tiff];
+
+// check_image decodes the file as a whole, so that a truncated or otherwise
+// corrupt photo is caught before it is published under a clean name. The
+// format is taken from the contents of the file, not its extension.
+pub fn check_image(path: &Path) -> Result<(), String> {
+    let bytes = fs::read(path).map_err(|err| err.to_string())?;
+    let reader = ImageReader::new(Cursor::new(&bytes))
+        .with_guessed_format()
+        .map_err(|err| err.to_string())?;
+    let format = match reader.format() {
+        Some(format) if FORMATS.contains(&format) => format,
+        _ => return Err(String::from("not a JPEG, PNG or TIFF image")),
+    };
+    reader.decode().map_err(|err| err.to_string())?;
+
+    // The JPEG decoder fills in missing data rather than failing, so a JPEG
+    // cut short is only told by its lacking end marker.
+    if format == ImageFormat::Jpeg && !ends_jpeg(&bytes) {
+        return Err(String::from("the JPEG data is truncated"));
+    }
+
+    Ok(())
+}
+
+// ends_jpeg tells whether the data ends with the end of image marker of JPEG,
+// allowing for padding after it.
+fn ends_jpeg(bytes: &[u8]) -> bool {
+    let end = bytes
+        .iter()
+        .rposition(|&byte| byte != 0)
+        .map_or(0, |last| last + 1);
+
+    bytes[..end].ends_with(&[0xff, 0xd9])
+}
+
+// find_corrupt_images lists the files in the directory that are not decodable
+// images, in the order they were given.
+pub fn find_corrupt_images<'a>(
+    dir: &Path,
+    files: impl IntoIterator<Item = &'a String>,
+) -> Vec<String> {
+    files
+        .into_iter()
+        .filter(|file| match check_image(&dir.join(file)) {
+            Ok(()) => false,
+            Err(err) => {
+                log::debug!("{} is not a valid image: {}", file, err);
+                true
+            }
+        })
+        .cloned()
+        .collect()
+}
+
+// Dimensions are the width and height of an image in pixels, written as
+// `1600x1200`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Dimensions {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl std::str::FromStr for Dimensions {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<Dimensions, &'static str> {
+        let error = "dimensions must be a width and height in pixels, e.g. 1600x1200";
+        let (width, height) = value.split_once(['x', 'X']).ok_or(error)?;
+
+        Ok(Dimensions {
+            width: width.trim().parse().map_err(|_| error)?,
+            height: height.trim().parse().map_err(|_| error)?,
+        })
+    }
+}
+
+impl fmt::Display for Dimensions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+impl Dimensions {
+    // covers tells whether an image of these dimensions is at least as large
+    // as min in either orientation, so that a portrait photo meets a minimum
+    // written for landscape ones: the longer and shorter sides are compared.
+    pub fn covers(self, min: Dimensions) -> bool {
+        let sides = |d: Dimensions| (d.width.max(d.height), d.width.min(d.height));
+        let (long, short) = sides(self);
+        let (min_long, min_short) = sides(min);
+
+        long >= min_long && short >= min_short
+    }
+}
+
+// read_dimensions reads the dimensions of the image from its header, without
+// decoding the image.
+pub fn read_dimensions(path: &Path) -> Result<Dimensions, String> {
+    let (width, height) = ImageReader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|err| err.to_string())?
+        .into_dimensions()
+        .map_err(|err| err.to_string())?;
+
+    Ok(Dimensions { width, height })
+}
+
+// find_small_images lists the images in the directory that are smaller than
+// min, with their dimensions, in the order they were given. Files whose
+// dimensions cannot be read are left to verifying images.
+pub fn find_small_images<'a>(
+    dir: &Path,
+    files: impl IntoIterator<Item = &'a String>,
+    min: Dimensions,
+) -> Vec<(String, Dimensions)> {
+    files
+        .into_iter()
+        .filter_map(|file| match read_dimensions(&dir.join(file)) {
+            Ok(dimensions) if !dimensions.covers(min) => Some((file.clone(), dimensions)),
+            Ok(_) => None,
+            Err(err) => {
+                log::debug!("could not read the dimensions of {}: {}", file, err);
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn find_truncated_and_foreign_files() {
+        let dir = Path::new("tests/tmp_image_check");
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir(dir).unwrap();
+        let photo = fs::read("tests/files/00243344.1.jpg").unwrap();
+        fs::write(dir.join("whole.jpg"), &photo).unwrap();
+        fs::write(dir.join("truncated.jpg"), &photo[..photo.len() / 2]).unwrap();
+        fs::write(dir.join("notes.jpg"), b"not a photo").unwrap();
+
+        let files = vec![
+            "notes.jpg".to_string(),
+            "truncated.jpg".to_string(),
+            "whole.jpg".to_string(),
+        ];
+        let corrupt = find_corrupt_images(dir, &files);
+
+        fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(vec!["notes.jpg", "truncated.jpg"], corrupt);
+    }
+
+    #[test]
+    fn find_images_below_minimum_dimensions() {
+        let min: Dimensions = "1200x1100".parse().unwrap();
+        assert_eq!(
+            Dimensions {
+                width: 1200,
+                height: 1100
+            },
+            min
+        );
+        assert!("1200".parse::<Dimensions>().is_err());
+        assert!("wide x tall".parse::<Dimensions>().is_err());
+        // Portrait photos are held to the minimum turned on its side.
+        assert!(Dimensions {
+            width: 1100,
+            height: 1200
+        }
+        .covers(min));
+
+        let files = ["00243878.1.jpg", "00243878.2.jpg", "notes.txt"].map(String::from);
+        let small = find_small_images(Path::new("tests/files"), &files, min);
+
+        assert_eq!(
+            vec![(
+                "00243878.1.jpg".to_string(),
+                Dimensions {
+                    width: 1148,
+                    height: 1011
+                }
+            )],
+            small
+        );
+    }
+}
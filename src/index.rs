@@ -0,0 +1,74 @@
+use crate::Matching;
+
+// FileIndex finds the files of an inventory number without scanning the whole
+// directory for every row. It keeps the names sorted in the form they are
+// compared in, so that the names starting with a number sit next to each
+// other and are found by a binary search.
+pub struct FileIndex<'a> {
+    files: Vec<String>,
+    // The comparable form of each name with its position in files, sorted.
+    names: Vec<(String, usize)>,
+    matching: &'a Matching,
+}
+
+impl<'a> FileIndex<'a> {
+    pub fn new(files: Vec<String>, matching: &'a Matching) -> FileIndex<'a> {
+        let mut names: Vec<(String, usize)> = files
+            .iter()
+            .enumerate()
+            .map(|(position, file)| (matching.comparable(crate::base_name(file)), position))
+            .collect();
+        names.sort();
+
+        FileIndex {
+            files,
+            names,
+            matching,
+        }
+    }
+
+    // files_of finds the files whose names start with the inventory number, in
+    // the order they were given.
+    pub fn files_of(&self, object_id: &str) -> Vec<String> {
+        let prefix = self.matching.comparable(object_id);
+        let start = self
+            .names
+            .partition_point(|(name, _)| name.as_str() < prefix.as_str());
+
+        let mut positions: Vec<usize> = self.names[start..]
+            .iter()
+            .take_while(|(name, _)| name.starts_with(&prefix))
+            .filter(|(name, _)| self.matching.ends_number(&name[prefix.len()..]))
+            .map(|&(_, position)| position)
+            .collect();
+        positions.sort_unstable();
+
+        positions
+            .into_iter()
+            .map(|position| self.files[position].to_owned())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_files_by_prefix() {
+        let files: Vec<String> = (0..1000)
+            .map(|number| format!("{:08}.1.jpg", 243000 + number))
+            .chain([String::from("00243344"), String::from("00243344.2.jpg")])
+            .collect();
+        let matching = Matching::default();
+        let index = FileIndex::new(files, &matching);
+
+        assert_eq!(
+            vec!["00243344.1.jpg", "00243344", "00243344.2.jpg"],
+            index.files_of("00243344")
+        );
+        assert_eq!(12, index.files_of("0024334").len());
+        assert!(index.files_of("00245").is_empty());
+        assert_eq!(1002, index.files_of("").len());
+    }
+}
@@ -0,0 +1,131 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::operation::Operation;
+
+// The journal lives in the renamed directory, so a later undo only needs the directory path.
+pub const JOURNAL_FILE_NAME: &str = ".rename-journal.json";
+
+// The history keeps the runs of all directories, so it lives in the home directory.
+pub const DEFAULT_HISTORY: &str = "~/.rename/history.db";
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Journal {
+    #[serde(default)]
+    pub operation: Operation,
+    // The directory holding the new names, if not the journal's own directory.
+    #[serde(default)]
+    pub target_dir: Option<String>,
+    pub renamings: Vec<JournalEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub old: String,
+    pub new: String,
+}
+
+impl Journal {
+    pub fn record(&mut self, old: &str, new: &str) {
+        self.renamings.push(JournalEntry {
+            old: old.to_owned(),
+            new: new.to_owned(),
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.renamings.is_empty()
+    }
+
+    pub fn write(&self, dir: &Path) -> std::io::Result<()> {
+        let contents = serde_json::to_string_pretty(self)?;
+        fs::write(dir.join(JOURNAL_FILE_NAME), contents)
+    }
+
+    // append writes the journal after the renamings of an earlier journal in
+    // dir with the same operation and target, so that undo reverts both.
+    pub fn append(&self, dir: &Path) -> std::io::Result<()> {
+        match Journal::read(dir) {
+            Ok(mut earlier)
+                if earlier.operation == self.operation && earlier.target_dir == self.target_dir =>
+            {
+                earlier.renamings.extend(self.renamings.iter().cloned());
+                earlier.write(dir)
+            }
+            _ => self.write(dir),
+        }
+    }
+
+    // forget takes the renamings of this journal out of the journal in dir,
+    // which is removed once none are left.
+    #[cfg(feature = "sqlite")]
+    pub fn forget(&self, dir: &Path) -> std::io::Result<()> {
+        match Journal::read(dir) {
+            Ok(mut current)
+                if current.operation == self.operation && current.target_dir == self.target_dir =>
+            {
+                current
+                    .renamings
+                    .retain(|entry| !self.renamings.contains(entry));
+                if current.is_empty() {
+                    Journal::remove(dir)
+                } else {
+                    current.write(dir)
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn read(dir: &Path) -> std::io::Result<Journal> {
+        let contents = fs::read_to_string(dir.join(JOURNAL_FILE_NAME))?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    pub fn remove(dir: &Path) -> std::io::Result<()> {
+        fs::remove_file(dir.join(JOURNAL_FILE_NAME))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_and_read_journal() {
+        let dir = Path::new("tests/tmp_journal");
+        let _ = fs::create_dir(dir);
+
+        let mut journal = Journal::default();
+        journal.record("00243878.1.jpg", "1_1.jpg");
+        journal.record("00243878.2.jpg", "1_2.jpg");
+        journal.write(dir).unwrap();
+
+        let read = Journal::read(dir).unwrap();
+
+        fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(journal, read);
+    }
+
+    #[test]
+    fn append_to_journal() {
+        let dir = Path::new("tests/tmp_journal_append");
+        let _ = fs::create_dir(dir);
+
+        let mut first = Journal::default();
+        first.record("00243878.1.jpg", "1_1.jpg");
+        first.append(dir).unwrap();
+        let mut second = Journal::default();
+        second.record("00243878.2.jpg", "1_2.jpg");
+        second.append(dir).unwrap();
+
+        let read = Journal::read(dir).unwrap();
+
+        fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(2, read.renamings.len());
+        assert_eq!("1_2.jpg", read.renamings[1].new);
+    }
+}
@@ -0,0 +1,117 @@
+// Windows is the strictest of the platforms the photos end up on, so new names
+// are held to its rules.
+
+// Characters Windows does not allow in file names, besides control characters.
+const ILLEGAL_CHARS: &[char] = &['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+// Names of devices, which Windows does not allow as file names, also with an
+// extension.
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+// The longest file name, in bytes, that all common file systems allow.
+pub const MAX_LENGTH: usize = 255;
+
+// check tells why the name is not a legal file name, if it is not.
+pub fn check(name: &str) -> Result<(), &'static str> {
+    if name.is_empty() {
+        return Err("the name is empty");
+    }
+    if name.chars().any(is_illegal) {
+        return Err("the name holds a character that is not allowed, such as : or ?");
+    }
+    if is_reserved(name) {
+        return Err("the name is reserved for a device, such as CON or NUL");
+    }
+    if name.ends_with(['.', ' ']) {
+        return Err("the name ends in a dot or space");
+    }
+    if name.len() > MAX_LENGTH {
+        return Err("the name is longer than 255 bytes");
+    }
+
+    Ok(())
+}
+
+// sanitize makes the name legal: illegal characters become underscores,
+// trailing dots and spaces are removed, an underscore is added to reserved
+// names and long names are shortened, keeping their extension.
+pub fn sanitize(name: &str) -> String {
+    let mut name: String = name
+        .chars()
+        .map(|c| if is_illegal(c) { '_' } else { c })
+        .collect();
+
+    name.truncate(name.trim_end_matches(['.', ' ']).len());
+
+    if is_reserved(&name) {
+        let stem_length = name.find('.').unwrap_or(name.len());
+        name.insert(stem_length, '_');
+    }
+
+    if name.len() > MAX_LENGTH {
+        let (stem, extension) = match name.rfind('.') {
+            Some(dot) if name.len() - dot < MAX_LENGTH => name.split_at(dot),
+            _ => (name.as_str(), ""),
+        };
+        let mut stem_length = MAX_LENGTH - extension.len();
+        while !stem.is_char_boundary(stem_length) {
+            stem_length -= 1;
+        }
+        name = format!("{}{}", &stem[..stem_length], extension);
+    }
+
+    if name.is_empty() {
+        name.push('_');
+    }
+
+    name
+}
+
+fn is_illegal(c: char) -> bool {
+    c.is_control() || ILLEGAL_CHARS.contains(&c)
+}
+
+// is_reserved tells whether the part of the name before the first dot is the
+// name of a device.
+fn is_reserved(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or_default();
+    RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(stem.trim_end()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_and_sanitize_names() {
+        assert!(check("12_1.jpg").is_ok());
+        assert!(check("Café 12.jpg").is_ok());
+
+        let long_name = format!("{}.jpg", "a".repeat(300));
+        for (name, sanitized) in [
+            ("12:1?.jpg", "12_1_.jpg"),
+            ("a/b\\c.jpg", "a_b_c.jpg"),
+            ("tab\t.jpg", "tab_.jpg"),
+            ("con.jpg", "con_.jpg"),
+            ("NUL", "NUL_"),
+            ("LPT1.tar.gz", "LPT1_.tar.gz"),
+            ("12_1.jpg. ", "12_1.jpg"),
+            ("...", "_"),
+            (long_name.as_str(), &format!("{}.jpg", "a".repeat(251))),
+        ] {
+            assert!(check(name).is_err(), "{:?} should be illegal", name);
+            assert_eq!(sanitized, sanitize(name));
+            assert!(check(sanitized).is_ok(), "{:?} should be legal", sanitized);
+        }
+
+        // Multibyte characters are not cut in half.
+        let long_name = format!("{}.jpg", "é".repeat(200));
+        assert_eq!(format!("{}.jpg", "é".repeat(125)), sanitize(&long_name));
+        assert!(check("COM10.jpg").is_ok());
+    }
+}
@@ -1,283 +1,4713 @@
-use csv;
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-pub fn run(config: Config) -> Result<(), Box<dyn std::error::Error>> {
-    let file_names = list_files(&config.dir);
+use regex::Regex;
 
-    let csv_rows = read_csv(&config.data_file)?;
+mod audit;
+mod backup;
+mod builder;
+#[cfg(feature = "capi")]
+mod capi;
+mod capture;
+mod condition;
+mod conflict;
+mod date;
+mod duplicate;
+mod error;
+mod executor;
+mod filter;
+#[cfg(feature = "sqlite")]
+mod history;
+mod image_check;
+mod index;
+mod journal;
+mod legality;
+mod lock;
+mod long_path;
+mod lots;
+mod mapping;
+mod merge;
+mod operation;
+mod options;
+mod parts;
+mod plan;
+mod post_check;
+mod preview;
+mod progress;
+#[cfg(feature = "python")]
+mod python;
+mod reader;
+mod report;
+mod retry;
+mod rollback;
+#[cfg(feature = "s3")]
+mod s3;
+mod script;
+#[cfg(feature = "sftp")]
+mod sftp;
+mod sidecar;
+mod size;
+mod staging;
+mod state;
+mod storage;
+mod strict;
+mod suffix;
+mod table;
+mod template;
+mod timings;
+mod transform;
+#[cfg(feature = "watch")]
+mod watch;
 
-    let renamings = determine_renamings(csv_rows, file_names);
+pub use backup::{restore, BACKUP_DIR};
+pub use builder::ConfigBuilder;
+pub use condition::Condition;
+pub use conflict::{Conflict, ConflictError, ConflictPolicy};
+use date::Date;
+pub use error::{ParseError, RenameError};
+use filter::FileFilter;
+#[cfg(feature = "sqlite")]
+pub use history::{list_runs, show_run, undo_run, HistoryRun};
+pub use image_check::Dimensions;
+use index::FileIndex;
+pub use journal::DEFAULT_HISTORY;
+use journal::{Journal, JournalEntry};
+use lock::DirLock;
+pub use lock::LockHolder;
+pub use lots::Lots;
+use operation::Transfer;
+pub use operation::{Link, Operation};
+pub use options::OPTIONS;
+pub use parts::plan_from_parts;
+pub use plan::{PlanRow, RenamePlan, Status};
+pub use post_check::Discrepancy;
+use progress::Progress;
+use reader::Records;
+pub use reader::{CatalogueRow, Column, Encoding, Format, Widths, DEFAULT_QUERY};
+pub use report::{
+    to_json, CrowdedLot, DuplicateRow, LowResolutionLot, Renaming, Report, ReportFormat, RowError,
+    RunSummary, SmallImage, UnmatchedRow,
+};
+use retry::Retry;
+pub use rollback::RollbackError;
+#[cfg(feature = "s3")]
+pub use s3::S3Storage;
+use script::Script;
+#[cfg(feature = "sftp")]
+pub use sftp::SftpStorage;
+use sidecar::Sidecars;
+pub use size::FileSize;
+use state::{State, StateLog};
+pub use storage::{LocalStorage, Storage};
+pub use strict::StrictError;
+pub use suffix::SuffixStrategy;
+use template::{split_extension, NameFields};
+pub use template::{Template, TemplateError, DEFAULT_TEMPLATE};
+pub use timings::Timings;
+pub use transform::{LotTransform, Normalization, Transform};
+#[cfg(feature = "watch")]
+pub use watch::watch;
 
-    let result = rename_all_files(&config.dir, renamings);
+pub fn run(config: Config) -> Result<Report, RenameError> {
+    let dry_run = config.dry_run;
+    let color = config.color;
+    let preview_html = config.preview_html.clone();
+    // The locks are held from planning until the last file is renamed, so no
+    // other run changes the directories in between.
+    let locks = if !dry_run && config.storage().is_local() {
+        let dirs = std::iter::once(&config.dir).chain(&config.extra_dirs);
+        lock::acquire_all(dirs.map(PathBuf::as_path), &[])?
+    } else {
+        vec![]
+    };
+    let plan = plan(config)?;
 
-    match result {
-        Ok(()) => Ok(()),
-        Err(err) => Err(Box::new(err)),
+    if let Some(path) = &preview_html {
+        let dir = plan.storage.is_local().then(|| plan.source_dir());
+        fs::write(path, preview::render(&plan.rows(), dir))?;
+        log::info!("Wrote preview to {}", path.display());
+    }
+    if dry_run {
+        print!("{}", table::render(&plan.rows(), color));
+        plan.validate()?;
+        return Ok(plan.report());
     }
+
+    plan.execute_locked(locks)
 }
 
-fn read_csv(file_name: &String) -> Result<Vec<csv::StringRecord>, Box<dyn std::error::Error>> {
-    let mut rows: Vec<csv::StringRecord> = vec![];
+// check validates the data file against the directory without touching any
+// files, whatever the configuration. It plans the run in strict mode, so that
+// missing columns, unusable or repeated rows, files and rows that match
+// nothing and conflicts all fail the check, listed together.
+pub fn check(mut config: Config) -> Result<Report, RenameError> {
+    config.dry_run = true;
+    config.strict = true;
+    let color = config.color;
+    let plan = plan(config)?;
 
-    let mut reader = csv::ReaderBuilder::new()
-        .delimiter(b'\t')
-        .from_path(file_name)?;
-    for result in reader.records() {
-        let record = result?;
-        rows.push(record);
-    }
+    print!("{}", table::render(&plan.rows(), color));
+    plan.validate()?;
+    Ok(plan.report())
+}
 
-    Ok(rows)
+// plan determines what a run with this configuration would do, without
+// touching any files.
+pub fn plan(config: Config) -> Result<RenamePlan, RenameError> {
+    plan_with(config, None)
 }
 
-fn determine_renamings(
-    csv_rows: Vec<csv::StringRecord>,
-    files: Vec<String>,
-) -> HashMap<String, String> {
-    let mut renamings: HashMap<String, String> = HashMap::new();
+// plan_with plans the run after the given rows, or those of the data files if
+// none are given.
+fn plan_with(config: Config, data: Option<Records>) -> Result<RenamePlan, RenameError> {
+    let started = Instant::now();
+    let storage = config.storage();
+    if storage.is_local() && state::exists(&config.dir) {
+        if !config.resume {
+            return Err(RenameError::InterruptedRun(
+                config.dir.display().to_string(),
+            ));
+        }
+        return plan_resumption(&config);
+    }
+
+    let from_regex = config
+        .from_regex
+        .as_deref()
+        .map(whole_name_regex)
+        .transpose()?;
+    let naming = Naming {
+        template: match &from_regex {
+            Some(regex) => Template::parse_with_groups(&config.template, regex)?,
+            None => Template::parse(&config.template)?,
+        },
+        keep_extension: config.keep_extension,
+        lowercase_extension: config.lowercase_extension,
+        suffix: config.suffix.clone(),
+        resequence: config.resequence,
+        lot_width: config.lot_width,
+        index_width: config.index_width,
+        lot_transforms: config.lot_transforms.clone(),
+        transforms: config.transforms.clone(),
+        source_dir: config.dir.clone(),
+        normalization: config.normalization,
+        script: config.script.as_deref().map(Script::load).transpose()?,
+        sanitize: config.sanitize,
+        lot_dirs: config.lot_dirs,
+        date_column: None,
+        hero_column: None,
+    };
+
+    let operation = match (config.link, config.copy) {
+        (Some(Link::Hard), _) => Operation::HardLink,
+        (Some(Link::Sym), _) => Operation::Symlink,
+        (None, true) => Operation::Copy,
+        (None, false) => Operation::Rename,
+    };
+    let target_dir = config.output_dir.as_deref().unwrap_or(&config.dir);
+    let in_place = operation == Operation::Rename && target_dir == config.dir;
+
+    let mut filter = FileFilter::new(&config.include, &config.exclude)?;
+    if storage.is_local() {
+        filter = filter.ignore_file(&config.dir)?;
+    }
+    let listing_started = Instant::now();
+    let mut file_names = storage.list_files(&config.dir)?;
+    file_names.retain(|file| filter.matches(file));
+    for dir in &config.extra_dirs {
+        file_names.extend(list_extra_dir(&*storage, &config, dir)?);
+    }
+    let non_unicode_files = match storage.is_local() {
+        true => std::iter::once(&config.dir)
+            .chain(&config.extra_dirs)
+            .flat_map(list_non_unicode)
+            .collect(),
+        false => vec![],
+    };
+    let mut listing = listing_started.elapsed();
+    let (file_names, sidecars) = Sidecars::split(file_names, &config.sidecars);
+
+    let (matches, reading) = match &from_regex {
+        Some(regex) => (
+            determine_renamings_by_regex(file_names.clone(), regex, &naming)?,
+            Duration::ZERO,
+        ),
+        None => match_catalogue(&config, data, file_names.clone(), naming)?,
+    };
+    if config.on_bad_row == MalformedPolicy::Error && !matches.bad_rows.is_empty() {
+        return Err(RenameError::BadRows(matches.bad_rows));
+    }
+    let crowded_lots = match config.max_per_lot {
+        Some(max) => find_crowded_lots(&matches.renamings, &matches.objects, max),
+        None => vec![],
+    };
+    if config.on_duplicate == DuplicatePolicy::Error && !matches.duplicate_rows.is_empty() {
+        return Err(RenameError::DuplicateRows(matches.duplicate_rows));
+    }
+    if config.on_crowded_lot == CrowdedLotPolicy::Error && !crowded_lots.is_empty() {
+        return Err(RenameError::CrowdedLots(crowded_lots));
+    }
 
-    for row in csv_rows {
-        let lot_number = row.get(0).expect("Malformed csv row: 0th value not found.");
-        let inventory_number = row.get(8).expect("Malformed csv row: 8th value not found.");
+    let mut unmatched_files: Vec<String> = file_names
+        .into_iter()
+        .filter(|file| {
+            !matches.renamings.contains_key(file)
+                && !matches.malformed_files.contains(file)
+                && !matches.ignored_files.contains(file)
+                && ![
+                    journal::JOURNAL_FILE_NAME,
+                    lock::LOCK_FILE_NAME,
+                    filter::IGNORE_FILE_NAME,
+                    backup::BACKUP_DIR,
+                ]
+                .contains(&base_name(file))
+        })
+        .collect();
+    unmatched_files.extend(sidecars.follow(&unmatched_files));
+    let mut malformed_files = matches.malformed_files;
+    malformed_files.extend(sidecars.follow(&malformed_files));
 
-        let object_files = filter_object_files(files.clone(), inventory_number.to_string());
-        for object_file in object_files {
-            let suffix = extract_file_suffix(&object_file);
-            let new_name = compose_new_name(lot_number, suffix);
-            renamings.insert(object_file, new_name);
+    let mut renamings = matches.renamings;
+    // Files renamed by an earlier run keep their names, so a second run does
+    // not rename them again.
+    let journaled = if in_place && storage.is_local() {
+        renamed_by_journal(&config.dir, config.history.as_deref())
+    } else {
+        BTreeSet::new()
+    };
+    let mut renamed_before = find_renamed_before(&renamings, &journaled);
+    for file in &renamed_before {
+        renamings.remove(file);
+    }
+    renamed_before.extend(sidecars.follow(&renamed_before));
+    // Files of the wrong size, such as failed uploads, keep their names.
+    let mut wrong_size_files = if config.min_size.is_some() || config.max_size.is_some() {
+        size::find_wrong_sizes(
+            &config.dir,
+            renamings.keys(),
+            config.min_size,
+            config.max_size,
+        )
+    } else {
+        vec![]
+    };
+    for file in &wrong_size_files {
+        renamings.remove(file);
+    }
+    wrong_size_files.extend(sidecars.follow(&wrong_size_files));
+    // Corrupt images keep their names, which other files cannot take then.
+    let mut corrupt_files = if config.verify_images {
+        image_check::find_corrupt_images(&config.dir, renamings.keys())
+    } else {
+        vec![]
+    };
+    for file in &corrupt_files {
+        renamings.remove(file);
+    }
+    corrupt_files.extend(sidecars.follow(&corrupt_files));
+    // So do images smaller than the minimum dimensions, and their sidecars
+    // with them.
+    let low_resolution_lots = match config.min_dimensions {
+        Some(min) => {
+            let small_images = image_check::find_small_images(&config.dir, renamings.keys(), min);
+            for (file, _) in &small_images {
+                renamings.remove(file);
+            }
+            group_by_lot(small_images, &matches.objects, &sidecars)
         }
+        None => vec![],
+    };
+    if config.skip > 0 || config.limit.is_some() {
+        renamings = take_chunk(renamings, &matches.objects, config.skip, config.limit);
     }
+    sidecars.share_fate(&mut renamings, &[], config.lowercase_extension);
 
-    renamings
+    // All files count as existing, also those left out by the filter. Renaming
+    // in place, the names of files that are renamed themselves become free, so
+    // files may swap names. Files skipped for a taken name keep theirs, which
+    // may take the name of another file in turn.
+    let listing_started = Instant::now();
+    let mut all_files = storage.list(target_dir)?;
+    if config.lot_dirs {
+        let lot_dirs: BTreeSet<&str> = renamings
+            .values()
+            .filter_map(|new_name| new_name.split_once('/'))
+            .map(|(lot_dir, _)| lot_dir)
+            .collect();
+        for lot_dir in lot_dirs {
+            for file in storage.list(&target_dir.join(lot_dir))? {
+                all_files.push(format!("{}/{}", lot_dir, file));
+            }
+        }
+    }
+    listing += listing_started.elapsed();
+    let mut skipped_files = vec![];
+    let existing_files = loop {
+        let existing_files: Vec<String> = all_files
+            .iter()
+            .filter(|file| !in_place || renamings.get(*file).is_none_or(|new| new == *file))
+            .cloned()
+            .collect();
+        let mut skipped =
+            conflict::resolve_existing_targets(&mut renamings, &existing_files, config.on_conflict);
+        // A file is not renamed without its sidecars, nor they without it.
+        let skipped_along =
+            sidecars.share_fate(&mut renamings, &skipped, config.lowercase_extension);
+        skipped.extend(skipped_along);
+        if skipped.is_empty() {
+            break existing_files;
+        }
+        skipped_files.extend(skipped);
+    };
+    skipped_files.sort();
+    let mut objects = matches.objects;
+    for (file, sidecar) in sidecars.pairs() {
+        if let Some(object) = objects
+            .get(file)
+            .filter(|_| renamings.contains_key(sidecar))
+        {
+            objects.insert(sidecar.clone(), object.clone());
+        }
+    }
+    let duplicate_files = if config.find_duplicates {
+        duplicate::find_duplicates(&config.dir, renamings.keys())?
+    } else {
+        vec![]
+    };
+    let mut conflicts = conflict::detect_conflicts(&renamings, &existing_files);
+    if in_place {
+        for cycle in staging::find_cycles(&renamings) {
+            log::debug!("files swap names: {}", cycle.join(" -> "));
+        }
+    }
+    if config.on_conflict == ConflictPolicy::Overwrite {
+        conflicts.retain(|conflict| !matches!(conflict, Conflict::ExistingTarget { .. }));
+    }
+
+    Ok(RenamePlan {
+        renamings,
+        conflicts,
+        unmatched_files,
+        unmatched_rows: matches.unmatched_rows,
+        malformed_files,
+        skipped_files,
+        duplicate_files,
+        corrupt_files,
+        wrong_size_files,
+        low_resolution_lots,
+        renamed_before,
+        non_unicode_files,
+        bad_rows: matches.bad_rows,
+        crowded_lots,
+        duplicate_rows: matches.duplicate_rows,
+        timings: config.timings.then(|| Timings {
+            reading,
+            listing,
+            planning: started.elapsed().saturating_sub(reading + listing),
+            ..Timings::default()
+        }),
+        objects,
+        resumed: vec![],
+        source_dir: config.dir.clone(),
+        target_dir: PathBuf::from(target_dir),
+        operation,
+        jobs: config.jobs,
+        stage_all: config.stage_all,
+        strict: config.strict,
+        verify: config.verify,
+        post_check: config.post_check,
+        retry: config.retry(),
+        skip_locked: config.skip_locked,
+        on_conflict: config.on_conflict,
+        progress: config.progress,
+        audit_log: config.audit_log.clone(),
+        mapping_file: config.mapping_file.clone(),
+        history: config.history.clone(),
+        backup_dir: config.backup.as_ref().map(|dir| target_dir.join(dir)),
+        storage,
+    })
 }
 
-fn compose_new_name(lot_number: &str, suffix: &str) -> String {
-    format!("{}_{}.jpg", lot_number, suffix)
+// renamed_by_journal lists the names given by the runs that renamed files in
+// place in the directory and were not undone. If a history is kept, the runs
+// in it count as well.
+#[cfg_attr(not(feature = "sqlite"), allow(unused_variables, unused_mut))]
+fn renamed_by_journal(dir: &Path, history: Option<&Path>) -> BTreeSet<String> {
+    let mut renamed = match Journal::read(dir) {
+        Ok(journal) if journal.operation == Operation::Rename && journal.target_dir.is_none() => {
+            journal
+                .renamings
+                .into_iter()
+                .map(|entry| entry.new)
+                .collect()
+        }
+        _ => BTreeSet::new(),
+    };
+    #[cfg(feature = "sqlite")]
+    if let Some(history) = history {
+        match history::renamed_in(history, dir) {
+            Ok(names) => renamed.extend(names),
+            Err(err) => log::warn!("could not read the history: {}", err),
+        }
+    }
+
+    renamed
 }
 
-// extract_file_suffix gets the number between the two periods.
-fn extract_file_suffix(file_name: &str) -> &str {
-    let name_parts_between_periods = file_name.split(".").collect::<Vec<&str>>();
-    name_parts_between_periods[1]
+// find_renamed_before lists the files to rename that an earlier run renamed:
+// those given their name by a run in the journal, and those the plan would
+// give the name they already have. Under a template that keeps the inventory
+// number, renamed files match their row again, and would otherwise be renamed
+// once more, possibly in another order. A file that merely has the name the
+// plan gives another file of its object is renamed as planned.
+fn find_renamed_before(
+    renamings: &BTreeMap<String, String>,
+    journaled: &BTreeSet<String>,
+) -> Vec<String> {
+    renamings
+        .iter()
+        .filter(|(old_name, new_name)| journaled.contains(*old_name) || old_name == new_name)
+        .map(|(old_name, _)| old_name.clone())
+        .collect()
 }
 
-fn rename_all_files(dir: &str, renamings: HashMap<String, String>) -> std::io::Result<()> {
-    for (old_name, new_name) in &renamings {
-        println!("renaming {} to {}", old_name, new_name);
-        let directory = std::path::Path::new(dir);
-        let old_path = directory.join(old_name);
-        let new_path = directory.join(new_name);
+// find_crowded_lots lists the lots with more matching files than the maximum,
+// counting all files of a lot, also those left for later runs or renamed
+// before, but not their sidecars.
+fn find_crowded_lots(
+    renamings: &BTreeMap<String, String>,
+    objects: &BTreeMap<String, (String, String)>,
+    max: usize,
+) -> Vec<CrowdedLot> {
+    let mut files_per_lot: BTreeMap<&str, usize> = BTreeMap::new();
+    for old_name in renamings.keys() {
+        if let Some((lot, _)) = objects.get(old_name) {
+            *files_per_lot.entry(lot).or_default() += 1;
+        }
+    }
+
+    files_per_lot
+        .into_iter()
+        .filter(|&(_, files)| files > max)
+        .map(|(lot, files)| CrowdedLot {
+            lot: lot.to_owned(),
+            files,
+        })
+        .collect()
+}
 
-        fs::rename(old_path, new_path)?;
+// group_by_lot gathers the small images of each lot, ordered by lot, with
+// their sidecars.
+fn group_by_lot(
+    small_images: Vec<(String, Dimensions)>,
+    objects: &BTreeMap<String, (String, String)>,
+    sidecars: &Sidecars,
+) -> Vec<LowResolutionLot> {
+    let mut images_per_lot: BTreeMap<&str, Vec<SmallImage>> = BTreeMap::new();
+    for (file, dimensions) in small_images {
+        let lot = objects.get(&file).map_or("", |(lot, _)| lot.as_str());
+        images_per_lot.entry(lot).or_default().push(SmallImage {
+            sidecars: sidecars.of(&file).to_vec(),
+            file,
+            dimensions,
+        });
     }
 
-    Ok(())
+    images_per_lot
+        .into_iter()
+        .map(|(lot, images)| LowResolutionLot {
+            lot: lot.to_owned(),
+            images,
+        })
+        .collect()
 }
 
-pub struct Config {
-    pub data_file: String,
-    pub dir: String,
+// take_chunk keeps the renamings in the chunk of the plan, ordered by old
+// name, that starts after skipping some and holds at most limit. The others
+// are left for later runs. Chunks hold whole objects, so that a later run
+// numbers the rest of the photos of an object as this one would have: the
+// objects whose files make up the first skip files are skipped, and the chunk
+// ends before the object that would take it past the limit, unless that
+// object is the first one.
+fn take_chunk(
+    renamings: BTreeMap<String, String>,
+    objects: &BTreeMap<String, (String, String)>,
+    skip: usize,
+    limit: Option<usize>,
+) -> BTreeMap<String, String> {
+    let total = renamings.len();
+    let mut groups: Vec<Vec<(String, String)>> = vec![];
+    let mut group_of: HashMap<&(String, String), usize> = HashMap::new();
+    for (old_name, new_name) in renamings {
+        let group = match objects.get(&old_name) {
+            Some(object) => *group_of.entry(object).or_insert(groups.len()),
+            None => groups.len(),
+        };
+        if group == groups.len() {
+            groups.push(vec![]);
+        }
+        groups[group].push((old_name, new_name));
+    }
+
+    let mut skipped = 0;
+    let mut chunk: BTreeMap<String, String> = BTreeMap::new();
+    for group in groups {
+        if skipped < skip {
+            skipped += group.len();
+            continue;
+        }
+        if limit.is_some_and(|limit| !chunk.is_empty() && chunk.len() + group.len() > limit) {
+            break;
+        }
+        chunk.extend(group);
+    }
+    log::info!(
+        "renaming {} of {} files, leaving {} for later runs",
+        chunk.len(),
+        total,
+        total - chunk.len()
+    );
+
+    chunk
 }
 
-impl Config {
-    pub fn new(data_file: String, dir: String) -> Config {
-        Config { data_file, dir }
+// plan_resumption plans the rest of the interrupted run in the directory.
+fn plan_resumption(config: &Config) -> Result<RenamePlan, RenameError> {
+    let source_dir = config.dir.clone();
+    let (state, done) = state::read(&source_dir)?;
+    let target_dir = state
+        .target_dir
+        .map(PathBuf::from)
+        .unwrap_or_else(|| source_dir.clone());
+
+    let mut renamings = BTreeMap::new();
+    let mut resumed = vec![];
+    for entry in state.renamings {
+        // A file renamed just before the run was killed may not be recorded.
+        let renamed_unrecorded = state.operation == Operation::Rename
+            && !source_dir.join(&entry.old).exists()
+            && !source_dir.join(staging::temp_name(&entry.old)).exists()
+            && target_dir.join(&entry.new).exists();
+        let staged_done = done.contains(&staging::temp_name(&entry.old));
+        if done.contains(&entry.old) || staged_done || renamed_unrecorded {
+            resumed.push((entry.old, entry.new));
+        } else {
+            renamings.insert(entry.old, entry.new);
+        }
     }
+    let backup_dir = config.backup.as_ref().map(|dir| target_dir.join(dir));
+    log::info!(
+        "resuming interrupted run: {} of {} file(s) done",
+        resumed.len(),
+        resumed.len() + renamings.len()
+    );
+
+    Ok(RenamePlan {
+        renamings,
+        conflicts: vec![],
+        unmatched_files: vec![],
+        unmatched_rows: vec![],
+        malformed_files: vec![],
+        skipped_files: vec![],
+        duplicate_files: vec![],
+        corrupt_files: vec![],
+        wrong_size_files: vec![],
+        low_resolution_lots: vec![],
+        renamed_before: vec![],
+        non_unicode_files: vec![],
+        bad_rows: vec![],
+        crowded_lots: vec![],
+        duplicate_rows: vec![],
+        timings: config.timings.then(Timings::default),
+        objects: BTreeMap::new(),
+        resumed,
+        source_dir,
+        target_dir,
+        operation: state.operation,
+        jobs: config.jobs,
+        stage_all: config.stage_all,
+        strict: false,
+        verify: config.verify,
+        post_check: config.post_check,
+        retry: config.retry(),
+        skip_locked: config.skip_locked,
+        // The new names were free when the run was planned, so a file with one
+        // of them is an incomplete copy left by the interrupted run.
+        on_conflict: ConflictPolicy::Overwrite,
+        progress: config.progress,
+        audit_log: config.audit_log.clone(),
+        mapping_file: config.mapping_file.clone(),
+        history: config.history.clone(),
+        backup_dir,
+        storage: config.storage(),
+    })
+}
+
+// MalformedPolicy determines what happens to files that match a row, but whose
+// name lacks a photo number, and to rows of the data file that cannot be used.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum MalformedPolicy {
+    // Leave the file or row alone and report it.
+    #[default]
+    Skip,
+    // Abort the run.
+    Error,
+}
 
-    pub fn from_args(args: &[String]) -> Result<Config, &'static str> {
-        if args.len() != 3 {
-            return Err("received incorrect number of arguments: need 2");
+impl std::str::FromStr for MalformedPolicy {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<MalformedPolicy, &'static str> {
+        match value {
+            "skip" => Ok(MalformedPolicy::Skip),
+            "error" => Ok(MalformedPolicy::Error),
+            _ => Err("policy must be skip or error"),
         }
+    }
+}
+
+// CrowdedLotPolicy determines what happens to lots with more files than the
+// maximum per lot.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum CrowdedLotPolicy {
+    // Rename the files of the lot and report it.
+    #[default]
+    Warn,
+    // Abort the run.
+    Error,
+}
 
-        let data_file = args[1].clone();
-        let dir = args[2].clone();
+impl std::str::FromStr for CrowdedLotPolicy {
+    type Err = &'static str;
 
-        if !validate_dir(&dir) {
-            return Err("given directory path is not a directory");
+    fn from_str(value: &str) -> Result<CrowdedLotPolicy, &'static str> {
+        match value {
+            "warn" => Ok(CrowdedLotPolicy::Warn),
+            "error" => Ok(CrowdedLotPolicy::Error),
+            _ => Err("policy must be warn or error"),
         }
+    }
+}
+
+// DuplicatePolicy determines which of the rows that give an inventory number
+// different lot numbers names its files.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum DuplicatePolicy {
+    // The first of the rows, reporting the others.
+    First,
+    // The last of the rows, reporting the others.
+    Last,
+    // None; abort the run.
+    #[default]
+    Error,
+}
+
+impl std::str::FromStr for DuplicatePolicy {
+    type Err = &'static str;
 
-        Ok(Config::new(data_file, dir))
+    fn from_str(value: &str) -> Result<DuplicatePolicy, &'static str> {
+        match value {
+            "first" => Ok(DuplicatePolicy::First),
+            "last" => Ok(DuplicatePolicy::Last),
+            "error" => Ok(DuplicatePolicy::Error),
+            _ => Err("policy must be first, last or error"),
+        }
     }
 }
 
-fn validate_dir(file: &str) -> bool {
-    let result = fs::metadata(file);
-    match result {
-        Ok(metadata) => metadata.is_dir(),
-        Err(_) => false,
+// MatchMode determines where an inventory number may end in a file name.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum MatchMode {
+    // Anywhere, so `0024` matches `00243344.1.jpg`.
+    #[default]
+    Prefix,
+    // Only at a separator (`.`, `_` or `-`) or the end of the name.
+    Boundary,
+}
+
+impl std::str::FromStr for MatchMode {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<MatchMode, &'static str> {
+        match value {
+            "prefix" => Ok(MatchMode::Prefix),
+            "boundary" => Ok(MatchMode::Boundary),
+            _ => Err("match mode must be prefix or boundary"),
+        }
     }
 }
 
-fn list_files(dir: &str) -> Vec<String> {
-    let mut files: Vec<String> = vec![];
+// Matches is the outcome of matching the files against the rows.
+#[derive(Debug, Default)]
+struct Matches {
+    renamings: BTreeMap<String, String>,
+    unmatched_rows: Vec<UnmatchedRow>,
+    malformed_files: Vec<String>,
+    // Files of lots that do not take part, which are left alone silently.
+    ignored_files: Vec<String>,
+    // The lot and inventory number of each file that matched a row.
+    objects: BTreeMap<String, (String, String)>,
+    // Rows that could not be matched for lacking a column or holding an
+    // invalid value.
+    bad_rows: Vec<RowError>,
+    duplicate_rows: Vec<DuplicateRow>,
+}
 
-    if let Ok(entries) = fs::read_dir(dir) {
-        for entry in entries {
-            if let Ok(entry) = entry {
-                files.push(entry.file_name().to_str().unwrap().to_owned());
-            }
+// RowValues holds what a row of the data file is matched and named by.
+struct RowValues<'a> {
+    lot: Cow<'a, str>,
+    inventory: &'a str,
+    // The key the files are matched by, if not the inventory number.
+    key: Option<String>,
+    date: Option<Date>,
+    // The photo number of the photo to show first, if the row names one.
+    hero: Option<&'a str>,
+    // Whether the row meets the conditions that select the rows taking part.
+    selected: bool,
+}
+
+// read_row takes the values from the row that matching and naming need, or
+// tells why the row cannot be used.
+fn read_row<'a>(
+    row: &'a CatalogueRow,
+    columns: &'a csv::StringRecord,
+    row_number: usize,
+    matching: &Matching,
+    naming: &Naming,
+) -> Result<RowValues<'a>, RowError> {
+    let missing_column = |column| RowError {
+        row: row_number,
+        column: Some(column),
+        message: String::from("the row has no such column"),
+    };
+
+    let lot = matching.clean(&row.lot);
+    let lot = if matching.normalize_lot {
+        lots::normalize_lot(lot)
+    } else {
+        Cow::Borrowed(lot)
+    };
+    let inventory = matching.clean(&row.inventory);
+    if let Some(&column) = naming
+        .template
+        .columns()
+        .iter()
+        .find(|&&c| columns.get(c).is_none())
+    {
+        return Err(missing_column(column));
+    }
+
+    let date = match naming.date_column.filter(|_| naming.template.uses_date()) {
+        Some(column) => {
+            let value = columns.get(column).ok_or(missing_column(column))?;
+            let date = Date::parse(value).ok_or_else(|| RowError {
+                row: row_number,
+                column: Some(column),
+                message: format!("{:?} is not a date", value),
+            })?;
+            Some(date)
         }
+        None => None,
+    };
+
+    let key = matching.key(columns).map_err(missing_column)?;
+
+    let hero = match naming.hero_column {
+        Some(column) => Some(columns.get(column).ok_or(missing_column(column))?.trim())
+            .filter(|hero| !hero.is_empty()),
+        None => None,
+    };
+
+    let mut selected = true;
+    for (column, condition) in &matching.conditions {
+        selected &= condition.holds(columns.get(*column).ok_or(missing_column(*column))?);
     }
 
-    files
+    Ok(RowValues {
+        lot,
+        inventory,
+        key,
+        date,
+        hero,
+        selected,
+    })
 }
 
-// filter_object_files finds files prefixed with this inventory number.
-fn filter_object_files(files: Vec<String>, object_id: String) -> Vec<String> {
-    files
-        .into_iter()
-        .filter(|element| element.starts_with(&object_id))
-        .collect()
+// NamingRow is a row that names an object, kept whole if the object has files
+// and otherwise as what it is reported as.
+enum NamingRow {
+    Files(usize, CatalogueRow, csv::StringRecord),
+    Unmatched(UnmatchedRow),
+    Bad(RowError),
+    // The row is not in the lots that take part.
+    Ignored,
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+// determine_renamings matches the rows, as they are read, against the files.
+fn determine_renamings(
+    rows: impl IntoIterator<Item = Result<CatalogueRow, RenameError>>,
+    files: Vec<String>,
+    lot_column: usize,
+    inventory_column: usize,
+    matching: &Matching,
+    naming: &Naming,
+    on_malformed: MalformedPolicy,
+) -> Result<Matches, RenameError> {
+    let mut matches = Matches::default();
+    let file_index = FileIndex::new(files, matching);
+    let uses_date = naming.template.uses_date();
 
-    #[test]
-    fn end_to_end() {
-        // The pattern that new files must match.
-        let expression = regex::Regex::new(r"[1-9][0-9]*_[1-9][1]?.jpg").unwrap();
+    // The row and lot number each key was first seen with.
+    let mut first_rows: HashMap<String, (usize, String)> = HashMap::new();
+    // The rows that name objects, in order, with their key; a row that another
+    // one overrules is taken out. Duplicate rows are settled before any files
+    // are matched, so that each object is named by a single row.
+    let mut naming_rows: Vec<Option<(String, NamingRow)>> = vec![];
+    let mut naming_row_of: HashMap<String, usize> = HashMap::new();
+    // The keys of the rows that are left out.
+    let mut left_out: HashSet<String> = HashSet::new();
+    for (index, row) in rows.into_iter().enumerate() {
+        let row_number = index + 1;
+        // A record the reader could not parse is a bad row like any other;
+        // the rows after it can still be read.
+        let row = match row {
+            Ok(row) => row,
+            Err(RenameError::CsvParse(err)) if err.line.is_some() => {
+                matches.bad_rows.push(RowError {
+                    row: row_number,
+                    column: None,
+                    message: err.to_string(),
+                });
+                continue;
+            }
+            Err(RenameError::MissingColumn { column, .. }) => {
+                matches.bad_rows.push(RowError {
+                    row: row_number,
+                    column: Some(column),
+                    message: String::from("the row has no such column"),
+                });
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+        // Naming and matching take columns by their index in the data file.
+        let columns = row.columns(lot_column, inventory_column);
+        let values = match read_row(&row, &columns, row_number, matching, naming) {
+            Ok(values) => values,
+            Err(err) => {
+                matches.bad_rows.push(err);
+                continue;
+            }
+        };
+        let key = values
+            .key
+            .clone()
+            .unwrap_or_else(|| values.inventory.to_owned());
+        // The files of rows that are left out are left alone, whatever other
+        // rows say about them.
+        if !values.selected {
+            if left_out.insert(key.clone()) {
+                matches.ignored_files.extend(file_index.files_of(&key));
+            }
+            continue;
+        }
+        // A row repeating an object under another lot number than its first
+        // row is reported. The first or the last row names the files.
+        match first_rows.get(&key) {
+            Some((_, first_lot)) if *first_lot == values.lot => {}
+            Some((first_row, first_lot)) => {
+                matches.duplicate_rows.push(DuplicateRow {
+                    row: row_number,
+                    lot: values.lot.clone().into_owned(),
+                    first_row: *first_row,
+                    first_lot: first_lot.clone(),
+                    inventory: key.clone(),
+                });
+            }
+            None => {
+                first_rows.insert(key.clone(), (row_number, values.lot.clone().into_owned()));
+            }
+        }
+        match naming_row_of.get(&key) {
+            Some(_) if matching.on_duplicate != DuplicatePolicy::Last => continue,
+            Some(&overruled) => naming_rows[overruled] = None,
+            None => {}
+        }
 
-        let images_dir = std::path::Path::new("tests/files/");
-        let test_dir = std::path::Path::new("tests/tmp/");
-        let data_file = "tests/data.csv";
+        // Only the rows of objects with files are kept whole, so that a large
+        // catalogue is not held in memory; the others are kept as what they
+        // report.
+        let naming_row = if !file_index.files_of(&key).is_empty() {
+            NamingRow::Files(row_number, row, columns)
+        } else if matching
+            .lots
+            .as_ref()
+            .is_some_and(|lots| !lots.contains(&values.lot))
+        {
+            NamingRow::Ignored
+        } else {
+            match format_lot(naming, &values.lot) {
+                Ok(_) => {
+                    log::debug!(
+                        "row {}: lot {}, inventory number {} matched 0 file(s)",
+                        row_number,
+                        values.lot,
+                        key
+                    );
+                    NamingRow::Unmatched(UnmatchedRow {
+                        row: row_number,
+                        lot: values.lot.into_owned(),
+                        inventory: key.clone(),
+                    })
+                }
+                Err(message) => NamingRow::Bad(RowError {
+                    row: row_number,
+                    column: Some(lot_column),
+                    message,
+                }),
+            }
+        };
+        naming_row_of.insert(key.clone(), naming_rows.len());
+        naming_rows.push(Some((key, naming_row)));
+    }
 
-        let config = Config::new(
-            String::from(data_file),
-            String::from(test_dir.to_str().unwrap()),
+    for (key, naming_row) in naming_rows.into_iter().flatten() {
+        if left_out.contains(&key) {
+            continue;
+        }
+        let (row_number, row, columns) = match naming_row {
+            NamingRow::Files(row_number, row, columns) => (row_number, row, columns),
+            NamingRow::Unmatched(row) => {
+                matches.unmatched_rows.push(row);
+                continue;
+            }
+            NamingRow::Bad(err) => {
+                matches.bad_rows.push(err);
+                continue;
+            }
+            NamingRow::Ignored => continue,
+        };
+        // The row was read before, so it can be read again.
+        let Ok(values) = read_row(&row, &columns, row_number, matching, naming) else {
+            continue;
+        };
+        let columns = &columns;
+        let (lot_number, inventory_number, row_date) =
+            (values.lot.as_ref(), values.inventory, values.date);
+        let key = key.as_str();
+
+        let object_files = file_index.files_of(key);
+        if let Some(lots) = &matching.lots {
+            if !lots.contains(lot_number) {
+                matches.ignored_files.extend(object_files);
+                continue;
+            }
+        }
+        // A lot that the lot transforms cannot change would keep its number
+        // and could take the names of another lot, so its row is not used.
+        let lot = match format_lot(naming, lot_number) {
+            Ok(lot) => lot,
+            Err(message) => {
+                matches.bad_rows.push(RowError {
+                    row: row_number,
+                    column: Some(lot_column),
+                    message,
+                });
+                continue;
+            }
+        };
+        log::debug!(
+            "row {}: lot {}, inventory number {} matched {} file(s): {}",
+            row_number,
+            lot_number,
+            key,
+            object_files.len(),
+            object_files.join(", ")
         );
+        if object_files.is_empty() {
+            matches.unmatched_rows.push(UnmatchedRow {
+                row: row_number,
+                lot: lot_number.to_owned(),
+                inventory: key.to_owned(),
+            });
+        }
+        let mut suffixes: Vec<Option<String>> = match naming.suffix {
+            SuffixStrategy::CaptureTime => {
+                capture::number_by_capture_time(&naming.source_dir, &object_files)
+            }
+            _ => object_files
+                .iter()
+                .map(|file| naming.suffix.extract(base_name(file)).map(str::to_owned))
+                .collect(),
+        };
+        let hero = values.hero.and_then(|hero| {
+            let led = suffix::lead_with(suffixes.clone(), hero);
+            if led.is_none() {
+                log::warn!(
+                    "row {}: no photo of inventory number {} has hero number {}",
+                    row_number,
+                    key,
+                    hero
+                );
+            }
+            led
+        });
+        match hero {
+            Some(led) => suffixes = led,
+            None if naming.resequence => suffixes = suffix::resequence(suffixes),
+            None => {}
+        }
+        for (object_file, suffix) in object_files.into_iter().zip(suffixes) {
+            matches
+                .objects
+                .insert(object_file.clone(), (lot_number.to_owned(), key.to_owned()));
+            let Some(suffix) = suffix else {
+                match on_malformed {
+                    MalformedPolicy::Skip => {
+                        matches.malformed_files.push(object_file);
+                        continue;
+                    }
+                    MalformedPolicy::Error => {
+                        return Err(RenameError::MalformedFileName(object_file));
+                    }
+                }
+            };
+            let date = match row_date {
+                None if uses_date => {
+                    let date = Date::of_file(&naming.source_dir.join(&object_file));
+                    Some(date.ok_or_else(|| RenameError::MissingDate(object_file.clone()))?)
+                }
+                date => date,
+            };
+            let fields = NameFields {
+                lot: lot_number,
+                inventory: inventory_number,
+                index: &suffix,
+                rest: &naming.suffix.rest(base_name(&object_file)),
+                file_name: base_name(&object_file),
+                row: columns,
+                date,
+            };
+            let mut new_name = compose_new_name(naming, &fields, &lot);
+            if let Some(script) = &naming.script {
+                new_name = script.new_name(&fields, new_name)?;
+            }
+            new_name = make_legal(naming, &object_file, new_name)?;
+            if naming.lot_dirs {
+                let lot_dir = make_legal(naming, &object_file, lot.clone())?;
+                new_name = format!("{}/{}", lot_dir, new_name);
+            }
+            matches.renamings.insert(object_file, new_name);
+        }
+    }
+    matches.bad_rows.sort_by_key(|err| err.row);
 
-        // Copy tests directory to tmp.
-        let _ = fs::create_dir(test_dir);
+    Ok(matches)
+}
 
-        let file_names = list_files(images_dir.to_str().unwrap());
-        for file in file_names.clone() {
-            let old_path = images_dir.join(&file);
-            let new_path = test_dir.join(&file);
+// match_catalogue matches the files against the rows of the data files, and
+// tells how long reading them took.
+fn match_catalogue(
+    config: &Config,
+    data: Option<Records>,
+    files: Vec<String>,
+    naming: Naming,
+) -> Result<(Matches, Duration), RenameError> {
+    let reading_started = Instant::now();
+    let data = match data {
+        Some(records) => vec![(PathBuf::new(), records)],
+        None => reader::read_all(config)?,
+    };
+    // The data files have the same header, so the key columns are found in
+    // the first one.
+    let headers = data
+        .first()
+        .and_then(|(_, records)| records.headers.clone());
+    let key_columns = config
+        .key_columns
+        .iter()
+        .map(|column| column.resolve(headers.as_deref()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let comparing = Matching {
+        ignore_case: config.ignore_case,
+        trim: config.trim,
+        normalization: config.normalization,
+        ..Matching::default()
+    };
+    let mut records =
+        merge::merge_records(data, &key_columns, &config.key_separator, move |value| {
+            comparing.comparable(comparing.clean(value))
+        })?;
+    let reading = Cell::new(reading_started.elapsed());
 
-            fs::copy(old_path, new_path).unwrap();
+    let matching = Matching {
+        ignore_case: config.ignore_case,
+        trim: config.trim,
+        mode: config.match_mode,
+        key_columns,
+        key_separator: config.key_separator.clone(),
+        lots: config.lots.clone(),
+        conditions: config
+            .conditions
+            .iter()
+            .map(|condition| {
+                let column = condition.column.resolve(records.headers.as_deref())?;
+                Ok((column, condition.clone()))
+            })
+            .collect::<Result<_, RenameError>>()?,
+        normalization: config.normalization,
+        normalize_lot: config.normalize_lot,
+        on_duplicate: config.on_duplicate,
+    };
+    let naming = Naming {
+        date_column: config
+            .date_column
+            .as_ref()
+            .map(|column| column.resolve(records.headers.as_deref()))
+            .transpose()?,
+        hero_column: config
+            .hero_column
+            .as_ref()
+            .map(|column| column.resolve(records.headers.as_deref()))
+            .transpose()?,
+        ..naming
+    };
 
-            // Assert all old file names don't match the pattern to which the files
-            // ought to be renamed.
-            assert!(!expression.is_match(&file));
+    // Delimited data files are read and parsed as their rows are matched.
+    let rows = std::iter::from_fn(|| {
+        let reading_started = Instant::now();
+        let row = records.rows.next();
+        reading.set(reading.get() + reading_started.elapsed());
+        row
+    });
+    let matches = determine_renamings(
+        rows,
+        files,
+        records.lot_column,
+        records.inventory_column,
+        &matching,
+        &naming,
+        config.on_malformed,
+    )?;
+
+    Ok((matches, reading.get()))
+}
+
+// whole_name_regex compiles the pattern to match file names as a whole.
+fn whole_name_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    Regex::new(&format!("^(?:{})$", pattern))
+}
+
+// determine_renamings_by_regex names the files that match the regex after its
+// capture groups, without a data file. The other files are unmatched.
+fn determine_renamings_by_regex(
+    files: Vec<String>,
+    regex: &Regex,
+    naming: &Naming,
+) -> Result<Matches, RenameError> {
+    let mut matches = Matches::default();
+    let uses_date = naming.template.uses_date();
+
+    for file in files {
+        let Some(captures) = regex.captures(base_name(&file)) else {
+            continue;
+        };
+        let groups: csv::StringRecord = captures
+            .iter()
+            .map(|group| group.map_or("", |group| group.as_str()))
+            .collect();
+        let date = if uses_date {
+            let date = Date::of_file(&naming.source_dir.join(&file));
+            Some(date.ok_or_else(|| RenameError::MissingDate(file.clone()))?)
+        } else {
+            None
+        };
+        let fields = NameFields {
+            lot: "",
+            inventory: "",
+            index: "",
+            rest: "",
+            file_name: base_name(&file),
+            row: &groups,
+            date,
+        };
+        let mut new_name = compose_new_name(naming, &fields, "");
+        if let Some(script) = &naming.script {
+            new_name = script.new_name(&fields, new_name)?;
         }
+        new_name = make_legal(naming, &file, new_name)?;
+        log::debug!("{} matched the regex", file);
+        matches.renamings.insert(file, new_name);
+    }
 
-        run(config).expect("Running failed");
+    Ok(matches)
+}
 
-        let new_file_names = list_files(test_dir.to_str().unwrap());
-        assert_eq!(file_names.len(), new_file_names.len());
+// Matching determines how files are matched against inventory numbers.
+#[derive(Default)]
+struct Matching {
+    // Ignore differences in case between file names and inventory numbers.
+    ignore_case: bool,
+    // Strip whitespace around the lot and inventory numbers in the data file.
+    trim: bool,
+    mode: MatchMode,
+    // Columns whose values, joined by key_separator, make up the prefix of the
+    // file names instead of the inventory number.
+    key_columns: Vec<usize>,
+    key_separator: String,
+    // The lots that take part; all lots if not set.
+    lots: Option<Lots>,
+    // The conditions a row must meet to take part, with the column they apply to.
+    conditions: Vec<(usize, Condition)>,
+    // Compare file names and the data file in this normalization form.
+    normalization: Option<Normalization>,
+    // Write lot numbers as plain integers, without thousands separators or
+    // leading zeros.
+    normalize_lot: bool,
+    on_duplicate: DuplicatePolicy,
+}
 
-        // Assert all moved files have a name that matches the pattern.
-        for file in new_file_names {
-            assert!(expression.is_match(&file));
+impl Matching {
+    // key joins the values of the key columns of the row, or is None if the
+    // inventory number is the key. Err holds a column the row lacks.
+    fn key(&self, row: &csv::StringRecord) -> Result<Option<String>, usize> {
+        if self.key_columns.is_empty() {
+            return Ok(None);
         }
 
-        fs::remove_dir_all("tests/tmp").expect("Could not delete tests directory.");
+        let values = self
+            .key_columns
+            .iter()
+            .map(|&column| row.get(column).map(|value| self.clean(value)).ok_or(column))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Some(values.join(&self.key_separator)))
     }
 
-    #[test]
-    fn determine_file_names() {
-        let data = String::from("1		\"Henricus Johannes (Harrie) Kuyten, Utrecht 1883-1952 Schoorl ...\"	\"Henricus Johannes (Harrie) Kuyten, Utrecht 1883-1952 Schoorl, Beach view with various people, oil on canvas, 43 x 36 cm.\"	EUR	4000	6000	3000	00243878										\n2		\"Henricus Johannes (Harrie) Kuyten, Utrecht 1883-1952 Schoorl ...\"	\"Henricus Johannes (Harrie) Kuyten, Utrecht 1883-1952 Schoorl, Beach view, pastel drawing, dated 1951, 31,5 x 23 cm\"	EUR	500	700	380	00243880										\n3		\"Very large antique blue/white Chinese porcelain lidded vase ...\"	\"Very large antique blue/white Chinese porcelain lidded vase with decoration of floral motifs, Qing Dynasty, approx. h.59 cm.\"	EUR	2000	3000	1500	00243344										");
+    // comparable is the form in which file names and inventory numbers are
+    // compared: normalized and, when ignoring case, lowercased.
+    fn comparable(&self, value: &str) -> String {
+        let value = match self.normalization {
+            Some(normalization) => normalization.apply(value),
+            None => value.to_owned(),
+        };
+        if self.ignore_case {
+            value.to_lowercase()
+        } else {
+            value
+        }
+    }
 
-        let mut rows: Vec<csv::StringRecord> = vec![];
+    // ends_number tells whether the rest of a file name after the inventory
+    // number lets it match: always in prefix mode, and in boundary mode only if
+    // the number ends there.
+    fn ends_number(&self, rest: &str) -> bool {
+        match self.mode {
+            MatchMode::Prefix => true,
+            MatchMode::Boundary => rest.is_empty() || rest.starts_with(['.', '_', '-']),
+        }
+    }
 
-        let mut rdr = csv::ReaderBuilder::new()
-            .has_headers(false)
-            .delimiter(b'\t')
-            .from_reader(data.as_bytes());
-        for result in rdr.records() {
-            // The iterator yields Result<StringRecord, Error>, so we check the
-            // error here.
-            let record = result.unwrap();
-            rows.push(record);
+    fn clean<'a>(&self, value: &'a str) -> &'a str {
+        if self.trim {
+            value.trim()
+        } else {
+            value
         }
+    }
+}
 
-        let file_names = vec![
-            "00243878.1.jpg".to_string(),
-            "00243878.2.jpg".to_string(),
-            "00243878.3.jpg".to_string(),
-            "00243880.1.jpg".to_string(),
-            "00243880.2.jpg".to_string(),
-            "00243344.1.jpg".to_string(),
-            "00243344.2.jpg".to_string(),
-            "00243344.3.jpg".to_string(),
-        ];
+// Naming determines how new names are composed.
+#[derive(Default)]
+struct Naming {
+    template: Template,
+    // Replace the extension of the rendered name by the one of the original file.
+    keep_extension: bool,
+    lowercase_extension: bool,
+    suffix: SuffixStrategy,
+    // Number the photos of each object 1, 2, 3 and so on in the order of their
+    // photo numbers, instead of by the photo numbers themselves.
+    resequence: bool,
+    // The minimum widths of the lot and photo number, reached by padding with
+    // zeros. A width of 0 leaves the number as is.
+    lot_width: usize,
+    index_width: usize,
+    // Applied to the padded lot number, in order.
+    lot_transforms: Vec<LotTransform>,
+    // Applied to the composed name, in order.
+    transforms: Vec<Transform>,
+    // The directory holding the files, whose capture times may number them.
+    source_dir: PathBuf,
+    // The normalization form of the new names.
+    normalization: Option<Normalization>,
+    // Names the files instead of the template, which it may fall back on.
+    script: Option<Script>,
+    // Make new names legal on all platforms instead of refusing them.
+    sanitize: bool,
+    // Place the files in a subdirectory per lot, named by its padded number.
+    lot_dirs: bool,
+    // The column with the date of the row for {date}; the date of the file
+    // if not set.
+    date_column: Option<usize>,
+    // The column with the photo number of the photo to show first, which is
+    // numbered 1 and the others after it.
+    hero_column: Option<usize>,
+}
 
-        let renamings = determine_renamings(rows, file_names);
+// compose_new_name composes the new name of a file from its fields and its lot
+// number as format_lot writes it.
+fn compose_new_name(naming: &Naming, fields: &NameFields, lot: &str) -> String {
+    let index = pad_with_zeros(fields.index, naming.index_width);
+    let mut new_name = naming.template.render(&NameFields {
+        lot,
+        index: &index,
+        ..*fields
+    });
 
-        let expected_renamings: HashMap<String, String> = HashMap::from([
-            ("00243878.1.jpg".to_string(), "1_1.jpg".to_string()),
-            ("00243878.2.jpg".to_string(), "1_2.jpg".to_string()),
-            ("00243878.3.jpg".to_string(), "1_3.jpg".to_string()),
-            ("00243880.1.jpg".to_string(), "2_1.jpg".to_string()),
-            ("00243880.2.jpg".to_string(), "2_2.jpg".to_string()),
-            ("00243344.1.jpg".to_string(), "3_1.jpg".to_string()),
-            ("00243344.2.jpg".to_string(), "3_2.jpg".to_string()),
-            ("00243344.3.jpg".to_string(), "3_3.jpg".to_string()),
-        ]);
+    let (_, extension) = split_extension(fields.file_name);
+    if naming.keep_extension && !extension.is_empty() {
+        let extension = if naming.lowercase_extension {
+            extension.to_lowercase()
+        } else {
+            extension.to_owned()
+        };
 
-        assert_eq!(expected_renamings, renamings)
+        let (stem, _) = split_extension(&new_name);
+        new_name = format!("{}.{}", stem, extension);
     }
 
-    #[test]
-    fn filter_object_files_test() {
-        let files = list_files("tests/files");
-        let object_id = String::from("00243344");
+    let new_name = naming
+        .transforms
+        .iter()
+        .fold(new_name, |name, transform| transform.apply(&name));
 
-        //TODO: refactor vector to a collection that is unordered (hash map?).
-        assert_eq!(
-            vec![
-                "00243344.6.jpg",
-                "00243344.7.jpg",
-                "00243344.5.jpg",
-                "00243344.4.jpg",
-                "00243344.1.jpg",
-                "00243344.3.jpg",
-                "00243344.2.jpg",
-            ],
-            filter_object_files(files, object_id)
+    match naming.normalization {
+        Some(normalization) => normalization.apply(&new_name),
+        None => new_name,
+    }
+}
+
+// make_legal sanitizes the new name of the file or, if it is illegal and may
+// not be sanitized, refuses it.
+fn make_legal(naming: &Naming, file: &str, name: String) -> Result<String, RenameError> {
+    if naming.sanitize {
+        return Ok(legality::sanitize(&name));
+    }
+
+    match legality::check(&name) {
+        Ok(()) => Ok(name),
+        Err(reason) => Err(RenameError::IllegalName {
+            file: file.to_owned(),
+            name,
+            reason,
+        }),
+    }
+}
+
+// format_lot writes the lot number as it appears in new names: padded with
+// zeros, then changed by the lot transforms. It fails if a transform cannot
+// change the lot.
+fn format_lot(naming: &Naming, lot: &str) -> Result<String, String> {
+    naming
+        .lot_transforms
+        .iter()
+        .try_fold(pad_with_zeros(lot, naming.lot_width), |lot, transform| {
+            transform.apply(&lot)
+        })
+}
+
+fn pad_with_zeros(value: &str, width: usize) -> String {
+    format!("{:0>width$}", value, width = width)
+}
+
+// rename_all_files either renames all files or none: when a renaming fails, the
+// completed ones are rolled back. Directories in new names are created first.
+// Renamings still in effect are recorded in a journal in the source directory,
+// so that the run can be reverted with `undo`. The journal keeps the renamings
+// of earlier runs that were not undone yet. With more than one job, the files
+// are renamed in parallel. Progress is kept in a state file, so that an
+// interrupted run can be resumed; the renamings it already completed are
+// passed as resumed. The journal and the state file are only kept on the local
+// file system. With stage_all, every file renamed in place is staged under a
+// temporary name first.
+// Files the transfer skips because they are locked keep their old names, as
+// do the files that were to take those over; they are returned after the
+// renamed ones, by their current names. That is the temporary name of a file
+// that was locked after it was staged and could not get its old name back.
+fn rename_all_files(
+    transfer: &Transfer,
+    renamings: BTreeMap<String, String>,
+    resumed: Vec<(String, String)>,
+    jobs: usize,
+    progress: bool,
+    stage_all: bool,
+) -> Result<(Vec<Renaming>, Vec<Renaming>), RenameError> {
+    let local = transfer.storage.is_local();
+    // Deep directories, such as those on a network share, would otherwise
+    // exceed the length Windows allows for paths.
+    let (source, target, backup) = if local {
+        (
+            long_path::extended(transfer.source)?,
+            long_path::extended(transfer.target)?,
+            transfer.backup.map(long_path::extended).transpose()?,
+        )
+    } else {
+        (
+            transfer.source.to_path_buf(),
+            transfer.target.to_path_buf(),
+            transfer.backup.map(Path::to_path_buf),
+        )
+    };
+    let transfer = &Transfer {
+        source: &source,
+        target: &target,
+        backup: backup.as_deref(),
+        ..*transfer
+    };
+    let new_dirs: BTreeSet<&Path> = renamings
+        .values()
+        .filter_map(|new_name| Path::new(new_name).parent())
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .collect();
+    for dir in new_dirs {
+        transfer
+            .storage
+            .create_dir_all(&transfer.target.join(dir))?;
+    }
+    let target_dir = if transfer.target == transfer.source || !local {
+        None
+    } else {
+        Some(
+            fs::canonicalize(transfer.target)?
+                .to_string_lossy()
+                .into_owned(),
+        )
+    };
+    let mut journal = Journal {
+        operation: transfer.operation,
+        target_dir: target_dir.clone(),
+        renamings: vec![],
+    };
+    let pairs: Vec<(&String, &String)> = renamings.iter().collect();
+
+    let state_log = if !local {
+        None
+    } else if resumed.is_empty() {
+        let state = State {
+            operation: transfer.operation,
+            target_dir,
+            renamings: pairs
+                .iter()
+                .map(|(old_name, new_name)| JournalEntry {
+                    old: old_name.to_string(),
+                    new: new_name.to_string(),
+                })
+                .collect(),
+        };
+        Some(StateLog::create(transfer.source, &state)?)
+    } else {
+        Some(StateLog::open(transfer.source)?)
+    };
+    let recording = Transfer {
+        state: state_log.as_ref(),
+        ..*transfer
+    };
+
+    // Renaming in place, files whose name another file takes are first staged
+    // under a temporary name, so that any permutation of names is safe. A
+    // resumed run finds some of them staged already.
+    let staged: BTreeMap<&String, String> =
+        if transfer.operation == Operation::Rename && transfer.target == transfer.source {
+            let blocked = if stage_all {
+                renamings
+                    .iter()
+                    .filter(|(old_name, new_name)| old_name != new_name)
+                    .map(|(old_name, _)| old_name)
+                    .collect()
+            } else {
+                staging::blocked_names(&renamings)
+            };
+            renamings
+                .keys()
+                .filter(|old_name| is_staged(transfer, old_name))
+                .chain(blocked)
+                .map(|old_name| (old_name, staging::temp_name(old_name)))
+                .collect()
+        } else {
+            BTreeMap::new()
+        };
+    let stagings: Vec<(&String, &String)> = staged
+        .iter()
+        .filter(|(old_name, _)| !is_staged(transfer, old_name))
+        .map(|(old_name, temp_name)| (*old_name, temp_name))
+        .collect();
+    if !stagings.is_empty() {
+        log::info!(
+            "staging {} file(s) under a temporary name to free their names",
+            stagings.len()
         );
     }
 
-    #[test]
-    fn read_dir_contents() {
-        let dir = "tests/files";
+    let progress = Progress::new((stagings.len() + pairs.len()) as u64, progress);
+    let mut completed: Vec<(&String, &String)> = resumed
+        .iter()
+        .map(|(old_name, new_name)| (old_name, new_name))
+        .collect();
+    let mut locked: Vec<(&String, &String)> = vec![];
+    // Staging is not recorded: until a file has its new name, a resumed run
+    // still has to rename it.
+    let mut failure = transfer_all(
+        transfer,
+        &stagings,
+        jobs,
+        &progress,
+        &mut completed,
+        &mut locked,
+    );
 
-        assert_eq!(
-            vec![
-                "00243880.6.jpg",
-                "00243880.4.jpg",
-                "00243880.5.jpg",
-                "00243880.1.jpg",
-                "00243880.2.jpg",
-                "00243880.3.jpg",
-                "00243344.6.jpg",
-                "00243344.7.jpg",
-                "00243878.1.jpg",
-                "00243344.5.jpg",
-                "00243878.3.jpg",
-                "00243878.2.jpg",
-                "00243344.4.jpg",
-                "00243878.6.jpg",
-                "00243878.7.jpg",
+    // A locked file that could not be staged keeps its name, so the files that
+    // were to take it over keep theirs as well. Those staged already get their
+    // old names back.
+    let mut left: BTreeSet<&String> = locked.drain(..).map(|(old_name, _)| old_name).collect();
+    if failure.is_none() && !left.is_empty() {
+        loop {
+            let waiting: Vec<&String> = pairs
+                .iter()
+                .filter(|(old_name, new_name)| !left.contains(old_name) && left.contains(new_name))
+                .map(|(old_name, _)| *old_name)
+                .collect();
+            if waiting.is_empty() {
+                break;
+            }
+            left.extend(waiting);
+        }
+        for &old_name in &left {
+            let Some(temp_name) = staged.get(old_name) else {
+                continue;
+            };
+            if !is_staged(transfer, old_name) {
+                continue;
+            }
+            if let Err(source) = transfer.revert(old_name, temp_name) {
+                failure = Some((old_name, temp_name, source));
+                break;
+            }
+            completed.retain(|&(completed_name, _)| completed_name != old_name);
+        }
+    }
+
+    if failure.is_none() {
+        let moves: Vec<(&String, &String)> = pairs
+            .iter()
+            .filter(|(old_name, _)| !left.contains(old_name))
+            .map(|&(old_name, new_name)| (staged.get(old_name).unwrap_or(old_name), new_name))
+            .collect();
+        progress.inc((pairs.len() - moves.len()) as u64);
+        failure = transfer_all(
+            &recording,
+            &moves,
+            jobs,
+            &progress,
+            &mut completed,
+            &mut locked,
+        );
+    }
+    progress.finish_and_clear();
+
+    if let Some((old_name, new_name, source)) = failure {
+        let (rolled_back, not_rolled_back) = rollback::roll_back(transfer, &completed);
+
+        if local {
+            for (old_name, new_name, _) in &not_rolled_back {
+                journal.record(old_name, new_name);
+            }
+            if !journal.is_empty() {
+                journal.append(transfer.source)?;
+            }
+            state::remove(transfer.source)?;
+        }
+
+        return Err(RenameError::Rollback(RollbackError {
+            operation: transfer.operation,
+            old_name: old_name.clone(),
+            new_name: new_name.clone(),
+            source,
+            rolled_back,
+            not_rolled_back,
+        }));
+    }
+
+    // Files locked when they were to get their new names stay where they were
+    // staged, unless they can be given back their old names. Another file may
+    // have taken those already, as in a swap.
+    let mut left_staged: HashMap<&String, &String> = HashMap::new();
+    for &(moved_name, new_name) in &locked {
+        let Some(&(old_name, _)) = pairs.iter().find(|(_, pair_new)| *pair_new == new_name) else {
+            continue;
+        };
+        if moved_name != old_name {
+            let taken = completed
+                .iter()
+                .any(|&(_, completed_new)| completed_new == old_name);
+            let reverted = match taken {
+                true => Err(String::from("another file took its name")),
+                false => transfer
+                    .revert(old_name, moved_name)
+                    .map_err(|err| err.to_string()),
+            };
+            if let Err(reason) = reverted {
+                log::warn!(
+                    "{} is left under its temporary name {}: {}",
+                    old_name,
+                    moved_name,
+                    reason
+                );
+                left_staged.insert(old_name, moved_name);
+            }
+        }
+        left.insert(old_name);
+    }
+
+    let (left, pairs): (Vec<_>, Vec<_>) = pairs
+        .into_iter()
+        .partition(|(old_name, _)| left.contains(old_name));
+    // Files left under their temporary name are reported by it.
+    let left: Vec<(&String, &String)> = left
+        .into_iter()
+        .map(|(old_name, new_name)| (*left_staged.get(old_name).unwrap_or(&old_name), new_name))
+        .collect();
+    let mut completed: Vec<(&String, &String)> = resumed
+        .iter()
+        .map(|(old_name, new_name)| (old_name, new_name))
+        .chain(pairs)
+        .collect();
+    completed.sort();
+    if local {
+        for (old_name, new_name) in &completed {
+            journal.record(old_name, new_name);
+        }
+        if !journal.is_empty() {
+            journal.append(transfer.source)?;
+        }
+        state::remove(transfer.source)?;
+    }
+
+    let to_renamings = |pairs: Vec<(&String, &String)>| -> Vec<Renaming> {
+        pairs
+            .into_iter()
+            .map(|(old_name, new_name)| Renaming {
+                old: old_name.clone(),
+                new: new_name.clone(),
+            })
+            .collect()
+    };
+    Ok((to_renamings(completed), to_renamings(left)))
+}
+
+// is_staged tells whether the file waits under its temporary name.
+fn is_staged(transfer: &Transfer, old_name: &str) -> bool {
+    let temp_path = transfer.source.join(staging::temp_name(old_name));
+    transfer.storage.exists(&temp_path).unwrap_or(false)
+}
+
+// transfer_all applies the transfer to the renamings, adding those that
+// succeed to completed and those it skips to locked, and returns the first
+// failure.
+fn transfer_all<'a>(
+    transfer: &Transfer,
+    renamings: &[(&'a String, &'a String)],
+    jobs: usize,
+    progress: &Progress,
+    completed: &mut Vec<(&'a String, &'a String)>,
+    locked: &mut Vec<(&'a String, &'a String)>,
+) -> Option<(&'a String, &'a String, std::io::Error)> {
+    let outcomes = if jobs > 1 {
+        executor::rename_in_parallel(transfer, renamings, jobs, progress)
+    } else {
+        executor::rename_sequentially(transfer, renamings, progress)
+    };
+
+    let mut failure = None;
+    for (&(old_name, new_name), outcome) in renamings.iter().zip(outcomes) {
+        match outcome {
+            Some(Ok(())) => completed.push((old_name, new_name)),
+            Some(Err(source)) if transfer.skips(&source) => {
+                log::warn!("{} is locked, leaving it alone: {}", old_name, source);
+                locked.push((old_name, new_name));
+            }
+            Some(Err(source)) if failure.is_none() => failure = Some((old_name, new_name, source)),
+            _ => {}
+        }
+    }
+
+    failure
+}
+
+// undo reverts the renamings recorded in the journal of the last run in dir.
+pub fn undo(dir: impl AsRef<Path>) -> Result<(), RenameError> {
+    let dir = dir.as_ref();
+    let held = [DirLock::acquire(dir)?];
+    let journal = Journal::read(dir)?;
+    // Files from extra directories are journaled by their absolute path.
+    let extra_dirs = journal
+        .renamings
+        .iter()
+        .map(|entry| Path::new(&entry.old))
+        .filter(|old| old.is_absolute())
+        .filter_map(Path::parent);
+    let dirs = journal.target_dir.as_deref().map(Path::new).into_iter();
+    let _locks = lock::acquire_all(dirs.chain(extra_dirs), &held)?;
+    revert(dir, &journal)?;
+    Journal::remove(dir)?;
+
+    Ok(())
+}
+
+// revert reverts the renamings of the journal, whose old names are in dir.
+fn revert(dir: &Path, journal: &Journal) -> Result<(), RenameError> {
+    let transfer = Transfer {
+        storage: &LocalStorage::default(),
+        source: dir,
+        target: journal.target_dir.as_deref().map_or(dir, Path::new),
+        operation: journal.operation,
+        verify: false,
+        overwrite: false,
+        backup: None,
+        state: None,
+        audit: None,
+        retry: Retry::default(),
+        skip_locked: false,
+    };
+
+    // Files that swapped names are staged under a temporary name first, as when
+    // they were renamed.
+    let staged: BTreeMap<String, String> =
+        if journal.operation == Operation::Rename && journal.target_dir.is_none() {
+            let restorings: BTreeMap<String, String> = journal
+                .renamings
+                .iter()
+                .map(|entry| (entry.new.clone(), entry.old.clone()))
+                .collect();
+            staging::blocked_names(&restorings)
+                .into_iter()
+                .map(|new_name| (new_name.clone(), staging::temp_name(new_name)))
+                .collect()
+        } else {
+            BTreeMap::new()
+        };
+    for entry in journal.renamings.iter() {
+        if let Some(temp_name) = staged.get(&entry.new) {
+            transfer.revert(temp_name, &entry.new)?;
+        }
+    }
+
+    for entry in journal.renamings.iter().rev() {
+        match journal.operation {
+            Operation::Rename => log::info!("restoring {} to {}", entry.new, entry.old),
+            Operation::Copy | Operation::HardLink | Operation::Symlink => {
+                log::info!("removing {}", entry.new)
+            }
+        }
+
+        match staged.get(&entry.new) {
+            Some(temp_name) => transfer.revert(&entry.old, temp_name)?,
+            None => transfer.revert(&entry.old, &entry.new)?,
+        }
+    }
+
+    // The lot directories are removed once they are empty again, the deepest
+    // first. Those still holding other files are left.
+    let lot_dirs: BTreeSet<&Path> = journal
+        .renamings
+        .iter()
+        .filter_map(|entry| Path::new(&entry.new).parent())
+        .filter(|lot_dir| !lot_dir.as_os_str().is_empty())
+        .collect();
+    for lot_dir in lot_dirs.into_iter().rev() {
+        let _ = fs::remove_dir(transfer.target.join(lot_dir));
+    }
+
+    Ok(())
+}
+
+#[derive(Clone)]
+pub struct Config {
+    pub data_file: PathBuf,
+    // More data files in the same format and layout, such as the parts of a
+    // large sale, whose rows are merged with those of data_file.
+    pub extra_data_files: Vec<PathBuf>,
+    pub dir: PathBuf,
+    // More directories, such as one for each photographer, whose files are
+    // renamed along with those in dir. They are moved to the output directory
+    // or, without one, to dir.
+    pub extra_dirs: Vec<PathBuf>,
+    pub dry_run: bool,
+    pub delimiter: u8,
+    // The encoding of a delimited data file; UTF-8 if not set.
+    pub encoding: Option<Encoding>,
+    pub lot_column: Column,
+    pub inventory_column: Column,
+    // The column with the date that {date} stands for; the date the photo was
+    // taken or last modified if not set.
+    pub date_column: Option<Column>,
+    // The column with the photo number of the photo of each object to show
+    // first, which is numbered 1 and the others 2, 3 and so on after it.
+    pub hero_column: Option<Column>,
+    // Whether the first row of the data file is a header rather than data.
+    pub has_headers: bool,
+    pub template: String,
+    pub keep_extension: bool,
+    pub lowercase_extension: bool,
+    pub jobs: usize,
+    // Renaming in place, stage every file under a temporary name before
+    // giving any file its new name, not only files whose name is taken.
+    pub stage_all: bool,
+    pub format: Format,
+    // The worksheet to read from an xlsx data file; the first one if not set.
+    pub sheet: Option<String>,
+    // The columns of a fixed-width data file, which it cannot be read without.
+    pub widths: Option<Widths>,
+    // The keys of the lot and inventory number in a JSON data file.
+    pub lot_key: String,
+    pub inventory_key: String,
+    // The query selecting the lot and inventory number from an SQLite data file.
+    pub query: String,
+    // Copy files to their new names instead of renaming them.
+    pub copy: bool,
+    // Link files under their new names instead of renaming them.
+    pub link: Option<Link>,
+    // The directory to place the new files in, created if it does not exist; the
+    // source directory if not set.
+    pub output_dir: Option<PathBuf>,
+    // Fail instead of reporting files that match no row.
+    pub strict: bool,
+    // What to do with files whose new name already exists.
+    pub on_conflict: ConflictPolicy,
+    // Match file names and inventory numbers regardless of case.
+    pub ignore_case: bool,
+    // Strip whitespace around the lot and inventory numbers in the data file.
+    pub trim: bool,
+    // Write lot numbers as plain integers followed by their suffix, if any,
+    // so `1.001`, `1 001` and `01001` all become `1001`, but keep `101A`.
+    pub normalize_lot: bool,
+    // Only rename the files of these lots.
+    pub lots: Option<Lots>,
+    // Only rename the files of rows that meet all of these conditions.
+    pub conditions: Vec<Condition>,
+    // Where an inventory number may end in a file name.
+    pub match_mode: MatchMode,
+    // Columns whose values, joined by key_separator, make up the prefix of the
+    // file names; the inventory column if empty.
+    pub key_columns: Vec<Column>,
+    pub key_separator: String,
+    // Check every copied file, including files moved to another file system,
+    // against the original with SHA-256.
+    pub verify: bool,
+    // After renaming, list the directories again and check that every file has
+    // its new name and no longer its old one.
+    pub post_check: bool,
+    // Copy the extended attributes of files that are copied, including files
+    // moved to another file system, along with their timestamps.
+    pub preserve_xattrs: bool,
+    // Keep running and rename files as they are added to the directory.
+    pub watch: bool,
+    // Glob patterns selecting the files that take part; all files if empty.
+    pub include: Vec<String>,
+    // Glob patterns of files to leave out.
+    pub exclude: Vec<String>,
+    pub on_malformed: MalformedPolicy,
+    // What to do with rows of the data file that lack a column or hold an
+    // invalid value.
+    pub on_bad_row: MalformedPolicy,
+    // Which row names the files of an inventory number that rows of the data
+    // file give different lot numbers.
+    pub on_duplicate: DuplicatePolicy,
+    pub suffix: SuffixStrategy,
+    // Number the photos of each object 1, 2, 3 and so on in the order of their
+    // photo numbers, closing gaps in the numbering.
+    pub resequence: bool,
+    // Pad the lot and photo number with zeros up to these widths; 0 disables padding.
+    pub lot_width: usize,
+    pub index_width: usize,
+    // Applied to the lot number in new names, in order, such as an offset.
+    pub lot_transforms: Vec<LotTransform>,
+    // Applied to each new name, in order.
+    pub transforms: Vec<Transform>,
+    // Show a progress bar while renaming.
+    pub progress: bool,
+    // Finish a run that was interrupted instead of refusing to start.
+    pub resume: bool,
+    // The most detailed log messages to show.
+    pub log_level: log::LevelFilter,
+    // Write a machine-readable report of the run, to report_file or stdout.
+    pub report_format: Option<ReportFormat>,
+    pub report_file: Option<PathBuf>,
+    // Append a line for every renaming to this file.
+    pub audit_log: Option<PathBuf>,
+    // Write the old and new name of every renamed file, with its lot and
+    // inventory number, to this CSV file after renaming.
+    pub mapping_file: Option<PathBuf>,
+    // Record the runs that renamed files in this SQLite database, so that
+    // they can be looked up and undone later. A leading `~` stands for the
+    // home directory.
+    pub history: Option<PathBuf>,
+    // Write the plan as an HTML page with a thumbnail of every file to this
+    // file before renaming.
+    pub preview_html: Option<PathBuf>,
+    // Match file names and write new names in this Unicode normalization form.
+    pub normalization: Option<Normalization>,
+    // A Rhai script that composes the new names.
+    pub script: Option<PathBuf>,
+    // Make new names legal on all platforms, by replacing illegal characters
+    // and shortening long names, instead of refusing them.
+    pub sanitize: bool,
+    // Place the new files in a subdirectory per lot of the target directory,
+    // such as `0001/0001_1.jpg` with a lot width of 4.
+    pub lot_dirs: bool,
+    // Colour the status of the files in the plan table.
+    pub color: bool,
+    // Look for files to rename that are byte-identical, which are reported or,
+    // in strict mode, fail the run.
+    pub find_duplicates: bool,
+    // Move files replaced when overwriting to a subdirectory per run of this
+    // directory, relative to the target directory, instead of destroying
+    // them.
+    pub backup: Option<PathBuf>,
+    // Decode the files to rename and leave alone those that are not intact
+    // JPEG, PNG or TIFF images, which are reported or, in strict mode, fail
+    // the run.
+    pub verify_images: bool,
+    // Leave alone files to rename that are smaller than min_size or larger
+    // than max_size, such as the empty files of failed uploads, which are
+    // reported or, in strict mode, fail the run.
+    pub min_size: Option<FileSize>,
+    pub max_size: Option<FileSize>,
+    // Leave alone images smaller than these dimensions in either orientation,
+    // which are reported by lot or, in strict mode, fail the run.
+    pub min_dimensions: Option<Dimensions>,
+    // Extensions of sidecar files, such as xmp, which are renamed along with
+    // the file of the same stem.
+    pub sidecars: Vec<String>,
+    // Rename the files whose name as a whole matches this regular expression
+    // after its capture groups, which the template refers to by name or
+    // number, instead of after a data file.
+    pub from_regex: Option<String>,
+    // Try a renaming failing with a transient error, such as a busy file on a
+    // network share, this many times again, waiting retry_delay before the
+    // first retry and twice as long before each next one.
+    pub retries: u32,
+    pub retry_delay: Duration,
+    // Leave files that another program holds, such as photos open in an
+    // image editor on Windows, under their old names and report them,
+    // instead of failing the run.
+    pub skip_locked: bool,
+    // Rename only a chunk of the files to rename, ordered by name: skipping
+    // this many and taking at most limit, so a large plan can be applied and
+    // checked in parts. Chunks hold whole objects.
+    pub skip: usize,
+    pub limit: Option<usize>,
+    // Rename symbolic links to files along with regular files. Other entries,
+    // such as subdirectories, are never renamed.
+    pub symlinks: bool,
+    // Measure how long reading the data file, listing the files, planning and
+    // renaming took, and report it.
+    pub timings: bool,
+    // The most files a lot may have, such as the number of images a catalogue
+    // takes per lot. Lots with more are reported, or fail the run as set by
+    // on_crowded_lot; unlimited if not set.
+    pub max_per_lot: Option<usize>,
+    pub on_crowded_lot: CrowdedLotPolicy,
+    // Where the files are renamed; the local file system if not set.
+    pub storage: Option<Arc<dyn Storage>>,
+}
+
+impl Config {
+    pub fn new(data_file: impl Into<PathBuf>, dir: impl Into<PathBuf>) -> Config {
+        Config {
+            data_file: data_file.into(),
+            extra_data_files: vec![],
+            extra_dirs: vec![],
+            dir: dir.into(),
+            dry_run: false,
+            delimiter: b'\t',
+            encoding: None,
+            lot_column: Column::Index(0),
+            inventory_column: Column::Index(8),
+            date_column: None,
+            hero_column: None,
+            has_headers: true,
+            template: String::from(DEFAULT_TEMPLATE),
+            keep_extension: false,
+            lowercase_extension: false,
+            jobs: 1,
+            stage_all: false,
+            format: Format::Csv,
+            sheet: None,
+            widths: None,
+            lot_key: String::from("lot"),
+            inventory_key: String::from("inventory"),
+            query: String::from(DEFAULT_QUERY),
+            copy: false,
+            link: None,
+            output_dir: None,
+            strict: false,
+            on_conflict: ConflictPolicy::Abort,
+            ignore_case: false,
+            trim: false,
+            normalize_lot: false,
+            lots: None,
+            conditions: vec![],
+            match_mode: MatchMode::Prefix,
+            key_columns: vec![],
+            key_separator: String::from("_"),
+            verify: false,
+            post_check: false,
+            preserve_xattrs: false,
+            watch: false,
+            include: vec![],
+            exclude: vec![],
+            on_malformed: MalformedPolicy::Skip,
+            on_bad_row: MalformedPolicy::Skip,
+            on_duplicate: DuplicatePolicy::Error,
+            suffix: SuffixStrategy::default(),
+            resequence: false,
+            lot_width: 0,
+            index_width: 0,
+            lot_transforms: vec![],
+            transforms: vec![],
+            progress: false,
+            resume: false,
+            log_level: log::LevelFilter::Info,
+            report_format: None,
+            report_file: None,
+            audit_log: None,
+            mapping_file: None,
+            history: None,
+            preview_html: None,
+            normalization: None,
+            script: None,
+            sanitize: false,
+            lot_dirs: false,
+            color: false,
+            find_duplicates: false,
+            backup: None,
+            verify_images: false,
+            min_size: None,
+            max_size: None,
+            min_dimensions: None,
+            sidecars: vec![],
+            from_regex: None,
+            retries: 0,
+            retry_delay: retry::DEFAULT_RETRY_DELAY,
+            skip_locked: false,
+            skip: 0,
+            limit: None,
+            symlinks: false,
+            timings: false,
+            max_per_lot: None,
+            on_crowded_lot: CrowdedLotPolicy::Warn,
+            storage: None,
+        }
+    }
+
+    // builder starts a ConfigBuilder for renaming the files in dir after the
+    // data file.
+    pub fn builder(data_file: impl Into<PathBuf>, dir: impl Into<PathBuf>) -> ConfigBuilder {
+        ConfigBuilder::new(data_file, dir)
+    }
+
+    // retry is how renamings failing with a transient error are retried.
+    fn retry(&self) -> Retry {
+        Retry {
+            attempts: self.retries,
+            delay: self.retry_delay,
+        }
+    }
+
+    // storage is where the files are renamed.
+    fn storage(&self) -> Arc<dyn Storage> {
+        self.storage.clone().unwrap_or_else(|| {
+            Arc::new(LocalStorage {
+                verify: self.verify,
+                symlinks: self.symlinks,
+                xattrs: self.preserve_xattrs,
+            })
+        })
+    }
+}
+
+fn validate_dir(file: &Path) -> bool {
+    let result = fs::metadata(file);
+    match result {
+        Ok(metadata) => metadata.is_dir(),
+        Err(_) => false,
+    }
+}
+
+// list_extra_dir lists the files in one of the extra directories, filtered
+// like those in the directory. They are listed by their absolute path, which
+// tells where they came from and which leaves them where they are when joined
+// to the directory.
+fn list_extra_dir(
+    storage: &dyn Storage,
+    config: &Config,
+    dir: &Path,
+) -> Result<Vec<String>, RenameError> {
+    let filter = FileFilter::new(&config.include, &config.exclude)?.ignore_file(dir)?;
+    let dir = std::path::absolute(dir)?;
+    if dir.to_str().is_none() {
+        return Err(RenameError::Io(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{} is not valid Unicode", dir.display()),
+        )));
+    }
+
+    Ok(storage
+        .list_files(&dir)?
+        .into_iter()
+        .filter(|file| filter.matches(file))
+        .filter_map(|file| dir.join(file).to_str().map(str::to_owned))
+        .collect())
+}
+
+// base_name is the name of a file without the directory that files in the
+// extra directories are listed with.
+fn base_name(file: &str) -> &str {
+    match Path::new(file).file_name().and_then(|name| name.to_str()) {
+        Some(name) => name,
+        None => file,
+    }
+}
+
+// list_files lists the names of the files in the directory. Names that are
+// not valid Unicode, which Linux allows, cannot be matched against the data
+// file and are left out with a warning.
+fn list_files(dir: impl AsRef<Path>) -> Vec<String> {
+    list_entries(dir.as_ref(), |_| true)
+}
+
+// list_regular_files lists the names of the regular files in the directory,
+// leaving out subdirectories and other entries. Symbolic links are left out
+// too, unless symlinks is set and they point to a regular file.
+fn list_regular_files(dir: impl AsRef<Path>, symlinks: bool) -> Vec<String> {
+    let dir = dir.as_ref();
+    list_entries(dir, |entry| match entry.file_type() {
+        Ok(file_type) if file_type.is_symlink() => {
+            symlinks && fs::metadata(entry.path()).is_ok_and(|metadata| metadata.is_file())
+        }
+        Ok(file_type) => file_type.is_file(),
+        Err(_) => false,
+    })
+}
+
+// list_non_unicode lists the files in the directory whose names are not valid
+// Unicode, with the invalid bytes replaced, for reporting them as left alone.
+fn list_non_unicode(dir: impl AsRef<Path>) -> Vec<String> {
+    let dir = dir.as_ref();
+    let mut files: Vec<String> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .flatten()
+            .filter(|entry| entry.file_name().to_str().is_none())
+            .map(|entry| dir.join(entry.file_name()).to_string_lossy().into_owned())
+            .collect(),
+        Err(_) => vec![],
+    };
+    files.sort();
+
+    files
+}
+
+// list_entries lists the names of the entries in the directory that are kept.
+fn list_entries(dir: &Path, keep: impl Fn(&fs::DirEntry) -> bool) -> Vec<String> {
+    let mut files: Vec<String> = vec![];
+
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.flatten().filter(|entry| keep(entry)) {
+            match entry.file_name().into_string() {
+                Ok(name) => files.push(name),
+                Err(name) => log::warn!(
+                    "leaving out {}: its name is not valid Unicode",
+                    name.to_string_lossy()
+                ),
+            }
+        }
+    }
+
+    // Directories are read in an order that differs between file systems;
+    // listing them by name keeps runs, and the order of the photos of each
+    // object, the same everywhere.
+    files.sort();
+
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    #[test]
+    fn end_to_end() {
+        // The pattern that new files must match.
+        let expression = regex::Regex::new(r"[1-9][0-9]*_[1-9][1]?.jpg").unwrap();
+
+        let images_dir = std::path::Path::new("tests/files/");
+        let test_dir = std::path::Path::new("tests/tmp/");
+        let data_file = "tests/data.csv";
+
+        let config = Config::new(
+            String::from(data_file),
+            String::from(test_dir.to_str().unwrap()),
+        );
+
+        // Copy tests directory to tmp.
+        let _ = fs::create_dir(test_dir);
+
+        let file_names = list_files(images_dir.to_str().unwrap());
+        for file in file_names.clone() {
+            let old_path = images_dir.join(&file);
+            let new_path = test_dir.join(&file);
+
+            fs::copy(old_path, new_path).unwrap();
+
+            // Assert all old file names don't match the pattern to which the files
+            // ought to be renamed.
+            assert!(!expression.is_match(&file));
+        }
+
+        run(config).expect("Running failed");
+
+        let new_file_names = list_files(test_dir.to_str().unwrap());
+        // The renamed files plus the journal.
+        assert_eq!(file_names.len() + 1, new_file_names.len());
+
+        // Assert all moved files have a name that matches the pattern.
+        for file in new_file_names.iter().filter(|file| !file.starts_with('.')) {
+            assert!(expression.is_match(file));
+        }
+
+        fs::remove_dir_all("tests/tmp").expect("Could not delete tests directory.");
+    }
+
+    // catalogue yields the rows as the reader of a delimited data file would.
+    fn catalogue(
+        rows: impl IntoIterator<Item = csv::StringRecord> + 'static,
+        lot_column: usize,
+        inventory_column: usize,
+    ) -> reader::Rows {
+        let table = reader::Table {
+            headers: None,
+            rows: Box::new(
+                rows.into_iter()
+                    .map(|row| Ok(row.iter().map(String::from).collect())),
+            ),
+        };
+        table
+            .records(&Column::Index(lot_column), &Column::Index(inventory_column))
+            .unwrap()
+            .rows
+    }
+
+    #[test]
+    fn determine_file_names() {
+        let data = String::from("1		\"Henricus Johannes (Harrie) Kuyten, Utrecht 1883-1952 Schoorl ...\"	\"Henricus Johannes (Harrie) Kuyten, Utrecht 1883-1952 Schoorl, Beach view with various people, oil on canvas, 43 x 36 cm.\"	EUR	4000	6000	3000	00243878										\n2		\"Henricus Johannes (Harrie) Kuyten, Utrecht 1883-1952 Schoorl ...\"	\"Henricus Johannes (Harrie) Kuyten, Utrecht 1883-1952 Schoorl, Beach view, pastel drawing, dated 1951, 31,5 x 23 cm\"	EUR	500	700	380	00243880										\n3		\"Very large antique blue/white Chinese porcelain lidded vase ...\"	\"Very large antique blue/white Chinese porcelain lidded vase with decoration of floral motifs, Qing Dynasty, approx. h.59 cm.\"	EUR	2000	3000	1500	00243344										");
+
+        let mut rows: Vec<csv::StringRecord> = vec![];
+
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .delimiter(b'\t')
+            .from_reader(data.as_bytes());
+        for result in rdr.records() {
+            // The iterator yields Result<StringRecord, Error>, so we check the
+            // error here.
+            let record = result.unwrap();
+            rows.push(record);
+        }
+
+        let file_names = vec![
+            "00243878.1.jpg".to_string(),
+            "00243878.2.jpg".to_string(),
+            "00243878.3.jpg".to_string(),
+            "00243880.1.jpg".to_string(),
+            "00243880.2.jpg".to_string(),
+            "00243344.1.jpg".to_string(),
+            "00243344.2.jpg".to_string(),
+            "00243344.3.jpg".to_string(),
+        ];
+
+        let matches = determine_renamings(
+            catalogue(rows, 0, 8),
+            file_names,
+            0,
+            8,
+            &Matching::default(),
+            &Naming::default(),
+            MalformedPolicy::Skip,
+        )
+        .unwrap();
+
+        let expected_renamings: BTreeMap<String, String> = BTreeMap::from([
+            ("00243878.1.jpg".to_string(), "1_1.jpg".to_string()),
+            ("00243878.2.jpg".to_string(), "1_2.jpg".to_string()),
+            ("00243878.3.jpg".to_string(), "1_3.jpg".to_string()),
+            ("00243880.1.jpg".to_string(), "2_1.jpg".to_string()),
+            ("00243880.2.jpg".to_string(), "2_2.jpg".to_string()),
+            ("00243344.1.jpg".to_string(), "3_1.jpg".to_string()),
+            ("00243344.2.jpg".to_string(), "3_2.jpg".to_string()),
+            ("00243344.3.jpg".to_string(), "3_3.jpg".to_string()),
+        ]);
+
+        assert_eq!(expected_renamings, matches.renamings);
+        assert!(matches.unmatched_rows.is_empty());
+    }
+
+    #[test]
+    fn determine_file_names_with_custom_columns() {
+        let data = "00243878;1\n00243880;2\n";
+
+        let rows: Vec<csv::StringRecord> = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .delimiter(b';')
+            .from_reader(data.as_bytes())
+            .records()
+            .map(|result| result.unwrap())
+            .collect();
+
+        let file_names = vec!["00243878.1.jpg".to_string(), "00243880.2.jpg".to_string()];
+
+        let renamings = determine_renamings(
+            catalogue(rows, 1, 0),
+            file_names,
+            1,
+            0,
+            &Matching::default(),
+            &Naming::default(),
+            MalformedPolicy::Skip,
+        )
+        .unwrap()
+        .renamings;
+
+        let expected_renamings: BTreeMap<String, String> = BTreeMap::from([
+            ("00243878.1.jpg".to_string(), "1_1.jpg".to_string()),
+            ("00243880.2.jpg".to_string(), "2_2.jpg".to_string()),
+        ]);
+
+        assert_eq!(expected_renamings, renamings)
+    }
+
+    #[test]
+    fn determine_renamings_collects_bad_rows() {
+        let rows = vec![
+            csv::StringRecord::from(vec!["1", "00243878"]),
+            csv::StringRecord::from(vec!["2"]),
+            csv::StringRecord::from(vec!["3", "00243344"]),
+        ];
+        let files = vec![
+            String::from("00243878.1.jpg"),
+            String::from("00243344.1.jpg"),
+        ];
+
+        let matches = determine_renamings(
+            catalogue(rows, 0, 1),
+            files.clone(),
+            0,
+            1,
+            &Matching::default(),
+            &Naming::default(),
+            MalformedPolicy::Skip,
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![RowError {
+                row: 2,
+                column: Some(1),
+                message: String::from("the row has no such column"),
+            }],
+            matches.bad_rows
+        );
+        assert_eq!(2, matches.renamings.len());
+
+        // A record the reader cannot parse is skipped in the same way.
+        let reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader("1,00243878\n2\n3,00243344\n".as_bytes());
+        let rows = reader::Table {
+            headers: None,
+            rows: Box::new(reader.into_records().map(|row| {
+                row.map(|row| row.iter().map(String::from).collect())
+                    .map_err(error::csv_error)
+            })),
+        };
+        let rows = rows
+            .records(&Column::Index(0), &Column::Index(1))
+            .unwrap()
+            .rows;
+
+        let matches = determine_renamings(
+            rows,
+            files,
+            0,
+            1,
+            &Matching::default(),
+            &Naming::default(),
+            MalformedPolicy::Skip,
+        )
+        .unwrap();
+
+        assert_eq!(1, matches.bad_rows.len());
+        assert_eq!(
+            (2, None),
+            (matches.bad_rows[0].row, matches.bad_rows[0].column)
+        );
+        assert_eq!(2, matches.renamings.len());
+    }
+
+    #[test]
+    fn determine_file_names_with_template() {
+        let rows = vec![csv::StringRecord::from(vec!["7", "00243878", "A"])];
+        let file_names = vec!["00243878.1.jpg".to_string()];
+        let naming = Naming {
+            template: Template::parse("{column:2}{lot}-{index}-{stem}.{ext}").unwrap(),
+            ..Naming::default()
+        };
+
+        let renamings = determine_renamings(
+            catalogue(rows, 0, 1),
+            file_names,
+            0,
+            1,
+            &Matching::default(),
+            &naming,
+            MalformedPolicy::Skip,
+        )
+        .unwrap()
+        .renamings;
+
+        assert_eq!(
+            BTreeMap::from([(
+                "00243878.1.jpg".to_string(),
+                "A7-1-00243878.1.jpg".to_string()
+            )]),
+            renamings
+        );
+    }
+
+    #[test]
+    fn compose_new_name_keeps_extension() {
+        let row = csv::StringRecord::new();
+        let fields = |file_name| NameFields {
+            lot: "1",
+            inventory: "00243878",
+            index: "2",
+            rest: "",
+            file_name,
+            row: &row,
+            date: None,
+        };
+        let mut naming = Naming {
+            keep_extension: true,
+            ..Naming::default()
+        };
+
+        assert_eq!(
+            "1_2.CR2",
+            compose_new_name(&naming, &fields("00243878.2.CR2"), "1")
+        );
+        assert_eq!(
+            "1_2.jpg",
+            compose_new_name(&naming, &fields("00243878"), "1")
+        );
+
+        naming.lowercase_extension = true;
+        assert_eq!(
+            "1_2.cr2",
+            compose_new_name(&naming, &fields("00243878.2.CR2"), "1")
+        );
+    }
+
+    #[test]
+    fn determine_renamings_reports_unmatched_rows() {
+        let rows = vec![
+            csv::StringRecord::from(vec!["1", "00243878"]),
+            csv::StringRecord::from(vec!["2", "00243880"]),
+        ];
+        let file_names = vec!["00243878.1.jpg".to_string()];
+
+        let unmatched_rows = determine_renamings(
+            catalogue(rows, 0, 1),
+            file_names,
+            0,
+            1,
+            &Matching::default(),
+            &Naming::default(),
+            MalformedPolicy::Skip,
+        )
+        .unwrap()
+        .unmatched_rows;
+
+        assert_eq!(
+            vec![UnmatchedRow {
+                row: 2,
+                lot: "2".to_string(),
+                inventory: "00243880".to_string(),
+            }],
+            unmatched_rows
+        );
+    }
+
+    #[test]
+    fn determine_renamings_with_malformed_file_names() {
+        let rows = vec![csv::StringRecord::from(vec!["1", "00243878"])];
+        let file_names = vec![
+            "00243878.1.jpg".to_string(),
+            "00243878.jpg".to_string(),
+            "00243878".to_string(),
+        ];
+
+        let matches = determine_renamings(
+            catalogue(rows.clone(), 0, 1),
+            file_names.clone(),
+            0,
+            1,
+            &Matching::default(),
+            &Naming::default(),
+            MalformedPolicy::Skip,
+        )
+        .unwrap();
+
+        assert_eq!(1, matches.renamings.len());
+        assert_eq!(
+            vec!["00243878.jpg".to_string(), "00243878".to_string()],
+            matches.malformed_files
+        );
+
+        let result = determine_renamings(
+            catalogue(rows, 0, 1),
+            file_names,
+            0,
+            1,
+            &Matching::default(),
+            &Naming::default(),
+            MalformedPolicy::Error,
+        );
+
+        assert!(matches!(result, Err(RenameError::MalformedFileName(_))));
+    }
+
+    #[test]
+    fn determine_renamings_resequenced() {
+        let rows = vec![csv::StringRecord::from(vec!["1", "00243878"])];
+        let file_names = vec![
+            "00243878.3.jpg".to_string(),
+            "00243878.7.jpg".to_string(),
+            "00243878.10.jpg".to_string(),
+        ];
+        let naming = Naming {
+            resequence: true,
+            ..Naming::default()
+        };
+
+        let renamings = determine_renamings(
+            catalogue(rows, 0, 1),
+            file_names,
+            0,
+            1,
+            &Matching::default(),
+            &naming,
+            MalformedPolicy::Skip,
+        )
+        .unwrap()
+        .renamings;
+
+        assert_eq!("1_1.jpg", renamings["00243878.3.jpg"]);
+        assert_eq!("1_2.jpg", renamings["00243878.7.jpg"]);
+        assert_eq!("1_3.jpg", renamings["00243878.10.jpg"]);
+    }
+
+    #[test]
+    fn determine_renamings_with_hero() {
+        let rows = vec![
+            csv::StringRecord::from(vec!["1", "00243878", "3"]),
+            csv::StringRecord::from(vec!["2", "00243880", ""]),
+        ];
+        let file_names = vec![
+            "00243878.1.jpg".to_string(),
+            "00243878.2.jpg".to_string(),
+            "00243878.3.jpg".to_string(),
+            "00243880.2.jpg".to_string(),
+        ];
+        let naming = Naming {
+            hero_column: Some(2),
+            ..Naming::default()
+        };
+
+        let renamings = determine_renamings(
+            catalogue(rows, 0, 1),
+            file_names,
+            0,
+            1,
+            &Matching::default(),
+            &naming,
+            MalformedPolicy::Skip,
+        )
+        .unwrap()
+        .renamings;
+
+        assert_eq!("1_1.jpg", renamings["00243878.3.jpg"]);
+        assert_eq!("1_2.jpg", renamings["00243878.1.jpg"]);
+        assert_eq!("1_3.jpg", renamings["00243878.2.jpg"]);
+        // Without a hero, the photo numbers are kept.
+        assert_eq!("2_2.jpg", renamings["00243880.2.jpg"]);
+    }
+
+    #[test]
+    fn determine_renamings_reports_lots_that_cannot_be_transformed() {
+        // Padded lot 012 cannot drop by 100, and kept as is it would take the
+        // names of lot 112.
+        let rows = vec![
+            csv::StringRecord::from(vec!["12", "00243878"]),
+            csv::StringRecord::from(vec!["112", "00243880"]),
+        ];
+        let file_names = vec!["00243878.1.jpg".to_string(), "00243880.1.jpg".to_string()];
+        let naming = Naming {
+            lot_width: 3,
+            lot_transforms: vec!["subtract:100".parse().unwrap()],
+            ..Naming::default()
+        };
+
+        let matches = determine_renamings(
+            catalogue(rows, 0, 1),
+            file_names,
+            0,
+            1,
+            &Matching::default(),
+            &naming,
+            MalformedPolicy::Skip,
+        )
+        .unwrap();
+
+        assert_eq!(
+            BTreeMap::from([("00243880.1.jpg".to_string(), "012_1.jpg".to_string())]),
+            matches.renamings
+        );
+        assert_eq!(
+            vec![RowError {
+                row: 1,
+                column: Some(0),
+                message: String::from("cannot subtract 100 from lot 012"),
+            }],
+            matches.bad_rows
+        );
+    }
+
+    #[test]
+    fn determine_renamings_normalizes_lots() {
+        let rows = vec![
+            csv::StringRecord::from(vec!["1.001", "00243878"]),
+            csv::StringRecord::from(vec!["0101A", "00243880"]),
+        ];
+        let file_names = vec!["00243878.1.jpg".to_string(), "00243880.1.jpg".to_string()];
+        let matching = Matching {
+            normalize_lot: true,
+            ..Matching::default()
+        };
+
+        let renamings = determine_renamings(
+            catalogue(rows, 0, 1),
+            file_names,
+            0,
+            1,
+            &matching,
+            &Naming::default(),
+            MalformedPolicy::Skip,
+        )
+        .unwrap()
+        .renamings;
+
+        assert_eq!("1001_1.jpg", renamings["00243878.1.jpg"]);
+        assert_eq!("101A_1.jpg", renamings["00243880.1.jpg"]);
+    }
+
+    #[test]
+    fn compose_new_name_pads_numbers() {
+        let row = csv::StringRecord::new();
+        let fields = NameFields {
+            lot: "1",
+            inventory: "00243878",
+            index: "2",
+            rest: "",
+            file_name: "00243878.2.jpg",
+            row: &row,
+            date: None,
+        };
+        let mut naming = Naming {
+            lot_width: 4,
+            index_width: 2,
+            ..Naming::default()
+        };
+
+        let lot = |naming: &Naming| format_lot(naming, fields.lot).unwrap();
+        assert_eq!(
+            "0001_02.jpg",
+            compose_new_name(&naming, &fields, &lot(&naming))
+        );
+
+        naming.lot_width = 0;
+        assert_eq!(
+            "1_02.jpg",
+            compose_new_name(&naming, &fields, &lot(&naming))
+        );
+
+        naming.index_width = 1;
+        assert_eq!("1_2.jpg", compose_new_name(&naming, &fields, &lot(&naming)));
+    }
+
+    #[test]
+    fn compose_new_name_applies_transforms() {
+        let row = csv::StringRecord::from(vec!["Café Schoorl"]);
+        let fields = NameFields {
+            lot: "1",
+            inventory: "00243878",
+            index: "2",
+            rest: "",
+            file_name: "00243878.2.JPG",
+            row: &row,
+            date: None,
+        };
+        let naming = Naming {
+            template: Template::parse("{lot} {column:0}.{ext}").unwrap(),
+            transforms: vec![
+                Transform::StripDiacritics,
+                Transform::Underscores,
+                Transform::Lowercase,
+            ],
+            ..Naming::default()
+        };
+
+        assert_eq!(
+            "1_cafe_schoorl.jpg",
+            compose_new_name(&naming, &fields, "1")
+        );
+    }
+
+    #[test]
+    fn compose_new_name_transforms_lot() {
+        let row = csv::StringRecord::new();
+        let fields = NameFields {
+            lot: "12",
+            inventory: "00243878",
+            index: "2",
+            rest: "",
+            file_name: "00243878.2.jpg",
+            row: &row,
+            date: None,
+        };
+        let naming = Naming {
+            lot_width: 4,
+            lot_transforms: vec![
+                LotTransform::Add(1000),
+                LotTransform::Prefix(String::from("A-")),
+            ],
+            ..Naming::default()
+        };
+
+        let lot = format_lot(&naming, fields.lot).unwrap();
+        assert_eq!("A-1012_2.jpg", compose_new_name(&naming, &fields, &lot));
+    }
+
+    #[test]
+    fn determine_renamings_with_dates() {
+        let dir = Path::new("tests/tmp_dates");
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir(dir).unwrap();
+        let file = fs::File::create(dir.join("00243880.1.jpg")).unwrap();
+        let modified = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_714_557_600);
+        file.set_modified(modified).unwrap();
+
+        let rows = vec![
+            csv::StringRecord::from(vec!["1", "00243878", "01-05-2024"]),
+            csv::StringRecord::from(vec!["2", "00243880", "soon"]),
+        ];
+        let files = vec![
+            String::from("00243878.1.jpg"),
+            String::from("00243880.1.jpg"),
+        ];
+        let mut naming = Naming {
+            template: Template::parse("{date}_{lot}_{index}.jpg").unwrap(),
+            source_dir: dir.to_path_buf(),
+            date_column: Some(2),
+            ..Naming::default()
+        };
+
+        let by_column = determine_renamings(
+            catalogue(rows.clone(), 0, 1),
+            files.clone(),
+            0,
+            1,
+            &Matching::default(),
+            &naming,
+            MalformedPolicy::Skip,
+        );
+        naming.date_column = None;
+        let by_file = determine_renamings(
+            catalogue(rows[1..].to_vec(), 0, 1),
+            files,
+            0,
+            1,
+            &Matching::default(),
+            &naming,
+            MalformedPolicy::Skip,
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(
+            "row 2, column 3: \"soon\" is not a date",
+            by_column.unwrap().bad_rows[0].to_string()
+        );
+        assert_eq!(
+            Some(&String::from("20240501_2_1.jpg")),
+            by_file.unwrap().renamings.get("00243880.1.jpg")
+        );
+
+        let matches = determine_renamings(
+            catalogue(rows[..1].to_vec(), 0, 1),
+            vec![String::from("00243878.1.jpg")],
+            0,
+            1,
+            &Matching::default(),
+            &Naming {
+                date_column: Some(2),
+                ..naming
+            },
+            MalformedPolicy::Skip,
+        )
+        .unwrap();
+        assert_eq!(
+            Some(&String::from("20240501_1_1.jpg")),
+            matches.renamings.get("00243878.1.jpg")
+        );
+    }
+
+    #[test]
+    fn determine_renamings_refuses_illegal_names() {
+        let rows = || vec![csv::StringRecord::from(vec!["1", "00243878", "A/B: C"])];
+        let files = || vec![String::from("00243878.1.jpg")];
+        let mut naming = Naming {
+            template: Template::parse("{lot} {column:2}.jpg").unwrap(),
+            ..Naming::default()
+        };
+
+        let result = determine_renamings(
+            catalogue(rows(), 0, 1),
+            files(),
+            0,
+            1,
+            &Matching::default(),
+            &naming,
+            MalformedPolicy::Skip,
+        );
+        assert!(matches!(result, Err(RenameError::IllegalName { .. })));
+
+        naming.sanitize = true;
+        let matches = determine_renamings(
+            catalogue(rows(), 0, 1),
+            files(),
+            0,
+            1,
+            &Matching::default(),
+            &naming,
+            MalformedPolicy::Skip,
+        )
+        .unwrap();
+        assert_eq!(
+            Some(&"1 A_B_ C.jpg".to_string()),
+            matches.renamings.get("00243878.1.jpg")
+        );
+    }
+
+    #[test]
+    fn filter_object_files_test() {
+        let files = list_files("tests/files");
+        let object_id = String::from("00243344");
+
+        assert_eq!(
+            HashSet::from([
+                "00243344.6.jpg",
+                "00243344.7.jpg",
+                "00243344.5.jpg",
+                "00243344.4.jpg",
+                "00243344.1.jpg",
+                "00243344.3.jpg",
+                "00243344.2.jpg",
+            ]),
+            FileIndex::new(files, &Matching::default())
+                .files_of(&object_id)
+                .iter()
+                .map(String::as_str)
+                .collect(),
+        );
+    }
+
+    #[test]
+    fn determine_renamings_with_composite_key() {
+        let rows = vec![
+            csv::StringRecord::from(vec!["1", "AB12", "00243878"]),
+            csv::StringRecord::from(vec!["2", "CD34", "00243878"]),
+        ];
+        let files = vec![
+            String::from("AB12-00243878.1.jpg"),
+            String::from("CD34-00243878.1.jpg"),
+        ];
+        let matching = Matching {
+            key_columns: vec![1, 2],
+            key_separator: String::from("-"),
+            ..Matching::default()
+        };
+
+        let matches = determine_renamings(
+            catalogue(rows, 0, 2),
+            files,
+            0,
+            2,
+            &matching,
+            &Naming::default(),
+            MalformedPolicy::Skip,
+        )
+        .unwrap();
+
+        assert_eq!(
+            Some(&"1_1.jpg".to_string()),
+            matches.renamings.get("AB12-00243878.1.jpg")
+        );
+        assert_eq!(
+            Some(&"2_1.jpg".to_string()),
+            matches.renamings.get("CD34-00243878.1.jpg")
+        );
+    }
+
+    #[test]
+    fn determine_renamings_for_selected_lots() {
+        let rows = vec![
+            csv::StringRecord::from(vec!["1", "00243878"]),
+            csv::StringRecord::from(vec!["2", "00243880"]),
+        ];
+        let files = vec![
+            String::from("00243878.1.jpg"),
+            String::from("00243880.1.jpg"),
+        ];
+        let matching = Matching {
+            lots: Some("2".parse().unwrap()),
+            ..Matching::default()
+        };
+
+        let matches = determine_renamings(
+            catalogue(rows, 0, 1),
+            files,
+            0,
+            1,
+            &matching,
+            &Naming::default(),
+            MalformedPolicy::Skip,
+        )
+        .unwrap();
+
+        assert_eq!(
+            BTreeMap::from([("00243880.1.jpg".to_string(), "2_1.jpg".to_string())]),
+            matches.renamings
+        );
+        assert_eq!(vec!["00243878.1.jpg"], matches.ignored_files);
+        assert!(matches.unmatched_rows.is_empty());
+    }
+
+    #[test]
+    fn determine_renamings_for_rows_meeting_conditions() {
+        let rows = vec![
+            csv::StringRecord::from(vec!["1", "00243878", "approved"]),
+            csv::StringRecord::from(vec!["2", "00243880", "draft"]),
+            csv::StringRecord::from(vec!["3", "00243344"]),
+        ];
+        let files = vec![
+            String::from("00243878.1.jpg"),
+            String::from("00243880.1.jpg"),
+            String::from("00243344.1.jpg"),
+        ];
+        let matching = Matching {
+            conditions: vec![(2, "2=approved".parse().unwrap())],
+            ..Matching::default()
+        };
+
+        let matches = determine_renamings(
+            catalogue(rows, 0, 1),
+            files,
+            0,
+            1,
+            &matching,
+            &Naming::default(),
+            MalformedPolicy::Skip,
+        )
+        .unwrap();
+
+        assert_eq!(
+            BTreeMap::from([("00243878.1.jpg".to_string(), "1_1.jpg".to_string())]),
+            matches.renamings
+        );
+        assert_eq!(vec!["00243880.1.jpg"], matches.ignored_files);
+        // A row without the column cannot be told to meet the condition.
+        assert_eq!(
+            vec![3],
+            matches
+                .bad_rows
+                .iter()
+                .map(|row| row.row)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn determine_renamings_of_duplicate_rows() {
+        let rows = vec![
+            csv::StringRecord::from(vec!["1", "00243878"]),
+            csv::StringRecord::from(vec!["1", "00243878"]),
+            csv::StringRecord::from(vec!["2", "00243878"]),
+        ];
+        let renamings = |on_duplicate| {
+            let matching = Matching {
+                on_duplicate,
+                ..Matching::default()
+            };
+            determine_renamings(
+                catalogue(rows.clone(), 0, 1),
+                vec![String::from("00243878.1.jpg")],
+                0,
+                1,
+                &matching,
+                &Naming::default(),
+                MalformedPolicy::Skip,
+            )
+            .unwrap()
+        };
+
+        let first = renamings(DuplicatePolicy::First);
+        assert_eq!(
+            Some(&"1_1.jpg".to_string()),
+            first.renamings.get("00243878.1.jpg")
+        );
+        assert_eq!(
+            vec![DuplicateRow {
+                row: 3,
+                lot: "2".to_string(),
+                first_row: 1,
+                first_lot: "1".to_string(),
+                inventory: "00243878".to_string(),
+            }],
+            first.duplicate_rows
+        );
+
+        let last = renamings(DuplicatePolicy::Last);
+        assert_eq!(
+            Some(&"2_1.jpg".to_string()),
+            last.renamings.get("00243878.1.jpg")
+        );
+        assert_eq!(first.duplicate_rows, last.duplicate_rows);
+    }
+
+    #[test]
+    fn determine_renamings_settles_duplicate_rows_first() {
+        let rows = vec![
+            csv::StringRecord::from(vec!["1", "00243878"]),
+            csv::StringRecord::from(vec!["2", "00243878"]),
+            csv::StringRecord::from(vec!["1", "00243878"]),
+            csv::StringRecord::from(vec!["3", "00243880"]),
+            csv::StringRecord::from(vec!["4", "00243880"]),
+        ];
+        let files = vec![
+            String::from("00243878.1.jpg"),
+            String::from("00243880.1.jpg"),
+        ];
+        let renamings = |lots: Option<&str>| {
+            let matching = Matching {
+                on_duplicate: DuplicatePolicy::Last,
+                lots: lots.map(|lots| lots.parse().unwrap()),
+                ..Matching::default()
+            };
+            determine_renamings(
+                catalogue(rows.clone(), 0, 1),
+                files.clone(),
+                0,
+                1,
+                &matching,
+                &Naming::default(),
+                MalformedPolicy::Skip,
+            )
+            .unwrap()
+        };
+
+        // The last row names the files, though an earlier row had its lot.
+        let all = renamings(None);
+        assert_eq!(
+            Some(&"1_1.jpg".to_string()),
+            all.renamings.get("00243878.1.jpg")
+        );
+        assert_eq!(
+            Some(&"4_1.jpg".to_string()),
+            all.renamings.get("00243880.1.jpg")
+        );
+        assert_eq!(2, all.duplicate_rows.len());
+
+        // The last row of an object that is left out leaves its files alone,
+        // even if an earlier row was not left out.
+        let third = renamings(Some("1,3"));
+        assert_eq!(
+            vec![("00243878.1.jpg", "1_1.jpg")],
+            third
+                .renamings
+                .iter()
+                .map(|(old, new)| (old.as_str(), new.as_str()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(vec!["00243880.1.jpg"], third.ignored_files);
+        assert!(third.unmatched_rows.is_empty());
+    }
+
+    #[test]
+    fn filter_object_files_at_boundary() {
+        let files = vec![
+            String::from("0024.1.jpg"),
+            String::from("0024_2.jpg"),
+            String::from("0024"),
+            String::from("00243344.1.jpg"),
+        ];
+        let matching = Matching {
+            mode: MatchMode::Boundary,
+            ..Matching::default()
+        };
+
+        assert_eq!(
+            4,
+            FileIndex::new(files.clone(), &Matching::default())
+                .files_of("0024")
+                .len()
+        );
+        assert_eq!(
+            vec!["0024.1.jpg", "0024_2.jpg", "0024"],
+            FileIndex::new(files, &matching).files_of("0024")
+        );
+    }
+
+    #[test]
+    fn filter_object_files_ignoring_case() {
+        let files = vec![
+            String::from("a00243344.1.jpg"),
+            String::from("A00243880.1.jpg"),
+        ];
+        let matching = Matching {
+            ignore_case: true,
+            trim: true,
+            ..Matching::default()
+        };
+
+        assert!(FileIndex::new(files.clone(), &Matching::default())
+            .files_of("A00243344")
+            .is_empty());
+        assert_eq!(
+            vec!["a00243344.1.jpg"],
+            FileIndex::new(files, &matching).files_of(matching.clean(" A00243344 "))
+        );
+    }
+
+    #[test]
+    fn filter_object_files_normalized() {
+        // A decomposed name, as macOS hands out.
+        let files = vec![String::from("Cafe\u{301}.1.jpg")];
+        let matching = Matching {
+            normalization: Some(Normalization::Nfc),
+            ..Matching::default()
+        };
+
+        assert!(FileIndex::new(files.clone(), &Matching::default())
+            .files_of("Caf\u{e9}")
+            .is_empty());
+        assert_eq!(
+            files,
+            FileIndex::new(files.clone(), &matching).files_of("Caf\u{e9}")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn list_files_reports_invalid_names() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = Path::new("tests/tmp_invalid_names");
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir(dir).unwrap();
+        fs::write(dir.join("00243878.1.jpg"), "").unwrap();
+        fs::write(
+            dir.join(std::ffi::OsStr::from_bytes(b"00243878.\xff.jpg")),
+            "",
+        )
+        .unwrap();
+
+        let files = list_files(dir);
+        let non_unicode = list_non_unicode(dir);
+
+        fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(vec!["00243878.1.jpg"], files);
+        assert_eq!(
+            vec![String::from(
+                "tests/tmp_invalid_names/00243878.\u{fffd}.jpg"
+            )],
+            non_unicode
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn list_regular_files_only() {
+        let dir = Path::new("tests/tmp_regular_files");
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir(dir).unwrap();
+        fs::write(dir.join("00243878.1.jpg"), "").unwrap();
+        fs::create_dir(dir.join("00243878.2.jpg")).unwrap();
+        std::os::unix::fs::symlink("00243878.1.jpg", dir.join("00243878.3.jpg")).unwrap();
+        std::os::unix::fs::symlink("00243878.2.jpg", dir.join("00243878.4.jpg")).unwrap();
+
+        let files = list_regular_files(dir, false);
+        let with_symlinks = list_regular_files(dir, true);
+        let all = list_files(dir);
+
+        fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(vec!["00243878.1.jpg"], files);
+        // Links to directories are no files either.
+        assert_eq!(vec!["00243878.1.jpg", "00243878.3.jpg"], with_symlinks);
+        assert_eq!(4, all.len());
+    }
+
+    #[test]
+    fn list_files_by_name() {
+        let files = list_files("tests/files");
+
+        let mut sorted = files.clone();
+        sorted.sort();
+        assert_eq!(sorted, files);
+    }
+
+    #[test]
+    fn read_dir_contents() {
+        let dir = "tests/files";
+
+        // The order in which a directory is read differs between file systems.
+        assert_eq!(
+            HashSet::from([
+                "00243880.6.jpg",
+                "00243880.4.jpg",
+                "00243880.5.jpg",
+                "00243880.1.jpg",
+                "00243880.2.jpg",
+                "00243880.3.jpg",
+                "00243344.6.jpg",
+                "00243344.7.jpg",
+                "00243878.1.jpg",
+                "00243344.5.jpg",
+                "00243878.3.jpg",
+                "00243878.2.jpg",
+                "00243344.4.jpg",
+                "00243878.6.jpg",
+                "00243878.7.jpg",
                 "00243344.1.jpg",
                 "00243878.5.jpg",
                 "00243344.3.jpg",
                 "00243344.2.jpg",
                 "00243878.4.jpg",
+            ]),
+            list_files(dir).iter().map(String::as_str).collect(),
+        );
+    }
+
+    #[test]
+    fn conflicts_abort_before_renaming() {
+        let images_dir = std::path::Path::new("tests/files/");
+        let test_dir = std::path::Path::new("tests/tmp_conflict/");
+
+        let _ = fs::create_dir(test_dir);
+
+        let file_names = list_files(images_dir.to_str().unwrap());
+        for file in &file_names {
+            fs::copy(images_dir.join(file), test_dir.join(file)).unwrap();
+        }
+        fs::write(test_dir.join("3_1.jpg"), "").unwrap();
+        let file_names = list_files(test_dir.to_str().unwrap());
+
+        let config = Config::new(
+            String::from("tests/data.csv"),
+            String::from(test_dir.to_str().unwrap()),
+        );
+
+        let err = run(config).expect_err("Running should fail on conflicts");
+        assert!(err
+            .to_string()
+            .contains("00243344.1.jpg cannot be renamed to 3_1.jpg"));
+        assert_eq!(file_names, list_files(test_dir.to_str().unwrap()));
+
+        fs::remove_dir_all(test_dir).expect("Could not delete tests directory.");
+    }
+
+    #[test]
+    fn failed_rename_rolls_back_completed_renamings() {
+        let test_dir = std::path::Path::new("tests/tmp_rollback/");
+
+        let _ = fs::create_dir(test_dir);
+
+        let file_names = vec![
+            "00243878.1.jpg",
+            "00243878.2.jpg",
+            "00243878.3.jpg",
+            // The new name of the last file is taken, so its renaming fails.
+            "1_3.jpg",
+        ];
+        for file in &file_names {
+            fs::write(test_dir.join(file), "").unwrap();
+        }
+
+        let renamings: BTreeMap<String, String> = BTreeMap::from([
+            ("00243878.1.jpg".to_string(), "1_1.jpg".to_string()),
+            ("00243878.2.jpg".to_string(), "1_2.jpg".to_string()),
+            ("00243878.3.jpg".to_string(), "1_3.jpg".to_string()),
+        ]);
+
+        let transfer = Transfer {
+            storage: &LocalStorage::default(),
+            source: test_dir,
+            target: test_dir,
+            operation: Operation::Rename,
+            verify: false,
+            overwrite: false,
+            backup: None,
+            state: None,
+            audit: None,
+            retry: Retry::default(),
+            skip_locked: false,
+        };
+        let err = rename_all_files(&transfer, renamings.clone(), vec![], 1, false, false)
+            .expect_err("Renaming should fail");
+        let RenameError::Rollback(err) = err else {
+            panic!("expected a rollback error, got {:?}", err);
+        };
+
+        assert_eq!("00243878.3.jpg", err.old_name);
+        assert!(err.not_rolled_back.is_empty());
+        assert_eq!(file_names, list_files(test_dir.to_str().unwrap()));
+
+        // Staged files are given back their old names too.
+        let err = rename_all_files(&transfer, renamings, vec![], 1, false, true)
+            .expect_err("Renaming should fail");
+        let RenameError::Rollback(err) = err else {
+            panic!("expected a rollback error, got {:?}", err);
+        };
+
+        assert_eq!(".rename-tmp.00243878.3.jpg", err.old_name);
+        assert!(err.not_rolled_back.is_empty());
+        assert_eq!(file_names, list_files(test_dir.to_str().unwrap()));
+
+        fs::remove_dir_all(test_dir).expect("Could not delete tests directory.");
+    }
+
+    #[test]
+    fn parallel_renaming() {
+        let images_dir = std::path::Path::new("tests/files/");
+        let test_dir = std::path::Path::new("tests/tmp_parallel/");
+
+        let _ = fs::create_dir(test_dir);
+
+        let file_names = list_files(images_dir.to_str().unwrap());
+        for file in &file_names {
+            fs::copy(images_dir.join(file), test_dir.join(file)).unwrap();
+        }
+
+        let mut config = Config::new(
+            String::from("tests/data.csv"),
+            String::from(test_dir.to_str().unwrap()),
+        );
+        config.jobs = 4;
+
+        let report = run(config).expect("Running failed");
+        assert_eq!(file_names.len(), report.renamed.len());
+        assert_eq!("00243344.1.jpg", report.renamed[0].old);
+
+        let journal = Journal::read(test_dir).unwrap();
+        assert_eq!(file_names.len(), journal.renamings.len());
+        for entry in journal.renamings {
+            assert!(test_dir.join(entry.new).exists());
+        }
+
+        fs::remove_dir_all(test_dir).expect("Could not delete tests directory.");
+    }
+
+    #[test]
+    fn copy_to_output_dir() {
+        let images_dir = std::path::Path::new("tests/files/");
+        let test_dir = std::path::Path::new("tests/tmp_copy/");
+        let output_dir = std::path::Path::new("tests/tmp_copy_output/");
+
+        let _ = fs::create_dir(test_dir);
+        let _ = fs::create_dir(output_dir);
+
+        let file_names = list_files(images_dir.to_str().unwrap());
+        for file in &file_names {
+            fs::copy(images_dir.join(file), test_dir.join(file)).unwrap();
+        }
+
+        let mut config = Config::new(
+            String::from("tests/data.csv"),
+            String::from(test_dir.to_str().unwrap()),
+        );
+        config.copy = true;
+        config.output_dir = Some(output_dir.to_path_buf());
+
+        run(config).expect("Running failed");
+
+        let mut source_files = list_files(test_dir.to_str().unwrap());
+        source_files.retain(|file| !file.starts_with('.'));
+        assert_eq!(file_names, source_files);
+        assert_eq!(
+            file_names.len(),
+            list_files(output_dir.to_str().unwrap()).len()
+        );
+        assert!(output_dir.join("3_1.jpg").exists());
+
+        undo(test_dir).expect("Undoing failed");
+        assert!(list_files(output_dir.to_str().unwrap()).is_empty());
+        assert_eq!(file_names, list_files(test_dir.to_str().unwrap()));
+
+        fs::remove_dir_all(test_dir).expect("Could not delete tests directory.");
+        fs::remove_dir_all(output_dir).expect("Could not delete tests directory.");
+    }
+
+    #[test]
+    fn lock_the_output_dir() {
+        let test_dir = Path::new("tests/tmp_lock_source");
+        let output_dir = Path::new("tests/tmp_lock_output");
+        let _ = fs::remove_dir_all(test_dir);
+        let _ = fs::remove_dir_all(output_dir);
+        fs::create_dir(test_dir).unwrap();
+        fs::create_dir(output_dir).unwrap();
+        fs::write(test_dir.join("00243878.1.jpg"), "").unwrap();
+        let config = || {
+            let mut config = Config::new("tests/data.csv", test_dir);
+            config.output_dir = Some(output_dir.to_path_buf());
+            config
+        };
+
+        let lock = DirLock::acquire(output_dir).unwrap();
+        let locked_run = plan(config()).unwrap().execute();
+        drop(lock);
+        run(config()).unwrap();
+        let lock = DirLock::acquire(output_dir).unwrap();
+        let locked_undo = undo(test_dir);
+        drop(lock);
+        let undone = undo(test_dir);
+        let left = list_files(test_dir);
+
+        fs::remove_dir_all(test_dir).unwrap();
+        fs::remove_dir_all(output_dir).unwrap();
+
+        assert!(matches!(locked_run, Err(RenameError::Locked { .. })));
+        assert!(matches!(locked_undo, Err(RenameError::Locked { .. })));
+        assert!(undone.is_ok());
+        assert_eq!(vec!["00243878.1.jpg"], left);
+    }
+
+    #[test]
+    fn merge_directories() {
+        let images_dir = Path::new("tests/files/");
+        let first_dir = Path::new("tests/tmp_merge_first/");
+        let second_dir = Path::new("tests/tmp_merge_second/");
+        // Staged files of the extra directory wait in that directory.
+        for stage_all in [false, true] {
+            let _ = fs::create_dir(first_dir);
+            let _ = fs::create_dir(second_dir);
+            for file in list_files(images_dir) {
+                let dir = if file.starts_with("00243344") {
+                    second_dir
+                } else {
+                    first_dir
+                };
+                fs::copy(images_dir.join(&file), dir.join(&file)).unwrap();
+            }
+
+            let mut config = Config::new("tests/data.csv", first_dir);
+            config.extra_dirs = vec![second_dir.to_path_buf()];
+            config.stage_all = stage_all;
+            let plan = plan(config).unwrap();
+            let moved = plan
+                .renamings
+                .iter()
+                .find(|(_, new)| *new == "3_1.jpg")
+                .map(|(old, _)| PathBuf::from(old));
+            let executed = plan.execute();
+            let merged = list_files(first_dir);
+            let left = list_files(second_dir);
+            let undone = undo(first_dir);
+            let restored = list_files(second_dir);
+
+            fs::remove_dir_all(first_dir).unwrap();
+            fs::remove_dir_all(second_dir).unwrap();
+
+            assert!(executed.is_ok(), "{:?}", executed.err());
+            assert!(undone.is_ok(), "{:?}", undone.err());
+            assert_eq!(
+                Some(std::path::absolute(second_dir.join("00243344.1.jpg")).unwrap()),
+                moved
+            );
+            assert!(merged.contains(&String::from("1_1.jpg")));
+            assert!(merged.contains(&String::from("3_1.jpg")));
+            assert!(left.is_empty());
+            assert!(restored.contains(&String::from("00243344.1.jpg")));
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn link_under_new_names() {
+        use std::os::unix::fs::MetadataExt;
+
+        let images_dir = std::path::Path::new("tests/files/");
+        let test_dir = std::path::Path::new("tests/tmp_link/");
+        let output_dir = test_dir.join("links");
+
+        let _ = fs::create_dir(test_dir);
+
+        for file in list_files(images_dir) {
+            fs::copy(images_dir.join(&file), test_dir.join(&file)).unwrap();
+        }
+
+        let mut config = Config::new("tests/data.csv", test_dir);
+        config.link = Some(Link::Hard);
+        run(config.clone()).expect("Running failed");
+
+        let original = fs::metadata(test_dir.join("00243344.1.jpg")).unwrap();
+        let link = fs::metadata(test_dir.join("3_1.jpg")).unwrap();
+        assert_eq!(original.ino(), link.ino());
+        undo(test_dir).expect("Undoing failed");
+        assert!(!test_dir.join("3_1.jpg").exists());
+
+        config.link = Some(Link::Sym);
+        config.output_dir = Some(output_dir.clone());
+        run(config).expect("Running failed");
+
+        let target = fs::read_link(output_dir.join("3_1.jpg")).unwrap();
+        assert!(target.is_absolute());
+        assert_eq!(
+            fs::read(test_dir.join("00243344.1.jpg")).unwrap(),
+            fs::read(output_dir.join("3_1.jpg")).unwrap()
+        );
+
+        fs::remove_dir_all(test_dir).expect("Could not delete tests directory.");
+    }
+
+    #[test]
+    fn rename_into_new_output_dir() {
+        let images_dir = std::path::Path::new("tests/files/");
+        let test_dir = std::path::Path::new("tests/tmp_output_dir/");
+        let output_dir = test_dir.join("renamed");
+
+        let _ = fs::create_dir(test_dir);
+
+        let file_names = list_files(images_dir.to_str().unwrap());
+        for file in &file_names {
+            fs::copy(images_dir.join(file), test_dir.join(file)).unwrap();
+        }
+
+        let mut config = Config::new(
+            String::from("tests/data.csv"),
+            String::from(test_dir.to_str().unwrap()),
+        );
+        config.output_dir = Some(output_dir.to_path_buf());
+
+        run(config).expect("Running failed");
+
+        assert_eq!(
+            file_names.len(),
+            list_files(output_dir.to_str().unwrap()).len()
+        );
+        assert!(!test_dir.join("00243344.1.jpg").exists());
+
+        undo(test_dir).expect("Undoing failed");
+        assert!(test_dir.join("00243344.1.jpg").exists());
+        assert!(list_files(output_dir.to_str().unwrap()).is_empty());
+
+        fs::remove_dir_all(test_dir).expect("Could not delete tests directory.");
+    }
+
+    #[test]
+    fn rename_into_lot_dirs() {
+        let images_dir = std::path::Path::new("tests/files/");
+        let test_dir = std::path::Path::new("tests/tmp_lot_dirs/");
+
+        let _ = fs::create_dir(test_dir);
+
+        for file in list_files(images_dir) {
+            fs::copy(images_dir.join(&file), test_dir.join(&file)).unwrap();
+        }
+
+        let mut config = Config::new("tests/data.csv", test_dir);
+        config.lot_dirs = true;
+        config.lot_width = 4;
+
+        let report = run(config).expect("Running failed");
+        assert!(report
+            .renamed
+            .iter()
+            .any(|renaming| renaming.new == "0003/0003_1.jpg"));
+        assert!(test_dir.join("0003/0003_1.jpg").exists());
+        assert!(!test_dir.join("00243344.1.jpg").exists());
+
+        undo(test_dir).expect("Undoing failed");
+        assert!(test_dir.join("00243344.1.jpg").exists());
+        assert!(!test_dir.join("0003").exists());
+
+        fs::remove_dir_all(test_dir).expect("Could not delete tests directory.");
+    }
+
+    #[test]
+    fn report_unmatched_files() {
+        let images_dir = std::path::Path::new("tests/files/");
+        let test_dir = std::path::Path::new("tests/tmp_unmatched/");
+
+        let _ = fs::create_dir(test_dir);
+
+        let file_names = list_files(images_dir.to_str().unwrap());
+        for file in &file_names {
+            fs::copy(images_dir.join(file), test_dir.join(file)).unwrap();
+        }
+        fs::write(test_dir.join("00999999.1.jpg"), "").unwrap();
+
+        let mut config = Config::new(
+            String::from("tests/data.csv"),
+            String::from(test_dir.to_str().unwrap()),
+        );
+        config.strict = true;
+
+        let err = run(config).expect_err("Running should fail in strict mode");
+        assert!(matches!(err, RenameError::Strict(err) if err.unmatched_files.len() == 1));
+
+        let config = Config::new(
+            String::from("tests/data.csv"),
+            String::from(test_dir.to_str().unwrap()),
+        );
+
+        let report = run(config).expect("Running failed");
+        assert_eq!(vec!["00999999.1.jpg".to_string()], report.unmatched_files);
+        assert!(test_dir.join("00999999.1.jpg").exists());
+
+        fs::remove_dir_all(test_dir).expect("Could not delete tests directory.");
+    }
+
+    #[test]
+    fn report_duplicate_files() {
+        let images_dir = std::path::Path::new("tests/files/");
+        let test_dir = std::path::Path::new("tests/tmp_duplicates/");
+
+        let _ = fs::create_dir(test_dir);
+
+        for file in list_files(images_dir) {
+            fs::copy(images_dir.join(&file), test_dir.join(&file)).unwrap();
+        }
+        // The last photo was exported twice.
+        fs::copy(
+            images_dir.join("00243344.6.jpg"),
+            test_dir.join("00243344.7.jpg"),
+        )
+        .unwrap();
+
+        let mut config = Config::new("tests/data.csv", test_dir);
+        config.find_duplicates = true;
+        config.dry_run = true;
+        config.strict = true;
+
+        let err = run(config.clone()).expect_err("Running should fail in strict mode");
+        assert!(matches!(err, RenameError::Strict(err) if err.duplicate_files.len() == 1));
+
+        config.strict = false;
+        let report = run(config).expect("Running failed");
+        assert_eq!(
+            vec![vec![
+                "00243344.6.jpg".to_string(),
+                "00243344.7.jpg".to_string()
+            ]],
+            report.duplicate_files
+        );
+
+        fs::remove_dir_all(test_dir).expect("Could not delete tests directory.");
+    }
+
+    #[test]
+    fn check_without_touching_files() {
+        let images_dir = std::path::Path::new("tests/files/");
+        let test_dir = std::path::Path::new("tests/tmp_check/");
+
+        let _ = fs::create_dir(test_dir);
+
+        for file in list_files(images_dir) {
+            fs::copy(images_dir.join(&file), test_dir.join(&file)).unwrap();
+        }
+        // The last row of the data file, of lot 3, was pasted again as lot 4.
+        let data = fs::read_to_string("tests/data.csv").unwrap();
+        let last_row = data.lines().last().unwrap().strip_prefix('3').unwrap();
+        let data_file = std::path::Path::new("tests/tmp_check.csv");
+        fs::write(data_file, format!("{}4{}\n", data, last_row)).unwrap();
+
+        let passed = check(Config::new("tests/data.csv", test_dir));
+        let err = check(Config::new(data_file, test_dir));
+        let files = list_files(test_dir);
+
+        fs::remove_dir_all(test_dir).expect("Could not delete tests directory.");
+        fs::remove_file(data_file).unwrap();
+
+        assert!(passed.is_ok());
+        match err {
+            Err(RenameError::DuplicateRows(rows)) => assert_eq!(
+                vec![DuplicateRow {
+                    row: 4,
+                    lot: "4".to_string(),
+                    first_row: 3,
+                    first_lot: "3".to_string(),
+                    inventory: "00243344".to_string()
+                }],
+                rows
+            ),
+            result => panic!("Checking should fail, got {:?}", result),
+        }
+        assert_eq!(list_files(images_dir), files);
+    }
+
+    #[test]
+    fn leave_corrupt_images_alone() {
+        let images_dir = std::path::Path::new("tests/files/");
+        let test_dir = std::path::Path::new("tests/tmp_corrupt/");
+
+        let _ = fs::create_dir(test_dir);
+
+        // Decoding is slow in debug builds, so take the photos of one lot.
+        for file in list_files(images_dir) {
+            if file.starts_with("00243344") {
+                fs::copy(images_dir.join(&file), test_dir.join(&file)).unwrap();
+            }
+        }
+        // The upload of the last photo was cut short.
+        let photo = fs::read(images_dir.join("00243344.7.jpg")).unwrap();
+        fs::write(test_dir.join("00243344.7.jpg"), &photo[..photo.len() / 2]).unwrap();
+
+        let mut config = Config::new("tests/data.csv", test_dir);
+        config.verify_images = true;
+        config.strict = true;
+
+        let err = run(config.clone()).expect_err("Running should fail in strict mode");
+        assert!(matches!(err, RenameError::Strict(err) if err.corrupt_files.len() == 1));
+
+        config.strict = false;
+        let report = run(config).expect("Running failed");
+        assert_eq!(vec!["00243344.7.jpg".to_string()], report.corrupt_files);
+        assert!(test_dir.join("00243344.7.jpg").exists());
+        assert!(test_dir.join("3_1.jpg").exists());
+
+        fs::remove_dir_all(test_dir).expect("Could not delete tests directory.");
+    }
+
+    #[test]
+    fn leave_files_of_wrong_size_alone() {
+        let images_dir = std::path::Path::new("tests/files/");
+        let test_dir = std::path::Path::new("tests/tmp_wrong_size/");
+
+        let _ = fs::create_dir(test_dir);
+
+        for file in list_files(images_dir) {
+            if file.starts_with("00243344") {
+                fs::copy(images_dir.join(&file), test_dir.join(&file)).unwrap();
+            }
+        }
+        // The upload of the last photo failed altogether.
+        fs::write(test_dir.join("00243344.7.jpg"), "").unwrap();
+
+        let mut config = Config::new("tests/data.csv", test_dir);
+        config.min_size = Some(FileSize(1));
+        config.strict = true;
+
+        let err = run(config.clone()).expect_err("Running should fail in strict mode");
+        assert!(matches!(err, RenameError::Strict(err) if err.wrong_size_files.len() == 1));
+
+        config.strict = false;
+        let report = run(config).expect("Running failed");
+        assert_eq!(vec!["00243344.7.jpg".to_string()], report.wrong_size_files);
+        assert!(test_dir.join("00243344.7.jpg").exists());
+        assert!(test_dir.join("3_1.jpg").exists());
+
+        fs::remove_dir_all(test_dir).expect("Could not delete tests directory.");
+    }
+
+    #[test]
+    fn leave_small_images_alone() {
+        let images_dir = std::path::Path::new("tests/files/");
+        let test_dir = std::path::Path::new("tests/tmp_small_images/");
+
+        let _ = fs::create_dir(test_dir);
+
+        for file in list_files(images_dir) {
+            if file.starts_with("00243878") {
+                fs::copy(images_dir.join(&file), test_dir.join(&file)).unwrap();
+            }
+        }
+
+        fs::write(test_dir.join("00243878.1.xmp"), "").unwrap();
+
+        let mut config = Config::new("tests/data.csv", test_dir);
+        config.min_dimensions = Some(Dimensions {
+            width: 1200,
+            height: 1100,
+        });
+        config.sidecars = vec![String::from("xmp")];
+        config.strict = true;
+
+        let err = run(config.clone()).expect_err("Running should fail in strict mode");
+        assert!(matches!(err, RenameError::Strict(err) if err.low_resolution_lots.len() == 1));
+
+        config.strict = false;
+        let report = run(config).expect("Running failed");
+        let lot = &report.low_resolution_lots[0];
+        assert_eq!("1", lot.lot);
+        assert_eq!(
+            vec!["00243878.1.jpg", "00243878.4.jpg", "00243878.5.jpg"],
+            lot.images
+                .iter()
+                .map(|image| image.file.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            "lot 1: 00243878.1.jpg (1148x1011) with 00243878.1.xmp, 00243878.4.jpg (1128x1000), 00243878.5.jpg (1553x1026)",
+            lot.to_string()
+        );
+        assert_eq!(4, report.renamed.len());
+        assert_eq!(4, report.summary().skipped);
+        assert!(test_dir.join("00243878.1.jpg").exists());
+        assert!(test_dir.join("00243878.1.xmp").exists());
+
+        fs::remove_dir_all(test_dir).expect("Could not delete tests directory.");
+    }
+
+    #[test]
+    fn run_twice() {
+        let images_dir = std::path::Path::new("tests/files/");
+        let test_dir = std::path::Path::new("tests/tmp_twice/");
+
+        let _ = fs::create_dir(test_dir);
+        for file in list_files(images_dir) {
+            if file.starts_with("00243344") {
+                fs::copy(images_dir.join(&file), test_dir.join(&file)).unwrap();
+            }
+        }
+
+        // The new names still hold the inventory number, so they match again.
+        let config = Config::builder("tests/data.csv", test_dir)
+            .template("{inventory}_{index}.jpg")
+            .suffix("trailing-digits".parse().unwrap())
+            .build()
+            .unwrap();
+        let first = run(config.clone()).expect("Running failed");
+        let file_names = list_files(test_dir);
+        let second = run(config.clone()).expect("Running again failed");
+        Journal::remove(test_dir).unwrap();
+        let third = run(config).expect("Running without a journal failed");
+
+        assert_eq!(7, first.renamed.len());
+        assert!(second.renamed.is_empty());
+        assert_eq!(7, second.renamed_before.len());
+        assert!(third.renamed.is_empty());
+        assert_eq!(7, third.renamed_before.len());
+        assert_eq!(file_names[1..], list_files(test_dir));
+
+        fs::remove_dir_all(test_dir).expect("Could not delete tests directory.");
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn history_that_cannot_be_written_does_not_fail_the_run() {
+        let images_dir = Path::new("tests/files");
+        let test_dir = Path::new("tests/tmp_unwritable_history");
+        let _ = fs::remove_dir_all(test_dir);
+        fs::create_dir(test_dir).unwrap();
+        fs::copy(
+            images_dir.join("00243344.1.jpg"),
+            test_dir.join("00243344.1.jpg"),
+        )
+        .unwrap();
+
+        // A directory cannot be opened as a database.
+        let mut config = Config::new("tests/data.csv", test_dir);
+        config.history = Some(PathBuf::from("tests/files"));
+        let report = run(config);
+
+        fs::remove_dir_all(test_dir).unwrap();
+
+        assert_eq!(1, report.expect("Running failed").renamed.len());
+    }
+
+    #[test]
+    fn find_files_renamed_before() {
+        let renamings = BTreeMap::from([
+            ("00243878_1.jpg".to_string(), "00243878_1.jpg".to_string()),
+            ("00243878_2.jpg".to_string(), "00243878_2.jpg".to_string()),
+            // Renumbered, so the first two do not count as renamed.
+            ("00243880_2.jpg".to_string(), "00243880_1.jpg".to_string()),
+            ("00243880_3.jpg".to_string(), "00243880_2.jpg".to_string()),
+            ("00243344_5.jpg".to_string(), "00243344_1.jpg".to_string()),
+        ]);
+        let journaled = BTreeSet::from(["00243344_5.jpg".to_string()]);
+
+        assert_eq!(
+            vec!["00243344_5.jpg", "00243878_1.jpg", "00243878_2.jpg"],
+            find_renamed_before(&renamings, &journaled)
+        );
+    }
+
+    #[test]
+    fn rename_by_regex() {
+        let test_dir = std::path::Path::new("tests/tmp_regex/");
+        let _ = fs::remove_dir_all(test_dir);
+        fs::create_dir(test_dir).unwrap();
+        for file in ["00243344.1.jpg", "00243344.12.jpg", "notes.txt"] {
+            fs::write(test_dir.join(file), file).unwrap();
+        }
+
+        let config = Config::builder("", test_dir)
+            .from_regex(r"(?P<id>\d+)\.(?P<n>\d+)\.jpg")
+            .template("{id}-{n}.jpeg")
+            .build()
+            .unwrap();
+        let report = run(config).expect("Running failed");
+        let file_names = list_files(test_dir);
+        let renamed = fs::read_to_string(test_dir.join("00243344-12.jpeg")).unwrap();
+
+        fs::remove_dir_all(test_dir).unwrap();
+
+        assert_eq!(2, report.renamed.len());
+        assert_eq!(vec!["notes.txt".to_string()], report.unmatched_files);
+        assert_eq!("00243344.12.jpg", renamed);
+        assert_eq!(
+            vec![
+                ".rename-journal.json",
+                "00243344-1.jpeg",
+                "00243344-12.jpeg",
+                "notes.txt"
+            ],
+            file_names
+        );
+
+        let builder = || Config::builder("", "tests/files").from_regex(r"(\d+)\.jpg");
+        assert!(builder().template("{2}.jpg").build().is_err());
+        assert!(builder().template("{lot}.jpg").build().is_err());
+        assert!(builder()
+            .template("{1}.jpg")
+            .lot_dirs(true)
+            .build()
+            .is_err());
+        assert!(Config::builder("", "tests/files")
+            .from_regex("(")
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn plan_a_chunk() {
+        let file_names = list_files("tests/files");
+        // Skipping part of lot 3 skips all of it, and lot 2 does not fit
+        // next to lot 1.
+        let config = Config::builder("tests/data.csv", "tests/files")
+            .skip(2)
+            .limit(10)
+            .build()
+            .unwrap();
+
+        let plan = plan(config).expect("Planning failed");
+        assert_eq!(
+            file_names[7..14].iter().collect::<Vec<_>>(),
+            plan.renamings.keys().collect::<Vec<_>>()
+        );
+        // The files left for later are not unmatched.
+        assert!(plan.unmatched_files.is_empty());
+        assert!(plan.conflicts.is_empty());
+    }
+
+    #[test]
+    fn rename_in_chunks() {
+        let images_dir = Path::new("tests/files");
+        let test_dir = Path::new("tests/tmp_chunks");
+        let _ = fs::remove_dir_all(test_dir);
+        fs::create_dir(test_dir).unwrap();
+        for file in list_files("tests/files") {
+            // Lot 1 misses a photo, which renumbering closes.
+            if file != "00243878.2.jpg" {
+                fs::copy(images_dir.join(&file), test_dir.join(&file)).unwrap();
+            }
+        }
+
+        let mut renamed = vec![];
+        for _ in 0..3 {
+            let config = Config::builder("tests/data.csv", test_dir)
+                .resequence(true)
+                .limit(8)
+                .build()
+                .unwrap();
+            let report = plan(config).unwrap().execute().unwrap();
+            renamed.push(report.renamed.len());
+        }
+        let files = list_files(test_dir.to_str().unwrap());
+
+        fs::remove_dir_all(test_dir).unwrap();
+
+        assert_eq!(vec![7, 6, 6], renamed);
+        let mut expected: Vec<String> = (1..=6)
+            .map(|index| format!("1_{}.jpg", index))
+            .chain((1..=6).map(|index| format!("2_{}.jpg", index)))
+            .chain((1..=7).map(|index| format!("3_{}.jpg", index)))
+            .collect();
+        expected.sort();
+        assert_eq!(expected, files[1..]);
+    }
+
+    #[test]
+    fn plan_with_max_per_lot() {
+        let config = Config::builder("tests/data.csv", "tests/files")
+            .max_per_lot(6)
+            .build()
+            .unwrap();
+        let plan = plan(config).expect("Planning failed");
+        // Lots 1 and 3 have seven photos, lot 2 has six.
+        assert_eq!(
+            vec![
+                CrowdedLot {
+                    lot: "1".to_string(),
+                    files: 7
+                },
+                CrowdedLot {
+                    lot: "3".to_string(),
+                    files: 7
+                }
+            ],
+            plan.crowded_lots
+        );
+        assert_eq!(20, plan.renamings.len());
+
+        let config = Config::builder("tests/data.csv", "tests/files")
+            .max_per_lot(6)
+            .on_crowded_lot(CrowdedLotPolicy::Error)
+            .build()
+            .unwrap();
+        let err = super::plan(config).expect_err("Planning should fail");
+        assert!(matches!(err, RenameError::CrowdedLots(lots) if lots.len() == 2));
+    }
+
+    #[test]
+    fn rename_sidecars_along() {
+        let images_dir = std::path::Path::new("tests/files/");
+        let test_dir = std::path::Path::new("tests/tmp_sidecars/");
+
+        let _ = fs::create_dir(test_dir);
+
+        fs::copy(
+            images_dir.join("00243344.1.jpg"),
+            test_dir.join("00243344.1.jpg"),
+        )
+        .unwrap();
+        fs::write(test_dir.join("00243344.1.xmp"), "<x:xmpmeta/>").unwrap();
+        fs::write(test_dir.join("00243344.1.CR2"), "raw").unwrap();
+        // Without a photo of its own, a sidecar is matched like any file.
+        fs::write(test_dir.join("00243344.2.xmp"), "<x:xmpmeta/>").unwrap();
+
+        let config = Config::builder("tests/data.csv", test_dir)
+            .sidecar("xmp")
+            .sidecar(".cr2")
+            .build()
+            .unwrap();
+        run(config).expect("Running failed");
+
+        assert_eq!(
+            vec![
+                ".rename-journal.json",
+                "3_1.CR2",
+                "3_1.jpg",
+                "3_1.xmp",
+                "3_2.jpg"
+            ],
+            list_files(test_dir)
+        );
+
+        fs::remove_dir_all(test_dir).expect("Could not delete tests directory.");
+    }
+
+    #[test]
+    fn plan_then_execute() {
+        let images_dir = std::path::Path::new("tests/files/");
+        let test_dir = std::path::Path::new("tests/tmp_plan/");
+
+        let _ = fs::create_dir(test_dir);
+
+        let file_names = list_files(images_dir.to_str().unwrap());
+        for file in &file_names {
+            fs::copy(images_dir.join(file), test_dir.join(file)).unwrap();
+        }
+
+        let config = Config::new(
+            String::from("tests/data.csv"),
+            String::from(test_dir.to_str().unwrap()),
+        );
+
+        let plan = plan(config).expect("Planning failed");
+        assert_eq!(file_names.len(), plan.renamings.len());
+        assert_eq!(
+            Some(&"3_1.jpg".to_string()),
+            plan.renamings.get("00243344.1.jpg")
+        );
+        assert!(plan.conflicts.is_empty());
+        assert_eq!(file_names, list_files(test_dir.to_str().unwrap()));
+        assert_eq!(
+            PlanRow {
+                old: Some("00243344.1.jpg".to_string()),
+                new: Some("3_1.jpg".to_string()),
+                lot: Some("3".to_string()),
+                inventory: Some("00243344".to_string()),
+                status: Status::Ok,
+            },
+            plan.rows()[0]
+        );
+
+        let report = plan.execute().expect("Executing failed");
+        assert!(test_dir.join("3_1.jpg").exists());
+        // Files are renamed in the order of their old names.
+        let renamed: Vec<String> = report.renamed.into_iter().map(|r| r.old).collect();
+        assert_eq!(file_names, renamed);
+
+        fs::remove_dir_all(test_dir).expect("Could not delete tests directory.");
+    }
+
+    // MemoryStorage keeps the paths of files in memory, like a bucket.
+    #[derive(Debug, Default)]
+    struct MemoryStorage {
+        paths: std::sync::Mutex<std::collections::BTreeSet<PathBuf>>,
+        // Paths held by another program, which cannot be renamed.
+        locked: std::collections::BTreeSet<PathBuf>,
+    }
+
+    impl Storage for MemoryStorage {
+        fn list(&self, dir: &Path) -> io::Result<Vec<String>> {
+            let paths = self.paths.lock().unwrap();
+            Ok(paths
+                .iter()
+                .filter(|path| path.parent() == Some(dir))
+                .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+                .collect())
+        }
+
+        fn exists(&self, path: &Path) -> io::Result<bool> {
+            Ok(self.paths.lock().unwrap().contains(path))
+        }
+
+        fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+            if self.locked.contains(from) {
+                return Err(io::Error::from(io::ErrorKind::ResourceBusy));
+            }
+            self.remove(from)?;
+            self.paths.lock().unwrap().insert(to.to_path_buf());
+            Ok(())
+        }
+
+        fn copy(&self, from: &Path, to: &Path) -> io::Result<()> {
+            if !self.exists(from)? {
+                return Err(io::Error::from(io::ErrorKind::NotFound));
+            }
+            self.paths.lock().unwrap().insert(to.to_path_buf());
+            Ok(())
+        }
+
+        fn remove(&self, path: &Path) -> io::Result<()> {
+            match self.paths.lock().unwrap().remove(path) {
+                true => Ok(()),
+                false => Err(io::Error::from(io::ErrorKind::NotFound)),
+            }
+        }
+    }
+
+    #[test]
+    fn rename_in_storage() {
+        let dir = Path::new("bucket/photos");
+        let storage = Arc::new(MemoryStorage::default());
+        let file_names = list_files("tests/files/");
+        for file in &file_names {
+            storage.paths.lock().unwrap().insert(dir.join(file));
+        }
+
+        let mut config = Config::new("tests/data.csv", dir);
+        config.storage = Some(storage.clone());
+
+        let report = plan(config)
+            .expect("Planning failed")
+            .execute()
+            .expect("Executing failed");
+
+        assert_eq!(file_names.len(), report.renamed.len());
+        assert!(storage.exists(&dir.join("3_1.jpg")).unwrap());
+        assert!(!storage.exists(&dir.join("00243344.1.jpg")).unwrap());
+        // Nothing is written to the local file system.
+        assert!(!Path::new("bucket").exists());
+    }
+
+    #[test]
+    fn skip_locked_files() {
+        let dir = Path::new("bucket/photos");
+        let storage = || {
+            let storage = MemoryStorage {
+                locked: BTreeSet::from([dir.join("00243878.2.jpg")]),
+                ..MemoryStorage::default()
+            };
+            for file in list_files("tests/files/") {
+                storage.paths.lock().unwrap().insert(dir.join(file));
+            }
+            Arc::new(storage)
+        };
+
+        let mut config = Config::new("tests/data.csv", dir);
+        config.storage = Some(storage());
+        let failed = plan(config).unwrap().execute();
+
+        let skipping = storage();
+        let mut config = Config::new("tests/data.csv", dir);
+        config.storage = Some(skipping.clone());
+        config.skip_locked = true;
+        let report = plan(config).unwrap().execute().unwrap();
+
+        assert!(matches!(failed, Err(RenameError::Rollback(_))));
+        assert_eq!(19, report.renamed.len());
+        assert_eq!(
+            vec![Renaming {
+                old: String::from("00243878.2.jpg"),
+                new: String::from("1_2.jpg"),
+            }],
+            report.locked
+        );
+        assert_eq!(1, report.summary().skipped);
+        assert!(skipping.exists(&dir.join("00243878.2.jpg")).unwrap());
+        assert!(skipping.exists(&dir.join("1_3.jpg")).unwrap());
+    }
+
+    #[test]
+    fn skip_files_waiting_for_locked_files() {
+        let dir = Path::new("bucket/photos");
+        let renamings = BTreeMap::from([
+            ("a.jpg".to_string(), "b.jpg".to_string()),
+            ("b.jpg".to_string(), "c.jpg".to_string()),
+            ("x.jpg".to_string(), "y.jpg".to_string()),
+        ]);
+
+        for stage_all in [false, true] {
+            let storage = MemoryStorage {
+                locked: BTreeSet::from([dir.join("b.jpg")]),
+                ..MemoryStorage::default()
+            };
+            for file in ["a.jpg", "b.jpg", "x.jpg"] {
+                storage.paths.lock().unwrap().insert(dir.join(file));
+            }
+            let transfer = Transfer {
+                storage: &storage,
+                source: dir,
+                target: dir,
+                operation: Operation::Rename,
+                verify: false,
+                overwrite: false,
+                backup: None,
+                state: None,
+                audit: None,
+                retry: Retry::default(),
+                skip_locked: true,
+            };
+
+            let (renamed, locked) =
+                rename_all_files(&transfer, renamings.clone(), vec![], 1, false, stage_all)
+                    .unwrap();
+
+            assert_eq!(
+                vec!["x.jpg"],
+                renamed.iter().map(|r| &r.old).collect::<Vec<_>>()
+            );
+            assert_eq!(
+                vec!["a.jpg", "b.jpg"],
+                locked.iter().map(|r| &r.old).collect::<Vec<_>>()
+            );
+            assert_eq!(vec!["a.jpg", "b.jpg", "y.jpg"], storage.list(dir).unwrap());
+        }
+    }
+
+    #[test]
+    fn report_files_locked_under_their_temporary_name() {
+        let dir = Path::new("bucket/photos");
+        let renamings = BTreeMap::from([
+            ("a.jpg".to_string(), "b.jpg".to_string()),
+            ("b.jpg".to_string(), "a.jpg".to_string()),
+        ]);
+        // a.jpg is staged, then locked before it can take the name of b.jpg,
+        // which meanwhile took its old name.
+        let storage = MemoryStorage {
+            locked: BTreeSet::from([dir.join(staging::temp_name("a.jpg"))]),
+            ..MemoryStorage::default()
+        };
+        for file in ["a.jpg", "b.jpg"] {
+            storage.paths.lock().unwrap().insert(dir.join(file));
+        }
+        let transfer = Transfer {
+            storage: &storage,
+            source: dir,
+            target: dir,
+            operation: Operation::Rename,
+            verify: false,
+            overwrite: false,
+            backup: None,
+            state: None,
+            audit: None,
+            retry: Retry::default(),
+            skip_locked: true,
+        };
+
+        let (renamed, locked) =
+            rename_all_files(&transfer, renamings, vec![], 1, false, false).unwrap();
+
+        assert_eq!(
+            vec!["b.jpg"],
+            renamed.iter().map(|r| &r.old).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![Renaming {
+                old: staging::temp_name("a.jpg"),
+                new: String::from("b.jpg"),
+            }],
+            locked
+        );
+        assert_eq!(
+            vec![staging::temp_name("a.jpg"), String::from("a.jpg")],
+            storage.list(dir).unwrap()
+        );
+    }
+
+    #[test]
+    fn plan_leaves_out_excluded_files() {
+        let images_dir = std::path::Path::new("tests/files/");
+        let test_dir = std::path::Path::new("tests/tmp_filter/");
+
+        let _ = fs::create_dir(test_dir);
+
+        let file_names = list_files(images_dir.to_str().unwrap());
+        for file in &file_names {
+            fs::copy(images_dir.join(file), test_dir.join(file)).unwrap();
+        }
+        fs::write(test_dir.join(".DS_Store"), "").unwrap();
+        fs::write(test_dir.join("00243344.1.jpg.thumb"), "").unwrap();
+
+        let mut config = Config::new(
+            String::from("tests/data.csv"),
+            String::from(test_dir.to_str().unwrap()),
+        );
+        config.include = vec![String::from("*.jpg")];
+        config.exclude = vec![String::from("00243344.7.*")];
+
+        let plan = plan(config).expect("Planning failed");
+        assert_eq!(file_names.len() - 1, plan.renamings.len());
+        assert!(!plan.renamings.contains_key("00243344.7.jpg"));
+        assert!(plan.unmatched_files.is_empty());
+
+        fs::remove_dir_all(test_dir).expect("Could not delete tests directory.");
+    }
+
+    #[test]
+    fn plan_with_named_columns() {
+        let mut config = Config::new(String::from("tests/data.csv"), String::from("tests/files"));
+        config.lot_column = Column::Name(String::from("Lot Number"));
+        config.inventory_column = Column::Name(String::from("Inv Number"));
+
+        let renaming_plan = plan(config).expect("Planning failed");
+        assert_eq!(
+            Some(&"3_1.jpg".to_string()),
+            renaming_plan.renamings.get("00243344.1.jpg")
+        );
+
+        let mut config = Config::new(String::from("tests/data.csv"), String::from("tests/files"));
+        config.lot_column = Column::Name(String::from("Lot"));
+
+        assert!(matches!(plan(config), Err(RenameError::UnknownColumn(_))));
+    }
+
+    #[test]
+    fn undo_restores_original_names() {
+        let images_dir = std::path::Path::new("tests/files/");
+        let test_dir = std::path::Path::new("tests/tmp_undo/");
+
+        let _ = fs::create_dir(test_dir);
+
+        let file_names = list_files(images_dir.to_str().unwrap());
+        for file in &file_names {
+            fs::copy(images_dir.join(file), test_dir.join(file)).unwrap();
+        }
+
+        let config = Config::new(
+            String::from("tests/data.csv"),
+            String::from(test_dir.to_str().unwrap()),
+        );
+
+        run(config).expect("Running failed");
+        assert_ne!(file_names, list_files(test_dir.to_str().unwrap()));
+
+        undo(test_dir).expect("Undoing failed");
+        assert_eq!(file_names, list_files(test_dir.to_str().unwrap()));
+
+        fs::remove_dir_all(test_dir).expect("Could not delete tests directory.");
+    }
+
+    #[test]
+    fn rollback_restores_overwritten_files_from_the_backup() {
+        let test_dir = Path::new("tests/tmp_backup_rollback");
+        let _ = fs::remove_dir_all(test_dir);
+        fs::create_dir(test_dir).unwrap();
+        fs::write(test_dir.join("a.jpg"), "a").unwrap();
+        fs::write(test_dir.join("1_1.jpg"), "earlier").unwrap();
+        let backup_dir = test_dir.join(BACKUP_DIR).join("run");
+
+        // The second file is missing, so the run fails and is rolled back.
+        let renamings = BTreeMap::from([
+            ("a.jpg".to_string(), "1_1.jpg".to_string()),
+            ("missing.jpg".to_string(), "1_2.jpg".to_string()),
+        ]);
+        let transfer = Transfer {
+            storage: &LocalStorage::default(),
+            source: test_dir,
+            target: test_dir,
+            operation: Operation::Rename,
+            verify: false,
+            overwrite: true,
+            backup: Some(&backup_dir),
+            state: None,
+            audit: None,
+            retry: Retry::default(),
+            skip_locked: false,
+        };
+        let err = rename_all_files(&transfer, renamings, vec![], 1, false, false);
+        let earlier = fs::read_to_string(test_dir.join("1_1.jpg"));
+        let a = fs::read_to_string(test_dir.join("a.jpg"));
+
+        fs::remove_dir_all(test_dir).unwrap();
+
+        assert!(matches!(err, Err(RenameError::Rollback(_))));
+        assert_eq!("earlier", earlier.unwrap());
+        assert_eq!("a", a.unwrap());
+    }
+
+    #[test]
+    fn back_up_overwritten_files() {
+        let images_dir = std::path::Path::new("tests/files/");
+        let test_dir = std::path::Path::new("tests/tmp_backup/");
+        let _ = fs::remove_dir_all(test_dir);
+        fs::create_dir(test_dir).unwrap();
+        for file in list_files(images_dir) {
+            fs::copy(images_dir.join(&file), test_dir.join(&file)).unwrap();
+        }
+        fs::write(test_dir.join("1_1.jpg"), "earlier").unwrap();
+
+        let config = Config::builder("tests/data.csv", test_dir)
+            .on_conflict(ConflictPolicy::Overwrite)
+            .backup(BACKUP_DIR)
+            .build()
+            .unwrap();
+        run(config).expect("Running failed");
+
+        let backup_dir = test_dir.join(BACKUP_DIR);
+        let runs = list_files(&backup_dir);
+        assert_eq!(1, runs.len());
+        assert_eq!(
+            "earlier",
+            fs::read_to_string(backup_dir.join(&runs[0]).join("1_1.jpg")).unwrap()
+        );
+
+        let dir = test_dir.to_str().unwrap();
+        assert!(restore(dir, Path::new(BACKUP_DIR)).is_err());
+        undo(dir).expect("Undoing failed");
+        restore(dir, Path::new(BACKUP_DIR)).expect("Restoring failed");
+
+        assert_eq!(
+            "earlier",
+            fs::read_to_string(test_dir.join("1_1.jpg")).unwrap()
+        );
+        assert!(test_dir.join("00243878.1.jpg").exists());
+        assert!(!backup_dir.exists());
+
+        fs::remove_dir_all(test_dir).expect("Could not delete tests directory.");
+    }
+
+    #[test]
+    fn swap_names() {
+        let test_dir = std::path::Path::new("tests/tmp_swap/");
+        let _ = fs::remove_dir_all(test_dir);
+        fs::create_dir(test_dir).unwrap();
+        fs::write(test_dir.join("1_1.jpg"), "first").unwrap();
+        fs::write(test_dir.join("2_1.jpg"), "second").unwrap();
+        let data_file = "tests/tmp_swap.toml";
+        fs::write(data_file, "\"1_\" = 2\n\"2_\" = 1\n").unwrap();
+
+        let config = Config::builder(data_file, test_dir.to_str().unwrap())
+            .format(Format::Toml)
+            .suffix("trailing-digits".parse().unwrap())
+            .build()
+            .unwrap();
+        let report = run(config).expect("Swapping failed");
+        let file_names = list_files(test_dir.to_str().unwrap());
+        let first = fs::read_to_string(test_dir.join("2_1.jpg")).unwrap();
+        let journal = Journal::read(test_dir).unwrap();
+        undo(test_dir).expect("Undoing failed");
+        let restored = fs::read_to_string(test_dir.join("1_1.jpg")).unwrap();
+
+        fs::remove_dir_all(test_dir).unwrap();
+        fs::remove_file(data_file).unwrap();
+
+        assert_eq!(2, report.renamed.len());
+        assert_eq!(2, journal.renamings.len());
+        assert_eq!("first", first);
+        assert_eq!("first", restored);
+        assert_eq!(
+            vec![".rename-journal.json", "1_1.jpg", "2_1.jpg"],
+            file_names
+        );
+    }
+
+    #[test]
+    fn stage_all_files() {
+        let test_dir = std::path::Path::new("tests/tmp_stage_all/");
+        let _ = fs::remove_dir_all(test_dir);
+        fs::create_dir(test_dir).unwrap();
+        for file in ["00243878.1.jpg", "00243878.2.jpg", "1_1.jpg"] {
+            fs::write(test_dir.join(file), file).unwrap();
+        }
+        let data_file = "tests/tmp_stage_all.toml";
+        fs::write(data_file, "\"00243878\" = 1\n\"1_\" = 2\n").unwrap();
+
+        let config = Config::builder(data_file, test_dir.to_str().unwrap())
+            .format(Format::Toml)
+            .suffix("trailing-digits".parse().unwrap())
+            .stage_all(true)
+            .build()
+            .unwrap();
+        let report = run(config).expect("Renaming failed");
+        let file_names = list_files(test_dir.to_str().unwrap());
+        let moved = fs::read_to_string(test_dir.join("2_1.jpg")).unwrap();
+
+        fs::remove_dir_all(test_dir).unwrap();
+        fs::remove_file(data_file).unwrap();
+
+        assert_eq!(3, report.renamed.len());
+        assert_eq!("1_1.jpg", moved);
+        assert_eq!(
+            vec![".rename-journal.json", "1_1.jpg", "1_2.jpg", "2_1.jpg"],
+            file_names
+        );
+    }
+
+    #[test]
+    fn resume_interrupted_run() {
+        let test_dir = std::path::Path::new("tests/tmp_resume/");
+        let _ = fs::remove_dir_all(test_dir);
+        fs::create_dir(test_dir).unwrap();
+
+        // The interrupted run renamed the first file, but not the second.
+        fs::copy("tests/files/00243878.1.jpg", test_dir.join("1_1.jpg")).unwrap();
+        fs::copy(
+            "tests/files/00243878.2.jpg",
+            test_dir.join("00243878.2.jpg"),
+        )
+        .unwrap();
+        let state = State {
+            renamings: vec![
+                JournalEntry {
+                    old: "00243878.1.jpg".to_string(),
+                    new: "1_1.jpg".to_string(),
+                },
+                JournalEntry {
+                    old: "00243878.2.jpg".to_string(),
+                    new: "1_2.jpg".to_string(),
+                },
             ],
-            list_files(dir),
+            ..State::default()
+        };
+        StateLog::create(test_dir, &state)
+            .unwrap()
+            .record("00243878.1.jpg")
+            .unwrap();
+
+        let config = Config::new(
+            String::from("tests/data.csv"),
+            String::from(test_dir.to_str().unwrap()),
+        );
+        let interrupted = plan(config.clone());
+        let mut config = config;
+        config.resume = true;
+        let report = run(config).expect("Resuming failed");
+        let journal = Journal::read(test_dir).unwrap();
+        let file_names = list_files(test_dir.to_str().unwrap());
+
+        fs::remove_dir_all(test_dir).unwrap();
+
+        assert!(matches!(interrupted, Err(RenameError::InterruptedRun(_))));
+        assert_eq!(2, report.renamed.len());
+        assert_eq!(2, journal.renamings.len());
+        assert_eq!(
+            vec![".rename-journal.json", "1_1.jpg", "1_2.jpg"],
+            file_names
         );
     }
 
+    #[test]
+    fn dry_run_leaves_files_untouched() {
+        let images_dir = std::path::Path::new("tests/files/");
+        let test_dir = std::path::Path::new("tests/tmp_dry_run/");
+
+        let _ = fs::create_dir(test_dir);
+
+        let file_names = list_files(images_dir.to_str().unwrap());
+        for file in &file_names {
+            fs::copy(images_dir.join(file), test_dir.join(file)).unwrap();
+        }
+
+        let mut config = Config::new(
+            String::from("tests/data.csv"),
+            String::from(test_dir.to_str().unwrap()),
+        );
+        config.dry_run = true;
+
+        run(config).expect("Running failed");
+
+        assert_eq!(file_names, list_files(test_dir.to_str().unwrap()));
+
+        fs::remove_dir_all(test_dir).expect("Could not delete tests directory.");
+    }
+
     #[test]
     fn dir_exists() {
         let dir_existing = "src";
         let dir_not_existing = "my_random_nonexistent_dirname";
 
-        assert!(validate_dir(dir_existing));
-        assert!(!validate_dir(dir_not_existing));
+        assert!(validate_dir(Path::new(dir_existing)));
+        assert!(!validate_dir(Path::new(dir_not_existing)));
     }
 }
@@ -0,0 +1,146 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::SystemTime;
+
+use crate::RenameError;
+
+// The lock file lives in the directory while a run renames its files, so a
+// second run against the directory stops instead of renaming files the first
+// one is renaming too.
+pub const LOCK_FILE_NAME: &str = ".rename.lock";
+
+// LockHolder tells who holds the lock of a directory.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LockHolder {
+    pub user: String,
+    pub host: String,
+    pub pid: u32,
+    // When the lock was taken, in RFC 3339 and UTC.
+    pub since: String,
+}
+
+impl LockHolder {
+    fn current() -> LockHolder {
+        let env = |names: [&str; 2]| {
+            names
+                .iter()
+                .find_map(|name| std::env::var(name).ok())
+                .unwrap_or_else(|| String::from("unknown"))
+        };
+
+        LockHolder {
+            user: env(["USER", "USERNAME"]),
+            host: env(["HOSTNAME", "COMPUTERNAME"]),
+            pid: process::id(),
+            since: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+        }
+    }
+}
+
+impl fmt::Display for LockHolder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}@{} (process {}) since {}",
+            self.user, self.host, self.pid, self.since
+        )
+    }
+}
+
+// DirLock holds the lock of a directory, which is released when it is dropped.
+#[derive(Debug)]
+pub struct DirLock {
+    path: PathBuf,
+}
+
+impl DirLock {
+    // acquire takes the lock of the directory, or fails telling who holds it.
+    pub fn acquire(dir: &Path) -> Result<DirLock, RenameError> {
+        let path = dir.join(LOCK_FILE_NAME);
+        let mut file = match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                // A lock file that cannot be read is being written by its holder.
+                let holder = fs::read_to_string(&path)
+                    .ok()
+                    .and_then(|contents| serde_json::from_str(&contents).ok());
+                return Err(RenameError::Locked {
+                    dir: dir.display().to_string(),
+                    holder,
+                });
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let lock = DirLock { path };
+        file.write_all(serde_json::to_string(&LockHolder::current())?.as_bytes())?;
+
+        Ok(lock)
+    }
+}
+
+impl Drop for DirLock {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.path) {
+            log::warn!("could not remove lock {}: {}", self.path.display(), err);
+        }
+    }
+}
+
+// acquire_all takes the locks of the directories, each once, leaving out
+// those whose lock is held already. If one cannot be taken, the locks taken so
+// far are released.
+pub fn acquire_all<'a>(
+    dirs: impl IntoIterator<Item = &'a Path>,
+    held: &[DirLock],
+) -> Result<Vec<DirLock>, RenameError> {
+    let mut locked: HashSet<PathBuf> = held
+        .iter()
+        .filter_map(|lock| lock.path.parent())
+        .map(resolve)
+        .collect();
+    let mut locks = vec![];
+    for dir in dirs {
+        if locked.insert(resolve(dir)) {
+            locks.push(DirLock::acquire(dir)?);
+        }
+    }
+
+    Ok(locks)
+}
+
+// resolve gives the same path for a directory whichever way it is written, if
+// it exists.
+fn resolve(dir: &Path) -> PathBuf {
+    fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lock_directory() {
+        let dir = Path::new("tests/tmp_lock");
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir(dir).unwrap();
+
+        let lock = DirLock::acquire(dir).unwrap();
+        let err = DirLock::acquire(dir).expect_err("Locking twice should fail");
+        let locked_by_us = matches!(
+            &err,
+            RenameError::Locked { holder: Some(holder), .. } if holder.pid == process::id()
+        );
+        drop(lock);
+        let relocked = DirLock::acquire(dir).is_ok();
+
+        fs::remove_dir_all(dir).unwrap();
+
+        assert!(locked_by_us, "{}", err);
+        assert!(relocked);
+    }
+}
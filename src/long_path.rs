@@ -0,0 +1,49 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+// extended makes the directory usable for paths of any length. On Windows,
+// paths are limited to 260 characters unless written in the extended-length
+// form, such as `\\?\C:\photos` or `\\?\UNC\server\auctions` for a share.
+// Elsewhere, paths are used as they are.
+pub fn extended(dir: &Path) -> io::Result<PathBuf> {
+    #[cfg(windows)]
+    {
+        // Extended-length paths are passed to the file system as they are, so
+        // they must be absolute, with backslashes and without `.` or `..`,
+        // which absolute takes care of.
+        let dir = std::path::absolute(dir)?;
+        Ok(PathBuf::from(extended_form(&dir.to_string_lossy())))
+    }
+    #[cfg(not(windows))]
+    {
+        Ok(dir.to_path_buf())
+    }
+}
+
+// extended_form writes an absolute Windows path in the extended-length form.
+#[cfg(any(windows, test))]
+fn extended_form(path: &str) -> String {
+    if path.starts_with(r"\\?\") || path.starts_with(r"\\.\") {
+        path.to_owned()
+    } else if let Some(share) = path.strip_prefix(r"\\") {
+        format!(r"\\?\UNC\{}", share)
+    } else {
+        format!(r"\\?\{}", path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_extended_form() {
+        assert_eq!(r"\\?\C:\photos", extended_form(r"C:\photos"));
+        assert_eq!(
+            r"\\?\UNC\server\auctions\2024",
+            extended_form(r"\\server\auctions\2024")
+        );
+        assert_eq!(r"\\?\C:\photos", extended_form(r"\\?\C:\photos"));
+        assert_eq!(r"\\.\pipe\rename", extended_form(r"\\.\pipe\rename"));
+    }
+}
@@ -0,0 +1,144 @@
+use std::borrow::Cow;
+
+// Lots selects lot numbers by ranges such as `100-250` and single numbers such
+// as `7`, separated by commas.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Lots {
+    ranges: Vec<(u64, u64)>,
+    // Single lots, which need not be numbers, e.g. `12a`.
+    lots: Vec<String>,
+}
+
+impl std::str::FromStr for Lots {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<Lots, &'static str> {
+        let mut selection = Lots::default();
+
+        for part in value.split(',').map(str::trim) {
+            if part.is_empty() {
+                return Err("lots must be a comma-separated list of lots and ranges");
+            }
+
+            match part.split_once('-') {
+                Some((first, last)) => {
+                    let first = first.trim().parse::<u64>();
+                    let last = last.trim().parse::<u64>();
+                    match (first, last) {
+                        (Ok(first), Ok(last)) if first <= last => {
+                            selection.ranges.push((first, last))
+                        }
+                        _ => return Err("lot range must be two ascending numbers, e.g. 100-250"),
+                    }
+                }
+                None => selection.lots.push(part.to_owned()),
+            }
+        }
+
+        Ok(selection)
+    }
+}
+
+impl Lots {
+    // contains tells whether the lot is selected. Numbers are compared by value,
+    // so `007` is the same lot as `7`.
+    pub fn contains(&self, lot: &str) -> bool {
+        let lot = lot.trim();
+
+        match lot.parse::<u64>() {
+            Ok(number) => {
+                self.ranges
+                    .iter()
+                    .any(|&(first, last)| first <= number && number <= last)
+                    || self
+                        .lots
+                        .iter()
+                        .any(|selected| selected.parse::<u64>() == Ok(number))
+            }
+            Err(_) => self.lots.iter().any(|selected| selected == lot),
+        }
+    }
+}
+
+// Characters that separate thousands in lot numbers exported under various
+// locales, such as `1.001`, `1,001`, `1 001` and `1'001`.
+const SEPARATORS: [char; 6] = ['.', ',', ' ', '\'', '\u{a0}', '\u{202f}'];
+
+// normalize_lot writes the lot number as a plain integer, without thousands
+// separators or leading zeros, followed by its suffix, if any: `1.001` and
+// `1 001` become `1001`, `007` becomes `7` and `101A` stays `101A`. A separator
+// counts only when exactly three digits follow it. Lots that do not start with
+// a number or have another suffix than letters and digits, such as `1.5`, are
+// left as they are.
+pub fn normalize_lot(lot: &str) -> Cow<'_, str> {
+    let mut digits = String::new();
+    let mut rest = lot;
+    for (i, c) in lot.char_indices() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+        } else if SEPARATORS.contains(&c)
+            && !digits.is_empty()
+            && starts_with_group(&lot[i + c.len_utf8()..])
+        {
+            continue;
+        } else {
+            rest = &lot[i..];
+            break;
+        }
+        rest = &lot[i + c.len_utf8()..];
+    }
+
+    if digits.is_empty() || !rest.chars().all(char::is_alphanumeric) {
+        return Cow::Borrowed(lot);
+    }
+    let number = digits.trim_start_matches('0');
+    let number = if number.is_empty() { "0" } else { number };
+
+    Cow::Owned(format!("{}{}", number, rest))
+}
+
+// starts_with_group tells whether s starts with exactly three digits, as the
+// digits after a thousands separator do.
+fn starts_with_group(s: &str) -> bool {
+    s.chars().take_while(char::is_ascii_digit).count() == 3
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn select_lots() {
+        let lots: Lots = "100-250, 7,12a".parse().unwrap();
+
+        assert!(lots.contains("100"));
+        assert!(lots.contains("250"));
+        assert!(lots.contains("007"));
+        assert!(lots.contains("12a"));
+        assert!(!lots.contains("251"));
+        assert!(!lots.contains("12"));
+
+        assert!("250-100".parse::<Lots>().is_err());
+        assert!("1,,2".parse::<Lots>().is_err());
+        assert!("a-b".parse::<Lots>().is_err());
+    }
+
+    #[test]
+    fn normalize_lots() {
+        assert_eq!("1001", normalize_lot("1.001"));
+        assert_eq!("1001", normalize_lot("1 001"));
+        assert_eq!("1001", normalize_lot("1\u{a0}001"));
+        assert_eq!("1234567", normalize_lot("1,234,567"));
+        assert_eq!("7", normalize_lot("007"));
+        assert_eq!("0", normalize_lot("000"));
+        assert_eq!("101A", normalize_lot("101A"));
+        assert_eq!("1001bis", normalize_lot("1.001bis"));
+        // Lots that are not numbers are left alone.
+        assert_eq!("A12", normalize_lot("A12"));
+        assert_eq!("12.", normalize_lot("12."));
+        assert_eq!("12-3", normalize_lot("12-3"));
+        assert_eq!("1.5", normalize_lot("1.5"));
+        assert_eq!("12.34", normalize_lot("12.34"));
+        assert_eq!("1.0001", normalize_lot("1.0001"));
+    }
+}
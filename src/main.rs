@@ -1,20 +1,404 @@
-use std::env;
+use std::fs;
+use std::io::Write;
 use std::process;
 
-use rename::Config;
+use clap::{CommandFactory, Parser};
+
+#[cfg(feature = "sqlite")]
+use cli::HistoryCommand;
+use cli::{Cli, Command};
+use rename::{RenameError, Report, ReportFormat, RunSummary};
+
+mod cli;
+mod config_file;
+
+// The exit codes of the failures that scripts may want to tell apart. Any
+// other failure exits with 1.
+const BAD_ARGUMENTS: i32 = 2;
+const DATA_FILE_ERROR: i32 = 3;
+const CONFLICTS: i32 = 4;
+const PARTIAL_FAILURE: i32 = 5;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let args = config_file::with_config_file(std::env::args_os().collect()).unwrap_or_else(|err| {
+        eprintln!("Problem reading config file: {}", err);
+
+        process::exit(BAD_ARGUMENTS);
+    });
+    let cli = Cli::parse_from(args);
+    let log_level = cli.log_level();
+
+    init_logger(log_level);
+
+    let check = matches!(cli.command, Some(Command::Check(_)));
+    let (args, dry_run) = match cli.command {
+        Some(Command::Completions { shell }) => {
+            clap_complete::generate(shell, &mut Cli::command(), "rename", &mut std::io::stdout());
+
+            return;
+        }
+        Some(Command::Undo { directory }) => {
+            if let Err(e) = rename::undo(&directory) {
+                log::error!("Application error: {}", e);
+
+                process::exit(1);
+            }
+
+            return;
+        }
+        Some(Command::Restore { directory, backup }) => {
+            if let Err(e) = rename::restore(&directory, &backup) {
+                log::error!("Application error: {}", e);
+
+                process::exit(1);
+            }
+
+            return;
+        }
+        #[cfg(feature = "sqlite")]
+        Some(Command::History { command, history }) => {
+            if let Err(e) = run_history(command, &history) {
+                log::error!("Application error: {}", e);
+
+                process::exit(1);
+            }
+
+            return;
+        }
+        Some(Command::Plan(args)) => (args, true),
+        Some(Command::Apply(args)) => (args, false),
+        Some(Command::Check(args)) => (args, true),
+        None => match cli.run {
+            Some(args) => (args, false),
+            None => {
+                let _ = Cli::command().print_help();
+
+                process::exit(BAD_ARGUMENTS);
+            }
+        },
+    };
 
-    let config = Config::from_args(&args).unwrap_or_else(|err| {
+    let mut config = args.config(log_level).unwrap_or_else(|err| {
         eprintln!("Problem parsing arguments: {}", err);
 
-        process::exit(1);
+        process::exit(BAD_ARGUMENTS);
     });
+    config.dry_run |= dry_run;
+    if check && (config.report_file.is_some() || config.preview_html.is_some()) {
+        eprintln!("Problem parsing arguments: check writes no files, so it takes neither --report-file nor --preview-html");
+
+        process::exit(BAD_ARGUMENTS);
+    }
+
+    if config.watch && !check {
+        if let Err(e) = rename::watch(&config, |result| {
+            match result {
+                Ok(report) => {
+                    log_report(&report);
+                    if !report.renamed.is_empty() {
+                        log_summary(&report.summary());
+                    }
+                }
+                Err(e) => log::error!("Application error: {}", e),
+            }
+            true
+        }) {
+            log::error!("Application error: {}", e);
+
+            process::exit(exit_code(&e));
+        }
+
+        return;
+    }
+
+    let report_format = config.report_format;
+    let report_file = config.report_file.clone();
+    let dry_run = config.dry_run;
+
+    let result = if check {
+        rename::check(config)
+    } else {
+        rename::run(config)
+    };
+
+    if let Some(ReportFormat::Json) = report_format {
+        let json = rename::to_json(&result);
+        match &report_file {
+            Some(report_file) => {
+                if let Err(e) = fs::write(report_file, json) {
+                    log::error!("Could not write report: {}", e);
+                }
+            }
+            None => println!("{}", json),
+        }
+    }
+
+    match result {
+        Ok(report) => {
+            log_report(&report);
+            if !dry_run {
+                log_summary(&report.summary());
+            }
+            if check {
+                log::info!("Check passed: nothing keeps the files from being renamed");
+            }
+            log_timings(&report);
+        }
+        Err(e) => {
+            log::error!("Application error: {}", e);
+
+            process::exit(exit_code(&e));
+        }
+    }
+}
+
+// run_history lists, shows or undoes the runs in the history.
+#[cfg(feature = "sqlite")]
+fn run_history(command: HistoryCommand, history: &std::path::Path) -> Result<(), RenameError> {
+    match command {
+        HistoryCommand::List => {
+            for run in rename::list_runs(history)? {
+                println!("{}", run);
+            }
+        }
+        HistoryCommand::Show { run_id } => {
+            let (run, renamings) = rename::show_run(history, run_id)?;
+            println!("{}", run);
+            for renaming in renamings {
+                println!("  {} -> {}", renaming.old, renaming.new);
+            }
+        }
+        HistoryCommand::Undo { run_id } => rename::undo_run(history, run_id)?,
+    }
+
+    Ok(())
+}
+
+// exit_code tells the class of failure by the error that ended the run.
+fn exit_code(err: &RenameError) -> i32 {
+    match err {
+        #[cfg(feature = "xlsx")]
+        RenameError::Spreadsheet(_) => DATA_FILE_ERROR,
+        #[cfg(feature = "sqlite")]
+        RenameError::Sqlite(_) => DATA_FILE_ERROR,
+        RenameError::CsvParse(_)
+        | RenameError::Json(_)
+        | RenameError::Toml(_)
+        | RenameError::Yaml(_)
+        | RenameError::MissingKey { .. }
+        | RenameError::MissingColumn { .. }
+        | RenameError::UnknownColumn(_)
+        | RenameError::DataFile(..)
+        | RenameError::MismatchedHeaders(_)
+        | RenameError::DisagreeingDataFiles { .. }
+        | RenameError::BadRows(_) => DATA_FILE_ERROR,
+        RenameError::Conflict(_)
+        | RenameError::Strict(_)
+        | RenameError::CrowdedLots(_)
+        | RenameError::DuplicateRows(_) => CONFLICTS,
+        RenameError::Rollback(_) => PARTIAL_FAILURE,
+        RenameError::Template(_)
+        | RenameError::Glob(_)
+        | RenameError::Regex(_)
+        | RenameError::InvalidConfig(_) => BAD_ARGUMENTS,
+        _ => 1,
+    }
+}
+
+// log_report warns about the files and rows that were left alone.
+fn log_report(report: &Report) {
+    if !report.unmatched_files.is_empty() {
+        log::warn!(
+            "{} file(s) matched no row in the data file:",
+            report.unmatched_files.len()
+        );
+        for file in &report.unmatched_files {
+            log::warn!("  {}", file);
+        }
+    }
+    if !report.unmatched_rows.is_empty() {
+        log::warn!(
+            "{} row(s) matched no files in the directory:",
+            report.unmatched_rows.len()
+        );
+        for row in &report.unmatched_rows {
+            log::warn!(
+                "  row {}: lot {}, inventory number {}",
+                row.row,
+                row.lot,
+                row.inventory
+            );
+        }
+    }
+    if !report.malformed_files.is_empty() {
+        log::warn!(
+            "{} file(s) skipped for lacking a photo number:",
+            report.malformed_files.len()
+        );
+        for file in &report.malformed_files {
+            log::warn!("  {}", file);
+        }
+    }
+    if !report.skipped_files.is_empty() {
+        log::warn!(
+            "{} file(s) skipped because their new name already exists:",
+            report.skipped_files.len()
+        );
+        for file in &report.skipped_files {
+            log::warn!("  {}", file);
+        }
+    }
+    if !report.duplicate_files.is_empty() {
+        log::warn!(
+            "{} group(s) of files are identical:",
+            report.duplicate_files.len()
+        );
+        for files in &report.duplicate_files {
+            log::warn!("  {}", files.join(", "));
+        }
+    }
+    if !report.bad_rows.is_empty() {
+        log::warn!("{} row(s) of the data file skipped:", report.bad_rows.len());
+        for row in &report.bad_rows {
+            log::warn!("  {}", row);
+        }
+    }
+    if !report.corrupt_files.is_empty() {
+        log::warn!(
+            "{} file(s) skipped for not being intact images:",
+            report.corrupt_files.len()
+        );
+        for file in &report.corrupt_files {
+            log::warn!("  {}", file);
+        }
+    }
+    if !report.wrong_size_files.is_empty() {
+        log::warn!(
+            "{} file(s) skipped for being smaller or larger than allowed:",
+            report.wrong_size_files.len()
+        );
+        for file in &report.wrong_size_files {
+            log::warn!("  {}", file);
+        }
+    }
+    if !report.low_resolution_lots.is_empty() {
+        log::warn!(
+            "{} lot(s) have images skipped for being smaller than the minimum dimensions:",
+            report.low_resolution_lots.len()
+        );
+        for lot in &report.low_resolution_lots {
+            log::warn!("  {}", lot);
+        }
+    }
+    if !report.crowded_lots.is_empty() {
+        log::warn!(
+            "{} lot(s) have more files than the maximum per lot:",
+            report.crowded_lots.len()
+        );
+        for lot in &report.crowded_lots {
+            log::warn!("  {}", lot);
+        }
+    }
+    if !report.duplicate_rows.is_empty() {
+        log::warn!(
+            "{} row(s) give an inventory number another lot than an earlier row:",
+            report.duplicate_rows.len()
+        );
+        for row in &report.duplicate_rows {
+            log::warn!("  {}", row);
+        }
+    }
+    if !report.renamed_before.is_empty() {
+        log::info!(
+            "{} file(s) left alone because an earlier run renamed them:",
+            report.renamed_before.len()
+        );
+        for file in &report.renamed_before {
+            log::info!("  {}", file);
+        }
+    }
+    if !report.non_unicode_files.is_empty() {
+        log::warn!(
+            "{} file(s) left alone because their names are not valid Unicode:",
+            report.non_unicode_files.len()
+        );
+        for file in &report.non_unicode_files {
+            log::warn!("  {}", file);
+        }
+    }
+    if !report.locked.is_empty() {
+        log::warn!(
+            "{} file(s) were locked by another program and still need renaming; run again once they are closed:",
+            report.locked.len()
+        );
+        for renaming in &report.locked {
+            log::warn!("  {} to {}", renaming.old, renaming.new);
+        }
+    }
+}
+
+// log_timings tells how long the stages of the run took, if measured.
+fn log_timings(report: &Report) {
+    if let Some(timings) = &report.timings {
+        log::info!("{}", timings);
+    }
+}
+
+// log_summary concludes a run with its statistics.
+fn log_summary(summary: &RunSummary) {
+    log::info!(
+        "{} file(s) renamed, {} skipped, {} unmatched file(s), {} unmatched row(s)",
+        summary.renamed,
+        summary.skipped,
+        summary.unmatched_files.len(),
+        summary.unmatched_rows.len()
+    );
+}
+
+// init_logger writes log messages to stderr, prefixed with their level unless
+// they are plain progress messages.
+fn init_logger(level: log::LevelFilter) {
+    env_logger::Builder::new()
+        .filter_level(level)
+        .format(|buf, record| match record.level() {
+            log::Level::Info => writeln!(buf, "{}", record.args()),
+            level => writeln!(buf, "{}: {}", level.as_str().to_lowercase(), record.args()),
+        })
+        .init();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    if let Err(e) = rename::run(config) {
-        eprintln!("Application error: {}", e);
+    #[test]
+    fn exit_codes_by_failure() {
+        assert_eq!(
+            DATA_FILE_ERROR,
+            exit_code(&RenameError::UnknownColumn(String::from("Lot")))
+        );
+        assert_eq!(CONFLICTS, exit_code(&rename::StrictError::default().into()));
+        assert_eq!(
+            BAD_ARGUMENTS,
+            exit_code(&RenameError::Template(rename::TemplateError::UnclosedBrace))
+        );
+        assert_eq!(
+            BAD_ARGUMENTS,
+            exit_code(&RenameError::InvalidConfig(String::from(
+                "jobs must be a positive integer"
+            )))
+        );
+        assert_eq!(
+            1,
+            exit_code(&RenameError::Io(std::io::Error::other("disk full")))
+        );
 
-        process::exit(1);
+        let config = rename::ConfigBuilder::new("tests/missing.csv", "tests/files")
+            .dry_run(true)
+            .build()
+            .unwrap();
+        let err = rename::run(config).unwrap_err();
+        assert!(matches!(err, RenameError::DataFile(..)));
+        assert_eq!(DATA_FILE_ERROR, exit_code(&err));
     }
 }
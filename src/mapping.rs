@@ -0,0 +1,67 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::error::csv_error;
+use crate::{RenameError, Renaming};
+
+// write_mapping writes the old and new name of every renamed file, with the
+// lot and inventory number it matched, as a comma-separated file, so that
+// records still referring to the old names can be updated.
+pub fn write_mapping(
+    path: &Path,
+    renamed: &[Renaming],
+    objects: &BTreeMap<String, (String, String)>,
+) -> Result<(), RenameError> {
+    let mut writer = csv::Writer::from_path(path).map_err(csv_error)?;
+    writer
+        .write_record(["old", "new", "lot", "inventory"])
+        .map_err(csv_error)?;
+    for renaming in renamed {
+        let (lot, inventory) = objects
+            .get(&renaming.old)
+            .map(|(lot, inventory)| (lot.as_str(), inventory.as_str()))
+            .unwrap_or_default();
+        writer
+            .write_record([&renaming.old, &renaming.new, lot, inventory])
+            .map_err(csv_error)?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn write_old_and_new_names() {
+        let path = Path::new("tests/tmp_mapping.csv");
+        let renamed = vec![
+            Renaming {
+                old: "00243878.1.jpg".to_string(),
+                new: "1_1.jpg".to_string(),
+            },
+            Renaming {
+                old: "00243880, final.jpg".to_string(),
+                new: "2_1.jpg".to_string(),
+            },
+        ];
+        let objects = BTreeMap::from([(
+            "00243878.1.jpg".to_string(),
+            ("1".to_string(), "00243878".to_string()),
+        )]);
+
+        write_mapping(path, &renamed, &objects).unwrap();
+        let contents = fs::read_to_string(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            "old,new,lot,inventory\n\
+             00243878.1.jpg,1_1.jpg,1,00243878\n\
+             \"00243880, final.jpg\",2_1.jpg,,\n",
+            contents
+        );
+    }
+}
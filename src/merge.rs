@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::reader::{CatalogueRow, Records};
+use crate::RenameError;
+
+// merge_records merges the rows of data files that split up one catalogue, in
+// order. The data files must have the same header. A row that an earlier data
+// file holds as well is left out, but data files that give the same object
+// different lot numbers cannot be merged. Objects are told apart by the values
+// of the key columns, joined by the key separator, as when matching, or by
+// their inventory number if there are no key columns. Key and lot values are
+// passed through comparable first, so that rows which match the same files
+// are merged. Rows are merged as they are read, remembering only the lot
+// number of each object.
+pub fn merge_records(
+    data: Vec<(PathBuf, Records)>,
+    key_columns: &[usize],
+    key_separator: &str,
+    comparable: impl Fn(&str) -> String + 'static,
+) -> Result<Records, RenameError> {
+    if data.len() <= 1 {
+        return Ok(data
+            .into_iter()
+            .next()
+            .map(|(_, records)| records)
+            .unwrap_or_default());
+    }
+
+    let headers = data[0].1.headers.clone();
+    let (lot_column, inventory_column) = (data[0].1.lot_column, data[0].1.inventory_column);
+    let mut data_files = vec![];
+    let mut sources = vec![];
+    for (data_file, records) in data {
+        if records.headers != headers {
+            return Err(RenameError::MismatchedHeaders(data_file));
+        }
+        data_files.push(data_file);
+        sources.push(records.rows);
+    }
+
+    let key_separator = key_separator.to_owned();
+    let key_columns = key_columns.to_vec();
+    let key_of = move |row: &CatalogueRow| {
+        let lot = comparable(&row.lot);
+        if key_columns.is_empty() {
+            return (lot, comparable(&row.inventory));
+        }
+        let columns = row.columns(lot_column, inventory_column);
+        let key = key_columns
+            .iter()
+            .map(|&column| comparable(columns.get(column).unwrap_or("")))
+            .collect::<Vec<_>>()
+            .join(&key_separator);
+        (lot, key)
+    };
+
+    // The data file and lot number of each object seen so far, as compared and
+    // as written.
+    let mut lots: HashMap<String, (usize, String, String)> = HashMap::new();
+    let rows = sources
+        .into_iter()
+        .enumerate()
+        .flat_map(|(index, rows)| rows.map(move |row| (index, row)))
+        .filter_map(move |(index, row)| {
+            let row = match row {
+                Ok(row) => row,
+                Err(err) => return Some(Err(err)),
+            };
+            let (lot, key) = key_of(&row);
+
+            match lots.get(&key) {
+                Some((seen_in, _, _)) if *seen_in == index => {}
+                Some((_, seen_lot, _)) if *seen_lot == lot => return None,
+                Some((seen_in, _, seen_lot)) => {
+                    return Some(Err(RenameError::DisagreeingDataFiles {
+                        inventory: key,
+                        first: (data_files[*seen_in].clone(), seen_lot.clone()),
+                        second: (data_files[index].clone(), row.lot.clone()),
+                    }));
+                }
+                None => {
+                    lots.insert(key, (index, lot, row.lot.clone()));
+                }
+            }
+            Some(Ok(row))
+        });
+
+    Ok(Records {
+        headers,
+        lot_column,
+        inventory_column,
+        rows: Box::new(rows),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_data_files() {
+        let records = |rows: Vec<(&str, &str)>| Records {
+            headers: Some(vec![String::from("lot"), String::from("inventory")]),
+            ..Records::fixed(
+                rows.into_iter()
+                    .map(|(lot, inventory)| CatalogueRow::new(lot, inventory))
+                    .collect(),
+            )
+        };
+        let first = || {
+            (
+                PathBuf::from("part1.csv"),
+                records(vec![("1", "00243878"), ("2", "00243880")]),
+            )
+        };
+
+        // The second export repeats the last lot of the first.
+        let merged: Vec<CatalogueRow> = merge_records(
+            vec![
+                first(),
+                (
+                    PathBuf::from("part2.csv"),
+                    records(vec![("2", "00243880"), ("3", "00243344")]),
+                ),
+            ],
+            &[],
+            "",
+            str::to_owned,
+        )
+        .unwrap()
+        .rows
+        .collect::<Result<_, _>>()
+        .unwrap();
+        assert_eq!(
+            vec!["00243878", "00243880", "00243344"],
+            merged
+                .iter()
+                .map(|row| row.inventory.as_str())
+                .collect::<Vec<_>>()
+        );
+
+        let disagreeing = merge_records(
+            vec![
+                first(),
+                (PathBuf::from("part2.csv"), records(vec![("4", "00243880")])),
+            ],
+            &[],
+            "",
+            str::to_owned,
+        )
+        .unwrap()
+        .rows
+        .collect::<Result<Vec<_>, _>>();
+        assert!(matches!(
+            disagreeing,
+            Err(RenameError::DisagreeingDataFiles { inventory, .. }) if inventory == "00243880"
+        ));
+
+        let mut other_header = records(vec![]);
+        other_header.headers = Some(vec![String::from("inventory"), String::from("lot")]);
+        let mismatched = merge_records(
+            vec![first(), (PathBuf::from("part2.csv"), other_header)],
+            &[],
+            "",
+            str::to_owned,
+        );
+        assert!(matches!(mismatched, Err(RenameError::MismatchedHeaders(_))));
+
+        // With a composite key, objects sharing an inventory number are told
+        // apart by the collection they belong to.
+        let keyed = |data_file: &str, lot: &str, collection: &str| {
+            let row = CatalogueRow {
+                extra: vec![String::from(collection)],
+                ..CatalogueRow::new(lot, "00243880")
+            };
+            let records = Records {
+                headers: Some(vec![
+                    String::from("lot"),
+                    String::from("inventory"),
+                    String::from("collection"),
+                ]),
+                ..Records::fixed(vec![row])
+            };
+            (PathBuf::from(data_file), records)
+        };
+        let merged = merge_records(
+            vec![keyed("part1.csv", "1", "A"), keyed("part2.csv", "2", "B")],
+            &[2, 1],
+            "-",
+            str::to_owned,
+        )
+        .unwrap()
+        .rows
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+        assert_eq!(2, merged.len());
+
+        // Keys are compared as when matching, so rows that only differ in
+        // whitespace or case are merged.
+        let merged = merge_records(
+            vec![
+                first(),
+                (
+                    PathBuf::from("part2.csv"),
+                    records(vec![(" 2", "00243880 "), ("3", "A00243344")]),
+                ),
+                (
+                    PathBuf::from("part3.csv"),
+                    records(vec![("3", "a00243344")]),
+                ),
+            ],
+            &[],
+            "",
+            |value| value.trim().to_lowercase(),
+        )
+        .unwrap()
+        .rows
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+        assert_eq!(
+            vec!["00243878", "00243880", "A00243344"],
+            merged
+                .iter()
+                .map(|row| row.inventory.as_str())
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,493 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::audit::AuditLog;
+use crate::retry::Retry;
+use crate::state::StateLog;
+use crate::storage::Storage;
+
+// Operation is what happens to a file to give it its new name.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    #[default]
+    Rename,
+    // Copy the file to its new name, keeping the original.
+    Copy,
+    // Add a hard link under the new name, keeping the original.
+    HardLink,
+    // Add a symbolic link to the original under the new name.
+    Symlink,
+}
+
+impl Operation {
+    pub fn name(self) -> &'static str {
+        match self {
+            Operation::Rename => "rename",
+            Operation::Copy => "copy",
+            Operation::HardLink => "hardlink",
+            Operation::Symlink => "symlink",
+        }
+    }
+
+    pub fn verb(self) -> &'static str {
+        match self {
+            Operation::Rename => "renaming",
+            Operation::Copy => "copying",
+            Operation::HardLink => "hard-linking",
+            Operation::Symlink => "symlinking",
+        }
+    }
+
+    // apply gives the file its new name on the storage.
+    pub fn apply(self, storage: &dyn Storage, old_path: &Path, new_path: &Path) -> io::Result<()> {
+        match self {
+            Operation::Rename => storage.rename(old_path, new_path),
+            Operation::Copy => storage.copy(old_path, new_path),
+            Operation::HardLink => storage.hard_link(old_path, new_path),
+            Operation::Symlink => storage.symlink(old_path, new_path),
+        }
+    }
+
+    // revert undoes apply, which for all but a renaming means removing the
+    // new file.
+    pub fn revert(self, storage: &dyn Storage, old_path: &Path, new_path: &Path) -> io::Result<()> {
+        match self {
+            Operation::Rename => storage.rename(new_path, old_path),
+            Operation::Copy | Operation::HardLink | Operation::Symlink => storage.remove(new_path),
+        }
+    }
+}
+
+// Link is the kind of link that gives files their new name next to the
+// original one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Link {
+    Hard,
+    Sym,
+}
+
+impl std::str::FromStr for Link {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<Link, &'static str> {
+        match value {
+            "hard" => Ok(Link::Hard),
+            "sym" => Ok(Link::Sym),
+            _ => Err("link must be hard or sym"),
+        }
+    }
+}
+
+// move_file renames the file or, if the new path is on another file system,
+// copies it there and removes the original once the copy is complete.
+pub fn move_file(old_path: &Path, new_path: &Path, verify: bool, xattrs: bool) -> io::Result<()> {
+    match fs::rename(old_path, new_path) {
+        Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+            log::debug!(
+                "{} is on another file system, copying instead",
+                new_path.display()
+            );
+            copy_file(old_path, new_path, verify, xattrs)?;
+            fs::remove_file(old_path)
+        }
+        result => result,
+    }
+}
+
+// copy_file copies the file and checks that the copy has the length and, with
+// verify, the SHA-256 checksum of the original. The copy keeps the
+// permissions and the access and modification time of the original and, with
+// xattrs, its extended attributes. A bad copy is removed.
+pub fn copy_file(old_path: &Path, new_path: &Path, verify: bool, xattrs: bool) -> io::Result<()> {
+    // Reading the original for the copy may change its access time.
+    let metadata = fs::metadata(old_path)?;
+    // Copying takes the permissions along.
+    let length = fs::copy(old_path, new_path)?;
+
+    let intact =
+        metadata.len() == length && (!verify || checksum(old_path)? == checksum(new_path)?);
+    if !intact {
+        fs::remove_file(new_path)?;
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("copy of {} does not match the original", old_path.display()),
+        ));
+    }
+
+    // Checking the copy reads it, so its times are set after.
+    if let Err(err) = keep_metadata(old_path, new_path, &metadata, xattrs) {
+        fs::remove_file(new_path)?;
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+// keep_metadata gives the copy the access and modification time in the
+// metadata of the original and, with xattrs, its extended attributes.
+fn keep_metadata(
+    old_path: &Path,
+    new_path: &Path,
+    metadata: &fs::Metadata,
+    xattrs: bool,
+) -> io::Result<()> {
+    let times = fs::FileTimes::new()
+        .set_accessed(metadata.accessed()?)
+        .set_modified(metadata.modified()?);
+
+    // The copy may be read-only, like the original, so it is opened just to
+    // change its attributes.
+    let mut options = fs::OpenOptions::new();
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        // FILE_WRITE_ATTRIBUTES
+        options.access_mode(0x100);
+    }
+    #[cfg(not(windows))]
+    options.read(true);
+    options.open(new_path)?.set_times(times)?;
+
+    if xattrs {
+        copy_xattrs(old_path, new_path)?;
+    }
+
+    Ok(())
+}
+
+// copy_xattrs copies the extended attributes of the original to the copy.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+fn copy_xattrs(old_path: &Path, new_path: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let old_path = CString::new(old_path.as_os_str().as_bytes())?;
+    let new_path = CString::new(new_path.as_os_str().as_bytes())?;
+
+    // The names are listed one after the other, each ending in a null byte.
+    let names = read_xattr(|buffer, size| unsafe {
+        #[cfg(target_os = "macos")]
+        return libc::listxattr(old_path.as_ptr(), buffer.cast(), size, 0);
+        #[cfg(not(target_os = "macos"))]
+        libc::listxattr(old_path.as_ptr(), buffer.cast(), size)
+    })?;
+
+    for name in names
+        .split(|&byte| byte == 0)
+        .filter(|name| !name.is_empty())
+    {
+        let name = CString::new(name)?;
+        let value = read_xattr(|buffer, size| unsafe {
+            #[cfg(target_os = "macos")]
+            return libc::getxattr(old_path.as_ptr(), name.as_ptr(), buffer, size, 0, 0);
+            #[cfg(not(target_os = "macos"))]
+            libc::getxattr(old_path.as_ptr(), name.as_ptr(), buffer, size)
+        })?;
+
+        let (value, size) = (value.as_ptr().cast(), value.len());
+        let result = unsafe {
+            #[cfg(target_os = "macos")]
+            let result = libc::setxattr(new_path.as_ptr(), name.as_ptr(), value, size, 0, 0);
+            #[cfg(not(target_os = "macos"))]
+            let result = libc::setxattr(new_path.as_ptr(), name.as_ptr(), value, size, 0);
+            result
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+// read_xattr calls a function of libc that reads extended attributes into a
+// buffer, first to learn the size of the buffer and then to fill it.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+fn read_xattr(read: impl Fn(*mut libc::c_void, usize) -> isize) -> io::Result<Vec<u8>> {
+    let size = read(std::ptr::null_mut(), 0);
+    if size < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut buffer = vec![0u8; size as usize];
+    let size = read(buffer.as_mut_ptr().cast(), buffer.len());
+    if size < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    buffer.truncate(size as usize);
+
+    Ok(buffer)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+fn copy_xattrs(_old_path: &Path, _new_path: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "extended attributes cannot be copied on this platform",
+    ))
+}
+
+// checksum computes the SHA-256 checksum of the file contents.
+pub fn checksum(path: &Path) -> io::Result<Vec<u8>> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(hasher.finalize().to_vec())
+}
+
+// Transfer applies an operation to files from the source directory, placing
+// them under their new names in the target directory.
+pub struct Transfer<'a> {
+    pub storage: &'a dyn Storage,
+    pub source: &'a Path,
+    pub target: &'a Path,
+    pub operation: Operation,
+    // Log the SHA-256 checksum of the renamed files to the audit log.
+    pub verify: bool,
+    // Replace files that already have the new name, instead of failing.
+    pub overwrite: bool,
+    // Where files replaced by overwriting are moved to, instead of being lost.
+    pub backup: Option<&'a Path>,
+    // Where completed renamings are recorded, so an interrupted run can resume.
+    pub state: Option<&'a StateLog>,
+    // Where every renaming and its result is logged for later reference.
+    pub audit: Option<&'a AuditLog>,
+    // How often a renaming failing with a transient error is tried again.
+    pub retry: Retry,
+    // Leave files that another program holds alone instead of failing.
+    pub skip_locked: bool,
+}
+
+impl Transfer<'_> {
+    // skips tells whether a renaming that failed with the error is left alone
+    // while the others go on.
+    pub fn skips(&self, err: &io::Error) -> bool {
+        self.skip_locked && crate::retry::is_locked(err)
+    }
+
+    pub fn apply(&self, old_name: &str, new_name: &str) -> io::Result<()> {
+        let old_path = self.source.join(old_name);
+        let new_path = self.target.join(new_name);
+
+        // Whether renaming replaces an existing file differs between platforms,
+        // so check for it up front.
+        let check = !self.overwrite || self.backup.is_some();
+        if check && old_path != new_path && self.storage.exists(&new_path)? {
+            let Some(backup) = self.backup.filter(|_| self.overwrite) else {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} already exists", new_path.display()),
+                ));
+            };
+            let backup_path = backup.join(new_name);
+            if let Some(parent) = backup_path.parent() {
+                self.storage.create_dir_all(parent)?;
+            }
+            log::debug!("backing up {} to {}", new_name, backup_path.display());
+            self.storage.rename(&new_path, &backup_path)?;
+        }
+
+        // Links cannot replace a file, so a link overwriting one is made under
+        // a temporary name and renamed over it.
+        let replaced = !check
+            && matches!(self.operation, Operation::HardLink | Operation::Symlink)
+            && old_path != new_path
+            && self.storage.exists(&new_path)?;
+        let result = self.retry.run(old_name, || {
+            if !replaced {
+                return self.operation.apply(self.storage, &old_path, &new_path);
+            }
+            let temp_path = self.target.join(crate::staging::temp_name(new_name));
+            self.operation.apply(self.storage, &old_path, &temp_path)?;
+            self.storage.rename(&temp_path, &new_path).inspect_err(|_| {
+                let _ = self.storage.remove(&temp_path);
+            })
+        });
+        if result.is_err() {
+            if let Err(err) = self.restore_backup(new_name, &new_path) {
+                log::warn!("could not restore {} from the backup: {}", new_name, err);
+            }
+        }
+        if let Some(audit) = self.audit {
+            let checksum = match result {
+                Ok(()) if self.verify && self.storage.is_local() => checksum(&new_path).ok(),
+                _ => None,
+            };
+            let logged = audit.record(
+                self.operation.name(),
+                &old_path,
+                &new_path,
+                &result,
+                checksum,
+            );
+            if let Err(err) = logged {
+                log::warn!(
+                    "could not log renaming of {} to the audit log: {}",
+                    old_name,
+                    err
+                );
+            }
+        }
+        result?;
+
+        if let Some(state) = self.state {
+            // A resumed run also recognizes renamed files that were not recorded.
+            if let Err(err) = state.record(old_name) {
+                log::warn!("could not record renaming of {}: {}", old_name, err);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn revert(&self, old_name: &str, new_name: &str) -> io::Result<()> {
+        let old_path = self.source.join(old_name);
+        let new_path = self.target.join(new_name);
+
+        let result = self.retry.run(old_name, || {
+            self.operation.revert(self.storage, &old_path, &new_path)
+        });
+        if let Some(audit) = self.audit {
+            if let Err(err) = audit.record("revert", &old_path, &new_path, &result, None) {
+                log::warn!(
+                    "could not log reverting of {} to the audit log: {}",
+                    old_name,
+                    err
+                );
+            }
+        }
+
+        result?;
+
+        // The file that the renaming displaced gets its name back.
+        self.restore_backup(new_name, &new_path)
+    }
+
+    // restore_backup moves the file that renaming to new_name displaced back
+    // from the backup, if it was kept there.
+    fn restore_backup(&self, new_name: &str, new_path: &Path) -> io::Result<()> {
+        let Some(backup) = self.backup.filter(|_| self.overwrite) else {
+            return Ok(());
+        };
+        let backup_path = backup.join(new_name);
+        if !self.storage.exists(&backup_path)? {
+            return Ok(());
+        }
+        log::debug!("restoring {} from {}", new_name, backup_path.display());
+        self.storage.rename(&backup_path, new_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copy_and_verify() {
+        let dir = Path::new("tests/tmp_verify");
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir(dir).unwrap();
+        let old_path = Path::new("tests/files/00243878.1.jpg");
+        let new_path = dir.join("1_1.jpg");
+
+        copy_file(old_path, &new_path, true, false).unwrap();
+        let intact = checksum(old_path).unwrap() == checksum(&new_path).unwrap();
+        fs::write(&new_path, b"truncated").unwrap();
+        let corrupted = checksum(old_path).unwrap() == checksum(&new_path).unwrap();
+
+        fs::remove_dir_all(dir).unwrap();
+
+        assert!(intact);
+        assert!(!corrupted);
+        assert_eq!(32, checksum(old_path).unwrap().len());
+    }
+
+    #[test]
+    fn copy_keeps_timestamps_and_permissions() {
+        let dir = Path::new("tests/tmp_keep_metadata");
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir(dir).unwrap();
+        let old_path = dir.join("00243878.1.jpg");
+        let new_path = dir.join("1_1.jpg");
+        fs::copy("tests/files/00243878.1.jpg", &old_path).unwrap();
+        let taken =
+            std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+        let original = fs::File::options().write(true).open(&old_path).unwrap();
+        original
+            .set_times(fs::FileTimes::new().set_accessed(taken).set_modified(taken))
+            .unwrap();
+        drop(original);
+        let mut permissions = fs::metadata(&old_path).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&old_path, permissions).unwrap();
+
+        copy_file(&old_path, &new_path, true, false).unwrap();
+        let metadata = fs::metadata(&new_path).unwrap();
+
+        for path in [&old_path, &new_path] {
+            let mut permissions = fs::metadata(path).unwrap().permissions();
+            #[allow(clippy::permissions_set_readonly_false)]
+            permissions.set_readonly(false);
+            fs::set_permissions(path, permissions).unwrap();
+        }
+        fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(taken, metadata.modified().unwrap());
+        assert_eq!(taken, metadata.accessed().unwrap());
+        assert!(metadata.permissions().readonly());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn overwrite_with_links() {
+        let dir = Path::new("tests/tmp_overwrite_links");
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir(dir).unwrap();
+        fs::write(dir.join("00243878.1.jpg"), "new").unwrap();
+        let storage = crate::LocalStorage::default();
+
+        let mut linked = vec![];
+        for operation in [Operation::HardLink, Operation::Symlink] {
+            fs::write(dir.join("1_1.jpg"), "earlier").unwrap();
+            let transfer = Transfer {
+                storage: &storage,
+                source: dir,
+                target: dir,
+                operation,
+                verify: false,
+                overwrite: true,
+                backup: None,
+                state: None,
+                audit: None,
+                retry: Retry::default(),
+                skip_locked: false,
+            };
+            let result = transfer.apply("00243878.1.jpg", "1_1.jpg");
+            linked.push((
+                result.is_ok(),
+                fs::read_to_string(dir.join("1_1.jpg")).unwrap(),
+            ));
+            fs::remove_file(dir.join("1_1.jpg")).unwrap();
+        }
+        let left = crate::list_files(dir);
+
+        fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(
+            vec![(true, String::from("new")), (true, String::from("new"))],
+            linked
+        );
+        assert_eq!(vec!["00243878.1.jpg"], left);
+    }
+}
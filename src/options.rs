@@ -0,0 +1,414 @@
+use crate::ConfigBuilder;
+
+// Setter sets an option on a builder. Its value is parsed beforehand, so that
+// an invalid value leaves the builder as it was.
+#[cfg_attr(not(any(feature = "capi", feature = "python")), allow(dead_code))]
+pub type Setter = Box<dyn FnOnce(ConfigBuilder) -> ConfigBuilder>;
+
+// OPTIONS names the options that setter takes, in the order of the
+// command-line flags. They are all flags of a run, except those about the
+// output of the command-line program: --progress, --watch, --color, --report
+// and --report-file.
+pub const OPTIONS: &[&str] = &[
+    "from-regex",
+    "to",
+    "data-file",
+    "dir",
+    "dry-run",
+    "delimiter",
+    "encoding",
+    "lot-column",
+    "inventory-column",
+    "date-column",
+    "hero-column",
+    "key-column",
+    "key-separator",
+    "no-headers",
+    "ignore-case",
+    "lots",
+    "where",
+    "match",
+    "trim",
+    "normalize-lot",
+    "format",
+    "widths",
+    "sheet",
+    "lot-key",
+    "inventory-key",
+    "query",
+    "template",
+    "script",
+    "keep-extension",
+    "lowercase-extension",
+    "sidecars",
+    "lot-transform",
+    "transform",
+    "normalize",
+    "sanitize",
+    "suffix",
+    "resequence",
+    "on-malformed",
+    "on-bad-row",
+    "on-duplicate",
+    "max-per-lot",
+    "on-crowded-lot",
+    "pad-lot",
+    "pad-index",
+    "timings",
+    "symlinks",
+    "include",
+    "exclude",
+    "copy",
+    "link",
+    "output-dir",
+    "lot-dirs",
+    "strict",
+    "find-duplicates",
+    "verify-images",
+    "min-size",
+    "max-size",
+    "min-dimensions",
+    "on-conflict",
+    "backup",
+    "verify",
+    "post-check",
+    "preserve-xattrs",
+    "jobs",
+    "stage-all",
+    "retries",
+    "retry-delay",
+    "skip-locked",
+    "skip",
+    "limit",
+    "resume",
+    "audit-log",
+    "write-mapping",
+    "history",
+    "preview-html",
+];
+
+// setter parses the value of the option, named as the command-line flag
+// without its dashes, and returns how to set it on a builder. Flags are set by
+// true or false, and repeatable options are added to; those that take
+// comma-separated values on the command line, such as sidecars, take them here
+// too.
+#[cfg_attr(not(any(feature = "capi", feature = "python")), allow(dead_code))]
+pub fn setter(name: &str, value: &str) -> Result<Setter, String> {
+    fn parse<T: std::str::FromStr<Err = &'static str>>(value: &str) -> Result<T, String> {
+        value.parse().map_err(String::from)
+    }
+    fn flag(value: &str) -> Result<bool, String> {
+        match value {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            _ => Err(format!("{:?} is not true or false", value)),
+        }
+    }
+    fn number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+        value
+            .parse()
+            .map_err(|_| format!("{:?} is not a number", value))
+    }
+
+    let value = value.to_owned();
+    let set: Setter = match name {
+        "from-regex" => Box::new(move |b| b.from_regex(value)),
+        "to" | "template" => Box::new(move |b| b.template(value)),
+        "data-file" => Box::new(move |b| b.extra_data_file(value)),
+        "dir" => Box::new(move |b| b.extra_dir(value)),
+        "dry-run" => {
+            let dry_run = flag(&value)?;
+            Box::new(move |b| b.dry_run(dry_run))
+        }
+        "delimiter" => {
+            let delimiter = match value.as_str() {
+                "\\t" | "tab" => b'\t',
+                "comma" => b',',
+                "semicolon" => b';',
+                "pipe" => b'|',
+                _ if value.len() == 1 && value.is_ascii() => value.as_bytes()[0],
+                _ => return Err(String::from("delimiter must be a single ASCII character")),
+            };
+            Box::new(move |b| b.delimiter(delimiter))
+        }
+        "encoding" => {
+            let encoding = parse(&value)?;
+            Box::new(move |b| b.encoding(encoding))
+        }
+        "lot-column" => {
+            let column = parse(&value)?;
+            Box::new(move |b| b.lot_column(column))
+        }
+        "inventory-column" => {
+            let column = parse(&value)?;
+            Box::new(move |b| b.inventory_column(column))
+        }
+        "date-column" => {
+            let column = parse(&value)?;
+            Box::new(move |b| b.date_column(column))
+        }
+        "hero-column" => {
+            let column = parse(&value)?;
+            Box::new(move |b| b.hero_column(column))
+        }
+        "key-column" => {
+            let column = parse(&value)?;
+            Box::new(move |b| b.key_column(column))
+        }
+        "key-separator" => Box::new(move |b| b.key_separator(value)),
+        "no-headers" => {
+            let no_headers = flag(&value)?;
+            Box::new(move |b| b.has_headers(!no_headers))
+        }
+        "ignore-case" => {
+            let ignore_case = flag(&value)?;
+            Box::new(move |b| b.ignore_case(ignore_case))
+        }
+        "lots" => {
+            let lots = parse(&value)?;
+            Box::new(move |b| b.lots(lots))
+        }
+        "where" => {
+            let condition = parse(&value)?;
+            Box::new(move |b| b.condition(condition))
+        }
+        "match" => {
+            let mode = parse(&value)?;
+            Box::new(move |b| b.match_mode(mode))
+        }
+        "trim" => {
+            let trim = flag(&value)?;
+            Box::new(move |b| b.trim(trim))
+        }
+        "normalize-lot" => {
+            let normalize_lot = flag(&value)?;
+            Box::new(move |b| b.normalize_lot(normalize_lot))
+        }
+        "format" => {
+            let format = parse(&value)?;
+            Box::new(move |b| b.format(format))
+        }
+        "widths" => {
+            let widths = parse(&value)?;
+            Box::new(move |b| b.widths(widths))
+        }
+        "sheet" => Box::new(move |b| b.sheet(value)),
+        "lot-key" => Box::new(move |b| b.lot_key(value)),
+        "inventory-key" => Box::new(move |b| b.inventory_key(value)),
+        "query" => Box::new(move |b| b.query(value)),
+        "script" => Box::new(move |b| b.script(value)),
+        "keep-extension" => {
+            let keep_extension = flag(&value)?;
+            Box::new(move |b| b.keep_extension(keep_extension))
+        }
+        "lowercase-extension" => {
+            let lowercase_extension = flag(&value)?;
+            Box::new(move |b| b.lowercase_extension(lowercase_extension))
+        }
+        "sidecars" => Box::new(move |b| value.split(',').fold(b, |b, ext| b.sidecar(ext))),
+        "lot-transform" => {
+            let transform = parse(&value)?;
+            Box::new(move |b| b.lot_transform(transform))
+        }
+        "transform" => {
+            let transforms = value.split(',').map(parse).collect::<Result<Vec<_>, _>>()?;
+            Box::new(move |b| transforms.into_iter().fold(b, |b, t| b.transform(t)))
+        }
+        "normalize" => {
+            let normalization = parse(&value)?;
+            Box::new(move |b| b.normalization(normalization))
+        }
+        "sanitize" => {
+            let sanitize = flag(&value)?;
+            Box::new(move |b| b.sanitize(sanitize))
+        }
+        "suffix" => {
+            let suffix = parse(&value)?;
+            Box::new(move |b| b.suffix(suffix))
+        }
+        "resequence" => {
+            let resequence = flag(&value)?;
+            Box::new(move |b| b.resequence(resequence))
+        }
+        "on-malformed" => {
+            let policy = parse(&value)?;
+            Box::new(move |b| b.on_malformed(policy))
+        }
+        "on-bad-row" => {
+            let policy = parse(&value)?;
+            Box::new(move |b| b.on_bad_row(policy))
+        }
+        "on-duplicate" => {
+            let policy = parse(&value)?;
+            Box::new(move |b| b.on_duplicate(policy))
+        }
+        "max-per-lot" => {
+            let max = number(&value)?;
+            Box::new(move |b| b.max_per_lot(max))
+        }
+        "on-crowded-lot" => {
+            let policy = parse(&value)?;
+            Box::new(move |b| b.on_crowded_lot(policy))
+        }
+        "pad-lot" => {
+            let width = number(&value)?;
+            Box::new(move |b| b.lot_width(width))
+        }
+        "pad-index" => {
+            let width = number(&value)?;
+            Box::new(move |b| b.index_width(width))
+        }
+        "timings" => {
+            let timings = flag(&value)?;
+            Box::new(move |b| b.timings(timings))
+        }
+        "symlinks" => {
+            let symlinks = flag(&value)?;
+            Box::new(move |b| b.symlinks(symlinks))
+        }
+        "include" => Box::new(move |b| b.include(value)),
+        "exclude" => Box::new(move |b| b.exclude(value)),
+        "copy" => {
+            let copy = flag(&value)?;
+            Box::new(move |b| b.copy(copy))
+        }
+        "link" => {
+            let link = parse(&value)?;
+            Box::new(move |b| b.link(link))
+        }
+        "output-dir" => Box::new(move |b| b.output_dir(value)),
+        "lot-dirs" => {
+            let lot_dirs = flag(&value)?;
+            Box::new(move |b| b.lot_dirs(lot_dirs))
+        }
+        "strict" => {
+            let strict = flag(&value)?;
+            Box::new(move |b| b.strict(strict))
+        }
+        "find-duplicates" => {
+            let find_duplicates = flag(&value)?;
+            Box::new(move |b| b.find_duplicates(find_duplicates))
+        }
+        "verify-images" => {
+            let verify_images = flag(&value)?;
+            Box::new(move |b| b.verify_images(verify_images))
+        }
+        "min-size" => {
+            let size = parse(&value)?;
+            Box::new(move |b| b.min_size(size))
+        }
+        "max-size" => {
+            let size = parse(&value)?;
+            Box::new(move |b| b.max_size(size))
+        }
+        "min-dimensions" => {
+            let min = parse(&value)?;
+            Box::new(move |b| b.min_dimensions(min))
+        }
+        "on-conflict" => {
+            let policy = parse(&value)?;
+            Box::new(move |b| b.on_conflict(policy))
+        }
+        "backup" => Box::new(move |b| b.backup(value)),
+        "verify" => {
+            let verify = flag(&value)?;
+            Box::new(move |b| b.verify(verify))
+        }
+        "post-check" => {
+            let post_check = flag(&value)?;
+            Box::new(move |b| b.post_check(post_check))
+        }
+        "preserve-xattrs" => {
+            let preserve_xattrs = flag(&value)?;
+            Box::new(move |b| b.preserve_xattrs(preserve_xattrs))
+        }
+        "jobs" => {
+            let jobs = number(&value)?;
+            Box::new(move |b| b.jobs(jobs))
+        }
+        "stage-all" => {
+            let stage_all = flag(&value)?;
+            Box::new(move |b| b.stage_all(stage_all))
+        }
+        "retries" => {
+            let retries = number(&value)?;
+            Box::new(move |b| b.retries(retries))
+        }
+        "retry-delay" => {
+            let delay = humantime::parse_duration(&value).map_err(|err| err.to_string())?;
+            Box::new(move |b| b.retry_delay(delay))
+        }
+        "skip-locked" => {
+            let skip_locked = flag(&value)?;
+            Box::new(move |b| b.skip_locked(skip_locked))
+        }
+        "skip" => {
+            let skip = number(&value)?;
+            Box::new(move |b| b.skip(skip))
+        }
+        "limit" => {
+            let limit = number(&value)?;
+            Box::new(move |b| b.limit(limit))
+        }
+        "resume" => {
+            let resume = flag(&value)?;
+            Box::new(move |b| b.resume(resume))
+        }
+        "audit-log" => Box::new(move |b| b.audit_log(value)),
+        "write-mapping" => Box::new(move |b| b.mapping_file(value)),
+        "history" => Box::new(move |b| b.history(value)),
+        "preview-html" => Box::new(move |b| b.preview_html(value)),
+        _ => return Err(format!("unknown option {:?}", name)),
+    };
+
+    Ok(set)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_every_option() {
+        for name in OPTIONS {
+            let err = setter(name, "").err().unwrap_or_default();
+            assert!(!err.starts_with("unknown option"), "{}", name);
+        }
+        assert!(setter("progress", "true").is_err());
+    }
+
+    #[test]
+    fn set_options_by_flag() {
+        let config = [
+            ("dir", "tests/files"),
+            ("sidecars", "xmp,cr2"),
+            ("transform", "lowercase,underscores"),
+            ("max-per-lot", "6"),
+            ("on-crowded-lot", "error"),
+            ("retry-delay", "2s"),
+            ("no-headers", "true"),
+            ("write-mapping", "mapping.csv"),
+        ]
+        .into_iter()
+        .fold(
+            ConfigBuilder::new("tests/data.csv", "tests/files"),
+            |b, (name, value)| setter(name, value).unwrap()(b),
+        )
+        .build()
+        .unwrap();
+
+        assert_eq!(
+            vec![std::path::PathBuf::from("tests/files")],
+            config.extra_dirs
+        );
+        assert_eq!(vec!["xmp", "cr2"], config.sidecars);
+        assert_eq!(2, config.transforms.len());
+        assert_eq!(Some(6), config.max_per_lot);
+        assert_eq!(crate::CrowdedLotPolicy::Error, config.on_crowded_lot);
+        assert_eq!(std::time::Duration::from_secs(2), config.retry_delay);
+        assert!(!config.has_headers);
+        assert!(config.mapping_file.is_some());
+        assert!(setter("retries", "many").is_err());
+        assert!(setter("transform", "lowercase,bold").is_err());
+    }
+}
@@ -0,0 +1,121 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::reader::Records;
+use crate::storage::Storage;
+use crate::{CatalogueRow, Config, RenameError, RenamePlan};
+
+// plan_from_parts plans a run after rows and file names given in memory
+// instead of a data file and a directory listing, for programs that hold the
+// catalogue themselves. The rows take the place of the data file, so columns
+// are taken by index: the lot and inventory number are columns 0 and 1 and
+// the other values follow from column 2 on. The files are the ones in the
+// directory of the configuration; an output directory counts as empty. The
+// plan renames the files in that directory when it is executed. Options that
+// read the files themselves, such as verifying images or taking dates from
+// their metadata, still do.
+pub fn plan_from_parts(
+    mut config: Config,
+    rows: impl IntoIterator<Item = CatalogueRow>,
+    files: impl IntoIterator<Item = String>,
+) -> Result<RenamePlan, RenameError> {
+    let records = Records::fixed(rows.into_iter().collect());
+    let storage = config.storage();
+    config.storage = Some(std::sync::Arc::new(Listing {
+        dir: config.dir.clone(),
+        files: files.into_iter().collect(),
+    }));
+
+    let mut plan = crate::plan_with(config, Some(records))?;
+    plan.storage = storage;
+    Ok(plan)
+}
+
+// Listing is the storage a plan from parts is made in: it lists the given
+// files in the directory and nothing anywhere else, and cannot rename them.
+#[derive(Debug)]
+struct Listing {
+    dir: PathBuf,
+    files: Vec<String>,
+}
+
+impl Storage for Listing {
+    fn list(&self, dir: &Path) -> io::Result<Vec<String>> {
+        if dir == self.dir {
+            Ok(self.files.clone())
+        } else {
+            Ok(vec![])
+        }
+    }
+
+    fn exists(&self, path: &Path) -> io::Result<bool> {
+        Ok(path.parent() == Some(&self.dir)
+            && path
+                .file_name()
+                .is_some_and(|name| self.files.iter().any(|file| name == file.as_str())))
+    }
+
+    fn rename(&self, _from: &Path, _to: &Path) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    fn copy(&self, _from: &Path, _to: &Path) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    fn remove(&self, _path: &Path) -> io::Result<()> {
+        Err(read_only())
+    }
+}
+
+fn read_only() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "files given in memory cannot be renamed",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plan_from_rows_and_files_in_memory() {
+        // Neither the data file nor the directory exists.
+        let mut config = Config::new("missing.csv", "missing");
+        config.template = String::from("{lot}_{index}_{column:2}.jpg");
+        let rows = vec![
+            CatalogueRow {
+                extra: vec!["vase".to_string()],
+                ..CatalogueRow::new("3", "00243344")
+            },
+            CatalogueRow {
+                extra: vec!["view".to_string()],
+                ..CatalogueRow::new("12", "00243999")
+            },
+        ];
+        let files = vec![
+            "00243344.1.jpg".to_string(),
+            "00243344.2.jpg".to_string(),
+            "3_1_vase.jpg".to_string(),
+            "notes.txt".to_string(),
+        ];
+
+        let plan = plan_from_parts(config, rows, files).unwrap();
+
+        assert_eq!(
+            vec![
+                ("00243344.1.jpg", "3_1_vase.jpg"),
+                ("00243344.2.jpg", "3_2_vase.jpg")
+            ],
+            plan.renamings
+                .iter()
+                .map(|(old, new)| (old.as_str(), new.as_str()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(1, plan.conflicts.len());
+        assert_eq!(vec!["3_1_vase.jpg", "notes.txt"], plan.unmatched_files);
+        assert_eq!("00243999", plan.unmatched_rows[0].inventory);
+        assert!(plan.storage.is_local());
+    }
+}
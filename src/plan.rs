@@ -0,0 +1,393 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::audit::AuditLog;
+use crate::lock::{self, DirLock};
+use crate::operation::{Operation, Transfer};
+use crate::retry::Retry;
+use crate::storage::Storage;
+use crate::{
+    Conflict, ConflictError, ConflictPolicy, CrowdedLot, DuplicateRow, LowResolutionLot,
+    RenameError, Report, RowError, StrictError, Timings, UnmatchedRow,
+};
+
+// RenamePlan holds everything a run would do, so that it can be inspected
+// before any file is touched.
+#[derive(Debug)]
+pub struct RenamePlan {
+    // The new name of each file to rename, keyed by its old name. Files are
+    // renamed, logged and reported in the order of their old names. Names are
+    // Unicode; files whose names are not are in non_unicode_files instead.
+    pub renamings: BTreeMap<String, String>,
+    // Renamings that would overwrite another file; a plan with conflicts cannot
+    // be executed.
+    pub conflicts: Vec<Conflict>,
+    // Files in the directory that matched no row of the data file.
+    pub unmatched_files: Vec<String>,
+    // Rows of the data file that matched no files in the directory.
+    pub unmatched_rows: Vec<UnmatchedRow>,
+    // Files that matched a row, but whose name lacks a photo number.
+    pub malformed_files: Vec<String>,
+    // Files left alone because their new name is already taken.
+    pub skipped_files: Vec<String>,
+    // Groups of byte-identical files to rename, if duplicates were looked for.
+    pub duplicate_files: Vec<Vec<String>>,
+    // Files left alone because they are not intact images, if images were
+    // verified.
+    pub corrupt_files: Vec<String>,
+    // Files left alone because they are smaller or larger than allowed, if
+    // sizes were checked.
+    pub wrong_size_files: Vec<String>,
+    // Lots with images left alone because they are smaller than the minimum
+    // dimensions, if those were checked.
+    pub low_resolution_lots: Vec<LowResolutionLot>,
+    // Files left alone because an earlier run already renamed them.
+    pub renamed_before: Vec<String>,
+    // Files left alone because their names are not valid Unicode, which Linux
+    // allows, with the invalid bytes replaced.
+    pub non_unicode_files: Vec<String>,
+    // Rows of the data file that could not be used, and why.
+    pub bad_rows: Vec<RowError>,
+    // Lots with more files than the maximum per lot, if one was set.
+    pub crowded_lots: Vec<CrowdedLot>,
+    // Rows of the data file that give the inventory number of an earlier row
+    // another lot number.
+    pub duplicate_rows: Vec<DuplicateRow>,
+    // How long reading, listing and planning took, if measured.
+    pub(crate) timings: Option<Timings>,
+    // The lot and inventory number of each file that matched a row.
+    pub(crate) objects: BTreeMap<String, (String, String)>,
+    // Renamings that an interrupted run already completed, when resuming it.
+    pub resumed: Vec<(String, String)>,
+    pub(crate) source_dir: PathBuf,
+    pub(crate) target_dir: PathBuf,
+    pub(crate) operation: Operation,
+    pub(crate) jobs: usize,
+    pub(crate) stage_all: bool,
+    pub(crate) strict: bool,
+    pub(crate) verify: bool,
+    pub(crate) post_check: bool,
+    pub(crate) retry: Retry,
+    pub(crate) skip_locked: bool,
+    pub(crate) on_conflict: ConflictPolicy,
+    pub(crate) progress: bool,
+    pub(crate) audit_log: Option<PathBuf>,
+    pub(crate) mapping_file: Option<PathBuf>,
+    #[cfg_attr(not(feature = "sqlite"), allow(dead_code))]
+    pub(crate) history: Option<PathBuf>,
+    pub(crate) backup_dir: Option<PathBuf>,
+    pub(crate) storage: Arc<dyn Storage>,
+}
+
+impl RenamePlan {
+    pub fn source_dir(&self) -> &Path {
+        &self.source_dir
+    }
+
+    pub fn target_dir(&self) -> &Path {
+        &self.target_dir
+    }
+
+    pub fn operation(&self) -> Operation {
+        self.operation
+    }
+
+    // report lists what executing the plan leaves alone; nothing is renamed yet.
+    pub fn report(&self) -> Report {
+        Report {
+            renamed: vec![],
+            unmatched_files: self.unmatched_files.clone(),
+            unmatched_rows: self.unmatched_rows.clone(),
+            malformed_files: self.malformed_files.clone(),
+            skipped_files: self.skipped_files.clone(),
+            duplicate_files: self.duplicate_files.clone(),
+            corrupt_files: self.corrupt_files.clone(),
+            wrong_size_files: self.wrong_size_files.clone(),
+            low_resolution_lots: self.low_resolution_lots.clone(),
+            renamed_before: self.renamed_before.clone(),
+            non_unicode_files: self.non_unicode_files.clone(),
+            locked: vec![],
+            bad_rows: self.bad_rows.clone(),
+            crowded_lots: self.crowded_lots.clone(),
+            duplicate_rows: self.duplicate_rows.clone(),
+            timings: self.timings,
+        }
+    }
+
+    // rows lists every file the plan concerns and every unmatched row, for
+    // showing the plan as a table. Files are ordered by name, followed by the
+    // rows that matched no files.
+    pub fn rows(&self) -> Vec<PlanRow> {
+        let conflicting: HashSet<&str> = self
+            .conflicts
+            .iter()
+            .flat_map(|conflict| match conflict {
+                Conflict::DuplicateTarget { old_names, .. } => {
+                    old_names.iter().map(String::as_str).collect()
+                }
+                Conflict::ExistingTarget { old_name, .. } => vec![old_name.as_str()],
+            })
+            .collect();
+
+        let renamed = self.renamings.iter().map(|(old_name, new_name)| {
+            let status = if conflicting.contains(old_name.as_str()) {
+                Status::Conflict
+            } else {
+                Status::Ok
+            };
+            (old_name, Some(new_name), status)
+        });
+        let left_alone = self
+            .skipped_files
+            .iter()
+            .map(|file| (file, Status::Skipped))
+            .chain(
+                self.malformed_files
+                    .iter()
+                    .map(|file| (file, Status::Malformed)),
+            )
+            .chain(
+                self.corrupt_files
+                    .iter()
+                    .map(|file| (file, Status::Corrupt)),
+            )
+            .chain(
+                self.wrong_size_files
+                    .iter()
+                    .map(|file| (file, Status::WrongSize)),
+            )
+            .chain(
+                self.low_resolution_lots
+                    .iter()
+                    .flat_map(|lot| &lot.images)
+                    .flat_map(|image| iter::once(&image.file).chain(&image.sidecars))
+                    .map(|file| (file, Status::LowResolution)),
+            )
+            .chain(
+                self.renamed_before
+                    .iter()
+                    .map(|file| (file, Status::Renamed)),
+            )
+            .chain(
+                self.unmatched_files
+                    .iter()
+                    .map(|file| (file, Status::Unmatched)),
+            )
+            .map(|(file, status)| (file, None, status));
+
+        let mut rows: Vec<PlanRow> = renamed
+            .chain(left_alone)
+            .map(|(old_name, new_name, status)| {
+                let object = self.objects.get(old_name);
+                PlanRow {
+                    old: Some(old_name.clone()),
+                    new: new_name.cloned(),
+                    lot: object.map(|(lot, _)| lot.clone()),
+                    inventory: object.map(|(_, inventory)| inventory.clone()),
+                    status,
+                }
+            })
+            .collect();
+        rows.sort_by(|a, b| a.old.cmp(&b.old));
+
+        rows.extend(self.unmatched_rows.iter().map(|row| PlanRow {
+            old: None,
+            new: None,
+            lot: Some(row.lot.clone()),
+            inventory: Some(row.inventory.clone()),
+            status: Status::Unmatched,
+        }));
+
+        rows
+    }
+
+    // validate fails if the plan has conflicts or, in strict mode, any
+    // anomaly, all of which are then reported together.
+    pub fn validate(&self) -> Result<(), RenameError> {
+        if self.strict {
+            let err = StrictError {
+                conflicts: self.conflicts.clone(),
+                unmatched_files: self.unmatched_files.clone(),
+                unmatched_rows: self.unmatched_rows.clone(),
+                malformed_files: self.malformed_files.clone(),
+                duplicate_files: self.duplicate_files.clone(),
+                corrupt_files: self.corrupt_files.clone(),
+                wrong_size_files: self.wrong_size_files.clone(),
+                low_resolution_lots: self.low_resolution_lots.clone(),
+                bad_rows: self.bad_rows.clone(),
+                crowded_lots: self.crowded_lots.clone(),
+                duplicate_rows: self.duplicate_rows.clone(),
+            };
+            if !err.is_empty() {
+                return Err(err.into());
+            }
+        }
+        if !self.conflicts.is_empty() {
+            return Err(RenameError::Conflict(ConflictError {
+                conflicts: self.conflicts.clone(),
+            }));
+        }
+
+        Ok(())
+    }
+
+    // execute validates the plan and then renames all files or, if any renaming
+    // fails, none.
+    pub fn execute(self) -> Result<Report, RenameError> {
+        self.execute_locked(vec![])
+    }
+
+    // execute_locked executes the plan holding the locks of the directories
+    // it renames files in and to, also the extra directories and the output
+    // directory. Those in held were locked before planning.
+    pub(crate) fn execute_locked(self, held: Vec<DirLock>) -> Result<Report, RenameError> {
+        self.validate()?;
+
+        self.storage.create_dir_all(&self.target_dir)?;
+        let _locks = if self.storage.is_local() {
+            let extra_dirs: HashSet<PathBuf> = self
+                .renamings
+                .keys()
+                .filter(|old| Path::new(old).is_absolute())
+                .filter_map(|old| Path::new(old).parent().map(Path::to_path_buf))
+                .collect();
+            let dirs = [&self.source_dir, &self.target_dir]
+                .into_iter()
+                .chain(&extra_dirs)
+                .map(PathBuf::as_path);
+            lock::acquire_all(dirs, &held)?
+        } else {
+            vec![]
+        };
+        let audit_log = match &self.audit_log {
+            Some(path) => Some(AuditLog::open(path)?),
+            None => None,
+        };
+
+        let backup_dir = match &self.backup_dir {
+            Some(dir) => Some(crate::backup::run_dir(self.storage.as_ref(), dir)?),
+            None => None,
+        };
+        let transfer = Transfer {
+            storage: self.storage.as_ref(),
+            source: &self.source_dir,
+            target: &self.target_dir,
+            operation: self.operation,
+            verify: self.verify,
+            overwrite: self.on_conflict == ConflictPolicy::Overwrite,
+            backup: backup_dir.as_deref(),
+            state: None,
+            audit: audit_log.as_ref(),
+            retry: self.retry,
+            skip_locked: self.skip_locked,
+        };
+
+        let mut report = self.report();
+        let renaming_started = Instant::now();
+        let renamed = crate::rename_all_files(
+            &transfer,
+            self.renamings,
+            self.resumed,
+            self.jobs,
+            self.progress,
+            self.stage_all,
+        );
+        if let Some(dir) = backup_dir.as_deref().filter(|_| self.storage.is_local()) {
+            crate::backup::clean_up(dir);
+        }
+        (report.renamed, report.locked) = renamed?;
+        if let Some(timings) = &mut report.timings {
+            timings.renaming = renaming_started.elapsed();
+            timings.files = report.renamed.len();
+        }
+        if let Some(path) = &self.mapping_file {
+            crate::mapping::write_mapping(path, &report.renamed, &self.objects)?;
+        }
+        #[cfg(feature = "sqlite")]
+        if let Some(path) = self.history.as_ref().filter(|_| !report.renamed.is_empty()) {
+            // The files are renamed already, so a history that cannot be
+            // written does not fail the run.
+            match crate::history::record(
+                path,
+                self.operation,
+                &self.source_dir,
+                &self.target_dir,
+                &report.renamed,
+            ) {
+                Ok(id) => log::info!("recorded the run in the history as run {}", id),
+                Err(err) => log::warn!("could not record the run in the history: {}", err),
+            }
+        }
+        if self.post_check {
+            let discrepancies = crate::post_check::find_discrepancies(
+                self.storage.as_ref(),
+                &self.source_dir,
+                &self.target_dir,
+                self.operation,
+                &report.renamed,
+            )?;
+            if !discrepancies.is_empty() {
+                return Err(RenameError::PostCheck(discrepancies));
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+// PlanRow is a line of the plan table: a file with its new name, or a row of
+// the data file that matched no files.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlanRow {
+    pub old: Option<String>,
+    pub new: Option<String>,
+    pub lot: Option<String>,
+    pub inventory: Option<String>,
+    pub status: Status,
+}
+
+// Status tells what the plan does with a file or row.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    // The file is renamed.
+    Ok,
+    // The new name clashes with another file, so the plan cannot be executed.
+    Conflict,
+    // The new name is taken, so the file is left alone.
+    Skipped,
+    // The file lacks a photo number.
+    Malformed,
+    // The file is not an intact image.
+    Corrupt,
+    // The file is smaller or larger than allowed.
+    #[serde(rename = "wrong-size")]
+    WrongSize,
+    // The image is smaller than the minimum dimensions.
+    #[serde(rename = "low-resolution")]
+    LowResolution,
+    // An earlier run already renamed the file.
+    Renamed,
+    // The file matched no row, or the row no files.
+    Unmatched,
+}
+
+impl Status {
+    pub fn name(self) -> &'static str {
+        match self {
+            Status::Ok => "ok",
+            Status::Conflict => "conflict",
+            Status::Skipped => "skipped",
+            Status::Malformed => "malformed",
+            Status::Corrupt => "corrupt",
+            Status::WrongSize => "wrong-size",
+            Status::LowResolution => "low-resolution",
+            Status::Renamed => "renamed",
+            Status::Unmatched => "unmatched",
+        }
+    }
+}
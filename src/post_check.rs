@@ -0,0 +1,131 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::storage::Storage;
+use crate::{Operation, Renaming};
+
+// Discrepancy is a renaming that did not hold up when the directories were
+// listed again after the run, for instance because another program wrote to
+// them at the same time.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Discrepancy {
+    // The file is not found under its new name.
+    MissingTarget { old: String, new: String },
+    // The file is still found under its old name, though it was moved.
+    RemainingSource { old: String, new: String },
+}
+
+impl fmt::Display for Discrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Discrepancy::MissingTarget { old, new } => {
+                write!(f, "{} is missing after renaming {} to it", new, old)
+            }
+            Discrepancy::RemainingSource { old, new } => {
+                write!(f, "{} is still there after renaming it to {}", old, new)
+            }
+        }
+    }
+}
+
+// find_discrepancies lists the source and target directories again and
+// checks that every renamed file has its new name and, if it was moved rather
+// than copied or linked, no longer its old one. An old name that another file
+// took over, as in a swap, is expected to be there. Each directory is listed
+// once, also the subdirectories of lots.
+pub fn find_discrepancies(
+    storage: &dyn Storage,
+    source_dir: &Path,
+    target_dir: &Path,
+    operation: Operation,
+    renamed: &[Renaming],
+) -> io::Result<Vec<Discrepancy>> {
+    let mut listings: HashMap<PathBuf, HashSet<String>> = HashMap::new();
+    let mut exists = |path: &Path| -> io::Result<bool> {
+        let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+            return Ok(false);
+        };
+        if !listings.contains_key(dir) {
+            let files = storage.list(dir)?.into_iter().collect();
+            listings.insert(dir.to_path_buf(), files);
+        }
+        Ok(listings[dir].contains(&*name.to_string_lossy()))
+    };
+
+    let targets: HashSet<PathBuf> = renamed
+        .iter()
+        .map(|renaming| target_dir.join(&renaming.new))
+        .collect();
+    let mut discrepancies = vec![];
+    for renaming in renamed {
+        let source = source_dir.join(&renaming.old);
+        let target = target_dir.join(&renaming.new);
+        if !exists(&target)? {
+            discrepancies.push(Discrepancy::MissingTarget {
+                old: renaming.old.clone(),
+                new: renaming.new.clone(),
+            });
+        }
+        if operation == Operation::Rename && !targets.contains(&source) && exists(&source)? {
+            discrepancies.push(Discrepancy::RemainingSource {
+                old: renaming.old.clone(),
+                new: renaming.new.clone(),
+            });
+        }
+    }
+
+    Ok(discrepancies)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::LocalStorage;
+    use std::fs;
+
+    #[test]
+    fn find_renamings_that_did_not_hold_up() {
+        let dir = Path::new("tests/tmp_post_check");
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir_all(dir.join("1")).unwrap();
+        for name in ["1/1_1.jpg", "2_1.jpg", "00243880.1.jpg", "a.jpg", "b.jpg"] {
+            fs::write(dir.join(name), name).unwrap();
+        }
+        let renamed: Vec<Renaming> = [
+            ("00243878.1.jpg", "1/1_1.jpg"),
+            ("00243880.1.jpg", "2_1.jpg"),
+            ("00243344.1.jpg", "3_1.jpg"),
+            ("a.jpg", "b.jpg"),
+            ("b.jpg", "a.jpg"),
+        ]
+        .into_iter()
+        .map(|(old, new)| Renaming {
+            old: old.to_string(),
+            new: new.to_string(),
+        })
+        .collect();
+        let storage = LocalStorage::default();
+
+        let moved = find_discrepancies(&storage, dir, dir, Operation::Rename, &renamed);
+        let copied = find_discrepancies(&storage, dir, dir, Operation::Copy, &renamed);
+
+        fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(
+            vec![
+                Discrepancy::RemainingSource {
+                    old: String::from("00243880.1.jpg"),
+                    new: String::from("2_1.jpg"),
+                },
+                Discrepancy::MissingTarget {
+                    old: String::from("00243344.1.jpg"),
+                    new: String::from("3_1.jpg"),
+                },
+            ],
+            moved.unwrap()
+        );
+        assert_eq!(1, copied.unwrap().len());
+    }
+}
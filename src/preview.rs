@@ -0,0 +1,158 @@
+use std::fmt::Write;
+use std::io::Cursor;
+use std::path::Path;
+
+use image::{ImageFormat, ImageReader};
+
+use crate::PlanRow;
+
+// The largest width and height of a thumbnail, in pixels.
+const THUMBNAIL_SIZE: u32 = 160;
+
+const STYLE: &str = "\
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; }
+th, td { padding: 0.4em 0.8em; border-bottom: 1px solid #ddd; text-align: left; }
+td.photo { width: 160px; height: 160px; text-align: center; }
+.ok, .renamed { color: #2e7d32; }
+.conflict { color: #c62828; font-weight: bold; }
+.skipped, .malformed, .corrupt, .wrong-size, .low-resolution, .unmatched { color: #b26a00; }
+";
+
+// render lays out the rows of a plan as an HTML page for reviewing it in a
+// browser, with a thumbnail of every file next to its old and new name. The
+// thumbnails are embedded, so the page can be passed on as a single file.
+// Without a directory to read the files from, there are no thumbnails.
+pub fn render(rows: &[PlanRow], dir: Option<&Path>) -> String {
+    let mut html = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str("<title>Rename preview</title>\n<style>\n");
+    html.push_str(STYLE);
+    html.push_str("</style>\n</head>\n<body>\n<h1>Rename preview</h1>\n<table>\n");
+    html.push_str("<tr><th>Photo</th><th>Old name</th><th>New name</th><th>Lot</th>");
+    html.push_str("<th>Inventory</th><th>Status</th></tr>\n");
+
+    for row in rows {
+        let photo = match (dir, &row.old) {
+            (Some(dir), Some(old)) => thumbnail(&dir.join(old))
+                .map(|src| format!("<img src=\"{}\" alt=\"{}\">", src, escape(old))),
+            _ => None,
+        };
+        let cells = [&row.old, &row.new, &row.lot, &row.inventory]
+            .map(|value| value.as_deref().map_or(String::from("-"), escape));
+        let status = row.status.name();
+        let _ = writeln!(
+            html,
+            "<tr><td class=\"photo\">{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td>\
+             <td class=\"{}\">{}</td></tr>",
+            photo.unwrap_or_default(),
+            cells[0],
+            cells[1],
+            cells[2],
+            cells[3],
+            status,
+            status
+        );
+    }
+
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
+
+// thumbnail scales the image down to a JPEG thumbnail, as a data URI. Files
+// that are not images have none.
+fn thumbnail(path: &Path) -> Option<String> {
+    let image = match ImageReader::open(path).and_then(|reader| reader.with_guessed_format()) {
+        Ok(reader) => reader.decode().map_err(|err| err.to_string()),
+        Err(err) => Err(err.to_string()),
+    };
+    let image = match image {
+        Ok(image) => image,
+        Err(err) => {
+            log::debug!("no thumbnail of {}: {}", path.display(), err);
+            return None;
+        }
+    };
+
+    let mut jpeg = Cursor::new(vec![]);
+    image
+        .thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+        .into_rgb8()
+        .write_to(&mut jpeg, ImageFormat::Jpeg)
+        .ok()?;
+
+    Some(format!("data:image/jpeg;base64,{}", base64(jpeg.get_ref())))
+}
+
+// escape makes text safe to include in HTML.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// base64 encodes the bytes in standard Base64 with padding.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, &byte)| {
+            group | (byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Status;
+
+    #[test]
+    fn render_preview() {
+        let rows = vec![
+            PlanRow {
+                old: Some("00243878.1.jpg".to_string()),
+                new: Some("1_1.jpg".to_string()),
+                lot: Some("1".to_string()),
+                inventory: Some("00243878".to_string()),
+                status: Status::Ok,
+            },
+            PlanRow {
+                old: None,
+                new: None,
+                lot: Some("<12>".to_string()),
+                inventory: Some("00243999".to_string()),
+                status: Status::Unmatched,
+            },
+        ];
+
+        let html = render(&rows, Some(Path::new("tests/files")));
+
+        assert!(html.contains("<img src=\"data:image/jpeg;base64,/9j/"));
+        assert!(html.contains("<td>00243878.1.jpg</td><td>1_1.jpg</td><td>1</td>"));
+        assert!(html.contains("<td>&lt;12&gt;</td>"));
+        assert!(html.contains("<td class=\"unmatched\">unmatched</td>"));
+        assert!(!render(&rows, None).contains("<img"));
+
+        assert_eq!("TWFu", base64(b"Man"));
+        assert_eq!("TWE=", base64(b"Ma"));
+        assert_eq!("TQ==", base64(b"M"));
+    }
+}
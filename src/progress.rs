@@ -0,0 +1,63 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Progress counts the files processed by a run and, if shown, draws a bar on
+// stderr with how many are done and how long the rest will take. The bar
+// needs the progress feature; without it nothing is drawn.
+pub struct Progress {
+    position: AtomicU64,
+    #[cfg(feature = "progress")]
+    bar: indicatif::ProgressBar,
+}
+
+impl Progress {
+    // new shows a bar for length files if shown is set.
+    #[cfg_attr(not(feature = "progress"), allow(unused_variables))]
+    pub fn new(length: u64, shown: bool) -> Progress {
+        #[cfg(not(feature = "progress"))]
+        if shown {
+            log::warn!("a progress bar needs the progress feature; showing none");
+        }
+
+        Progress {
+            position: AtomicU64::new(0),
+            #[cfg(feature = "progress")]
+            bar: match shown {
+                true => {
+                    let style = indicatif::ProgressStyle::with_template(
+                        "{wide_bar} {pos}/{len} files, {eta} remaining",
+                    )
+                    .expect("progress bar template is valid");
+                    indicatif::ProgressBar::new(length).with_style(style)
+                }
+                false => indicatif::ProgressBar::hidden(),
+            },
+        }
+    }
+
+    // inc counts delta more files as processed.
+    pub fn inc(&self, delta: u64) {
+        self.position.fetch_add(delta, Ordering::SeqCst);
+        #[cfg(feature = "progress")]
+        self.bar.inc(delta);
+    }
+
+    // position is the number of files processed so far.
+    #[cfg(test)]
+    pub fn position(&self) -> u64 {
+        self.position.load(Ordering::SeqCst)
+    }
+
+    // suspend hides the bar while f writes to the terminal, such as a log line.
+    pub fn suspend<F: FnOnce() -> R, R>(&self, f: F) -> R {
+        #[cfg(feature = "progress")]
+        return self.bar.suspend(f);
+        #[cfg(not(feature = "progress"))]
+        f()
+    }
+
+    // finish_and_clear removes the bar once the run is done.
+    pub fn finish_and_clear(&self) {
+        #[cfg(feature = "progress")]
+        self.bar.finish_and_clear();
+    }
+}
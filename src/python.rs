@@ -0,0 +1,179 @@
+// The Python module, for scripts that plan and rename in-process instead of
+// running the command-line program and parsing its output:
+//
+//     import rename
+//     config = rename.Config("catalogue.csv", "photos", on_conflict="skip")
+//     plan = rename.plan(config)
+//     report = rename.execute(plan)
+//
+// Options are named as the command-line flags, with underscores for dashes.
+// Every flag of a run is taken, except those about the output of the
+// command-line program: progress, watch, color, report and report_file. Rows, renamings and reports are handed over as dicts and lists, with the
+// same fields as the JSON report.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+use serde::Serialize;
+
+use crate::options::setter;
+use crate::RenamePlan;
+
+create_exception!(rename, RenameError, PyException);
+
+/// The options of a run: the data file, the directory with the files to
+/// rename and the flags of a run, named with underscores for dashes. Flags
+/// take a bool and repeatable options such as include a list. The flags
+/// progress, watch, color, report and report_file are not taken.
+#[pyclass(name = "Config", frozen)]
+struct PyConfig {
+    config: crate::Config,
+}
+
+#[pymethods]
+impl PyConfig {
+    #[new]
+    #[pyo3(signature = (data_file, dir, **options))]
+    fn new(
+        data_file: PathBuf,
+        dir: PathBuf,
+        options: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<PyConfig> {
+        let mut builder = crate::Config::builder(data_file, dir);
+        for (name, value) in options.into_iter().flatten() {
+            let name = name.extract::<String>()?.replace('_', "-");
+            let values = match value.cast::<PyList>() {
+                Ok(list) => list.iter().collect(),
+                Err(_) => vec![value],
+            };
+            for value in values {
+                let value = match value.extract::<bool>() {
+                    Ok(flag) => flag.to_string(),
+                    Err(_) => value.str()?.to_string(),
+                };
+                let set = setter(&name, &value).map_err(PyValueError::new_err)?;
+                builder = set(builder);
+            }
+        }
+        let config = builder
+            .build()
+            .map_err(|err| PyValueError::new_err(err.to_string()))?;
+
+        Ok(PyConfig { config })
+    }
+}
+
+/// What a run would do, as planned by plan. It is executed at most once.
+#[pyclass(name = "Plan")]
+struct PyPlan {
+    plan: Option<RenamePlan>,
+}
+
+impl PyPlan {
+    fn get(&self) -> PyResult<&RenamePlan> {
+        self.plan
+            .as_ref()
+            .ok_or_else(|| RenameError::new_err("the plan was executed"))
+    }
+}
+
+#[pymethods]
+impl PyPlan {
+    /// Every file the plan concerns and every row that matched no files, as
+    /// dicts with the old and new name, lot, inventory number and status.
+    #[getter]
+    fn rows<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        to_python(py, &self.get()?.rows())
+    }
+
+    /// The new name of each file to rename, by its old name.
+    #[getter]
+    fn renamings(&self) -> PyResult<BTreeMap<String, String>> {
+        Ok(self.get()?.renamings.clone())
+    }
+
+    /// What executing the plan leaves alone, as the report of a run that
+    /// renamed nothing yet.
+    #[getter]
+    fn report<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        to_python(py, &self.get()?.report())
+    }
+}
+
+/// Plans a run without touching any files.
+#[pyfunction]
+fn plan(py: Python<'_>, config: &PyConfig) -> PyResult<PyPlan> {
+    let config = config.config.clone();
+    let plan = py.detach(|| crate::plan(config)).map_err(to_error)?;
+
+    Ok(PyPlan { plan: Some(plan) })
+}
+
+/// Renames the files as planned, or none if any renaming fails, and returns
+/// the report of the run.
+#[pyfunction]
+fn execute<'py>(py: Python<'py>, mut plan: PyRefMut<'_, PyPlan>) -> PyResult<Bound<'py, PyAny>> {
+    let plan = plan
+        .plan
+        .take()
+        .ok_or_else(|| RenameError::new_err("the plan was executed"))?;
+    let report = py.detach(|| plan.execute()).map_err(to_error)?;
+
+    to_python(py, &report)
+}
+
+/// Plans and renames the files in the directory after the data file, and
+/// returns the report of the run.
+#[pymodule(name = "rename")]
+mod module {
+    #[pymodule_export]
+    use super::{execute, plan, PyConfig, PyPlan, RenameError};
+}
+
+fn to_error(err: crate::RenameError) -> PyErr {
+    RenameError::new_err(err.to_string())
+}
+
+// to_python hands a value over as the dicts and lists of its JSON form.
+fn to_python<'py>(py: Python<'py>, value: &impl Serialize) -> PyResult<Bound<'py, PyAny>> {
+    let json = serde_json::to_string(value).expect("value is serializable");
+    py.import("json")?.call_method1("loads", (json,))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_options_by_keyword() {
+        Python::initialize();
+        Python::attach(|py| {
+            let options = PyDict::new(py);
+            options.set_item("on_conflict", "skip").unwrap();
+            options.set_item("include", vec!["*.jpg", "*.png"]).unwrap();
+            options.set_item("normalize_lot", true).unwrap();
+            options.set_item("max_per_lot", 6).unwrap();
+            let config = PyConfig::new(
+                PathBuf::from("tests/data.csv"),
+                PathBuf::from("tests/files"),
+                Some(&options),
+            )
+            .unwrap()
+            .config;
+
+            assert_eq!(crate::ConflictPolicy::Skip, config.on_conflict);
+            assert_eq!(vec!["*.jpg", "*.png"], config.include);
+            assert!(config.normalize_lot);
+            assert_eq!(Some(6), config.max_per_lot);
+
+            let options = PyDict::new(py);
+            options.set_item("progress", true).unwrap();
+            let err = PyConfig::new(PathBuf::new(), PathBuf::new(), Some(&options));
+            assert!(err.is_err_and(|err| err.is_instance_of::<PyValueError>(py)));
+        });
+    }
+}
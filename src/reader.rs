@@ -0,0 +1,943 @@
+#[cfg(feature = "xlsx")]
+use calamine::{open_workbook, Reader, Xlsx, XlsxError};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::error::csv_error;
+use crate::RenameError;
+
+// Format is the file format of the data file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    // Delimited text, tab-separated unless configured otherwise.
+    Csv,
+    // Text with columns at fixed positions, as laid out by Widths.
+    FixedWidth,
+    // An Excel workbook.
+    #[cfg(feature = "xlsx")]
+    Xlsx,
+    // A JSON array of objects.
+    Json,
+    // A TOML table mapping inventory numbers to lot numbers.
+    Toml,
+    // A YAML mapping of inventory numbers to lot numbers.
+    Yaml,
+    // An SQLite database, queried for the lot and inventory numbers.
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+}
+
+impl Format {
+    // has_fixed_columns tells whether the reader yields rows holding just the
+    // lot and inventory number, without other columns to refer to.
+    pub fn has_fixed_columns(self) -> bool {
+        match self {
+            Format::Json | Format::Toml | Format::Yaml => true,
+            #[cfg(feature = "sqlite")]
+            Format::Sqlite => true,
+            _ => false,
+        }
+    }
+}
+
+impl std::str::FromStr for Format {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<Format, &'static str> {
+        match value.to_lowercase().as_str() {
+            "csv" | "tsv" => Ok(Format::Csv),
+            "fixed-width" | "fixed" => Ok(Format::FixedWidth),
+            #[cfg(feature = "xlsx")]
+            "xlsx" => Ok(Format::Xlsx),
+            #[cfg(not(feature = "xlsx"))]
+            "xlsx" => Err("reading xlsx data files needs the xlsx feature"),
+            "json" => Ok(Format::Json),
+            "toml" => Ok(Format::Toml),
+            "yaml" | "yml" => Ok(Format::Yaml),
+            #[cfg(feature = "sqlite")]
+            "sqlite" => Ok(Format::Sqlite),
+            #[cfg(not(feature = "sqlite"))]
+            "sqlite" => Err("reading sqlite data files needs the sqlite feature"),
+            _ => {
+                Err("format must be one of csv, tsv, fixed-width, xlsx, json, toml, yaml or sqlite")
+            }
+        }
+    }
+}
+
+// Widths lays out the columns of a fixed-width data file, as the widths of
+// consecutive columns in characters, such as `6,10,40`. A column may also be
+// given by its zero-based offset and width, such as `20:10`, to skip filler
+// between columns; a width alone starts where the previous column ended.
+// Columns may not overlap and are given from left to right.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Widths(Vec<Range<usize>>);
+
+impl std::str::FromStr for Widths {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<Widths, &'static str> {
+        let error = "widths must be a list of widths or offset:width pairs, e.g. 6,10,20:40";
+        let number = |value: &str| value.trim().parse::<usize>().map_err(|_| error);
+
+        let mut columns = vec![];
+        let mut end = 0;
+        for column in value.split(',') {
+            let (offset, width) = match column.split_once(':') {
+                Some((offset, width)) => (number(offset)?, number(width)?),
+                None => (end, number(column)?),
+            };
+            if width == 0 {
+                return Err("widths must be positive");
+            }
+            if offset < end {
+                return Err("columns must not overlap and must be given from left to right");
+            }
+            end = offset.checked_add(width).ok_or(error)?;
+            columns.push(offset..end);
+        }
+
+        Ok(Widths(columns))
+    }
+}
+
+impl Widths {
+    // split cuts the line into its columns, without surrounding whitespace. A
+    // column past the end of a short line is empty.
+    fn split(&self, line: &str) -> Vec<String> {
+        let chars: Vec<char> = line.chars().collect();
+        self.0
+            .iter()
+            .map(|column| {
+                let start = column.start.min(chars.len());
+                let end = column.end.min(chars.len());
+                chars[start..end]
+                    .iter()
+                    .collect::<String>()
+                    .trim()
+                    .to_owned()
+            })
+            .collect()
+    }
+}
+
+// Encoding is the character encoding of a delimited data file, given by any
+// label of the WHATWG Encoding Standard, such as windows-1252 or utf-16.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Encoding(&'static encoding_rs::Encoding);
+
+impl std::str::FromStr for Encoding {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<Encoding, &'static str> {
+        encoding_rs::Encoding::for_label(value.trim().as_bytes())
+            .map(Encoding)
+            .ok_or("unknown encoding; use a label such as utf-8, windows-1252 or utf-16")
+    }
+}
+
+// Column identifies a column of the data file by its zero-based index or, if
+// the data file has a header, by its name.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Column {
+    Index(usize),
+    Name(String),
+}
+
+impl std::str::FromStr for Column {
+    type Err = &'static str;
+
+    // from_str reads a number as an index and anything else as a name.
+    fn from_str(value: &str) -> Result<Column, &'static str> {
+        if value.is_empty() {
+            return Err("column must be an index or a name");
+        }
+
+        Ok(value
+            .parse::<usize>()
+            .map(Column::Index)
+            .unwrap_or_else(|_| Column::Name(value.to_owned())))
+    }
+}
+
+impl Column {
+    // resolve finds the index of the column.
+    pub fn resolve(&self, headers: Option<&[String]>) -> Result<usize, RenameError> {
+        match self {
+            Column::Index(index) => Ok(*index),
+            Column::Name(name) => headers
+                .and_then(|headers| headers.iter().position(|header| header == name))
+                .ok_or_else(|| RenameError::UnknownColumn(name.clone())),
+        }
+    }
+}
+
+// STDIN is the data file name that stands for standard input.
+pub const STDIN: &str = "-";
+
+// CatalogueRow is a row of the data file: the lot and inventory number of an
+// object and the values of the other columns, in order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CatalogueRow {
+    pub lot: String,
+    pub inventory: String,
+    pub extra: Vec<String>,
+}
+
+impl CatalogueRow {
+    pub fn new(lot: impl Into<String>, inventory: impl Into<String>) -> CatalogueRow {
+        CatalogueRow {
+            lot: lot.into(),
+            inventory: inventory.into(),
+            extra: vec![],
+        }
+    }
+
+    // from_columns takes the lot and inventory number out of the values of a
+    // row. A row too short to hold either gives the missing column.
+    fn from_columns(
+        mut values: Vec<String>,
+        lot_column: usize,
+        inventory_column: usize,
+    ) -> Result<CatalogueRow, usize> {
+        if let Some(&column) = [lot_column, inventory_column]
+            .iter()
+            .find(|&&column| column >= values.len())
+        {
+            return Err(column);
+        }
+
+        let lot = values[lot_column].clone();
+        let inventory = values[inventory_column].clone();
+        let mut column = 0;
+        values.retain(|_| {
+            let keep = column != lot_column && column != inventory_column;
+            column += 1;
+            keep
+        });
+
+        Ok(CatalogueRow {
+            lot,
+            inventory,
+            extra: values,
+        })
+    }
+
+    // columns puts the lot and inventory number back among the other values,
+    // so that columns are found at the index they have in the data file.
+    pub(crate) fn columns(&self, lot_column: usize, inventory_column: usize) -> csv::StringRecord {
+        let count = if lot_column == inventory_column { 1 } else { 2 };
+        let mut extra = self.extra.iter();
+        (0..self.extra.len() + count)
+            .map(|column| {
+                if column == lot_column {
+                    self.lot.as_str()
+                } else if column == inventory_column {
+                    self.inventory.as_str()
+                } else {
+                    extra.next().map_or("", String::as_str)
+                }
+            })
+            .collect()
+    }
+}
+
+// Cells yields the values of the rows of a data file one by one, as they are
+// read.
+pub type Cells = Box<dyn Iterator<Item = Result<Vec<String>, RenameError>>>;
+
+// Table holds the header, if any, and the rows of a data file laid out in
+// columns, such as a delimited file or a worksheet. Its rows are streamed, so
+// that a large delimited data file is never held in memory as a whole.
+pub struct Table {
+    pub headers: Option<Vec<String>>,
+    pub rows: Cells,
+}
+
+impl Table {
+    // records takes the lot and inventory number out of every row, from the
+    // given columns. A row lacking either is a missing column.
+    pub fn records(self, lot: &Column, inventory: &Column) -> Result<Records, RenameError> {
+        let lot_column = lot.resolve(self.headers.as_deref())?;
+        let inventory_column = inventory.resolve(self.headers.as_deref())?;
+        let rows = self.rows.enumerate().map(move |(index, values)| {
+            CatalogueRow::from_columns(values?, lot_column, inventory_column).map_err(|column| {
+                RenameError::MissingColumn {
+                    row: index + 1,
+                    column,
+                }
+            })
+        });
+
+        Ok(Records {
+            headers: self.headers,
+            lot_column,
+            inventory_column,
+            rows: Box::new(rows),
+        })
+    }
+}
+
+// Rows yields the data rows of a data file one by one, as they are read.
+pub type Rows = Box<dyn Iterator<Item = Result<CatalogueRow, RenameError>>>;
+
+// Records holds the header, if any, and the data rows of the data file, with
+// the columns the lot and inventory numbers were taken from. Data files that
+// are not laid out in columns hold them in columns 0 and 1.
+pub struct Records {
+    pub headers: Option<Vec<String>>,
+    pub lot_column: usize,
+    pub inventory_column: usize,
+    pub rows: Rows,
+}
+
+impl Records {
+    // fixed holds rows read from a data file that is not laid out in columns.
+    pub fn fixed(rows: Vec<CatalogueRow>) -> Records {
+        Records {
+            rows: Box::new(rows.into_iter().map(Ok)),
+            ..Records::default()
+        }
+    }
+}
+
+impl Default for Records {
+    fn default() -> Records {
+        Records {
+            headers: None,
+            lot_column: 0,
+            inventory_column: 1,
+            rows: Box::new(std::iter::empty()),
+        }
+    }
+}
+
+// read_all reads the data file and any extra data files, in the configured
+// format. Data file names may be glob patterns, which are expanded in name
+// order. Failing to open or read a data file, also while its rows are
+// streamed, is told apart from other I/O errors.
+pub fn read_all(config: &crate::Config) -> Result<Vec<(PathBuf, Records)>, RenameError> {
+    let mut data = vec![];
+    for pattern in std::iter::once(&config.data_file).chain(&config.extra_data_files) {
+        for data_file in expand(pattern).map_err(|err| data_file_error(pattern, err))? {
+            let mut records =
+                read_rows(config, &data_file).map_err(|err| data_file_error(&data_file, err))?;
+            let path = data_file.clone();
+            records.rows = Box::new(
+                records
+                    .rows
+                    .map(move |row| row.map_err(|err| data_file_error(&path, err))),
+            );
+            data.push((data_file, records));
+        }
+    }
+
+    Ok(data)
+}
+
+// data_file_error attributes an I/O error to the data file.
+fn data_file_error(data_file: &Path, err: RenameError) -> RenameError {
+    match err {
+        RenameError::Io(err) => RenameError::DataFile(data_file.to_path_buf(), err),
+        err => err,
+    }
+}
+
+// read_rows reads the data file in the configured format. Delimited data files
+// are streamed; the other formats are read as a whole.
+pub fn read_rows(config: &crate::Config, data_file: &Path) -> Result<Records, RenameError> {
+    let table = match config.format {
+        Format::Csv => read_csv(
+            data_file,
+            config.delimiter,
+            config.has_headers,
+            config.encoding,
+        )?,
+        Format::FixedWidth => read_fixed_width(
+            data_file,
+            config.widths.as_ref().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "a fixed-width data file needs column widths",
+                )
+            })?,
+            config.has_headers,
+            config.encoding,
+        )?,
+        #[cfg(feature = "xlsx")]
+        Format::Xlsx => read_xlsx(data_file, config.sheet.as_deref(), config.has_headers)?,
+        Format::Json => {
+            return Ok(Records::fixed(read_json(
+                data_file,
+                &config.lot_key,
+                &config.inventory_key,
+            )?))
+        }
+        Format::Toml => {
+            return Ok(Records::fixed(mapping_rows(toml::from_str(&read_text(
+                data_file,
+            )?)?)))
+        }
+        Format::Yaml => {
+            return Ok(Records::fixed(mapping_rows(serde_yaml::from_str(
+                &read_text(data_file)?,
+            )?)))
+        }
+        #[cfg(feature = "sqlite")]
+        Format::Sqlite => return Ok(Records::fixed(read_sqlite(data_file, &config.query)?)),
+    };
+
+    table.records(&config.lot_column, &config.inventory_column)
+}
+
+// expand lists the files whose name matches the glob pattern in the last part
+// of the path. A path without wildcards is taken as is.
+pub fn expand(pattern: &Path) -> Result<Vec<PathBuf>, RenameError> {
+    let Some(name) = pattern.file_name().and_then(|name| name.to_str()) else {
+        return Ok(vec![pattern.to_path_buf()]);
+    };
+    if !name.contains(['*', '?', '[']) {
+        return Ok(vec![pattern.to_path_buf()]);
+    }
+
+    let matcher = globset::Glob::new(name)?.compile_matcher();
+    let dir = match pattern.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let files: Vec<PathBuf> = crate::list_files(dir)
+        .into_iter()
+        .filter(|file| matcher.is_match(file))
+        .map(|file| dir.join(file))
+        .collect();
+    if files.is_empty() {
+        return Err(RenameError::Io(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no data file matches {}", pattern.display()),
+        )));
+    }
+
+    Ok(files)
+}
+
+// read_csv reads the delimited data file, or standard input if the file name
+// is STDIN. Rows are read as they are taken. Given an encoding, the data is
+// transcoded to UTF-8 as it is read, unless a byte order mark tells another
+// Unicode encoding.
+pub fn read_csv(
+    file_name: &Path,
+    delimiter: u8,
+    has_headers: bool,
+    encoding: Option<Encoding>,
+) -> Result<Table, RenameError> {
+    let mut builder = csv::ReaderBuilder::new();
+    builder.delimiter(delimiter).has_headers(has_headers);
+    let input = open_text(file_name, encoding)?;
+
+    read_records(builder.from_reader(input), has_headers)
+}
+
+// read_fixed_width streams the lines of a fixed-width data file, from standard
+// input if the file name is STDIN, cut into the columns of the widths. Blank
+// lines are skipped.
+pub fn read_fixed_width(
+    file_name: &Path,
+    widths: &Widths,
+    has_headers: bool,
+    encoding: Option<Encoding>,
+) -> Result<Table, RenameError> {
+    let widths = widths.clone();
+    let mut lines = io::BufReader::new(open_text(file_name, encoding)?)
+        .lines()
+        .filter(|line| !line.as_ref().is_ok_and(|line| line.trim().is_empty()));
+    let headers = if has_headers {
+        lines.next().transpose()?.map(|line| widths.split(&line))
+    } else {
+        None
+    };
+
+    Ok(Table {
+        headers,
+        rows: Box::new(lines.map(move |line| Ok(widths.split(&line?)))),
+    })
+}
+
+// open_text opens the data file, or standard input if the file name is
+// STDIN, decoding it from the encoding if one is given. A byte order mark is
+// stripped, and decides the encoding if there is one.
+fn open_text(file_name: &Path, encoding: Option<Encoding>) -> io::Result<Box<dyn io::Read>> {
+    let input: Box<dyn io::Read> = if file_name == Path::new(STDIN) {
+        Box::new(io::stdin())
+    } else {
+        Box::new(File::open(file_name)?)
+    };
+
+    Ok(Box::new(
+        encoding_rs_io::DecodeReaderBytesBuilder::new()
+            .encoding(encoding.map(|Encoding(encoding)| encoding))
+            .bom_override(true)
+            .build(input),
+    ))
+}
+
+fn read_records<R: io::Read + 'static>(
+    mut reader: csv::Reader<R>,
+    has_headers: bool,
+) -> Result<Table, RenameError> {
+    let headers = if has_headers {
+        Some(values(reader.headers().map_err(csv_error)?))
+    } else {
+        None
+    };
+    let rows = reader
+        .into_records()
+        .map(|result| result.map(|record| values(&record)).map_err(csv_error));
+
+    Ok(Table {
+        headers,
+        rows: Box::new(rows),
+    })
+}
+
+// read_xlsx reads the given sheet, or the first one if none is given.
+#[cfg(feature = "xlsx")]
+pub fn read_xlsx(
+    file_name: &Path,
+    sheet: Option<&str>,
+    has_headers: bool,
+) -> Result<Table, RenameError> {
+    let mut workbook: Xlsx<_> = open_workbook(file_name)?;
+
+    let range = match sheet {
+        Some(sheet) => workbook.worksheet_range(sheet)?,
+        None => workbook
+            .worksheet_range_at(0)
+            .unwrap_or_else(|| Err(XlsxError::WorksheetNotFound(String::from("at index 0"))))?,
+    };
+
+    let rows: Vec<Vec<String>> = range
+        .rows()
+        .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+        .collect();
+    let mut rows = rows.into_iter();
+    let headers = if has_headers { rows.next() } else { None };
+
+    Ok(Table {
+        headers,
+        rows: Box::new(rows.map(Ok)),
+    })
+}
+
+// read_json reads an array of objects, from standard input if the file name
+// is STDIN. Each object becomes a row with the values under the lot and
+// inventory keys.
+pub fn read_json(
+    file_name: &Path,
+    lot_key: &str,
+    inventory_key: &str,
+) -> Result<Vec<CatalogueRow>, RenameError> {
+    let contents = read_text(file_name)?;
+    let objects: Vec<serde_json::Map<String, serde_json::Value>> = serde_json::from_str(&contents)?;
+
+    let mut rows: Vec<CatalogueRow> = vec![];
+    for (index, object) in objects.iter().enumerate() {
+        let value = |key: &str| match object.get(key) {
+            Some(serde_json::Value::String(value)) => Ok(value.clone()),
+            Some(serde_json::Value::Number(value)) => Ok(value.to_string()),
+            _ => Err(RenameError::MissingKey {
+                row: index + 1,
+                key: key.to_owned(),
+            }),
+        };
+
+        rows.push(CatalogueRow::new(value(lot_key)?, value(inventory_key)?));
+    }
+
+    Ok(rows)
+}
+
+// DEFAULT_QUERY selects the lot and inventory numbers from an SQLite database.
+pub const DEFAULT_QUERY: &str = "SELECT lot, inventory FROM lots";
+
+// read_sqlite runs the query on the database. Each result row becomes a row
+// with the first column as the lot number and the second as the inventory
+// number. Text columns are taken as they are, so
+// inventory numbers keep their leading zeros.
+#[cfg(feature = "sqlite")]
+pub fn read_sqlite(file_name: &Path, query: &str) -> Result<Vec<CatalogueRow>, RenameError> {
+    let connection = rusqlite::Connection::open_with_flags(
+        file_name,
+        rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY,
+    )?;
+    let mut statement = connection.prepare(query)?;
+
+    let mut rows: Vec<CatalogueRow> = vec![];
+    let mut results = statement.query([])?;
+    while let Some(result) = results.next()? {
+        let value = |index: usize| -> Result<String, RenameError> {
+            match result.get_ref(index)? {
+                rusqlite::types::ValueRef::Text(value) => {
+                    Ok(String::from_utf8_lossy(value).into_owned())
+                }
+                rusqlite::types::ValueRef::Integer(value) => Ok(value.to_string()),
+                rusqlite::types::ValueRef::Real(value) => Ok(value.to_string()),
+                _ => Err(RenameError::MissingColumn {
+                    row: rows.len() + 1,
+                    column: index,
+                }),
+            }
+        };
+
+        let lot = value(0)?;
+        let inventory = value(1)?;
+        rows.push(CatalogueRow::new(lot, inventory));
+    }
+
+    Ok(rows)
+}
+
+// LotNumber is a lot number in a mapping file, where it may be written with or
+// without quotes.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum LotNumber {
+    Number(u64),
+    Text(String),
+}
+
+// mapping_rows turns a mapping of inventory numbers to lot numbers into rows.
+fn mapping_rows(mapping: BTreeMap<String, LotNumber>) -> Vec<CatalogueRow> {
+    mapping
+        .into_iter()
+        .map(|(inventory, lot)| {
+            let lot = match lot {
+                LotNumber::Number(lot) => lot.to_string(),
+                LotNumber::Text(lot) => lot,
+            };
+            CatalogueRow::new(lot, inventory)
+        })
+        .collect()
+}
+
+// values takes the values out of a record of a delimited data file.
+fn values(record: &csv::StringRecord) -> Vec<String> {
+    record.iter().map(String::from).collect()
+}
+
+// read_text reads the whole file, or standard input if the file name is STDIN.
+fn read_text(file_name: &Path) -> io::Result<String> {
+    if file_name == Path::new(STDIN) {
+        io::read_to_string(io::stdin())
+    } else {
+        std::fs::read_to_string(file_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn collect(table: Table) -> (Option<Vec<String>>, Vec<Vec<String>>) {
+        (table.headers, table.rows.collect::<Result<_, _>>().unwrap())
+    }
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn read_fixed_width_columns() {
+        let data_file = "tests/tmp_fixed_width.txt";
+        fs::write(
+            data_file,
+            "\u{feff}LOT   INVENTORY ----TITLE\n1     00243878  ----Vase\n\n  12  00243344  --\n",
+        )
+        .unwrap();
+
+        // The dashes are filler between the inventory number and the title.
+        let widths: Widths = "6,10,20:10".parse().unwrap();
+        let (headers, rows) =
+            collect(read_fixed_width(Path::new(data_file), &widths, true, None).unwrap());
+
+        fs::remove_file(data_file).unwrap();
+
+        assert_eq!(Some(strings(&["LOT", "INVENTORY", "TITLE"])), headers);
+        assert_eq!(
+            vec![
+                strings(&["1", "00243878", "Vase"]),
+                strings(&["12", "00243344", ""])
+            ],
+            rows
+        );
+        assert_eq!(Widths(vec![0..6, 6..16, 20..30]), widths);
+        assert!("6,x".parse::<Widths>().is_err());
+        assert!("6,0".parse::<Widths>().is_err());
+        assert!("0:6,3:4".parse::<Widths>().is_err());
+        assert!("20:10,0:6".parse::<Widths>().is_err());
+        assert!(format!("{}:2", usize::MAX).parse::<Widths>().is_err());
+    }
+
+    #[test]
+    fn read_comma_separated_csv() {
+        let data_file = "tests/tmp_comma.csv";
+        fs::write(
+            data_file,
+            "\"Lot Number\",\"Lot Ext\",\"Inv Number\"\n1,,00243878\n2,,00243880\n",
+        )
+        .unwrap();
+
+        let (headers, rows) = collect(read_csv(Path::new(data_file), b',', true, None).unwrap());
+
+        fs::remove_file(data_file).unwrap();
+
+        assert_eq!(2, rows.len());
+        assert_eq!("00243880", rows[1][2]);
+        assert_eq!(
+            Some(strings(&["Lot Number", "Lot Ext", "Inv Number"])),
+            headers
+        );
+    }
+
+    #[test]
+    fn read_csv_in_other_encodings() {
+        let data_file = "tests/tmp_encoding.csv";
+        let expected = strings(&["1", "00243878", "Caf\u{e9}"]);
+
+        fs::write(data_file, b"Lot\tInv\tTitle\n1\t00243878\tCaf\xe9\n").unwrap();
+        let windows_1252 = read_csv(
+            Path::new(data_file),
+            b'\t',
+            true,
+            Some("windows-1252".parse().unwrap()),
+        )
+        .unwrap();
+        let windows_1252 = collect(windows_1252);
+
+        let mut utf_16: Vec<u8> = vec![0xff, 0xfe];
+        for unit in "Lot\tInv\tTitle\n1\t00243878\tCaf\u{e9}\n".encode_utf16() {
+            utf_16.extend(unit.to_le_bytes());
+        }
+        fs::write(data_file, utf_16).unwrap();
+        // The byte order mark wins over the given encoding.
+        let utf_16 = read_csv(
+            Path::new(data_file),
+            b'\t',
+            true,
+            Some("windows-1252".parse().unwrap()),
+        )
+        .unwrap();
+        let utf_16 = collect(utf_16);
+        // Without a given encoding, the byte order mark decides it.
+        let unencoded = collect(read_csv(Path::new(data_file), b'\t', true, None).unwrap());
+
+        fs::remove_file(data_file).unwrap();
+
+        assert_eq!(vec![expected.clone()], windows_1252.1);
+        assert_eq!(vec![expected.clone()], utf_16.1);
+        assert_eq!(vec![expected], unencoded.1);
+        assert!("klingon".parse::<Encoding>().is_err());
+    }
+
+    #[test]
+    fn read_records_from_reader() {
+        let input: &[u8] = b"Lot\tInv\n1\t00243878\n";
+        let reader = csv::ReaderBuilder::new()
+            .delimiter(b'\t')
+            .from_reader(input);
+
+        let (headers, rows) = collect(read_records(reader, true).unwrap());
+
+        assert_eq!(Some(strings(&["Lot", "Inv"])), headers);
+        assert_eq!(vec![strings(&["1", "00243878"])], rows);
+    }
+
+    #[test]
+    #[cfg(feature = "xlsx")]
+    fn read_xlsx_matches_csv() {
+        let csv_table = collect(read_csv(Path::new("tests/data.csv"), b'\t', true, None).unwrap());
+
+        assert_eq!(
+            csv_table,
+            collect(read_xlsx(Path::new("tests/data.xlsx"), None, true).unwrap())
+        );
+        assert_eq!(
+            csv_table,
+            collect(read_xlsx(Path::new("tests/data.xlsx"), Some("Lots"), true).unwrap())
+        );
+        assert_eq!(
+            csv_table.1.len() + 1,
+            read_xlsx(Path::new("tests/data.xlsx"), None, false)
+                .unwrap()
+                .rows
+                .count()
+        );
+        assert!(read_xlsx(Path::new("tests/data.xlsx"), Some("Missing"), true).is_err());
+    }
+
+    #[test]
+    fn expand_data_file_patterns() {
+        assert_eq!(
+            vec![PathBuf::from("tests/data.csv")],
+            expand(Path::new("tests/data.csv")).unwrap()
+        );
+        assert_eq!(
+            vec![
+                PathBuf::from("tests/data.csv"),
+                PathBuf::from("tests/data.xlsx")
+            ],
+            expand(Path::new("tests/data.*")).unwrap()
+        );
+        assert!(expand(Path::new("tests/missing-*.csv")).is_err());
+    }
+
+    #[test]
+    fn resolve_columns() {
+        let headers = strings(&["Lot Number", "Inv Number"]);
+
+        assert_eq!(1, Column::Index(1).resolve(None).unwrap());
+        assert_eq!(
+            1,
+            Column::Name("Inv Number".to_string())
+                .resolve(Some(&headers))
+                .unwrap()
+        );
+        assert!(matches!(
+            Column::Name("Lot".to_string()).resolve(Some(&headers)),
+            Err(RenameError::UnknownColumn(_))
+        ));
+        assert!(Column::Name("Lot Number".to_string())
+            .resolve(None)
+            .is_err());
+    }
+
+    #[test]
+    fn take_lot_and_inventory_numbers_from_columns() {
+        let table = Table {
+            headers: Some(strings(&["Title", "Inv", "Lot"])),
+            rows: Box::new(
+                vec![
+                    Ok(strings(&["Beach view", "00243880", "2"])),
+                    Ok(strings(&["Vase"])),
+                ]
+                .into_iter(),
+            ),
+        };
+
+        let records = table
+            .records(&Column::Name(String::from("Lot")), &Column::Index(1))
+            .unwrap();
+        let (lot_column, inventory_column) = (records.lot_column, records.inventory_column);
+        let rows: Vec<_> = records.rows.collect();
+
+        assert_eq!((2, 1), (lot_column, inventory_column));
+        let row = rows[0].as_ref().unwrap();
+        assert_eq!(
+            &CatalogueRow {
+                extra: strings(&["Beach view"]),
+                ..CatalogueRow::new("2", "00243880")
+            },
+            row
+        );
+        assert_eq!(
+            csv::StringRecord::from(vec!["Beach view", "00243880", "2"]),
+            row.columns(lot_column, inventory_column)
+        );
+        assert!(matches!(
+            rows[1],
+            Err(RenameError::MissingColumn { row: 2, column: 2 })
+        ));
+        assert_eq!(
+            csv::StringRecord::from(vec!["7", "A"]),
+            CatalogueRow {
+                extra: strings(&["A"]),
+                ..CatalogueRow::new("7", "7")
+            }
+            .columns(0, 0)
+        );
+    }
+
+    #[test]
+    fn read_toml_and_yaml_mappings() {
+        let expected = vec![
+            CatalogueRow::new("3", "00243344"),
+            CatalogueRow::new("1a", "00243878"),
+        ];
+
+        let toml = "\"00243878\" = \"1a\"\n\"00243344\" = 3\n";
+        assert_eq!(expected, mapping_rows(toml::from_str(toml).unwrap()));
+
+        let yaml = "\"00243878\": 1a\n\"00243344\": 3\n";
+        assert_eq!(expected, mapping_rows(serde_yaml::from_str(yaml).unwrap()));
+
+        assert!(toml::from_str::<BTreeMap<String, LotNumber>>("\"00243344\" = [3]").is_err());
+    }
+
+    #[test]
+    fn read_json_objects() {
+        let data_file = "tests/tmp_data.json";
+        fs::write(
+            data_file,
+            r#"[{"Lot": 1, "Inv": "00243878"}, {"Lot": "2", "Inv": "00243880", "Title": "Beach"}]"#,
+        )
+        .unwrap();
+
+        let rows = read_json(Path::new(data_file), "Lot", "Inv");
+        let missing = read_json(Path::new(data_file), "Lot", "Title");
+
+        fs::remove_file(data_file).unwrap();
+
+        assert_eq!(
+            vec![
+                CatalogueRow::new("1", "00243878"),
+                CatalogueRow::new("2", "00243880"),
+            ],
+            rows.unwrap()
+        );
+        assert!(matches!(
+            missing,
+            Err(RenameError::MissingKey { row: 1, .. })
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "sqlite")]
+    fn read_sqlite_query() {
+        let data_file = "tests/tmp_data.sqlite";
+        let _ = fs::remove_file(data_file);
+        let connection = rusqlite::Connection::open(data_file).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE lots (lot INTEGER, inventory TEXT);
+                 INSERT INTO lots VALUES (1, '00243878'), (2, '00243880'), (3, NULL);",
+            )
+            .unwrap();
+        drop(connection);
+
+        let rows = read_sqlite(
+            Path::new(data_file),
+            "SELECT lot, inventory FROM lots WHERE lot < 3",
+        );
+        let null = read_sqlite(Path::new(data_file), DEFAULT_QUERY);
+        let invalid = read_sqlite(Path::new(data_file), "SELECT lot FROM auctions");
+
+        fs::remove_file(data_file).unwrap();
+
+        assert_eq!(
+            vec![
+                CatalogueRow::new("1", "00243878"),
+                CatalogueRow::new("2", "00243880"),
+            ],
+            rows.unwrap()
+        );
+        assert!(matches!(
+            null,
+            Err(RenameError::MissingColumn { row: 3, column: 1 })
+        ));
+        assert!(matches!(invalid, Err(RenameError::Sqlite(_))));
+    }
+}
@@ -0,0 +1,314 @@
+use serde::Serialize;
+use std::fmt;
+
+use crate::{Dimensions, RenameError, Timings};
+
+// Report lists what the run did and what it left alone.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Report {
+    // The files given a new name, ordered by their old name.
+    pub renamed: Vec<Renaming>,
+    // Files in the directory that matched no row of the data file.
+    pub unmatched_files: Vec<String>,
+    // Rows of the data file that matched no files in the directory.
+    pub unmatched_rows: Vec<UnmatchedRow>,
+    // Files that matched a row, but whose name lacks a photo number.
+    pub malformed_files: Vec<String>,
+    // Files left alone because their new name is already taken.
+    pub skipped_files: Vec<String>,
+    // Groups of byte-identical files, if duplicates were looked for.
+    pub duplicate_files: Vec<Vec<String>>,
+    // Files left alone because they are not intact images, if images were
+    // verified.
+    pub corrupt_files: Vec<String>,
+    // Files left alone because they are smaller or larger than allowed, if
+    // sizes were checked.
+    pub wrong_size_files: Vec<String>,
+    // Lots with images left alone because they are smaller than the minimum
+    // dimensions, if those were checked.
+    pub low_resolution_lots: Vec<LowResolutionLot>,
+    // Files left alone because an earlier run already renamed them.
+    pub renamed_before: Vec<String>,
+    // Files left alone because their names are not valid Unicode, which Linux
+    // allows, with the invalid bytes replaced.
+    pub non_unicode_files: Vec<String>,
+    // Files left alone because another program held them, or held the file
+    // whose name they were to take, by their current names and with the names
+    // they still need. A file locked after it was staged may be left under its
+    // temporary name.
+    pub locked: Vec<Renaming>,
+    // Rows of the data file that could not be used, and why.
+    pub bad_rows: Vec<RowError>,
+    // Lots with more files than the maximum per lot, if one was set.
+    pub crowded_lots: Vec<CrowdedLot>,
+    // Rows of the data file that give the inventory number of an earlier row
+    // another lot number.
+    pub duplicate_rows: Vec<DuplicateRow>,
+    // How long the stages of the run took, if measured.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timings: Option<Timings>,
+}
+
+impl Report {
+    // summary counts what the run did, for showing at the end of a run.
+    pub fn summary(&self) -> RunSummary {
+        RunSummary {
+            renamed: self.renamed.len(),
+            skipped: self.malformed_files.len()
+                + self.skipped_files.len()
+                + self.corrupt_files.len()
+                + self.wrong_size_files.len()
+                + self
+                    .low_resolution_lots
+                    .iter()
+                    .flat_map(|lot| &lot.images)
+                    .map(|image| 1 + image.sidecars.len())
+                    .sum::<usize>()
+                + self.renamed_before.len()
+                + self.non_unicode_files.len()
+                + self.locked.len(),
+            unmatched_files: self.unmatched_files.clone(),
+            unmatched_rows: self.unmatched_rows.iter().map(|row| row.row).collect(),
+        }
+    }
+}
+
+// RunSummary holds the statistics of a run.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct RunSummary {
+    pub renamed: usize,
+    // Files that matched a row but were left alone.
+    pub skipped: usize,
+    pub unmatched_files: Vec<String>,
+    // The numbers of the rows that matched no files.
+    pub unmatched_rows: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Renaming {
+    pub old: String,
+    pub new: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UnmatchedRow {
+    // The row number, counted from 1 excluding the header.
+    pub row: usize,
+    pub lot: String,
+    pub inventory: String,
+}
+
+// CrowdedLot is a lot with more files than the maximum per lot.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CrowdedLot {
+    pub lot: String,
+    pub files: usize,
+}
+
+impl fmt::Display for CrowdedLot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "lot {}: {} files", self.lot, self.files)
+    }
+}
+
+// LowResolutionLot is a lot with images smaller than the minimum dimensions.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LowResolutionLot {
+    pub lot: String,
+    pub images: Vec<SmallImage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SmallImage {
+    pub file: String,
+    pub dimensions: Dimensions,
+    // The sidecars of the image, which are left alone with it.
+    pub sidecars: Vec<String>,
+}
+
+impl fmt::Display for LowResolutionLot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "lot {}: ", self.lot)?;
+        for (i, image) in self.images.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{} ({})", image.file, image.dimensions)?;
+            if !image.sidecars.is_empty() {
+                write!(f, " with {}", image.sidecars.join(", "))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// DuplicateRow is a row of the data file that gives the inventory number, or
+// the key, of an earlier row another lot number.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DuplicateRow {
+    // The row numbers, counted from 1 excluding the header.
+    pub row: usize,
+    pub lot: String,
+    pub first_row: usize,
+    pub first_lot: String,
+    pub inventory: String,
+}
+
+impl fmt::Display for DuplicateRow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "row {}: inventory number {} has lot {}, but lot {} on row {}",
+            self.row, self.inventory, self.lot, self.first_lot, self.first_row
+        )
+    }
+}
+
+// RowError tells why a row of the data file could not be used.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RowError {
+    // The row number, counted from 1 excluding the header.
+    pub row: usize,
+    // The zero-based column at fault, if the problem lies in one. It is shown
+    // counted from 1, like the row.
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.column {
+            Some(column) => write!(
+                f,
+                "row {}, column {}: {}",
+                self.row,
+                column + 1,
+                self.message
+            ),
+            None => write!(f, "row {}: {}", self.row, self.message),
+        }
+    }
+}
+
+// ReportFormat is the machine-readable format a report can be written in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    Json,
+}
+
+impl std::str::FromStr for ReportFormat {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<ReportFormat, &'static str> {
+        match value {
+            "json" => Ok(ReportFormat::Json),
+            _ => Err("report format must be json"),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    #[serde(flatten)]
+    report: Option<&'a Report>,
+    error: Option<String>,
+}
+
+// to_json describes the outcome of a run as a JSON object holding the report
+// fields, or just the error if the run failed.
+pub fn to_json(result: &Result<Report, RenameError>) -> String {
+    let json_report = match result {
+        Ok(report) => JsonReport {
+            report: Some(report),
+            error: None,
+        },
+        Err(err) => JsonReport {
+            report: None,
+            error: Some(err.to_string()),
+        },
+    };
+
+    serde_json::to_string_pretty(&json_report).expect("report is serializable")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarize_report() {
+        let report = Report {
+            renamed: vec![Renaming {
+                old: "00243878.1.jpg".to_string(),
+                new: "1_1.jpg".to_string(),
+            }],
+            unmatched_files: vec!["00999999.1.jpg".to_string()],
+            unmatched_rows: vec![UnmatchedRow {
+                row: 4,
+                lot: "4".to_string(),
+                inventory: "00243999".to_string(),
+            }],
+            malformed_files: vec!["00243878.jpg".to_string()],
+            skipped_files: vec!["00243880.1.jpg".to_string()],
+            duplicate_files: vec![],
+            corrupt_files: vec![],
+            wrong_size_files: vec![],
+            low_resolution_lots: vec![],
+            renamed_before: vec![],
+            non_unicode_files: vec![],
+            locked: vec![],
+            bad_rows: vec![],
+            crowded_lots: vec![],
+            duplicate_rows: vec![],
+            timings: None,
+        };
+
+        assert_eq!(
+            RunSummary {
+                renamed: 1,
+                skipped: 2,
+                unmatched_files: vec!["00999999.1.jpg".to_string()],
+                unmatched_rows: vec![4],
+            },
+            report.summary()
+        );
+    }
+
+    #[test]
+    fn report_as_json() {
+        let report = Report {
+            renamed: vec![Renaming {
+                old: "00243878.1.jpg".to_string(),
+                new: "1_1.jpg".to_string(),
+            }],
+            unmatched_files: vec!["00999999.1.jpg".to_string()],
+            unmatched_rows: vec![],
+            malformed_files: vec![],
+            skipped_files: vec![],
+            duplicate_files: vec![],
+            corrupt_files: vec![],
+            wrong_size_files: vec![],
+            low_resolution_lots: vec![],
+            renamed_before: vec![],
+            non_unicode_files: vec![],
+            locked: vec![],
+            bad_rows: vec![],
+            crowded_lots: vec![],
+            duplicate_rows: vec![],
+            timings: None,
+        };
+
+        let json: serde_json::Value = serde_json::from_str(&to_json(&Ok(report))).unwrap();
+
+        assert_eq!("1_1.jpg", json["renamed"][0]["new"]);
+        assert_eq!("00999999.1.jpg", json["unmatched_files"][0]);
+        assert!(json["error"].is_null());
+
+        let err = RenameError::MalformedFileName("README".to_string());
+        let json: serde_json::Value = serde_json::from_str(&to_json(&Err(err))).unwrap();
+
+        assert!(json["error"].as_str().unwrap().contains("README"));
+        assert!(json.get("renamed").is_none());
+    }
+}
@@ -0,0 +1,115 @@
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+// The wait before the first retry, if not configured otherwise.
+pub const DEFAULT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+// Retry is how often an operation on a file is tried again when it fails with
+// an error that may pass, as renaming on network shares does when a file is
+// briefly held by another client. The wait doubles before each attempt.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Retry {
+    pub attempts: u32,
+    pub delay: Duration,
+}
+
+impl Default for Retry {
+    fn default() -> Retry {
+        Retry {
+            attempts: 0,
+            delay: DEFAULT_RETRY_DELAY,
+        }
+    }
+}
+
+impl Retry {
+    // run calls the operation until it succeeds, fails with an error that
+    // persists, or the attempts run out, and returns its last result.
+    pub fn run<T>(
+        &self,
+        what: &str,
+        mut operation: impl FnMut() -> io::Result<T>,
+    ) -> io::Result<T> {
+        let mut delay = self.delay;
+        for attempt in 1..=self.attempts {
+            match operation() {
+                Err(err) if is_transient(&err) => {
+                    log::debug!(
+                        "{} failed, retrying in {:?} ({} of {}): {}",
+                        what,
+                        delay,
+                        attempt,
+                        self.attempts,
+                        err
+                    );
+                    thread::sleep(delay);
+                    delay = delay.saturating_mul(2);
+                }
+                result => return result,
+            }
+        }
+
+        operation()
+    }
+}
+
+// is_transient tells whether the error may pass when the operation is tried
+// again: the file is busy or locked, or access was refused, which network
+// file systems report while another client holds the file.
+fn is_transient(err: &io::Error) -> bool {
+    is_locked(err)
+        || matches!(
+            err.kind(),
+            io::ErrorKind::PermissionDenied
+                | io::ErrorKind::WouldBlock
+                | io::ErrorKind::Interrupted
+                | io::ErrorKind::TimedOut
+        )
+}
+
+// is_locked tells whether the error means that another program holds the
+// file, as an image editor does with the photos it has open on Windows.
+pub fn is_locked(err: &io::Error) -> bool {
+    // Windows reports files opened by another process as sharing or lock
+    // violations, which have no error kind of their own.
+    #[cfg(windows)]
+    if matches!(err.raw_os_error(), Some(32 | 33)) {
+        return true;
+    }
+
+    matches!(
+        err.kind(),
+        io::ErrorKind::ResourceBusy | io::ErrorKind::ExecutableFileBusy
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_transient_errors() {
+        let retry = Retry {
+            attempts: 2,
+            delay: Duration::from_millis(1),
+        };
+        let failing = |failures: u32, kind: io::ErrorKind| {
+            let mut calls = 0;
+            let result = retry.run("renaming", || {
+                calls += 1;
+                if calls <= failures {
+                    Err(io::Error::from(kind))
+                } else {
+                    Ok(())
+                }
+            });
+            (result.is_ok(), calls)
+        };
+
+        assert_eq!((true, 3), failing(2, io::ErrorKind::ResourceBusy));
+        assert_eq!((false, 3), failing(3, io::ErrorKind::PermissionDenied));
+        // Errors that persist are not retried.
+        assert_eq!((false, 1), failing(1, io::ErrorKind::NotFound));
+    }
+}
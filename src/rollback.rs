@@ -0,0 +1,71 @@
+use std::fmt;
+
+use crate::operation::{Operation, Transfer};
+
+// A renaming (old name, new name) that could not be reverted, and why.
+pub type FailedRestore = (String, String, std::io::Error);
+
+#[derive(Debug)]
+pub struct RollbackError {
+    // The operation that failed, which tells how the error reads.
+    pub operation: Operation,
+    pub old_name: String,
+    pub new_name: String,
+    pub source: std::io::Error,
+    // Renamings that were completed before the failure and reverted afterwards.
+    pub rolled_back: Vec<(String, String)>,
+    // Renamings that could not be reverted and are still in effect.
+    pub not_rolled_back: Vec<FailedRestore>,
+}
+
+impl fmt::Display for RollbackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} to {} failed: {}; rolled back {} renaming(s)",
+            self.operation.verb(),
+            self.old_name,
+            self.new_name,
+            self.source,
+            self.rolled_back.len()
+        )?;
+        for (old_name, new_name) in &self.rolled_back {
+            write!(f, "\n  restored {} from {}", old_name, new_name)?;
+        }
+        for (old_name, new_name, err) in &self.not_rolled_back {
+            write!(
+                f,
+                "\n  could not restore {} from {}: {}",
+                old_name, new_name, err
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for RollbackError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+// roll_back reverts the completed renamings in reverse order. It returns the
+// renamings that were reverted and those that could not be.
+pub fn roll_back(
+    transfer: &Transfer,
+    completed: &[(&String, &String)],
+) -> (Vec<(String, String)>, Vec<FailedRestore>) {
+    let mut rolled_back = vec![];
+    let mut not_rolled_back = vec![];
+
+    for (old_name, new_name) in completed.iter().rev() {
+        log::warn!("rolling back {} to {}", new_name, old_name);
+        match transfer.revert(old_name, new_name) {
+            Ok(()) => rolled_back.push((old_name.to_string(), new_name.to_string())),
+            Err(err) => not_rolled_back.push((old_name.to_string(), new_name.to_string(), err)),
+        }
+    }
+
+    (rolled_back, not_rolled_back)
+}
@@ -0,0 +1,98 @@
+use std::io;
+use std::path::Path;
+
+use s3::error::S3Error;
+use s3::Bucket;
+
+use crate::storage::{remote_path, Storage};
+
+// S3Storage renames objects in an S3 bucket. Directories are key prefixes, so
+// `photos/00243878.1.jpg` in directory `photos` is renamed to
+// `photos/1_1.jpg`. S3 has no renaming, so an object is copied to its new key
+// and then deleted.
+#[derive(Debug)]
+pub struct S3Storage {
+    bucket: Box<Bucket>,
+}
+
+impl S3Storage {
+    pub fn new(bucket: Box<Bucket>) -> S3Storage {
+        S3Storage { bucket }
+    }
+}
+
+impl Storage for S3Storage {
+    fn list(&self, dir: &Path) -> io::Result<Vec<String>> {
+        let prefix = match remote_path(dir)?.trim_start_matches('/') {
+            "" => String::new(),
+            dir => format!("{}/", dir),
+        };
+
+        let mut names: Vec<String> = self
+            .bucket
+            .list(prefix.clone(), Some(String::from("/")))
+            .map_err(to_io_error)?
+            .into_iter()
+            .flat_map(|page| page.contents)
+            .filter_map(|object| object.key.strip_prefix(&prefix).map(str::to_owned))
+            .filter(|name| !name.is_empty())
+            .collect();
+        names.sort();
+
+        Ok(names)
+    }
+
+    fn exists(&self, path: &Path) -> io::Result<bool> {
+        match self.bucket.head_object(key(path)?) {
+            Ok((_, status)) => Ok(is_success(status)),
+            Err(S3Error::HttpFailWithBody(404, _)) => Ok(false),
+            Err(err) => Err(to_io_error(err)),
+        }
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.copy(from, to)?;
+        self.remove(from)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let status = self
+            .bucket
+            .copy_object_internal(key(from)?, key(to)?)
+            .map_err(to_io_error)?;
+        check_status(status, from)
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        let response = self.bucket.delete_object(key(path)?).map_err(to_io_error)?;
+        check_status(response.status_code(), path)
+    }
+}
+
+// key is the object key of the path, without a leading slash.
+fn key(path: &Path) -> io::Result<String> {
+    Ok(remote_path(path)?.trim_start_matches('/').to_owned())
+}
+
+fn is_success(status: u16) -> bool {
+    (200..300).contains(&status)
+}
+
+fn check_status(status: u16, path: &Path) -> io::Result<()> {
+    if is_success(status) {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "S3 answered {} for {}",
+            status,
+            path.display()
+        )))
+    }
+}
+
+fn to_io_error(err: S3Error) -> io::Error {
+    match err {
+        S3Error::HttpFailWithBody(404, body) => io::Error::new(io::ErrorKind::NotFound, body),
+        err => io::Error::other(err),
+    }
+}
@@ -0,0 +1,107 @@
+use std::path::Path;
+
+use rhai::{Array, Dynamic, Engine, Map, Scope, AST};
+
+use crate::template::NameFields;
+
+// The function a script defines to name files.
+const NEW_NAME: &str = "new_name";
+
+// Script names files by a Rhai script instead of, or on top of, the template.
+// The script defines
+//
+//     fn new_name(row, fields, name) { ... }
+//
+// where row is the array of values in the row of the data file, fields is a
+// map with the lot, inventory, index and file, and name is the name composed
+// by the template. It returns the new name.
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+}
+
+impl Script {
+    pub fn load(path: &Path) -> Result<Script, Box<rhai::EvalAltResult>> {
+        let engine = Engine::new();
+        let ast = engine.compile_file(path.to_path_buf())?;
+
+        Ok(Script { engine, ast })
+    }
+
+    pub fn new_name(
+        &self,
+        fields: &NameFields,
+        name: String,
+    ) -> Result<String, Box<rhai::EvalAltResult>> {
+        let row: Array = fields
+            .row
+            .iter()
+            .map(|value| Dynamic::from(value.to_owned()))
+            .collect();
+        let mut map = Map::new();
+        map.insert("lot".into(), fields.lot.to_owned().into());
+        map.insert("inventory".into(), fields.inventory.to_owned().into());
+        map.insert("index".into(), fields.index.to_owned().into());
+        map.insert("file".into(), fields.file_name.to_owned().into());
+
+        self.engine
+            .call_fn(&mut Scope::new(), &self.ast, NEW_NAME, (row, map, name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn name_by_script() {
+        let path = Path::new("tests/tmp_naming.rhai");
+        fs::write(
+            path,
+            r#"
+            fn new_name(row, fields, name) {
+                if row[1] == "" {
+                    return name;
+                }
+                `${fields.lot}${row[1]}-${fields.index}.jpg`
+            }
+            "#,
+        )
+        .unwrap();
+        let script = Script::load(path);
+        fs::write(path, "fn new_name(row, fields, name) { 42 }").unwrap();
+        let invalid = Script::load(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        let script = script.unwrap();
+        let fields = |row| NameFields {
+            lot: "12",
+            inventory: "00243878",
+            index: "2",
+            rest: "",
+            file_name: "00243878.2.jpg",
+            row,
+            date: None,
+        };
+        let with_extension = csv::StringRecord::from(vec!["12", "A", "00243878"]);
+        let without_extension = csv::StringRecord::from(vec!["12", "", "00243878"]);
+
+        assert_eq!(
+            "12A-2.jpg",
+            script
+                .new_name(&fields(&with_extension), "12_2.jpg".to_string())
+                .unwrap()
+        );
+        assert_eq!(
+            "12_2.jpg",
+            script
+                .new_name(&fields(&without_extension), "12_2.jpg".to_string())
+                .unwrap()
+        );
+        assert!(invalid
+            .new_name(&fields(&with_extension), "12_2.jpg".to_string())
+            .is_err());
+        assert!(Script::load(Path::new("tests/missing.rhai")).is_err());
+    }
+}
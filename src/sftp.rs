@@ -0,0 +1,121 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use ssh2::{RenameFlags, Sftp};
+
+use crate::storage::{remote_path, Storage};
+
+// SftpStorage renames files on an SFTP server, over a session that the caller
+// has connected and authenticated.
+pub struct SftpStorage {
+    // The SFTP channel handles one request at a time.
+    sftp: Mutex<Sftp>,
+}
+
+impl SftpStorage {
+    pub fn new(session: &ssh2::Session) -> io::Result<SftpStorage> {
+        Ok(SftpStorage {
+            sftp: Mutex::new(session.sftp()?),
+        })
+    }
+}
+
+impl std::fmt::Debug for SftpStorage {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("SftpStorage")
+    }
+}
+
+impl Storage for SftpStorage {
+    fn list(&self, dir: &Path) -> io::Result<Vec<String>> {
+        let sftp = self.sftp.lock().unwrap();
+        let entries = match sftp.readdir(Path::new(&remote_path(dir)?)) {
+            Ok(entries) => entries,
+            Err(err) if is_not_found(&err) => return Ok(vec![]),
+            Err(err) => return Err(err.into()),
+        };
+        let mut names: Vec<String> = entries
+            .into_iter()
+            .filter(|(_, stat)| !stat.is_dir())
+            .filter_map(|(path, _)| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .map(str::to_owned)
+            })
+            .collect();
+        names.sort();
+
+        Ok(names)
+    }
+
+    fn exists(&self, path: &Path) -> io::Result<bool> {
+        let sftp = self.sftp.lock().unwrap();
+        match sftp.stat(Path::new(&remote_path(path)?)) {
+            Ok(_) => Ok(true),
+            Err(err) if is_not_found(&err) => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let sftp = self.sftp.lock().unwrap();
+        sftp.rename(
+            Path::new(&remote_path(from)?),
+            Path::new(&remote_path(to)?),
+            Some(RenameFlags::OVERWRITE | RenameFlags::ATOMIC | RenameFlags::NATIVE),
+        )?;
+
+        Ok(())
+    }
+
+    // copy streams the file through this machine, as SFTP cannot copy files on
+    // the server.
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let sftp = self.sftp.lock().unwrap();
+        let mut source = sftp.open(Path::new(&remote_path(from)?))?;
+        let mut target = sftp.create(Path::new(&remote_path(to)?))?;
+        io::copy(&mut source, &mut target)?;
+
+        Ok(())
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        let sftp = self.sftp.lock().unwrap();
+        sftp.unlink(Path::new(&remote_path(path)?))?;
+
+        Ok(())
+    }
+
+    // symlink links to the file by its name if the link is placed next to it,
+    // and by its full path otherwise.
+    fn symlink(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let original = if from.parent() == to.parent() {
+            PathBuf::from(from.file_name().unwrap_or_default())
+        } else {
+            PathBuf::from(remote_path(from)?)
+        };
+        let sftp = self.sftp.lock().unwrap();
+        sftp.symlink(&original, Path::new(&remote_path(to)?))?;
+
+        Ok(())
+    }
+
+    fn create_dir_all(&self, dir: &Path) -> io::Result<()> {
+        let sftp = self.sftp.lock().unwrap();
+        for dir in dir.ancestors().collect::<Vec<_>>().into_iter().rev() {
+            let dir = Path::new(&remote_path(dir)?).to_path_buf();
+            if dir.as_os_str().is_empty() || sftp.stat(&dir).is_ok() {
+                continue;
+            }
+            sftp.mkdir(&dir, 0o755)?;
+        }
+
+        Ok(())
+    }
+}
+
+// is_not_found tells whether the server answered that there is no such file.
+fn is_not_found(err: &ssh2::Error) -> bool {
+    err.code() == ssh2::ErrorCode::SFTP(2)
+}
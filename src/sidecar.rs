@@ -0,0 +1,160 @@
+use std::collections::{BTreeMap, HashSet};
+use std::iter;
+
+use crate::template::split_extension;
+
+// Sidecars holds the sidecar files of the files in a directory: files with
+// the same stem and one of the sidecar extensions, such as the XMP metadata
+// or the RAW original of a JPEG. Sidecars are not matched themselves, but
+// follow the file they belong to.
+#[derive(Debug, Default)]
+pub struct Sidecars {
+    by_file: BTreeMap<String, Vec<String>>,
+}
+
+impl Sidecars {
+    // split takes the sidecars out of the files. A file with a sidecar
+    // extension but no other file with its stem is an ordinary file. If
+    // several files share a stem, the sidecars belong to the first.
+    pub fn split(files: Vec<String>, extensions: &[String]) -> (Vec<String>, Sidecars) {
+        let is_sidecar = |file: &str| {
+            let (_, extension) = split_extension(file);
+            extensions
+                .iter()
+                .any(|sidecar| sidecar.eq_ignore_ascii_case(extension))
+        };
+
+        let mut firsts: BTreeMap<&str, &String> = BTreeMap::new();
+        for file in files.iter().filter(|file| !is_sidecar(file)) {
+            firsts.entry(split_extension(file).0).or_insert(file);
+        }
+
+        let mut by_file: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for file in files.iter().filter(|file| is_sidecar(file)) {
+            if let Some(&owner) = firsts.get(split_extension(file).0) {
+                by_file.entry(owner.clone()).or_default().push(file.clone());
+            }
+        }
+
+        let sidecars: HashSet<&String> = by_file.values().flatten().collect();
+        let files = files
+            .iter()
+            .filter(|file| !sidecars.contains(file))
+            .cloned()
+            .collect();
+
+        (files, Sidecars { by_file })
+    }
+
+    // of lists the sidecars of the file.
+    pub fn of(&self, file: &str) -> &[String] {
+        self.by_file.get(file).map_or(&[], Vec::as_slice)
+    }
+
+    // follow lists the sidecars of the files, which share their fate.
+    pub fn follow(&self, files: &[String]) -> Vec<String> {
+        files
+            .iter()
+            .flat_map(|file| self.of(file))
+            .cloned()
+            .collect()
+    }
+
+    // share_fate names the sidecars of renamed files after them, and keeps a
+    // file and its sidecars together when any of them is skipped. It returns
+    // the files skipped along.
+    pub fn share_fate(
+        &self,
+        renamings: &mut BTreeMap<String, String>,
+        skipped: &[String],
+        lowercase_extension: bool,
+    ) -> Vec<String> {
+        let skipped: HashSet<&String> = skipped.iter().collect();
+        let mut skipped_along = vec![];
+        for (file, sidecars) in &self.by_file {
+            let mut group = iter::once(file).chain(sidecars);
+            if group.any(|file| skipped.contains(file)) {
+                for file in iter::once(file).chain(sidecars) {
+                    if renamings.remove(file).is_some() {
+                        skipped_along.push(file.clone());
+                    }
+                }
+                continue;
+            }
+            if let Some(new_name) = renamings.get(file).cloned() {
+                for sidecar in sidecars {
+                    let sidecar_name = Sidecars::new_name(sidecar, &new_name, lowercase_extension);
+                    renamings.insert(sidecar.clone(), sidecar_name);
+                }
+            }
+        }
+
+        skipped_along
+    }
+
+    // pairs lists every sidecar with the file it belongs to.
+    pub fn pairs(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.by_file
+            .iter()
+            .flat_map(|(file, sidecars)| sidecars.iter().map(move |sidecar| (file, sidecar)))
+    }
+
+    // new_name names the sidecar after the new name of its file, keeping its
+    // own extension.
+    fn new_name(sidecar: &str, new_name: &str, lowercase_extension: bool) -> String {
+        let (stem, _) = split_extension(new_name);
+        let (_, extension) = split_extension(sidecar);
+        if lowercase_extension {
+            format!("{}.{}", stem, extension.to_lowercase())
+        } else {
+            format!("{}.{}", stem, extension)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_off_sidecars() {
+        let files: Vec<String> = [
+            "00243344.1.CR2",
+            "00243344.1.jpg",
+            "00243344.1.xmp",
+            "00243344.2.jpg",
+            "00243880.1.xmp",
+        ]
+        .iter()
+        .map(|file| file.to_string())
+        .collect();
+        let extensions = vec![String::from("xmp"), String::from("cr2")];
+
+        let (files, sidecars) = Sidecars::split(files, &extensions);
+
+        // The XMP file of 00243880 has no file to belong to.
+        assert_eq!(
+            vec!["00243344.1.jpg", "00243344.2.jpg", "00243880.1.xmp"],
+            files
+        );
+        assert_eq!(
+            vec!["00243344.1.CR2", "00243344.1.xmp"],
+            sidecars.of("00243344.1.jpg")
+        );
+        assert!(sidecars.of("00243344.2.jpg").is_empty());
+
+        let mut renamings = BTreeMap::from([
+            ("00243344.1.jpg".to_string(), "0003/3_1.jpg".to_string()),
+            ("00243344.2.jpg".to_string(), "3_2.jpg".to_string()),
+        ]);
+        sidecars.share_fate(&mut renamings, &[], false);
+        assert_eq!("0003/3_1.CR2", renamings["00243344.1.CR2"]);
+        assert_eq!("0003/3_1.xmp", renamings["00243344.1.xmp"]);
+
+        // A file goes nowhere without its sidecars.
+        renamings.remove("00243344.1.xmp");
+        let skipped = sidecars.share_fate(&mut renamings, &["00243344.1.xmp".to_string()], true);
+        assert_eq!(vec!["00243344.1.jpg", "00243344.1.CR2"], skipped);
+        assert_eq!(vec!["00243344.2.jpg"], renamings.keys().collect::<Vec<_>>());
+    }
+}
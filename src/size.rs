@@ -0,0 +1,98 @@
+use std::fs;
+use std::path::Path;
+
+// FileSize is a number of bytes, written as a number with an optional unit:
+// B, the decimal KB, MB and GB or the binary KiB, MiB and GiB, in any case,
+// such as `10KB` or `1.5 MiB`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FileSize(pub u64);
+
+impl std::str::FromStr for FileSize {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<FileSize, &'static str> {
+        let value = value.trim();
+        let unit_start = value
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(value.len());
+        let (number, unit) = value.split_at(unit_start);
+        let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+            "" | "b" => 1,
+            "kb" => 1000,
+            "mb" => 1000 * 1000,
+            "gb" => 1000 * 1000 * 1000,
+            "kib" => 1024,
+            "mib" => 1024 * 1024,
+            "gib" => 1024 * 1024 * 1024,
+            _ => return Err("size must be a number of bytes with an optional unit, e.g. 10KB"),
+        };
+        let number: f64 = number
+            .parse()
+            .map_err(|_| "size must be a number of bytes with an optional unit, e.g. 10KB")?;
+
+        Ok(FileSize((number * multiplier as f64).round() as u64))
+    }
+}
+
+// find_wrong_sizes lists the files in the directory that are smaller than min
+// or larger than max, such as the empty files of failed uploads, in the order
+// they were given. Files whose size cannot be read are listed as well.
+pub fn find_wrong_sizes<'a>(
+    dir: &Path,
+    files: impl IntoIterator<Item = &'a String>,
+    min: Option<FileSize>,
+    max: Option<FileSize>,
+) -> Vec<String> {
+    files
+        .into_iter()
+        .filter(|file| match fs::metadata(dir.join(file)) {
+            Ok(metadata) => {
+                let size = FileSize(metadata.len());
+                let wrong = min.is_some_and(|min| size < min) || max.is_some_and(|max| size > max);
+                if wrong {
+                    log::debug!("{} has a size of {} bytes", file, size.0);
+                }
+                wrong
+            }
+            Err(err) => {
+                log::debug!("could not read the size of {}: {}", file, err);
+                true
+            }
+        })
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sizes() {
+        assert_eq!(Ok(FileSize(512)), "512".parse());
+        assert_eq!(Ok(FileSize(10_000)), "10KB".parse());
+        assert_eq!(Ok(FileSize(1_572_864)), "1.5 MiB".parse());
+        assert_eq!(Ok(FileSize(2_000_000_000)), "2gb".parse());
+        assert!("ten".parse::<FileSize>().is_err());
+        assert!("10 parsecs".parse::<FileSize>().is_err());
+        assert!("".parse::<FileSize>().is_err());
+    }
+
+    #[test]
+    fn find_files_of_wrong_size() {
+        let dir = Path::new("tests/tmp_size");
+        let _ = fs::create_dir(dir);
+        fs::write(dir.join("empty.jpg"), "").unwrap();
+        fs::write(dir.join("photo.jpg"), [0; 2000]).unwrap();
+        fs::write(dir.join("huge.jpg"), [0; 5000]).unwrap();
+        let files = ["empty.jpg", "photo.jpg", "huge.jpg", "missing.jpg"].map(String::from);
+
+        let wrong = find_wrong_sizes(dir, &files, Some(FileSize(1)), Some(FileSize(4000)));
+        let small = find_wrong_sizes(dir, &files[..3], Some(FileSize(1000)), None);
+
+        fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(vec!["empty.jpg", "huge.jpg", "missing.jpg"], wrong);
+        assert_eq!(vec!["empty.jpg"], small);
+    }
+}
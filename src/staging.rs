@@ -0,0 +1,134 @@
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+// Files are moved out of the way under this prefix while they wait for their
+// new name, so that they are hidden and cannot be mistaken for photos.
+const TEMP_PREFIX: &str = ".rename-tmp.";
+
+// temp_name is the name a file is staged under during a run, in the
+// directory of the file itself, such as a lot directory or, for a file from an
+// extra directory, which is named by its absolute path, that directory.
+pub fn temp_name(old_name: &str) -> String {
+    let path = Path::new(old_name);
+    let Some(file_name) = path.file_name() else {
+        return format!("{}{}", TEMP_PREFIX, old_name);
+    };
+    let temp_name = format!("{}{}", TEMP_PREFIX, file_name.to_string_lossy());
+    match path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        Some(dir) => dir.join(temp_name).to_string_lossy().into_owned(),
+        None => temp_name,
+    }
+}
+
+// blocked_names lists the old names that are also the new name of another
+// renaming. Renaming in place, these files must be moved out of the way first,
+// or the other renaming would overwrite them. Files whose new name only
+// differs from the old one in case are listed as well: on case-insensitive
+// file systems, such as those of macOS and Windows, the new name is taken by
+// the file itself, so it is renamed in two steps through its temporary name.
+pub fn blocked_names(renamings: &BTreeMap<String, String>) -> Vec<&String> {
+    let new_names: HashSet<&str> = renamings
+        .iter()
+        .filter(|(old_name, new_name)| old_name != new_name)
+        .map(|(_, new_name)| new_name.as_str())
+        .collect();
+
+    renamings
+        .iter()
+        .filter(|(old_name, new_name)| {
+            old_name != new_name
+                && (new_names.contains(old_name.as_str()) || changes_case_only(old_name, new_name))
+        })
+        .map(|(old_name, _)| old_name)
+        .collect()
+}
+
+// changes_case_only tells whether the names are the same but for the case of
+// their letters.
+fn changes_case_only(old_name: &str, new_name: &str) -> bool {
+    old_name != new_name && old_name.to_lowercase() == new_name.to_lowercase()
+}
+
+// find_cycles lists the groups of files that take each other's names, such as
+// a swap of two files. Each cycle starts at its smallest name.
+pub fn find_cycles(renamings: &BTreeMap<String, String>) -> Vec<Vec<String>> {
+    let mut cycles = vec![];
+    let mut visited: HashSet<&str> = HashSet::new();
+
+    for start in renamings.keys() {
+        if visited.contains(start.as_str()) {
+            continue;
+        }
+
+        let mut path: Vec<&str> = vec![];
+        let mut current = start.as_str();
+        while let Some(new_name) = renamings.get(current) {
+            if visited.contains(current) || new_name == current {
+                break;
+            }
+            visited.insert(current);
+            path.push(current);
+            current = new_name;
+        }
+
+        // The walk ends on a name it passed itself only if it went round.
+        if let Some(position) = path.iter().position(|&name| name == current) {
+            cycles.push(
+                path[position..]
+                    .iter()
+                    .map(|&name| name.to_owned())
+                    .collect(),
+            );
+        }
+    }
+
+    cycles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_cycles_and_blocked_names() {
+        let renamings: BTreeMap<String, String> = BTreeMap::from([
+            // A swap.
+            ("1_1.jpg".to_string(), "2_1.jpg".to_string()),
+            ("2_1.jpg".to_string(), "1_1.jpg".to_string()),
+            // A cycle of three.
+            ("b.jpg".to_string(), "c.jpg".to_string()),
+            ("c.jpg".to_string(), "d.jpg".to_string()),
+            ("d.jpg".to_string(), "b.jpg".to_string()),
+            // A chain.
+            ("e.jpg".to_string(), "f.jpg".to_string()),
+            ("f.jpg".to_string(), "g.jpg".to_string()),
+            // A change of case only.
+            ("H.JPG".to_string(), "h.jpg".to_string()),
+            // Unrelated.
+            ("00243878.1.jpg".to_string(), "3_1.jpg".to_string()),
+            ("3_2.jpg".to_string(), "3_2.jpg".to_string()),
+        ]);
+
+        assert_eq!(
+            vec![
+                vec!["1_1.jpg".to_string(), "2_1.jpg".to_string()],
+                vec![
+                    "b.jpg".to_string(),
+                    "c.jpg".to_string(),
+                    "d.jpg".to_string()
+                ],
+            ],
+            find_cycles(&renamings)
+        );
+        assert_eq!(
+            vec!["1_1.jpg", "2_1.jpg", "H.JPG", "b.jpg", "c.jpg", "d.jpg", "f.jpg"],
+            blocked_names(&renamings)
+        );
+        assert_eq!(".rename-tmp.1_1.jpg", temp_name("1_1.jpg"));
+        assert_eq!("0001/.rename-tmp.1_1.jpg", temp_name("0001/1_1.jpg"));
+        assert_eq!(
+            "/photos/b/.rename-tmp.00243880.1.jpg",
+            temp_name("/photos/b/00243880.1.jpg")
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::journal::JournalEntry;
+use crate::operation::Operation;
+
+// The state file lives next to the journal while a run is in progress, and is
+// removed once the run has finished, whether it succeeded or was rolled back.
+pub const STATE_FILE_NAME: &str = ".rename-state.jsonl";
+
+// State is the plan of a run, written as the first line of the state file.
+// Each following line names a file whose renaming completed, so an interrupted
+// run loses at most the line being written.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct State {
+    pub operation: Operation,
+    // The directory holding the new names, if not the state file's own directory.
+    pub target_dir: Option<String>,
+    pub renamings: Vec<JournalEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Done {
+    done: String,
+}
+
+// StateLog appends completed renamings to the state file.
+pub struct StateLog {
+    file: Mutex<File>,
+}
+
+impl StateLog {
+    // create starts a state file in dir holding the plan.
+    pub fn create(dir: &Path, state: &State) -> io::Result<StateLog> {
+        let mut file = File::create(dir.join(STATE_FILE_NAME))?;
+        writeln!(file, "{}", serde_json::to_string(state)?)?;
+        file.sync_data()?;
+
+        Ok(StateLog {
+            file: Mutex::new(file),
+        })
+    }
+
+    // open continues the state file in dir.
+    pub fn open(dir: &Path) -> io::Result<StateLog> {
+        let file = OpenOptions::new()
+            .append(true)
+            .open(dir.join(STATE_FILE_NAME))?;
+
+        Ok(StateLog {
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn record(&self, old_name: &str) -> io::Result<()> {
+        let line = serde_json::to_string(&Done {
+            done: old_name.to_owned(),
+        })?;
+        let mut file = self.file.lock().unwrap();
+        writeln!(file, "{}", line)?;
+        file.sync_data()
+    }
+}
+
+pub fn exists(dir: &Path) -> bool {
+    dir.join(STATE_FILE_NAME).exists()
+}
+
+// read returns the plan in the state file in dir and the old names of the
+// files whose renaming completed.
+pub fn read(dir: &Path) -> io::Result<(State, HashSet<String>)> {
+    let mut lines = BufReader::new(File::open(dir.join(STATE_FILE_NAME))?).lines();
+
+    let state: State = match lines.next() {
+        Some(line) => serde_json::from_str(&line?)?,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "empty state file",
+            ))
+        }
+    };
+
+    let mut done = HashSet::new();
+    for line in lines {
+        // The last line is cut short if the run was killed while writing it.
+        match serde_json::from_str::<Done>(&line?) {
+            Ok(entry) => done.insert(entry.done),
+            Err(_) => break,
+        };
+    }
+
+    Ok((state, done))
+}
+
+pub fn remove(dir: &Path) -> io::Result<()> {
+    fs::remove_file(dir.join(STATE_FILE_NAME))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_and_read_state() {
+        let dir = Path::new("tests/tmp_state");
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir(dir).unwrap();
+
+        let state = State {
+            renamings: vec![
+                JournalEntry {
+                    old: "00243878.1.jpg".to_string(),
+                    new: "1_1.jpg".to_string(),
+                },
+                JournalEntry {
+                    old: "00243878.2.jpg".to_string(),
+                    new: "1_2.jpg".to_string(),
+                },
+            ],
+            ..State::default()
+        };
+        let log = StateLog::create(dir, &state).unwrap();
+        log.record("00243878.1.jpg").unwrap();
+        drop(log);
+        // A line cut short by a killed process.
+        StateLog::open(dir)
+            .unwrap()
+            .file
+            .lock()
+            .unwrap()
+            .write_all(b"{\"done\":\"0024")
+            .unwrap();
+
+        let (read_state, done) = read(dir).unwrap();
+
+        fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(state, read_state);
+        assert_eq!(HashSet::from(["00243878.1.jpg".to_string()]), done);
+    }
+}
@@ -0,0 +1,175 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::operation::{copy_file, move_file};
+
+// Storage is where the files are renamed: the local file system by default, or
+// a remote one such as an S3 bucket or an SFTP server. Paths are the configured
+// directories joined with the file names; a remote storage maps them onto its
+// own keys or paths.
+pub trait Storage: fmt::Debug + Send + Sync {
+    // list returns the names of the files in the directory, none if it does
+    // not exist. On the local file system, these include subdirectories and
+    // other entries, whose names are taken as well.
+    fn list(&self, dir: &Path) -> io::Result<Vec<String>>;
+
+    // list_files returns the names of the files in the directory that may be
+    // renamed: those list returns, if the storage has only files.
+    fn list_files(&self, dir: &Path) -> io::Result<Vec<String>> {
+        self.list(dir)
+    }
+
+    fn exists(&self, path: &Path) -> io::Result<bool>;
+
+    // rename moves the file to its new path, replacing any file there.
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    fn remove(&self, path: &Path) -> io::Result<()>;
+
+    // hard_link adds a name to the file, if the storage has hard links.
+    fn hard_link(&self, _from: &Path, _to: &Path) -> io::Result<()> {
+        Err(unsupported("hard links"))
+    }
+
+    // symlink creates a symbolic link to the file, if the storage has them.
+    fn symlink(&self, _from: &Path, _to: &Path) -> io::Result<()> {
+        Err(unsupported("symbolic links"))
+    }
+
+    // create_dir_all creates the directory and its parents, if the storage has
+    // directories at all.
+    fn create_dir_all(&self, _dir: &Path) -> io::Result<()> {
+        Ok(())
+    }
+
+    // is_local tells whether the paths are on the local file system, where the
+    // journal and the state of a run are kept next to the files.
+    fn is_local(&self) -> bool {
+        false
+    }
+}
+
+// LocalStorage renames files on the local file system.
+#[derive(Debug, Default, Clone)]
+pub struct LocalStorage {
+    // Check copied files, also those moved to another file system, with SHA-256.
+    pub verify: bool,
+    // Rename symbolic links to files along with regular files.
+    pub symlinks: bool,
+    // Copy the extended attributes of files along with their contents.
+    pub xattrs: bool,
+}
+
+impl Storage for LocalStorage {
+    fn list(&self, dir: &Path) -> io::Result<Vec<String>> {
+        Ok(crate::list_files(dir))
+    }
+
+    fn list_files(&self, dir: &Path) -> io::Result<Vec<String>> {
+        Ok(crate::list_regular_files(dir, self.symlinks))
+    }
+
+    fn exists(&self, path: &Path) -> io::Result<bool> {
+        Ok(path.exists())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        move_file(from, to, self.verify, self.xattrs)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<()> {
+        copy_file(from, to, self.verify, self.xattrs)
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn create_dir_all(&self, dir: &Path) -> io::Result<()> {
+        fs::create_dir_all(dir)
+    }
+
+    fn hard_link(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::hard_link(from, to)
+    }
+
+    // symlink links to the file by its name if the link is placed next to it,
+    // and by its absolute path otherwise.
+    fn symlink(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let original = if from.parent() == to.parent() {
+            PathBuf::from(from.file_name().unwrap_or_default())
+        } else {
+            fs::canonicalize(from)?
+        };
+
+        #[cfg(unix)]
+        return std::os::unix::fs::symlink(original, to);
+        #[cfg(windows)]
+        return std::os::windows::fs::symlink_file(original, to);
+        #[cfg(not(any(unix, windows)))]
+        return Err(unsupported("symbolic links"));
+    }
+
+    fn is_local(&self) -> bool {
+        true
+    }
+}
+
+fn unsupported(feature: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("the storage has no {}", feature),
+    )
+}
+
+// remote_path turns the path into a path with `/` separators, whatever the
+// local platform uses. Paths that climb out of a directory with `..` or start
+// with a drive or share, which mean nothing on a server, are refused rather
+// than quietly cut short.
+#[cfg(any(feature = "s3", feature = "sftp"))]
+pub(crate) fn remote_path(path: &Path) -> io::Result<String> {
+    use std::path::Component;
+
+    let mut parts = vec![];
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy()),
+            Component::CurDir | Component::RootDir => {}
+            Component::ParentDir | Component::Prefix(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} is not a path on the server", path.display()),
+                ))
+            }
+        }
+    }
+
+    Ok(if path.has_root() {
+        format!("/{}", parts.join("/"))
+    } else {
+        parts.join("/")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(any(feature = "s3", feature = "sftp"))]
+    #[test]
+    fn remote_paths() {
+        use super::*;
+
+        assert_eq!(
+            "photos/1_1.jpg",
+            remote_path(Path::new("./photos/1_1.jpg")).unwrap()
+        );
+        assert_eq!(
+            "/srv/photos",
+            remote_path(Path::new("/srv/photos")).unwrap()
+        );
+        assert!(remote_path(Path::new("photos/../secrets")).is_err());
+    }
+}
@@ -0,0 +1,180 @@
+use std::fmt;
+
+use crate::{Conflict, CrowdedLot, DuplicateRow, LowResolutionLot, RowError, UnmatchedRow};
+
+// StrictError lists everything that keeps a plan from running in strict mode,
+// so that a pipeline sees all anomalies of a run at once.
+#[derive(Debug, Default)]
+pub struct StrictError {
+    pub conflicts: Vec<Conflict>,
+    pub unmatched_files: Vec<String>,
+    pub unmatched_rows: Vec<UnmatchedRow>,
+    pub malformed_files: Vec<String>,
+    pub duplicate_files: Vec<Vec<String>>,
+    pub corrupt_files: Vec<String>,
+    pub wrong_size_files: Vec<String>,
+    pub low_resolution_lots: Vec<LowResolutionLot>,
+    pub bad_rows: Vec<RowError>,
+    pub crowded_lots: Vec<CrowdedLot>,
+    pub duplicate_rows: Vec<DuplicateRow>,
+}
+
+impl StrictError {
+    pub fn is_empty(&self) -> bool {
+        self.conflicts.is_empty()
+            && self.unmatched_files.is_empty()
+            && self.unmatched_rows.is_empty()
+            && self.malformed_files.is_empty()
+            && self.duplicate_files.is_empty()
+            && self.corrupt_files.is_empty()
+            && self.wrong_size_files.is_empty()
+            && self.low_resolution_lots.is_empty()
+            && self.bad_rows.is_empty()
+            && self.crowded_lots.is_empty()
+            && self.duplicate_rows.is_empty()
+    }
+}
+
+impl fmt::Display for StrictError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "strict mode found anomalies, nothing was renamed:")?;
+
+        if !self.conflicts.is_empty() {
+            write!(f, "\n{} conflict(s):", self.conflicts.len())?;
+            for conflict in &self.conflicts {
+                write!(f, "\n  {}", conflict)?;
+            }
+        }
+        if !self.unmatched_files.is_empty() {
+            write!(
+                f,
+                "\n{} file(s) matched no row in the data file:",
+                self.unmatched_files.len()
+            )?;
+            for file in &self.unmatched_files {
+                write!(f, "\n  {}", file)?;
+            }
+        }
+        if !self.unmatched_rows.is_empty() {
+            write!(
+                f,
+                "\n{} row(s) matched no files in the directory:",
+                self.unmatched_rows.len()
+            )?;
+            for row in &self.unmatched_rows {
+                write!(
+                    f,
+                    "\n  row {}: lot {}, inventory number {}",
+                    row.row, row.lot, row.inventory
+                )?;
+            }
+        }
+        if !self.malformed_files.is_empty() {
+            write!(
+                f,
+                "\n{} file(s) lack a photo number:",
+                self.malformed_files.len()
+            )?;
+            for file in &self.malformed_files {
+                write!(f, "\n  {}", file)?;
+            }
+        }
+        if !self.duplicate_files.is_empty() {
+            write!(
+                f,
+                "\n{} group(s) of files are identical:",
+                self.duplicate_files.len()
+            )?;
+            for files in &self.duplicate_files {
+                write!(f, "\n  {}", files.join(", "))?;
+            }
+        }
+        if !self.corrupt_files.is_empty() {
+            write!(
+                f,
+                "\n{} file(s) are not intact images:",
+                self.corrupt_files.len()
+            )?;
+            for file in &self.corrupt_files {
+                write!(f, "\n  {}", file)?;
+            }
+        }
+        if !self.wrong_size_files.is_empty() {
+            write!(
+                f,
+                "\n{} file(s) are smaller or larger than allowed:",
+                self.wrong_size_files.len()
+            )?;
+            for file in &self.wrong_size_files {
+                write!(f, "\n  {}", file)?;
+            }
+        }
+        if !self.low_resolution_lots.is_empty() {
+            write!(
+                f,
+                "\n{} lot(s) have images smaller than the minimum dimensions:",
+                self.low_resolution_lots.len()
+            )?;
+            for lot in &self.low_resolution_lots {
+                write!(f, "\n  {}", lot)?;
+            }
+        }
+        if !self.bad_rows.is_empty() {
+            write!(
+                f,
+                "\n{} row(s) of the data file cannot be used:",
+                self.bad_rows.len()
+            )?;
+            for row in &self.bad_rows {
+                write!(f, "\n  {}", row)?;
+            }
+        }
+        if !self.crowded_lots.is_empty() {
+            write!(
+                f,
+                "\n{} lot(s) have too many files:",
+                self.crowded_lots.len()
+            )?;
+            for lot in &self.crowded_lots {
+                write!(f, "\n  {}", lot)?;
+            }
+        }
+        if !self.duplicate_rows.is_empty() {
+            write!(
+                f,
+                "\n{} row(s) give an inventory number another lot:",
+                self.duplicate_rows.len()
+            )?;
+            for row in &self.duplicate_rows {
+                write!(f, "\n  {}", row)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for StrictError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_all_anomalies() {
+        let err = StrictError {
+            unmatched_files: vec!["00999999.1.jpg".to_string()],
+            malformed_files: vec!["00243878.jpg".to_string()],
+            ..StrictError::default()
+        };
+
+        assert!(!err.is_empty());
+        assert_eq!(
+            "strict mode found anomalies, nothing was renamed:\n\
+             1 file(s) matched no row in the data file:\n  00999999.1.jpg\n\
+             1 file(s) lack a photo number:\n  00243878.jpg",
+            err.to_string()
+        );
+        assert!(StrictError::default().is_empty());
+    }
+}
@@ -0,0 +1,262 @@
+use regex::Regex;
+
+// SuffixStrategy determines how the photo number is taken from a file name.
+#[derive(Debug, Clone, Default)]
+pub enum SuffixStrategy {
+    // The first period-separated segment after the first that is a number,
+    // excluding the extension, as in `00243344.1.jpg`, but also in
+    // `00243344.1.edited.jpg` and `00243344.10.jpg.bak`.
+    #[default]
+    Number,
+    // The Nth period-separated segment, counting from 0, excluding the extension.
+    Segment(usize),
+    // The digits at the end of the name without its extension, as in `IMG_0012.jpg`.
+    TrailingDigits,
+    // The first capture group of a regular expression, or the whole match if it
+    // has no groups.
+    Regex(Regex),
+    // The position of the photo among those of its object in the order they
+    // were taken, by the capture time in their EXIF data.
+    CaptureTime,
+}
+
+impl std::str::FromStr for SuffixStrategy {
+    type Err = &'static str;
+
+    // from_str accepts `number`, `segment:N`, `trailing-digits`,
+    // `regex:PATTERN` and `capture-time`.
+    fn from_str(value: &str) -> Result<SuffixStrategy, &'static str> {
+        if value == "number" {
+            return Ok(SuffixStrategy::Number);
+        }
+        if value == "trailing-digits" {
+            return Ok(SuffixStrategy::TrailingDigits);
+        }
+        if value == "capture-time" {
+            return Ok(SuffixStrategy::CaptureTime);
+        }
+        if let Some(segment) = value.strip_prefix("segment:") {
+            return segment
+                .parse()
+                .map(SuffixStrategy::Segment)
+                .map_err(|_| "suffix segment must be a non-negative integer");
+        }
+        if let Some(pattern) = value.strip_prefix("regex:") {
+            return Regex::new(pattern)
+                .map(SuffixStrategy::Regex)
+                .map_err(|_| "suffix regex is invalid");
+        }
+
+        Err("suffix strategy must be number, segment:N, trailing-digits, regex:PATTERN or capture-time")
+    }
+}
+
+impl SuffixStrategy {
+    // extract gets the photo number from the file name, if it has one. The
+    // capture time is not in the name, so it never yields one.
+    pub fn extract<'a>(&self, file_name: &'a str) -> Option<&'a str> {
+        let suffix = match self {
+            SuffixStrategy::Number | SuffixStrategy::Segment(_) => {
+                let segments = segments(file_name);
+                self.segment(&segments).map(|n| segments[n])
+            }
+            SuffixStrategy::TrailingDigits => {
+                let stem = match file_name.rsplit_once('.') {
+                    Some((stem, _)) => stem,
+                    None => file_name,
+                };
+                let digits = stem.len() - stem.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+                Some(&stem[stem.len() - digits..])
+            }
+            SuffixStrategy::Regex(regex) => regex.captures(file_name).and_then(|captures| {
+                captures
+                    .get(1)
+                    .or_else(|| captures.get(0))
+                    .map(|m| m.as_str())
+            }),
+            SuffixStrategy::CaptureTime => None,
+        };
+
+        suffix.filter(|suffix| !suffix.is_empty())
+    }
+
+    // rest is what follows the photo number up to the extension, with its
+    // leading period, such as `.edited` in `00243344.1.edited.jpg`. It is empty
+    // if the number is not a segment of the name.
+    pub fn rest(&self, file_name: &str) -> String {
+        let segments = segments(file_name);
+        match self.segment(&segments) {
+            Some(n) => segments[n + 1..]
+                .iter()
+                .map(|segment| format!(".{}", segment))
+                .collect(),
+            None => String::new(),
+        }
+    }
+
+    // segment finds the position of the photo number among the segments, for
+    // the strategies that take it from one.
+    fn segment(&self, segments: &[&str]) -> Option<usize> {
+        match self {
+            SuffixStrategy::Number => segments
+                .iter()
+                .skip(1)
+                .position(|segment| {
+                    !segment.is_empty() && segment.chars().all(|c| c.is_ascii_digit())
+                })
+                .map(|n| n + 1),
+            SuffixStrategy::Segment(n) => (*n < segments.len()).then_some(*n),
+            _ => None,
+        }
+    }
+}
+
+// resequence numbers the photo numbers of an object 1, 2, 3 and so on in
+// their numeric order, closing the gaps a photographer left. Files without a
+// photo number keep lacking one.
+pub fn resequence(suffixes: Vec<Option<String>>) -> Vec<Option<String>> {
+    let mut resequenced = vec![None; suffixes.len()];
+    let mut order: Vec<(usize, String)> = suffixes
+        .into_iter()
+        .enumerate()
+        .filter_map(|(position, suffix)| suffix.map(|suffix| (position, suffix)))
+        .collect();
+    // Numbers sort before anything else, which sorts as text.
+    order.sort_by_key(|(_, suffix)| {
+        let number = suffix.parse::<u64>().ok();
+        (number.is_none(), number, suffix.clone())
+    });
+
+    for (index, (position, _)) in order.into_iter().enumerate() {
+        resequenced[position] = Some((index + 1).to_string());
+    }
+    resequenced
+}
+
+// lead_with numbers the photo with the hero number 1 and the others 2, 3 and
+// so on in their numeric order, so the photo to show first comes first. It
+// returns None if no photo has the hero number.
+pub fn lead_with(suffixes: Vec<Option<String>>, hero: &str) -> Option<Vec<Option<String>>> {
+    let is_hero = |suffix: &str| {
+        suffix == hero
+            || matches!((suffix.parse::<u64>(), hero.parse::<u64>()), (Ok(a), Ok(b)) if a == b)
+    };
+    let hero_position = suffixes
+        .iter()
+        .position(|suffix| suffix.as_deref().is_some_and(is_hero))?;
+
+    let mut resequenced = resequence(suffixes);
+    let rank =
+        |suffix: &Option<String>| suffix.as_deref().map(|rank| rank.parse::<usize>().unwrap());
+    let hero_rank = rank(&resequenced[hero_position]);
+    for suffix in resequenced.iter_mut() {
+        match rank(suffix) {
+            Some(rank) if Some(rank) == hero_rank => *suffix = Some(String::from("1")),
+            Some(rank) if Some(rank) < hero_rank => *suffix = Some((rank + 1).to_string()),
+            _ => {}
+        }
+    }
+    Some(resequenced)
+}
+
+// segments splits the file name at its periods, leaving out the extension.
+fn segments(file_name: &str) -> Vec<&str> {
+    let mut segments: Vec<&str> = file_name.split('.').collect();
+    segments.pop();
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extract_segment() {
+        let strategy = SuffixStrategy::Segment(1);
+
+        assert_eq!(Some("1"), strategy.extract("00243878.1.jpg"));
+        assert_eq!(None, strategy.extract("README"));
+        assert_eq!(None, strategy.extract("IMG_1234.jpg"));
+        assert_eq!(None, strategy.extract("00243878..jpg"));
+        assert_eq!(
+            Some("b"),
+            SuffixStrategy::Segment(2).extract("00243878.a.b.jpg")
+        );
+    }
+
+    #[test]
+    fn extract_number() {
+        let strategy = SuffixStrategy::default();
+
+        assert_eq!(Some("1"), strategy.extract("00243878.1.jpg"));
+        assert_eq!(Some("1"), strategy.extract("00243878.1.edited.jpg"));
+        assert_eq!(Some("10"), strategy.extract("00243878.10.jpg.bak"));
+        assert_eq!(Some("2"), strategy.extract("00243878.front.2.jpg"));
+        assert_eq!(None, strategy.extract("00243878.jpg.bak"));
+        assert_eq!(None, strategy.extract("00243878..jpg"));
+
+        assert_eq!(".edited", strategy.rest("00243878.1.edited.jpg"));
+        assert_eq!(".jpg", strategy.rest("00243878.10.jpg.bak"));
+        assert_eq!("", strategy.rest("00243878.1.jpg"));
+        assert_eq!("", SuffixStrategy::TrailingDigits.rest("00243878_1.jpg"));
+    }
+
+    #[test]
+    fn resequence_numbers() {
+        let suffixes = ["10", "3", "7"]
+            .iter()
+            .map(|suffix| Some(suffix.to_string()))
+            .chain([None])
+            .collect();
+
+        assert_eq!(
+            vec![
+                Some("3".to_string()),
+                Some("1".to_string()),
+                Some("2".to_string()),
+                None
+            ],
+            resequence(suffixes)
+        );
+    }
+
+    #[test]
+    fn lead_with_hero() {
+        let suffixes: Vec<Option<String>> = ["1", "2", "10", "07"]
+            .iter()
+            .map(|suffix| Some(suffix.to_string()))
+            .collect();
+
+        let numbers = |suffixes: Vec<Option<String>>| -> Vec<String> {
+            suffixes.into_iter().map(Option::unwrap).collect()
+        };
+        assert_eq!(
+            vec!["2", "3", "1", "4"],
+            numbers(lead_with(suffixes.clone(), "10").unwrap())
+        );
+        assert_eq!(
+            vec!["2", "3", "4", "1"],
+            numbers(lead_with(suffixes.clone(), "7").unwrap())
+        );
+        assert!(lead_with(suffixes, "5").is_none());
+    }
+
+    #[test]
+    fn extract_trailing_digits() {
+        let strategy = SuffixStrategy::TrailingDigits;
+
+        assert_eq!(Some("0012"), strategy.extract("00243878_0012.jpg"));
+        assert_eq!(Some("3"), strategy.extract("00243878-3"));
+        assert_eq!(None, strategy.extract("00243878_a.jpg"));
+    }
+
+    #[test]
+    fn extract_regex() {
+        let strategy: SuffixStrategy = r"regex:_(\d+)_".parse().unwrap();
+
+        assert_eq!(Some("7"), strategy.extract("00243878_7_final.jpg"));
+        assert_eq!(None, strategy.extract("00243878.7.jpg"));
+        assert!("regex:(".parse::<SuffixStrategy>().is_err());
+        assert!("segment:x".parse::<SuffixStrategy>().is_err());
+    }
+}
@@ -0,0 +1,103 @@
+use crate::{PlanRow, Status};
+
+const HEADERS: [&str; 5] = ["OLD NAME", "NEW NAME", "LOT", "INVENTORY", "STATUS"];
+
+// Missing values, such as the new name of an unmatched file, are shown as this.
+const NONE: &str = "-";
+
+// render lays out the rows of a plan as a table with aligned columns. With
+// color, the status is coloured for terminals: green if all is well, red for
+// conflicts and yellow for files and rows that are left alone.
+pub fn render(rows: &[PlanRow], color: bool) -> String {
+    let cells: Vec<[&str; 4]> = rows
+        .iter()
+        .map(|row| {
+            [&row.old, &row.new, &row.lot, &row.inventory]
+                .map(|value| value.as_deref().unwrap_or(NONE))
+        })
+        .collect();
+
+    let mut widths = [0; 4];
+    for (i, width) in widths.iter_mut().enumerate() {
+        *width = cells
+            .iter()
+            .map(|row| row[i])
+            .chain([HEADERS[i]])
+            .map(|value| value.chars().count())
+            .max()
+            .unwrap_or_default();
+    }
+
+    let mut table = String::new();
+    let mut push_line = |values: [&str; 4], status: &str| {
+        for (value, width) in values.iter().zip(widths) {
+            table.push_str(value);
+            table.push_str(&" ".repeat(width - value.chars().count() + 2));
+        }
+        table.push_str(status);
+        table.push('\n');
+    };
+
+    push_line([HEADERS[0], HEADERS[1], HEADERS[2], HEADERS[3]], HEADERS[4]);
+    for (values, row) in cells.into_iter().zip(rows) {
+        let status = if color {
+            format!(
+                "\x1b[{}m{}\x1b[0m",
+                color_code(row.status),
+                row.status.name()
+            )
+        } else {
+            row.status.name().to_owned()
+        };
+        push_line(values, &status);
+    }
+
+    table
+}
+
+// color_code is the ANSI code of the colour of the status.
+fn color_code(status: Status) -> u8 {
+    match status {
+        Status::Ok | Status::Renamed => 32,
+        Status::Conflict => 31,
+        Status::Skipped
+        | Status::Malformed
+        | Status::Corrupt
+        | Status::WrongSize
+        | Status::LowResolution
+        | Status::Unmatched => 33,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_plan_table() {
+        let rows = vec![
+            PlanRow {
+                old: Some("00243878.1.jpg".to_string()),
+                new: Some("1_1.jpg".to_string()),
+                lot: Some("1".to_string()),
+                inventory: Some("00243878".to_string()),
+                status: Status::Ok,
+            },
+            PlanRow {
+                old: None,
+                new: None,
+                lot: Some("12".to_string()),
+                inventory: Some("00243999".to_string()),
+                status: Status::Unmatched,
+            },
+        ];
+
+        assert_eq!(
+            "OLD NAME        NEW NAME  LOT  INVENTORY  STATUS\n\
+             00243878.1.jpg  1_1.jpg   1    00243878   ok\n\
+             -               -         12   00243999   unmatched\n",
+            render(&rows, false)
+        );
+        assert!(render(&rows, true).contains("\x1b[32mok\x1b[0m"));
+    }
+}
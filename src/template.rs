@@ -0,0 +1,317 @@
+use std::fmt;
+
+use regex::Regex;
+
+use crate::date::{self, Date, DEFAULT_DATE_FORMAT};
+
+// The template reproducing the original hard-coded naming scheme.
+pub const DEFAULT_TEMPLATE: &str = "{lot}_{index}.jpg";
+
+#[derive(Debug, PartialEq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Segment {
+    Literal(String),
+    Field(Field),
+}
+
+#[derive(Debug, PartialEq)]
+enum Field {
+    Lot,
+    Inventory,
+    Index,
+    // What follows the photo number in the original name, before its
+    // extension.
+    Rest,
+    Name,
+    Stem,
+    Ext,
+    Column(usize),
+    // The date of the row or file, in this format.
+    Date(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TemplateError {
+    UnclosedBrace,
+    UnmatchedBrace,
+    UnknownField(String),
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TemplateError::UnclosedBrace => write!(f, "invalid template: unclosed '{{'"),
+            TemplateError::UnmatchedBrace => {
+                write!(
+                    f,
+                    "invalid template: unmatched '}}', use '}}}}' for a literal"
+                )
+            }
+            TemplateError::UnknownField(field) => {
+                write!(f, "invalid template: unknown field {{{}}}", field)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+// NameFields holds everything a template can refer to for a single file.
+pub struct NameFields<'a> {
+    pub lot: &'a str,
+    pub inventory: &'a str,
+    pub index: &'a str,
+    // The segments after the photo number in the file name, with their
+    // leading period.
+    pub rest: &'a str,
+    pub file_name: &'a str,
+    pub row: &'a csv::StringRecord,
+    // The date of the row or file, if the template has a date field.
+    pub date: Option<Date>,
+}
+
+impl Template {
+    // parse accepts literal text with fields in braces: {lot}, {inventory},
+    // {index}, {rest}, {name}, {stem}, {ext}, {column:N}, {date} and {date:FORMAT}.
+    // Braces are escaped by doubling.
+    pub fn parse(template: &str) -> Result<Template, TemplateError> {
+        Template::parse_with(template, parse_field)
+    }
+
+    // parse_with_groups accepts the capture groups of the regex by name or
+    // number, as in {id} or {1}, besides {name}, {stem}, {ext} and {date}. The
+    // groups are rendered from a row holding the captures of a file name.
+    pub fn parse_with_groups(template: &str, regex: &Regex) -> Result<Template, TemplateError> {
+        Template::parse_with(template, |name| {
+            let group = regex
+                .capture_names()
+                .position(|group| group == Some(name))
+                .or_else(|| name.parse().ok())
+                .filter(|&group| group < regex.captures_len());
+            if let Some(group) = group {
+                return Ok(Field::Column(group));
+            }
+            match parse_field(name)? {
+                field @ (Field::Name | Field::Stem | Field::Ext | Field::Date(_)) => Ok(field),
+                _ => Err(TemplateError::UnknownField(name.to_owned())),
+            }
+        })
+    }
+
+    fn parse_with(
+        template: &str,
+        parse_field: impl Fn(&str) -> Result<Field, TemplateError>,
+    ) -> Result<Template, TemplateError> {
+        let mut segments = vec![];
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '}' => return Err(TemplateError::UnmatchedBrace),
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(TemplateError::UnclosedBrace),
+                        }
+                    }
+
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Field(parse_field(&name)?));
+                }
+                c => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(Template { segments })
+    }
+
+    // columns lists the csv columns the template refers to.
+    pub fn columns(&self) -> Vec<usize> {
+        self.segments
+            .iter()
+            .filter_map(|segment| match segment {
+                Segment::Field(Field::Column(column)) => Some(*column),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // uses_date tells whether the template has a date field, which takes
+    // extra work to fill in.
+    pub fn uses_date(&self) -> bool {
+        self.segments
+            .iter()
+            .any(|segment| matches!(segment, Segment::Field(Field::Date(_))))
+    }
+
+    pub fn render(&self, fields: &NameFields) -> String {
+        let mut name = String::new();
+
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(literal) => name.push_str(literal),
+                Segment::Field(field) => match field {
+                    Field::Lot => name.push_str(fields.lot),
+                    Field::Inventory => name.push_str(fields.inventory),
+                    Field::Index => name.push_str(fields.index),
+                    Field::Rest => name.push_str(fields.rest),
+                    Field::Name => name.push_str(fields.file_name),
+                    Field::Stem => name.push_str(split_extension(fields.file_name).0),
+                    Field::Ext => name.push_str(split_extension(fields.file_name).1),
+                    Field::Column(column) => name.push_str(fields.row.get(*column).unwrap_or("")),
+                    Field::Date(format) => {
+                        if let Some(date) = fields.date {
+                            name.push_str(&date.format(format));
+                        }
+                    }
+                },
+            }
+        }
+
+        name
+    }
+}
+
+impl Default for Template {
+    fn default() -> Template {
+        Template::parse(DEFAULT_TEMPLATE).expect("default template is valid")
+    }
+}
+
+fn parse_field(name: &str) -> Result<Field, TemplateError> {
+    match name {
+        "lot" => Ok(Field::Lot),
+        "inventory" => Ok(Field::Inventory),
+        "index" => Ok(Field::Index),
+        "rest" => Ok(Field::Rest),
+        "name" => Ok(Field::Name),
+        "stem" => Ok(Field::Stem),
+        "ext" => Ok(Field::Ext),
+        "date" => Ok(Field::Date(String::from(DEFAULT_DATE_FORMAT))),
+        _ if name.starts_with("date:") => match &name["date:".len()..] {
+            format if date::check_format(format) => Ok(Field::Date(format.to_owned())),
+            _ => Err(TemplateError::UnknownField(name.to_owned())),
+        },
+        _ => name
+            .strip_prefix("column:")
+            .and_then(|column| column.parse::<usize>().ok())
+            .map(Field::Column)
+            .ok_or_else(|| TemplateError::UnknownField(name.to_owned())),
+    }
+}
+
+// split_extension splits a file name at its last period into stem and extension.
+pub(crate) fn split_extension(file_name: &str) -> (&str, &str) {
+    match file_name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => (stem, ext),
+        _ => (file_name, ""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_fields() {
+        let row = csv::StringRecord::from(vec!["3", "", "Chinese vase"]);
+        let fields = NameFields {
+            lot: "3",
+            inventory: "00243344",
+            index: "2",
+            rest: "",
+            file_name: "00243344.2.jpeg",
+            row: &row,
+            date: Date::parse("2024-05-01"),
+        };
+
+        let template =
+            Template::parse("{lot}-{index}_{column:2} ({stem}) {{{inventory}}}.{ext}").unwrap();
+
+        assert_eq!(
+            "3-2_Chinese vase (00243344.2) {00243344}.jpeg",
+            template.render(&fields)
+        );
+        assert_eq!(vec![2], template.columns());
+        assert!(!template.uses_date());
+
+        let template = Template::parse("{date}_{lot} {date:%d-%m-%y}").unwrap();
+        assert_eq!("20240501_3 01-05-24", template.render(&fields));
+        assert!(template.uses_date());
+
+        let template = Template::parse("{lot}_{index}{rest}.{ext}").unwrap();
+        assert_eq!("3_2.jpeg", template.render(&fields));
+        let fields = NameFields {
+            rest: ".edited",
+            ..fields
+        };
+        assert_eq!("3_2.edited.jpeg", template.render(&fields));
+    }
+
+    #[test]
+    fn render_groups() {
+        let regex = Regex::new(r"(?P<id>\d+)\.(?P<n>\d+)\.jpg").unwrap();
+        let captures = regex.captures("00243344.2.jpg").unwrap();
+        let row: csv::StringRecord = captures
+            .iter()
+            .map(|group| group.map_or("", |group| group.as_str()))
+            .collect();
+        let fields = NameFields {
+            lot: "",
+            inventory: "",
+            index: "",
+            rest: "",
+            file_name: "00243344.2.jpg",
+            row: &row,
+            date: None,
+        };
+
+        let template = Template::parse_with_groups("{id}-{n}.jpeg ({2}, {stem})", &regex).unwrap();
+        assert_eq!("00243344-2.jpeg (2, 00243344.2)", template.render(&fields));
+
+        assert_eq!(
+            Err(TemplateError::UnknownField("lot".to_string())),
+            Template::parse_with_groups("{lot}", &regex)
+        );
+        assert_eq!(
+            Err(TemplateError::UnknownField("3".to_string())),
+            Template::parse_with_groups("{3}", &regex)
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(Err(TemplateError::UnclosedBrace), Template::parse("{lot"));
+        assert_eq!(Err(TemplateError::UnmatchedBrace), Template::parse("lot}"));
+        assert_eq!(
+            Err(TemplateError::UnknownField("column:x".to_string())),
+            Template::parse("{column:x}")
+        );
+        assert_eq!(
+            Err(TemplateError::UnknownField("date:%B".to_string())),
+            Template::parse("{date:%B}")
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::time::Duration;
+
+// Timings tells how long the stages of a run took, to find out whether the
+// data file, the file system or the renaming itself is slow.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+pub struct Timings {
+    // Reading and parsing the data files.
+    #[serde(serialize_with = "milliseconds")]
+    pub reading: Duration,
+    // Listing the source and target directories.
+    #[serde(serialize_with = "milliseconds")]
+    pub listing: Duration,
+    // The rest of planning: matching, naming and checking for conflicts.
+    #[serde(serialize_with = "milliseconds")]
+    pub planning: Duration,
+    // Renaming the files, none in a dry run.
+    #[serde(serialize_with = "milliseconds")]
+    pub renaming: Duration,
+    // The number of files renamed.
+    pub files: usize,
+}
+
+impl Timings {
+    // throughput is the number of files renamed per second.
+    pub fn throughput(&self) -> f64 {
+        if self.renaming.is_zero() {
+            return 0.0;
+        }
+        self.files as f64 / self.renaming.as_secs_f64()
+    }
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "read data file in {:.2?}, listed files in {:.2?}, planned in {:.2?}",
+            self.reading, self.listing, self.planning
+        )?;
+        if self.files > 0 {
+            write!(
+                f,
+                ", renamed {} file(s) in {:.2?} ({:.1} files/s)",
+                self.files,
+                self.renaming,
+                self.throughput()
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+// milliseconds writes durations as fractional milliseconds in JSON reports.
+fn milliseconds<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64() * 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describe_timings() {
+        let timings = Timings {
+            reading: Duration::from_millis(12),
+            listing: Duration::from_millis(3),
+            planning: Duration::from_micros(1500),
+            renaming: Duration::from_millis(400),
+            files: 20,
+        };
+
+        assert_eq!(50.0, timings.throughput());
+        assert_eq!(
+            "read data file in 12.00ms, listed files in 3.00ms, planned in 1.50ms, \
+             renamed 20 file(s) in 400.00ms (50.0 files/s)",
+            timings.to_string()
+        );
+
+        let json = serde_json::to_value(timings).unwrap();
+        assert_eq!(400.0, json["renaming"]);
+    }
+}
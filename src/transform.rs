@@ -0,0 +1,188 @@
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+// Transform post-processes a new name, for instance to make it safe for URLs
+// and content management systems.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transform {
+    Lowercase,
+    Uppercase,
+    // Replace spaces by underscores.
+    Underscores,
+    // Remove accents and other diacritics, so `é` becomes `e`.
+    StripDiacritics,
+}
+
+impl std::str::FromStr for Transform {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<Transform, &'static str> {
+        match value {
+            "lowercase" => Ok(Transform::Lowercase),
+            "uppercase" => Ok(Transform::Uppercase),
+            "underscores" => Ok(Transform::Underscores),
+            "strip-diacritics" => Ok(Transform::StripDiacritics),
+            _ => Err("transform must be lowercase, uppercase, underscores or strip-diacritics"),
+        }
+    }
+}
+
+impl Transform {
+    pub fn apply(self, name: &str) -> String {
+        match self {
+            Transform::Lowercase => name.to_lowercase(),
+            Transform::Uppercase => name.to_uppercase(),
+            Transform::Underscores => name.replace(' ', "_"),
+            Transform::StripDiacritics => name
+                .nfd()
+                .filter(|&c| !is_combining_mark(c))
+                .nfc()
+                .collect(),
+        }
+    }
+}
+
+// LotTransform changes the lot number in new names, written as `add:1000` or
+// `subtract:1000` to offset it, or `prefix:A-` or `suffix:-B` to add text. An
+// online-only sale, for instance, can number its lots a block above the live
+// sale it runs next to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LotTransform {
+    Add(i64),
+    Prefix(String),
+    Suffix(String),
+}
+
+impl std::str::FromStr for LotTransform {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<LotTransform, &'static str> {
+        const FORMS: &str = "lot transform must be add:N, subtract:N, prefix:TEXT or suffix:TEXT";
+        let (kind, argument) = value.split_once(':').ok_or(FORMS)?;
+        match kind.trim() {
+            "add" => Ok(LotTransform::Add(
+                argument.trim().parse().map_err(|_| FORMS)?,
+            )),
+            "subtract" => {
+                let offset: i64 = argument.trim().parse().map_err(|_| FORMS)?;
+                Ok(LotTransform::Add(offset.checked_neg().ok_or(FORMS)?))
+            }
+            "prefix" => Ok(LotTransform::Prefix(argument.to_owned())),
+            "suffix" => Ok(LotTransform::Suffix(argument.to_owned())),
+            _ => Err(FORMS),
+        }
+    }
+}
+
+impl LotTransform {
+    // apply changes the lot number. Adding to it keeps the letters after the
+    // number, as in `101A`, and the width of a number padded with zeros. It
+    // fails for a lot that does not start with a number or would drop below
+    // zero.
+    pub fn apply(&self, lot: &str) -> Result<String, String> {
+        Ok(match self {
+            LotTransform::Add(offset) => {
+                let digits = lot.find(|c: char| !c.is_ascii_digit()).unwrap_or(lot.len());
+                let (number, rest) = lot.split_at(digits);
+                match number
+                    .parse::<u64>()
+                    .ok()
+                    .and_then(|number| number.checked_add_signed(*offset))
+                {
+                    Some(number) => format!("{:0>width$}{}", number, rest, width = digits),
+                    None if *offset < 0 => {
+                        return Err(format!("cannot subtract {} from lot {}", -offset, lot))
+                    }
+                    None => return Err(format!("cannot add {} to lot {}", offset, lot)),
+                }
+            }
+            LotTransform::Prefix(prefix) => format!("{}{}", prefix, lot),
+            LotTransform::Suffix(suffix) => format!("{}{}", lot, suffix),
+        })
+    }
+}
+
+// Normalization is a Unicode normalization form. The same accented letter can
+// be written composed, as one character, or decomposed, as a letter followed by
+// a combining accent. macOS hands out decomposed file names while data files
+// are mostly composed, so names that look the same may not match.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Normalization {
+    // Composed, as is common on Windows and Linux.
+    Nfc,
+    // Decomposed, as is common on macOS.
+    Nfd,
+}
+
+impl std::str::FromStr for Normalization {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<Normalization, &'static str> {
+        match value.to_lowercase().as_str() {
+            "nfc" => Ok(Normalization::Nfc),
+            "nfd" => Ok(Normalization::Nfd),
+            _ => Err("normalization must be nfc or nfd"),
+        }
+    }
+}
+
+impl Normalization {
+    pub fn apply(self, name: &str) -> String {
+        match self {
+            Normalization::Nfc => name.nfc().collect(),
+            Normalization::Nfd => name.nfd().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply_transforms() {
+        let name = "Kuyten Schoorl Café.JPG";
+
+        assert_eq!("kuyten schoorl café.jpg", Transform::Lowercase.apply(name));
+        assert_eq!("KUYTEN SCHOORL CAFÉ.JPG", Transform::Uppercase.apply(name));
+        assert_eq!(
+            "Kuyten_Schoorl_Café.JPG",
+            Transform::Underscores.apply(name)
+        );
+        assert_eq!(
+            "Kuyten Schoorl Cafe.JPG",
+            Transform::StripDiacritics.apply(name)
+        );
+        assert_eq!("Dvorak", Transform::StripDiacritics.apply("Dvořák"));
+    }
+
+    #[test]
+    fn apply_lot_transforms() {
+        let parse = |value: &str| value.parse::<LotTransform>().unwrap();
+
+        let apply = |transform: &str, lot: &str| parse(transform).apply(lot);
+
+        assert_eq!(Ok(String::from("1012")), apply("add:1000", "12"));
+        assert_eq!(Ok(String::from("1012")), apply("add:1000", "0012"));
+        assert_eq!(Ok(String::from("0002")), apply("subtract:10", "0012"));
+        assert_eq!(Ok(String::from("1101A")), apply("add: 1000", "101A"));
+        assert!(apply("subtract:100", "12").is_err());
+        assert!(apply("add:1000", "A12").is_err());
+        assert_eq!(Ok(String::from("A-12")), apply("prefix:A-", "12"));
+        assert_eq!(Ok(String::from("12-B")), apply("suffix:-B", "12"));
+        assert!("add:many".parse::<LotTransform>().is_err());
+        assert!("multiply:2".parse::<LotTransform>().is_err());
+        assert!("prefix".parse::<LotTransform>().is_err());
+    }
+
+    #[test]
+    fn apply_normalization() {
+        let composed = "Caf\u{e9}";
+        let decomposed = "Cafe\u{301}";
+
+        assert_eq!(composed, Normalization::Nfc.apply(decomposed));
+        assert_eq!(decomposed, Normalization::Nfd.apply(composed));
+        assert_eq!(composed, Normalization::Nfc.apply(composed));
+        assert!("nfkc".parse::<Normalization>().is_err());
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use notify::{EventKind, RecursiveMode, Watcher};
+
+use crate::{backup, journal, lock, state};
+use crate::{Config, RenameError, Report};
+
+// Files usually arrive in bursts, and a file that is still being copied
+// changes several times, so a run waits until the directory has been quiet
+// for this long.
+const QUIET_PERIOD: Duration = Duration::from_millis(500);
+
+// watch renames the files in the directory once, and again whenever files are
+// added to it. Each run re-reads the data file, so rows added to it during the
+// day are picked up as well. on_run receives the outcome of every run; the
+// watch ends when it returns false.
+pub fn watch<F>(config: &Config, mut on_run: F) -> Result<(), RenameError>
+where
+    F: FnMut(Result<Report, RenameError>) -> bool,
+{
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    watcher.watch(Path::new(&config.dir), RecursiveMode::NonRecursive)?;
+
+    loop {
+        let result = crate::run(config.clone());
+
+        // The files this run renamed and its journal change the directory too,
+        // but must not trigger another run.
+        let mut own_files: HashSet<String> = HashSet::new();
+        own_files.insert(journal::JOURNAL_FILE_NAME.to_string());
+        own_files.insert(state::STATE_FILE_NAME.to_string());
+        own_files.insert(lock::LOCK_FILE_NAME.to_string());
+        own_files.insert(backup::BACKUP_DIR.to_string());
+        if let Some(name) = config
+            .audit_log
+            .as_ref()
+            .and_then(|path| Path::new(path).file_name())
+        {
+            own_files.insert(name.to_string_lossy().into_owned());
+        }
+        if let Ok(report) = &result {
+            for renaming in &report.renamed {
+                own_files.insert(renaming.old.clone());
+                own_files.insert(renaming.new.clone());
+            }
+        }
+
+        if !on_run(result) {
+            return Ok(());
+        }
+
+        loop {
+            let event = match receiver.recv() {
+                Ok(event) => event?,
+                Err(_) => return Ok(()),
+            };
+            if is_arrival(&event, &own_files) {
+                break;
+            }
+        }
+        while let Ok(event) = receiver.recv_timeout(QUIET_PERIOD) {
+            event?;
+        }
+    }
+}
+
+// is_arrival tells whether the event concerns a file that was added to or
+// written in the directory by someone else than the watch itself.
+fn is_arrival(event: &notify::Event, own_files: &HashSet<String>) -> bool {
+    if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+        return false;
+    }
+
+    event.paths.iter().any(|path| {
+        path.file_name()
+            .map(|name| !own_files.contains(name.to_string_lossy().as_ref()))
+            .unwrap_or(false)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::thread;
+
+    #[test]
+    fn rename_arriving_files() {
+        let test_dir = "tests/tmp_watch";
+        let _ = fs::remove_dir_all(test_dir);
+        fs::create_dir(test_dir).unwrap();
+        fs::copy(
+            "tests/files/00243878.1.jpg",
+            format!("{}/00243878.1.jpg", test_dir),
+        )
+        .unwrap();
+
+        let config = Config::new(String::from("tests/data.csv"), String::from(test_dir));
+
+        let mut runs = 0;
+        let watching = thread::spawn(move || {
+            watch(&config, |result| {
+                runs += 1;
+                let report = result.expect("Run failed");
+                assert_eq!(1, report.renamed.len());
+                runs < 2
+            })
+        });
+
+        // Give the watcher time to do its first run before the next file arrives.
+        thread::sleep(Duration::from_millis(200));
+        fs::copy(
+            "tests/files/00243878.2.jpg",
+            format!("{}/00243878.2.jpg", test_dir),
+        )
+        .unwrap();
+
+        let outcome = watching.join();
+        let mut file_names: Vec<String> = fs::read_dir(test_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        file_names.sort();
+
+        fs::remove_dir_all(test_dir).unwrap();
+
+        outcome.unwrap().unwrap();
+        assert_eq!(
+            vec![".rename-journal.json", "1_1.jpg", "1_2.jpg"],
+            file_names
+        );
+    }
+}